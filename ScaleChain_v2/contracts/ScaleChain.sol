@@ -9,6 +9,10 @@ contract ScaleChain {
     uint[][] public signers; 
     SideNode[][10] public token_rings;
 
+    // nodes rebuild side chain history from these instead of scanning every transaction
+    event VoteSubmitted(uint indexed sid, uint bid, bytes block_header);
+    event SideChainReset(uint indexed sid);
+
     struct G1Point {
         uint X;
         uint Y;
@@ -175,6 +179,7 @@ contract ScaleChain {
         curr_hash[sid] = 0;
         uint[] memory new_signers; 
         signers[sid] = new_signers;
+        emit SideChainReset(sid);
     }
     
     
@@ -222,6 +227,7 @@ contract ScaleChain {
         curr_hash[sid] = sha256(curr_hash[sid], sha256(block_header));
         signers[sid].push(bitset);
         block_id[sid] = block_id[sid] + 1;
+        emit VoteSubmitted(sid, bid, block_header);
     }
 
     
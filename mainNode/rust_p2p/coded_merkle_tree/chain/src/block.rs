@@ -1,14 +1,15 @@
 use hex::FromHex;
 //use ser::{deserialize};
-use ser::{deserialize, serialize};
+use ser::{deserialize, serialize, Reader};
 use {BlockHeader, Transaction};
-use constants::{BASE_SYMBOL_SIZE, AGGREGATE, RATE};
+use constants::AGGREGATE;
+use params::CmtParams;
 use {Symbols, SymbolBase, SymbolUp};
 use bytes::Bytes;
 use coded_merkle_roots::{coded_merkle_roots, modular_code_merkle_roots};
 use hash::H256;
 use merkle_root::merkle_root;
-use decoder::{Code, Symbol};
+use decoder::{Code, Symbol, up_to_symbol};
 use rand::distributions::{Distribution, Bernoulli, Uniform};
 use CodingErr;
 use std::time::SystemTime;
@@ -20,29 +21,30 @@ pub struct Block {
 	pub block_header: BlockHeader,
 	pub transactions: Vec<Transaction>,
 	pub coded_tree: Vec<Symbols>, //Coded Merkle tree constructed from the transactions in the block
-	pub block_size_in_bytes: usize, // size of transactions in the block, used to specify block size for tests
+	pub params: CmtParams, // parameters the coded Merkle tree was built with
 }
 
 // index of the parent symbol on the coded Merkle tree
 // k is number of systematic symbols in the current layer
+// hashes are aggregated modularly, the parent of symbol i is i mod k_next where k_next = k / reduce_factor
 pub fn next_index(index: u32, k: u32, reduce_factor: u32) -> u32 {
-	if index <= k - 1 {
-		index / reduce_factor
-	}
-	else {
-		(index - k) / ((AGGREGATE as u32) - reduce_factor)
-	}
+	index % (k / reduce_factor)
+}
+
+// position of the hash of a symbol within its parent symbol
+pub fn hash_slot(index: u32, k: u32, reduce_factor: u32) -> u32 {
+	index / (k / reduce_factor)
 }
 
 // randomly sample a parity sibling of a systematic symbols
 // a parity sibling refers to a parity symbol that shares the same parent symbol as the systematic symbol
-pub fn sample_parity_sibling(index: u32, n: u32, header_size: u32, reduce_factor: u32) -> u32 {
+pub fn sample_parity_sibling(index: u32, n: u32, header_size: u32, reduce_factor: u32, rate: f32) -> u32 {
     // Use the same symbol if v is true, otherwise use a random sibling sampled uniformly
-	let d = Bernoulli::new(RATE as f64).unwrap();
+	let d = Bernoulli::new(rate as f64).unwrap();
     let v = d.sample(&mut rand::thread_rng());
     if v == true {return index;}
     else {
-    	let k = ((n as f32) * RATE) as u32;
+    	let k = ((n as f32) * rate) as u32;
         let mut siblings: Vec<u32> =  vec![];
 			if n > header_size { //if this is not the last layer
 				let parent: u32 = next_index(index, k, reduce_factor);
@@ -78,10 +80,59 @@ pub fn sample_parity_sibling(index: u32, n: u32, header_size: u32, reduce_factor
 // 	}
 // }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReconstructErr {
+	// bytes from this offset are neither a transaction nor padding
+	Malformed(usize),
+	// the transactions do not hash to the merkle root in the header
+	MerkleRoot,
+}
+
+// Block::new fills the block up to its size with 1s, and the last base symbol with 0s
+fn is_padding(data: &[u8]) -> bool {
+	let ones = data.iter().take_while(|b| **b == 1u8).count();
+	data[ones..].iter().all(|b| *b == 0u8)
+}
+
+// Transactions serialized back to back at the start of the data of a block, as Block::new lays them out.
+// Their number is not stored, they end where the rest is padding and the transactions read so far hash
+// to merkle_root_hash. Padding can read as a transaction, so the root is checked before reading on.
+pub fn transactions_from_bytes(data: &[u8], merkle_root_hash: &H256) -> Result<Vec<Transaction>, ReconstructErr> {
+	let mut transactions: Vec<Transaction> = vec![];
+	let mut hashes: Vec<H256> = vec![];
+	let mut offset = 0;
+	loop {
+		let rest = &data[offset..];
+		if is_padding(rest) && !hashes.is_empty() && merkle_root(&hashes) == *merkle_root_hash {
+			return Ok(transactions);
+		}
+		let transaction: Transaction = match Reader::new(rest).read() {
+			Ok(t) => t,
+			Err(_) if is_padding(rest) => return Err(ReconstructErr::MerkleRoot),
+			Err(_) => return Err(ReconstructErr::Malformed(offset)),
+		};
+		offset += transaction.bytes().len();
+		hashes.push(transaction.hash());
+		transactions.push(transaction);
+	}
+}
+
 impl Block {
+	// rebuild a decoded block from the data of its systematic symbols on the base layer,
+	// the coded Merkle tree is not rebuilt
+	pub fn reconstruct(header: BlockHeader, data: &[u8], params: &CmtParams) -> Result<Block, ReconstructErr> {
+		let transactions = transactions_from_bytes(data, &header.merkle_root_hash)?;
+		Ok(Block {
+			block_header: header,
+			transactions,
+			coded_tree: vec![],
+			params: params.clone(),
+		})
+	}
+
 	// construct a block 
 	// correct indicates if we will perform coding correctly or not on each level of the CMT
-	pub fn new(header: BlockHeader, transactions: &Vec<Transaction>, block_size: usize, header_size: u32, 
+	pub fn new(header: BlockHeader, transactions: &Vec<Transaction>, params: &CmtParams, 
 		codes: &Vec<Code>, correct: Vec<bool>) -> (Self, usize) {
 		// let block = Block {block_header: header.clone(), transactions: transactions.clone(), 
 		// coded_tree: vec![], block_size_in_bytes: block_size};
@@ -90,10 +141,10 @@ impl Block {
             block_header: header.clone(), 
             transactions: transactions.to_vec(), 
 			coded_tree: vec![], 
-            block_size_in_bytes: block_size
+            params: params.clone()
         };
 		//Compute coded Merkle tree and hashes of the last layer from the transactions	
-		let (trans_size, root_hashes, tree) = block.coded_merkle_roots(header_size, RATE, codes.to_vec(), correct);
+		let (trans_size, root_hashes, tree) = block.coded_merkle_roots(codes.to_vec(), correct);
 		let mut new_header = header;// block.block_header.clone();
         //new_header.num_symbols 
         
@@ -108,7 +159,7 @@ impl Block {
             block_header: new_header, 
             transactions: transactions.to_vec(), 
             coded_tree: tree, 
-            block_size_in_bytes: block_size
+            params: params.clone()
         };
         (block, trans_size)
 	}
@@ -136,7 +187,7 @@ impl Block {
 
 	//Returns hashes of the symbols on the top layer of coded Merkle tree 
 	//#[cfg(any(test, feature = "test-helpers"))]
-	pub fn coded_merkle_roots(&self, header_size: u32, rate: f32, codes: Vec<Code>, correct: Vec<bool>) -> (usize, Vec<H256>, Vec<Symbols>) {
+	pub fn coded_merkle_roots(&self, codes: Vec<Code>, correct: Vec<bool>) -> (usize, Vec<H256>, Vec<Symbols>) {
 		//Convert transactions into bytes and concatenate them into a Vec<u8>
         let start = SystemTime::now(); 
 		let mut trans_byte = self.transactions.iter().map(Transaction::bytes).collect::<Vec<Bytes>>();
@@ -148,8 +199,9 @@ impl Block {
 		let transactions_size_in_bytes = data.len();
 
 		//Append random data to meet target BLOCK_SIZE
-        let num_bytes = self.block_size_in_bytes - transactions_size_in_bytes;
-		if transactions_size_in_bytes < self.block_size_in_bytes {
+        let block_size = self.params.block_size as usize;
+        let num_bytes = block_size - transactions_size_in_bytes;
+		if transactions_size_in_bytes < block_size {
 
 			for _ in 0..num_bytes {
 				//let die = Uniform::from(0u8..=255u8);
@@ -168,26 +220,25 @@ impl Block {
 		// }
 
         // pad zeros to make the base layer integer number of base symbols
+		let symbol_size = self.params.base_symbol_size;
 		let original_size = data.len();
-		if original_size% BASE_SYMBOL_SIZE > 0 {
-			let padding = (original_size/BASE_SYMBOL_SIZE + 1) * BASE_SYMBOL_SIZE - original_size;
+		if original_size % symbol_size > 0 {
+			let padding = (original_size/symbol_size + 1) * symbol_size - original_size;
 			for i in 0..padding {
 				data.push(0x00);
 			}
 		}
         //println!("pad symbols size {:?}", data.len());
-		let k = data.len()/BASE_SYMBOL_SIZE;
+		let k = data.len()/symbol_size;
 		let mut symbols: Vec<SymbolBase> = Vec::<SymbolBase>::with_capacity(k);
 		for l in 0..k {
-			let mut symbol: SymbolBase = [0x00; BASE_SYMBOL_SIZE];
-			symbol.copy_from_slice(&data[l * BASE_SYMBOL_SIZE .. (l + 1) * BASE_SYMBOL_SIZE]);
-			symbols.push(symbol);
+			symbols.push(data[l * symbol_size .. (l + 1) * symbol_size].to_vec());
 		}
 		// construct CMT and the root hashes
         //println!("convert to symbol start CMT construted root num {:?}", start.elapsed());
         
 		//let (roots, tree) = coded_merkle_roots(&symbols, header_size, rate, codes, correct);
-        let (roots, tree) = modular_code_merkle_roots(&symbols, header_size, rate, codes, correct);
+        let (roots, tree) = modular_code_merkle_roots(&symbols, &self.params, codes, correct);
 
         //println!("CMT construted root num {} {:?}", roots.len(), start.elapsed());
 		(transaction_size, roots, tree)
@@ -206,14 +257,14 @@ impl Block {
 		let mut proof_indices: Vec<u32> = vec![];
 		let mut moving_index = index;
 		let mut moving_k = 0;
-		let reduce_factor = ((AGGREGATE as f32) * RATE) as u32;
+		let reduce_factor = self.params.reduce_factor() as u32;
 		//match &tree[lvl] {
 		match &self.coded_tree[lvl] {
 			Symbols::Base(syms) => {
-				moving_k = ((syms.len() as f32) * RATE) as u32;
+				moving_k = ((syms.len() as f32) * self.params.rate) as u32;
 			}
 			Symbols::Upper(syms) => {
-				moving_k = ((syms.len() as f32) * RATE) as u32;
+				moving_k = ((syms.len() as f32) * self.params.rate) as u32;
 			}
 		}
 		// find the index of next symbol in the proof on the next layer of CMT
@@ -232,7 +283,7 @@ impl Block {
         let num_layer = self.coded_tree.len();
         let mut symbols_all_levels: Vec<Vec<Symbol>> = Vec::with_capacity(num_layer);
 		let mut indices_all_levels: Vec<Vec<u64>> = Vec::with_capacity(num_layer);
-		let reduce_factor = ((AGGREGATE as f32) * RATE) as u32;
+		let reduce_factor = self.params.reduce_factor() as u32;
 		let header_size = self.block_header.coded_merkle_roots_hashes.len();
         //println!("coded tree size {}", self.coded_tree.len());
 
@@ -260,7 +311,7 @@ impl Block {
                     },
                     Symbols::Upper(syms_up)  => {
                         let len = layer_len[j];
-                        let sys_range = ((len as f32) *RATE) as usize;
+                        let sys_range = ((len as f32) * self.params.rate) as usize;
                         let par_range = len - sys_range;
                         
                         // choose systematic symbol
@@ -299,12 +350,22 @@ impl Block {
         (symbols_all_levels, indices_all_levels)
     }
 
-    
+
+	// Merkle proofs of sampled symbols, proofs[l][j] is the proof of the symbol idx[l][j] on layer l
+	pub fn sample_proofs(&self, idx: &Vec<Vec<u64>>) -> Vec<Vec<Vec<Symbol>>> {
+		idx.iter().enumerate().map(|(l, layer_idx)| {
+			layer_idx.iter().map(|i| {
+				let (proof, _) = self.merkle_proof(l, *i as u32);
+				proof.iter().map(up_to_symbol).collect()
+			}).collect()
+		}).collect()
+	}
+
     //take s random symbols from the base layer, and their Merkle proofs as symbols from other layers
 	pub fn sampling_to_decode(&self, s: u32) -> (Vec<Vec<Symbol>>, Vec<Vec<u64>>) {
 		let mut symbols_all_levels: Vec<Vec<Symbol>> = vec![];
 		let mut indices_all_levels: Vec<Vec<u64>> = vec![];
-		let reduce_factor = ((AGGREGATE as f32) * RATE) as u32;
+		let reduce_factor = self.params.reduce_factor() as u32;
 		let header_size = self.block_header.coded_merkle_roots_hashes.len();
 
 		if let Symbols::Base(syms) = &self.coded_tree[0] { // get the symbols on the base layer, syms is a vector of base symbols
@@ -325,7 +386,7 @@ impl Block {
             // as either the proof itself or one of its parity sibling (chosen uniformly at random)
 			for j in 0..up_symbols.len() { 
 				if let Symbols::Upper(syms_up) = &self.coded_tree[j+1] {
-					let chosen_index = sample_parity_sibling(up_indices[j], syms_up.len() as u32, header_size as u32, reduce_factor);
+					let chosen_index = sample_parity_sibling(up_indices[j], syms_up.len() as u32, header_size as u32, reduce_factor, self.params.rate);
 					let chosen_symbol = syms_up[chosen_index as usize]; //this symbols has type [H256; AGGREGATE]
 					//convert chosen_symbol to type "Symbol" 
 					let mut sym_byte = [0u8; 32 * AGGREGATE];
@@ -353,7 +414,7 @@ impl Block {
 				//push to upper levels if not seen before
 				for j in 0..up_symbols.len() {
 					if let Symbols::Upper(syms_up) = &self.coded_tree[j+1] {
-						let chosen_index = sample_parity_sibling(up_indices[j], syms_up.len() as u32, header_size as u32, reduce_factor);
+						let chosen_index = sample_parity_sibling(up_indices[j], syms_up.len() as u32, header_size as u32, reduce_factor, self.params.rate);
 						if !indices_all_levels[j+1].contains(&(chosen_index as u64)) {
 							let chosen_symbol = syms_up[chosen_index as usize]; //this symbols has type [H256; AGGREGATE]
 					        //convert chosen_symbol to type Symbol 
@@ -424,3 +485,45 @@ impl Block {
 //         assert!((data_size * 2) >= (n/step));
 // 	}
 // }
+
+#[cfg(test)]
+mod reconstruct_tests {
+	use super::*;
+	use fixtures::{params, block, transaction};
+
+	// systematic symbols of the base layer, as a decoder holds them
+	fn data(block: &Block) -> Vec<u8> {
+		let k = block.params.num_sys_symbols() as usize;
+		match &block.coded_tree[0] {
+			Symbols::Base(syms) => syms[..k].iter().flat_map(|s| s.iter().cloned()).collect(),
+			_ => unreachable!(),
+		}
+	}
+
+	#[test]
+	fn transactions_are_read_back_up_to_the_padding() {
+		let transactions = vec![transaction(1), transaction(2), transaction(3)];
+		let block = block(6, &transactions);
+		let rebuilt = Block::reconstruct(block.block_header.clone(), &data(&block), &params()).unwrap();
+		assert_eq!(rebuilt.transactions, transactions);
+		assert_eq!(rebuilt.merkle_root(), block.block_header.merkle_root_hash);
+
+		let one = self::block(6, &vec![transaction(7)]);
+		assert_eq!(transactions_from_bytes(&data(&one), &one.block_header.merkle_root_hash).unwrap(), vec![transaction(7)]);
+	}
+
+	#[test]
+	fn data_not_matching_the_header_is_rejected() {
+		let block = block(6, &vec![transaction(1), transaction(2)]);
+		let data = data(&block);
+		assert_eq!(transactions_from_bytes(&data, &H256::from(1)), Err(ReconstructErr::MerkleRoot));
+
+		let mut garbled = data.clone();
+		garbled[5] = 0xff;
+		match transactions_from_bytes(&garbled, &block.block_header.merkle_root_hash) {
+			Err(_) => (),
+			Ok(t) => panic!("garbled data read as {} transactions", t.len()),
+		}
+		assert_eq!(transactions_from_bytes(&[0x02; 64], &block.block_header.merkle_root_hash), Err(ReconstructErr::Malformed(0)));
+	}
+}
@@ -5,10 +5,12 @@ use ser::{deserialize, serialize};
 use crypto::dhash256;
 use compact::Compact;
 use hash::H256;
-use constants::{BASE_SYMBOL_SIZE, AGGREGATE, RATE};
+use constants::AGGREGATE;
+use params::CmtParams;
 use {Symbols, SymbolBase, SymbolUp};
-use block::next_index;
+use block::{next_index, hash_slot};
 use CodingErr;
+use decoder::{Symbol, symbol_to_up};
 
 
 #[derive(PartialEq, Clone, Serializable, Deserializable)]
@@ -20,6 +22,7 @@ pub struct BlockHeader {
 	pub bits: Compact,
 	pub nonce: u32,
 	pub coded_merkle_roots_hashes: Vec<H256>,//hashes of the symbols on the top layer of coded Merkle tree
+	pub code_id: H256,//id of the codes the block is encoded with, see code_registry::code_set_id
     //pub delimitor: Vec<u32>, // number of bytes in transactions 
 }
 
@@ -34,8 +37,8 @@ impl BlockHeader {
 	// Proof is a vector of symbols all the way to the top layer of CMT
 	// Return true if the symbol passes the Merkle proof check, false otherwise
 	//#[cfg(any(test, feature = "test-helpers"))]
-	pub fn verify_up(&self, symbol: SymbolUp, lvl: u32, index: u32, block_size: u32, proof: &Vec<SymbolUp>) -> bool {
-		let reduce_factor = ((AGGREGATE as f32) * RATE) as u32;
+	pub fn verify_up(&self, symbol: SymbolUp, lvl: u32, index: u32, block_size: u32, proof: &Vec<SymbolUp>, params: &CmtParams) -> bool {
+		let reduce_factor = params.reduce_factor() as u32;
 		let mut current_symbol = symbol;
 		let mut current_index = index;
 		let mut current_lvl = lvl;
@@ -43,17 +46,7 @@ impl BlockHeader {
 
 		for s in proof.iter() {
 			// hash_index is the index of next hash to compare with in the next symbol in the proof
-			let mut hash_index = 0;
-
-			// current symbol is a systematic symbol
-			if current_index <= current_k - 1 {
-				hash_index = current_index % reduce_factor;
-			}
-			// current symbol is a parity symbol
-			else {
-				hash_index = (current_index - current_k) % ((AGGREGATE as u32) -
-                                                       reduce_factor) + reduce_factor;
-			}
+			let hash_index = hash_slot(current_index, current_k, reduce_factor);
 
             //convert a symbol to a byte stream
 			let mut sym = [0u8; 32 * AGGREGATE];
@@ -63,7 +56,7 @@ impl BlockHeader {
         	}
 
 			if dhash256(&sym) != s[(hash_index as usize)]{
-			    println!("Failed at level {} with symbol index {}.", current_lvl, current_index);
+			    debug!("Failed at level {} with symbol index {}.", current_lvl, current_index);
                 return false;
 			}
 			else {
@@ -80,8 +73,8 @@ impl BlockHeader {
         	sym[j * 32 .. (j+1) * 32].copy_from_slice(&temp);
         }
 
-        if dhash256(&sym) != self.coded_merkle_roots_hashes[(current_index as usize)] {
-        	println!("Failed at level {} with symbol index {}.", current_lvl, current_index);
+        if self.coded_merkle_roots_hashes.get(current_index as usize) != Some(&dhash256(&sym)) {
+        	debug!("Failed at level {} with symbol index {}.", current_lvl, current_index);
             false
         } else{
         	true
@@ -90,144 +83,177 @@ impl BlockHeader {
 
     // Verify the Merkle proof of a base symbol using the hashes in the block header
     //#[cfg(any(test, feature = "test-helpers"))]
-	pub fn verify_base(&self, symbol: SymbolBase, index: u32, block_size: u32, proof: &Vec<SymbolUp>) -> bool {
-		let reduce_factor = ((AGGREGATE as f32) * RATE) as u32;
-		let mut hash_index = 0;
-			// current base symbol is a systematic symbol
-			if index <= block_size - 1 {
-				hash_index = index % reduce_factor;
-			}
-			// current base symbol is a parity symbol
-			else {
-				hash_index = (index - block_size) % ((AGGREGATE as u32) -
-                                                       reduce_factor) + reduce_factor;
-			}
+	pub fn verify_base(&self, symbol: &SymbolBase, index: u32, block_size: u32, proof: &Vec<SymbolUp>, params: &CmtParams) -> bool {
+		let reduce_factor = params.reduce_factor() as u32;
+		// the base layer is the top layer, its hashes are in the header
+		if proof.is_empty() {
+			return self.coded_merkle_roots_hashes.get(index as usize) == Some(&dhash256(symbol));
+		}
+		let hash_index = hash_slot(index, block_size, reduce_factor);
 
-		if dhash256(&symbol) != proof[0][(hash_index as usize)]{
-			    println!("Failed at base level with symbol index {}.", index);
+		if dhash256(symbol) != proof[0][(hash_index as usize)]{
+			    debug!("Failed at base level with symbol index {}.", index);
                 false
 			}
 			else {
 				self.verify_up(proof[0], 1, next_index(index, block_size, reduce_factor), 
-					block_size, &proof[1..].to_vec())
+					block_size, &proof[1..].to_vec(), params)
 			}
 		}
 
+	// Verify a symbol of any layer as it is transported, with its proof as symbols of the layers above
+	pub fn verify_symbol(&self, symbol: &Symbol, lvl: u32, index: u32, proof: &[Symbol], params: &CmtParams) -> bool {
+		let k_set = params.k_set();
+		let lvl = lvl as usize;
+		if lvl >= k_set.len() || proof.len() != k_set.len() - 1 - lvl {
+			return false;
+		}
+		if index as u64 >= ((k_set[lvl] as f32) / params.rate) as u64 {
+			return false;
+		}
+		let proof = match proof.iter().map(symbol_to_up).collect::<Option<Vec<SymbolUp>>>() {
+			Some(p) => p,
+			None => return false,
+		};
+		let block_size = params.num_sys_symbols() as u32;
+		match symbol {
+			Symbol::Base(s) if lvl == 0 && s.len() == params.base_symbol_size => {
+				self.verify_base(s, index, block_size, &proof, params)
+			},
+			Symbol::Upper(_) if lvl > 0 => {
+				let up = symbol_to_up(symbol).unwrap();
+				self.verify_up(up, lvl as u32, index, block_size, &proof, params)
+			},
+			_ => false,
+		}
+	}
+
     // This is a functionality at the light nodes
     // Verify that a malicious block producer does not do coding correctly, return true if the verification passes (or equivalently the coding is not done correct)
     // Proof is a set of symbols in the same layer that violate some encoding rules. merkle_proofs are their Merkle proofs
     // Error NotZero means all symbols of a parity equation does not sum up to zero. 
     // Error NotHash measn a symbol decoded from a parity equation does not match its hash.
-	pub fn verify_incorrect_coding(&self, proof: Symbols, lvl: u32, index: Vec<u32>, block_size: u32, merkle_proofs: Vec<Vec<SymbolUp>>, error_type: CodingErr) -> bool {
+    // The caller checks that index is a parity equation of the code on layer lvl.
+	pub fn verify_incorrect_coding(&self, proof: Symbols, lvl: u32, index: Vec<u32>, block_size: u32, merkle_proofs: Vec<Vec<SymbolUp>>, error_type: CodingErr, params: &CmtParams) -> bool {
+		// We put the index of the to-be-decoded symbol as the last element of index
+		// So for NotHash error, the size of index & merkle_proofs is one larger than the size of proof
+		if index.is_empty() {
+			debug!("Invalid incorrect-coding proof. No symbol is given.");
+			return false;
+		}
+		let num_known = match error_type {
+			CodingErr::NotZero => index.len(),
+			CodingErr::NotHash => index.len() - 1,
+			CodingErr::Stopped => {
+				debug!("Invalid incorrect-coding proof. A stopping set does not prove incorrect coding.");
+				return false;
+			},
+		};
+		if proof.len() != num_known || merkle_proofs.len() != index.len() {
+			debug!("Invalid incorrect-coding proof. Wrong number of symbols or Merkle proofs.");
+			return false;
+		}
 		match proof {
 			Symbols::Base(err_symbols) => {
 				// first check the Merkle proofs of all symbols in the incorrect-coding proof
 				for i in 0..err_symbols.len() {
-					if !self.verify_base(err_symbols[i], index[i], block_size, &merkle_proofs[i]) {
-						println!("Invalid incorrect-coding proof. Merkle proof of a symbol does not pass.");
+					if err_symbols[i].len() != params.base_symbol_size ||
+						!self.verify_base(&err_symbols[i], index[i], block_size, &merkle_proofs[i], params) {
+						debug!("Invalid incorrect-coding proof. Merkle proof of a symbol does not pass.");
 						return false;
 					}
 				}
+				let mut sum = vec![0u8; params.base_symbol_size];
+				for j in 0..params.base_symbol_size {
+					for i in 0..err_symbols.len() {
+						sum[j] = sum[j].bitxor(err_symbols[i][j]);
+					}
+				}
 				match error_type {
-					NotZero => {
-						let mut sum = [0u8; BASE_SYMBOL_SIZE];
-						for j in 0..BASE_SYMBOL_SIZE {
-							for i in 0..err_symbols.len() {
-								sum[j] = sum[j].bitxor(err_symbols[i][j]);
-							}
-						}
-						for j in 0..BASE_SYMBOL_SIZE {
+					CodingErr::NotZero => {
+						for j in 0..params.base_symbol_size {
 							if sum[j] != 0u8 {
 								return true;
 							}
 						}
-						println!("Invalid incorrect-coding proof. Symbols sum up to zero.");
+						debug!("Invalid incorrect-coding proof. Symbols sum up to zero.");
 						return false;
 					},
-					NotHash => {
-						let mut missing = [0u8; BASE_SYMBOL_SIZE];
-						for j in 0..BASE_SYMBOL_SIZE {
-							for i in 0..err_symbols.len() {
-								missing[j] = missing[j].bitxor(err_symbols[i][j]);
-							}
-						}
-						// We put the index of the to-be-decoded symbol as the last element of index
-						// So for NotHash error, the size of index & merkle_proofs is one larger than the size of proof
-						if !self.verify_base(missing, index[index.len()-1], block_size, &merkle_proofs[merkle_proofs.len()-1]) {
+					_ => {
+						// sum is the symbol decoded from the parity equation
+						if self.verify_hash_mismatch(dhash256(&sum), lvl, index[index.len()-1], block_size, &merkle_proofs[merkle_proofs.len()-1], params) {
 						    return true;
 						} else {
-							println!("Invalid incorrect-coding proof. Decoded symbol passes Merkle proof verification.");
+							debug!("Invalid incorrect-coding proof. Decoded symbol passes Merkle proof verification.");
 						    return false;
 						}
 					},
-					_ => {unreachable!();}
 				}
 			}
 			Symbols::Upper(err_symbols) => {
 				// first check the Merkle proofs of all symbols in the incorrect-coding proof
 				for i in 0..err_symbols.len() {
-					if !self.verify_up(err_symbols[i], lvl, index[i], block_size, &merkle_proofs[i]) {
-						println!("Invalid incorrect-coding proof. Merkle proof of a symbol does not pass.");
+					if !self.verify_up(err_symbols[i], lvl, index[i], block_size, &merkle_proofs[i], params) {
+						debug!("Invalid incorrect-coding proof. Merkle proof of a symbol does not pass.");
 						return false;
 					}
 				}
 				let symbol_size = 32 * AGGREGATE;
+				let mut sum = [0u8; 32 * AGGREGATE];
+				for i in 0..err_symbols.len() {
+					//Create a byte vector from an symbol on upper level
+					let mut sym = [0u8; 32 * AGGREGATE];
+					for k in 0..AGGREGATE {
+						let temp: [u8; 32] = err_symbols[i][k].clone().into();
+						sym[k * 32 .. (k+1) * 32].copy_from_slice(&temp);
+					}
+					for j in 0..symbol_size {
+						sum[j] = sum[j].bitxor(sym[j]);
+					}
+				}
 				match error_type {
-					NotZero => {
-						let mut sum = [0u8; 32 * AGGREGATE];
-						for i in 0..err_symbols.len() {
-							//Create a byte vector from an symbol on upper level
-							let mut sym = [0u8; 32 * AGGREGATE];
-							for k in 0..AGGREGATE {
-        	                    let temp: [u8; 32] = err_symbols[i][k].clone().into();
-        		                sym[k * 32 .. (k+1) * 32].copy_from_slice(&temp);
-        		            }
-							for j in 0..symbol_size {								
-								sum[j] = sum[j].bitxor(sym[j]);
-							}
-						}
+					CodingErr::NotZero => {
 						for j in 0..symbol_size {
 							if sum[j] != 0u8 {
 								return true;
 							}
 						}
-						println!("Invalid incorrect-coding proof. Symbols sum up to zero.");
+						debug!("Invalid incorrect-coding proof. Symbols sum up to zero.");
 						return false;
 					}
-					NotHash => {
-						let mut missing = [0u8; 32 * AGGREGATE];
-						for i in 0..err_symbols.len() {
-							//Create a byte vector from an symbol on upper level
-							let mut sym = [0u8; 32 * AGGREGATE];
-							for k in 0..AGGREGATE {
-        	                    let temp: [u8; 32] = err_symbols[i][k].clone().into();
-        		                sym[k * 32 .. (k+1) * 32].copy_from_slice(&temp);
-        		            }
-							for j in 0..symbol_size {								
-								missing[j] = missing[j].bitxor(sym[j]);
-							}
-						}
-
-						let mut decode = [H256::default(); AGGREGATE];
-						for k in 0..AGGREGATE {
-							let mut h = [0u8; 32];
-							h.copy_from_slice(&missing[k..k + 32]);
-							decode[k] = H256::from(h); 
-						}
-
-						// We put the index of the to-be-decoded symbol as the last element of index
-						if !self.verify_up(decode, lvl, index[index.len()-1], block_size, &merkle_proofs[merkle_proofs.len()-1]) {
+					_ => {
+						if self.verify_hash_mismatch(dhash256(&sum), lvl, index[index.len()-1], block_size, &merkle_proofs[merkle_proofs.len()-1], params) {
 						    return true;
 						} else {
-							println!("Invalid incorrect-coding proof. Decoded symbol passes Merkle proof verification.");
+							debug!("Invalid incorrect-coding proof. Decoded symbol passes Merkle proof verification.");
 						    return false;
 						}
 					},
-					_ => {unreachable!();}
 				}
 			}
 		}
 	}
+
+	// Return true if a symbol with hash decoded_hash at index of layer lvl is not the symbol committed in the tree.
+	// proof is the Merkle proof of that index, its first symbol holds the committed hash and must itself verify,
+	// otherwise anyone could claim a mismatch by sending a forged parent.
+	fn verify_hash_mismatch(&self, decoded_hash: H256, lvl: u32, index: u32, block_size: u32, proof: &Vec<SymbolUp>, params: &CmtParams) -> bool {
+		let reduce_factor = params.reduce_factor() as u32;
+		if proof.is_empty() {
+			// top layer, the committed hash is in the header
+			return match self.coded_merkle_roots_hashes.get(index as usize) {
+				Some(h) => *h != decoded_hash,
+				None => false,
+			};
+		}
+		let k = block_size / u32::pow(reduce_factor, lvl);
+		let parent = next_index(index, k, reduce_factor);
+		if !self.verify_up(proof[0], lvl + 1, parent, block_size, &proof[1..].to_vec(), params) {
+			debug!("Invalid incorrect-coding proof. Parent of the decoded symbol does not pass Merkle proof.");
+			return false;
+		}
+		proof[0][hash_slot(index, k, reduce_factor) as usize] != decoded_hash
+	}
 }
 
 impl fmt::Debug for BlockHeader {
@@ -241,6 +267,7 @@ impl fmt::Debug for BlockHeader {
 			.field("bits", &self.bits)
 			.field("nonce", &self.nonce)
 			.field("coded_merkle_roots_hashes", &self.coded_merkle_roots_hashes.len())
+			.field("code_id", &self.code_id)
             //.field("delimitor", &self.delimitor)
 			.finish()
 	}
@@ -273,6 +300,7 @@ mod tests {
 			bits: 5.into(),
 			nonce: 6,
 			coded_merkle_roots_hashes: vec![H256::default(); 8],
+			code_id: [7; 32].into(),
             len: 0,
 		};
 
@@ -295,6 +323,7 @@ mod tests {
 			0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 			0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 			0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+			7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
 		].into();
 
 		assert_eq!(stream.out(), expected);
@@ -318,6 +347,7 @@ mod tests {
 			0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 			0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 			0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+			7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
 		];
 
 		let mut reader = Reader::new(&buffer);
@@ -330,6 +360,7 @@ mod tests {
 			bits: 5.into(),
 			nonce: 6,
 			coded_merkle_roots_hashes: vec![H256::default(); 8],
+			code_id: [7; 32].into(),
             len: 0,
 		};
 
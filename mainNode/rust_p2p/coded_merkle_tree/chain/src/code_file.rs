@@ -0,0 +1,325 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ser::{Stream, Reader, CompactInteger, Error as ReaderError};
+use crypto::dhash256;
+use hash::H256;
+use decoder::Code;
+
+// Binary file of the encoding and decoding matrices of one code. The header repeats k, n, rate and
+// degree statistics so a file can be inspected without parsing the matrices, and carries the hash
+// of both matrices, which is also the id of the code.
+//
+// magic | version u32 | k u64 | n u64 | rate f32 | edges u64 | max var degree u32 |
+// max check degree u32 | hash | encoding matrix | decoding matrix
+//
+// A matrix is a compact count of rows, each a compact count of symbols and the compact symbols.
+pub const CODE_FILE_MAGIC: [u8; 4] = *b"CMTC";
+pub const CODE_FILE_VERSION: u32 = 1;
+const HEADER_LEN: usize = 4 + 4 + 8 + 8 + 4 + 8 + 4 + 4 + 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeFileErr {
+	Io(String),
+	Format(String),
+	Version(u32),
+	Checksum,
+	Invalid(String),
+}
+
+impl fmt::Display for CodeFileErr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CodeFileErr::Io(e) => write!(f, "io error: {}", e),
+			CodeFileErr::Format(e) => write!(f, "malformed code file: {}", e),
+			CodeFileErr::Version(v) => write!(f, "unsupported code file version {}", v),
+			CodeFileErr::Checksum => write!(f, "parity matrices do not match the hash in the header"),
+			CodeFileErr::Invalid(e) => write!(f, "invalid code: {}", e),
+		}
+	}
+}
+
+fn io_err(e: std::io::Error) -> CodeFileErr {
+	CodeFileErr::Io(e.to_string())
+}
+
+fn reader_err(e: ReaderError) -> CodeFileErr {
+	CodeFileErr::Format(format!("{:?}", e))
+}
+
+// degrees of the decoding matrix
+#[derive(Debug, Clone, PartialEq)]
+pub struct DegreeStats {
+	pub edges: u64,
+	pub max_var_degree: u32,
+	pub max_check_degree: u32,
+}
+
+impl DegreeStats {
+	fn of(parities: &[Vec<u64>], n: u64) -> DegreeStats {
+		let mut var_degrees = vec![0u32; n as usize];
+		for s in parities.iter().flatten() {
+			var_degrees[*s as usize] += 1;
+		}
+		DegreeStats {
+			edges: parities.iter().map(|p| p.len() as u64).sum(),
+			max_var_degree: var_degrees.into_iter().max().unwrap_or(0),
+			max_check_degree: parities.iter().map(|p| p.len() as u32).max().unwrap_or(0),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeFile {
+	pub k: u64,
+	pub n: u64,
+	pub rate: f32,
+	pub stats: DegreeStats,
+	pub hash: H256,
+	// parity equation i of the encoding matrix computes parity symbol k+i from systematic symbols
+	pub encode: Vec<Vec<u64>>,
+	pub decode: Vec<Vec<u64>>,
+}
+
+impl CodeFile {
+	// rows are sorted and empty decoding rows dropped, so the hash does not depend on the layout
+	pub fn new(k: u64, rate: f32, mut encode: Vec<Vec<u64>>, mut decode: Vec<Vec<u64>>) -> Result<CodeFile, CodeFileErr> {
+		let n = ((k as f32) / rate) as u64;
+		decode.retain(|p| !p.is_empty());
+		for p in encode.iter_mut().chain(decode.iter_mut()) {
+			p.sort();
+		}
+		check(k, n, &encode, &decode)?;
+		Ok(CodeFile {
+			k,
+			n,
+			rate,
+			stats: DegreeStats::of(&decode, n),
+			hash: dhash256(&matrices(&encode, &decode)),
+			encode,
+			decode,
+		})
+	}
+
+	// the k=K_encode.txt and k=K_decode.txt pair written by the code generators
+	pub fn from_text(dir: &Path, k: u64, rate: f32) -> Result<CodeFile, CodeFileErr> {
+		let encode = read_text(&dir.join(format!("k={}_encode.txt", k)))?;
+		let decode = read_text(&dir.join(format!("k={}_decode.txt", k)))?;
+		CodeFile::new(k, rate, encode, decode)
+	}
+
+	pub fn read(path: &Path) -> Result<CodeFile, CodeFileErr> {
+		let bytes = fs::read(path).map_err(io_err)?;
+		CodeFile::from_bytes(&bytes)
+	}
+
+	pub fn write(&self, path: &Path) -> Result<(), CodeFileErr> {
+		fs::write(path, self.to_bytes()).map_err(io_err)
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<CodeFile, CodeFileErr> {
+		if bytes.len() < HEADER_LEN {
+			return Err(CodeFileErr::Format("file is shorter than its header".to_string()));
+		}
+		if bytes[..4] != CODE_FILE_MAGIC {
+			return Err(CodeFileErr::Format("not a code file".to_string()));
+		}
+		let mut reader = Reader::new(&bytes[4..HEADER_LEN]);
+		let version: u32 = reader.read().map_err(reader_err)?;
+		if version != CODE_FILE_VERSION {
+			return Err(CodeFileErr::Version(version));
+		}
+		let k: u64 = reader.read().map_err(reader_err)?;
+		let n: u64 = reader.read().map_err(reader_err)?;
+		let rate: f32 = reader.read().map_err(reader_err)?;
+		let stats = DegreeStats {
+			edges: reader.read().map_err(reader_err)?,
+			max_var_degree: reader.read().map_err(reader_err)?,
+			max_check_degree: reader.read().map_err(reader_err)?,
+		};
+		let hash: H256 = reader.read().map_err(reader_err)?;
+
+		// a truncated or altered file fails here, before the matrices are trusted
+		let body = &bytes[HEADER_LEN..];
+		if dhash256(body) != hash {
+			return Err(CodeFileErr::Checksum);
+		}
+		let mut reader = Reader::new(body);
+		let encode = read_matrix(&mut reader)?;
+		let decode = read_matrix(&mut reader)?;
+		if !reader.is_finished() {
+			return Err(CodeFileErr::Format("data after the decoding matrix".to_string()));
+		}
+
+		let code = CodeFile::new(k, rate, encode, decode)?;
+		if code.n != n || code.stats != stats || code.hash != hash {
+			return Err(CodeFileErr::Format("header does not describe the matrices".to_string()));
+		}
+		Ok(code)
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut stream = Stream::default();
+		stream.append_slice(&CODE_FILE_MAGIC)
+			.append(&CODE_FILE_VERSION)
+			.append(&self.k)
+			.append(&self.n)
+			.append(&self.rate)
+			.append(&self.stats.edges)
+			.append(&self.stats.max_var_degree)
+			.append(&self.stats.max_check_degree)
+			.append(&self.hash)
+			.append_slice(&matrices(&self.encode, &self.decode));
+		stream.out().take()
+	}
+
+	// one code for encoding, and one code for decoding
+	pub fn codes(&self) -> (Code, Code) {
+		(to_code(&self.encode, self.n), to_code(&self.decode, self.n))
+	}
+}
+
+fn to_code(parities: &[Vec<u64>], n: u64) -> Code {
+	let mut symbols: Vec<Vec<u64>> = vec![vec![]; n as usize];
+	for (i, parity) in parities.iter().enumerate() {
+		for s in parity.iter() {
+			symbols[*s as usize].push(i as u64);
+		}
+	}
+	Code { parities: parities.to_vec(), symbols }
+}
+
+fn matrices(encode: &[Vec<u64>], decode: &[Vec<u64>]) -> Vec<u8> {
+	let mut stream = Stream::default();
+	for matrix in [encode, decode].iter() {
+		stream.append(&CompactInteger::from(matrix.len()));
+		for row in matrix.iter() {
+			stream.append(&CompactInteger::from(row.len()));
+			for s in row.iter() {
+				stream.append(&CompactInteger::from(*s));
+			}
+		}
+	}
+	stream.out().take()
+}
+
+fn read_matrix(reader: &mut Reader<&[u8]>) -> Result<Vec<Vec<u64>>, CodeFileErr> {
+	let rows: usize = reader.read::<CompactInteger>().map_err(reader_err)?.into();
+	let mut matrix = vec![];
+	for _ in 0..rows {
+		let len: usize = reader.read::<CompactInteger>().map_err(reader_err)?.into();
+		let mut row: Vec<u64> = vec![];
+		for _ in 0..len {
+			row.push(reader.read::<CompactInteger>().map_err(reader_err)?.into());
+		}
+		matrix.push(row);
+	}
+	Ok(matrix)
+}
+
+fn read_text(path: &Path) -> Result<Vec<Vec<u64>>, CodeFileErr> {
+	let file = File::open(path).map_err(|e| CodeFileErr::Io(format!("{}: {}", path.display(), e)))?;
+	let mut parities = vec![];
+	for (index, line) in BufReader::new(file).lines().enumerate() {
+		let line = line.map_err(io_err)?;
+		let parity = line.split_whitespace()
+			.map(|s| s.parse::<u64>())
+			.collect::<Result<Vec<u64>, _>>()
+			.map_err(|e| CodeFileErr::Format(format!("{} line {}: {}", path.display(), index + 1, e)))?;
+		parities.push(parity);
+	}
+	Ok(parities)
+}
+
+// The encoding matrix must be systematic and every codeword it produces must satisfy the decoding
+// matrix. The latter is checked on 64 random messages at once, one per bit of a word.
+fn check(k: u64, n: u64, encode: &[Vec<u64>], decode: &[Vec<u64>]) -> Result<(), CodeFileErr> {
+	if k == 0 || n <= k {
+		return Err(CodeFileErr::Invalid(format!("k={} leaves no parity symbols", k)));
+	}
+	if encode.len() as u64 != n - k {
+		return Err(CodeFileErr::Invalid(format!("{} encoding equations for {} parity symbols", encode.len(), n - k)));
+	}
+	for (i, parity) in encode.iter().enumerate() {
+		if parity.iter().filter(|s| **s >= k).collect::<Vec<&u64>>() != vec![&(k + i as u64)] {
+			return Err(CodeFileErr::Invalid(format!("encoding equation {} is not systematic", i)));
+		}
+	}
+	if let Some(s) = decode.iter().flatten().find(|s| **s >= n) {
+		return Err(CodeFileErr::Invalid(format!("symbol {} is beyond n={}", s, n)));
+	}
+	let mut covered = vec![false; n as usize];
+	for s in decode.iter().flatten() {
+		covered[*s as usize] = true;
+	}
+	if let Some(s) = covered.iter().position(|c| !c) {
+		return Err(CodeFileErr::Invalid(format!("symbol {} is in no decoding equation", s)));
+	}
+
+	let mut rng = StdRng::seed_from_u64(k);
+	let mut word: Vec<u64> = (0..k).map(|_| rng.gen()).collect();
+	for parity in encode.iter() {
+		word.push(parity.iter().filter(|s| **s < k).fold(0, |acc, s| acc ^ word[*s as usize]));
+	}
+	match decode.iter().position(|p| p.iter().fold(0, |acc, s| acc ^ word[*s as usize]) != 0) {
+		Some(j) => Err(CodeFileErr::Invalid(format!("decoding equation {} does not hold for the encoding matrix", j))),
+		None => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// k=2, n=4, parity 2 = s0 + s1 and parity 3 = s1
+	fn small() -> CodeFile {
+		CodeFile::new(2, 0.5, vec![vec![0, 1, 2], vec![1, 3]], vec![vec![2, 1, 0], vec![], vec![1, 3], vec![0, 2, 3]]).unwrap()
+	}
+
+	#[test]
+	fn binary_round_trip() {
+		let code = small();
+		assert_eq!(code.n, 4);
+		assert_eq!(code.decode, vec![vec![0, 1, 2], vec![1, 3], vec![0, 2, 3]]);
+		assert_eq!(code.stats, DegreeStats { edges: 8, max_var_degree: 2, max_check_degree: 3 });
+		assert_eq!(CodeFile::from_bytes(&code.to_bytes()), Ok(code.clone()));
+
+		let (encoding, decoding) = code.codes();
+		assert_eq!(encoding.symbols, vec![vec![0], vec![0, 1], vec![0], vec![1]]);
+		assert_eq!(decoding.parities.len(), 3);
+	}
+
+	#[test]
+	fn corrupted_files_are_rejected() {
+		let bytes = small().to_bytes();
+		assert_eq!(CodeFile::from_bytes(&bytes[..bytes.len() - 1]), Err(CodeFileErr::Checksum));
+		assert!(CodeFile::from_bytes(&bytes[..10]).is_err());
+
+		let mut flipped = bytes.clone();
+		*flipped.last_mut().unwrap() ^= 1;
+		assert_eq!(CodeFile::from_bytes(&flipped), Err(CodeFileErr::Checksum));
+
+		let mut version = bytes.clone();
+		version[4] = 9;
+		assert_eq!(CodeFile::from_bytes(&version), Err(CodeFileErr::Version(9)));
+	}
+
+	#[test]
+	fn mismatched_matrices_are_rejected() {
+		// parity 3 = s1 does not satisfy s0 + s3
+		assert!(CodeFile::new(2, 0.5, vec![vec![0, 1, 2], vec![1, 3]], vec![vec![0, 3], vec![1, 2]]).is_err());
+		assert!(CodeFile::new(2, 0.5, vec![vec![0, 1, 2]], vec![vec![0, 1, 2]]).is_err());
+		assert!(CodeFile::new(2, 0.5, vec![vec![0, 2, 3], vec![1, 3]], vec![vec![0, 1, 2, 3]]).is_err());
+	}
+
+	#[test]
+	fn reads_repository_codes() {
+		let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../src/LDPC_codes");
+		let code = CodeFile::from_text(&dir, 16, 0.25).unwrap();
+		assert_eq!(code.n, 64);
+		assert_eq!(CodeFile::from_bytes(&code.to_bytes()).unwrap().hash, code.hash);
+	}
+}
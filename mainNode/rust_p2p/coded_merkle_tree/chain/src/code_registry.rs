@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crypto::dhash256;
+use hash::H256;
+use code_file::{CodeFile, CodeFileErr};
+use decoder::Code;
+use params::CmtParams;
+
+// Codes of an ldpc directory, loaded once and cached by k. A binary k=K.code file is preferred,
+// the k=K_encode.txt and k=K_decode.txt pair is read otherwise. Code sets built from them are
+// kept by id, so the codes of a received block are found from its header.
+pub struct CodeRegistry {
+	dir: PathBuf,
+	codes: HashMap<u64, CodeFile>,
+	sets: HashMap<H256, CodeSet>,
+}
+
+// codes of all layers of a coded Merkle tree, the id goes into the block header so receivers
+// know which codes the block was encoded with
+#[derive(Debug, Clone)]
+pub struct CodeSet {
+	pub id: H256,
+	pub encoding: Vec<Code>,
+	pub decoding: Vec<Code>,
+}
+
+pub fn binary_path(dir: &Path, k: u64) -> PathBuf {
+	dir.join(format!("k={}.code", k))
+}
+
+// id of codes used on consecutive layers, from the base layer up
+pub fn code_set_id(hashes: &[H256]) -> H256 {
+	let mut bytes = vec![];
+	for h in hashes.iter() {
+		bytes.extend_from_slice(&h[..]);
+	}
+	dhash256(&bytes)
+}
+
+impl CodeRegistry {
+	pub fn new(dir: &str) -> CodeRegistry {
+		CodeRegistry {
+			dir: PathBuf::from(dir),
+			codes: HashMap::new(),
+			sets: HashMap::new(),
+		}
+	}
+
+	pub fn get(&mut self, k: u64, rate: f32) -> Result<&CodeFile, CodeFileErr> {
+		if !self.codes.contains_key(&k) {
+			let path = binary_path(&self.dir, k);
+			let code = if path.exists() {
+				CodeFile::read(&path)?
+			} else {
+				CodeFile::from_text(&self.dir, k, rate)?
+			};
+			if code.k != k {
+				return Err(CodeFileErr::Invalid(format!("{} holds a code for k={}", path.display(), code.k)));
+			}
+			self.codes.insert(k, code);
+		}
+		let code = &self.codes[&k];
+		if code.rate != rate {
+			return Err(CodeFileErr::Invalid(format!("code for k={} has rate {}, not {}", k, code.rate, rate)));
+		}
+		Ok(code)
+	}
+
+	// codes generated or converted at run time
+	pub fn insert(&mut self, code: CodeFile) {
+		self.codes.insert(code.k, code);
+	}
+
+	pub fn code_set(&mut self, params: &CmtParams) -> Result<CodeSet, CodeFileErr> {
+		let mut hashes = vec![];
+		let mut encoding = vec![];
+		let mut decoding = vec![];
+		for k in params.k_set() {
+			let code = self.get(k, params.rate)?;
+			let (code_e, code_d) = code.codes();
+			hashes.push(code.hash.clone());
+			encoding.push(code_e);
+			decoding.push(code_d);
+		}
+		for codes in [&encoding, &decoding].iter() {
+			params.validate(codes).map_err(CodeFileErr::Invalid)?;
+		}
+		let set = CodeSet {
+			id: code_set_id(&hashes),
+			encoding,
+			decoding,
+		};
+		self.sets.insert(set.id.clone(), set.clone());
+		Ok(set)
+	}
+
+	// a code set built before, None for codes this node does not know
+	pub fn find(&self, id: &H256) -> Option<&CodeSet> {
+		self.sets.get(id)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::fs;
+
+	#[test]
+	fn binary_files_take_precedence() {
+		let dir = env::temp_dir().join(format!("code_registry_{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let text = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../src/LDPC_codes");
+		let code = CodeFile::from_text(&text, 4, 0.25).unwrap();
+		code.write(&binary_path(&dir, 4)).unwrap();
+
+		let params = CmtParams { block_size: 128, base_symbol_size: 32, header_size: 16, ..CmtParams::default() };
+		let mut registry = CodeRegistry::new(dir.to_str().unwrap());
+		let set = registry.code_set(&params).unwrap();
+		assert_eq!(set.id, code_set_id(&[code.hash.clone()]));
+		assert_eq!(set.decoding[0].parities, code.decode);
+		assert_eq!(registry.find(&set.id).map(|s| s.decoding.len()), Some(1));
+		assert!(registry.find(&H256::default()).is_none());
+		assert!(registry.get(4, 0.5).is_err());
+
+		// cached, the file is not read again
+		fs::write(binary_path(&dir, 4), b"CMTC").unwrap();
+		assert!(registry.get(4, 0.25).is_ok());
+		assert!(CodeRegistry::new(dir.to_str().unwrap()).get(4, 0.25).is_err());
+		assert!(registry.get(8, 0.25).is_err());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
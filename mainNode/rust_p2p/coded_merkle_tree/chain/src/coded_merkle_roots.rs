@@ -1,14 +1,15 @@
 use crypto::dhash256;
 use hash::H256;
-use constants::{BASE_SYMBOL_SIZE, AGGREGATE, RATE};
+use constants::AGGREGATE;
+use params::CmtParams;
 use decoder::{Symbol, Code, Decoder};
 use ser::{Serializable, Deserializable, deserialize, serialize};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::time::SystemTime;
 
-//define the data type for a symbol on the base layer
+//define the data type for a symbol on the base layer, its size is CmtParams::base_symbol_size
 //#[derive(Serializable)]
-pub type SymbolBase = [u8; BASE_SYMBOL_SIZE];
+pub type SymbolBase = Vec<u8>;
 
 //define the data type for a symbol on the upper layers
 //#[derive(Serializable)]
@@ -37,12 +38,12 @@ impl Symbols{
 pub fn layer_to_layer_convert(symbols: &Vec<Symbol>) -> Symbols {
     //println!("layer_to_layer_convert len {}", symbols.len());
     let start = SystemTime::now();
-	match symbols[0] {
+	match &symbols[0] {
 		Symbol::Base(x) => {
 			let mut ss: Vec<SymbolBase> = vec![];
 			for i in 0..symbols.len() {
-				if let Symbol::Base(s) = symbols[i] {
-					ss.push(s);
+				if let Symbol::Base(s) = &symbols[i] {
+					ss.push(s.clone());
 				}
 			} 
 			return Symbols::Base(ss);
@@ -51,7 +52,7 @@ pub fn layer_to_layer_convert(symbols: &Vec<Symbol>) -> Symbols {
 			let mut ss_up: Vec<SymbolUp> = vec![];
 			for j in 0..symbols.len() {
 				let mut up_hash = [H256::default(); AGGREGATE]; // A symbol value in the hash form
-				if let Symbol::Upper(s_up) = symbols[j] {
+				if let Symbol::Upper(s_up) = &symbols[j] {
 					for t in 0..AGGREGATE {
 						let mut h = [0u8; 32];
 						h.copy_from_slice(&s_up[t*32..(t*32+32)]);
@@ -83,7 +84,7 @@ pub fn compute_hash(coded: &Symbols) -> Vec<H256> {
 }
 
 //Add zero-valued symbols to the base layer of CMT, such that the last layer of CMT has exactly one symbol 
-fn pad(symbols: &[SymbolBase], rate: f32) -> Vec<SymbolBase> {
+fn pad(symbols: &[SymbolBase], rate: f32, symbol_size: usize) -> Vec<SymbolBase> {
 	let mut data = symbols.to_vec();
 	let med = data.len() as f32;
 	let mut x = 1.0;
@@ -92,7 +93,7 @@ fn pad(symbols: &[SymbolBase], rate: f32) -> Vec<SymbolBase> {
 	}
 	let difference = (x * rate - med) as u64;
 	for _i in 0..difference {
-		data.push([0x00;BASE_SYMBOL_SIZE]);        
+		data.push(vec![0x00; symbol_size]);
 	}
 	data
 }
@@ -192,13 +193,13 @@ fn modular_hash_aggregate(coded: &Symbols, rate: f32) -> Symbols {
 
 pub fn modular_code_merkle_roots(
     symbols: &[SymbolBase], 
-    header_size: u32, 
-    rate: f32, 
+    params: &CmtParams, 
     codes: Vec<Code>, 
     correct: Vec<bool>) 
 -> (Vec<H256>, Vec<Symbols>) { 
     let start = SystemTime::now();
-    let data = pad(symbols, rate);
+    let (header_size, rate) = (params.header_size, params.rate);
+    let data = pad(symbols, rate, params.base_symbol_size);
     let n = ((data.len() as f32) / rate) as u32;
     //println!("n is {}", n);
     let reduce = (rate * (AGGREGATE as f32)).log2() ;
@@ -207,12 +208,12 @@ pub fn modular_code_merkle_roots(
 
     let mut tree: Vec<Symbols> = Vec::with_capacity(level as usize); 
 
-    let mut base_layer: Decoder = Decoder::new(0 as u32, codes[0].parities.to_vec(), codes[0].symbols.to_vec());
+    let mut base_layer: Decoder = Decoder::new(0 as u32, codes[0].parities.to_vec(), codes[0].symbols.to_vec(), params);
     
     //Perform encoding operation
     let mut sys_symbols_base: Vec<Symbol> = vec![];
     for j in 0..data.len() {
-        sys_symbols_base.push(Symbol::Base(data[j]));
+        sys_symbols_base.push(Symbol::Base(data[j].clone()));
     }
 
     let encoded_symbol = base_layer.encode(sys_symbols_base, correct[0]);
@@ -228,7 +229,8 @@ pub fn modular_code_merkle_roots(
         let mut upper_layer: Decoder = Decoder::new(
             (i+1) as u32, 
             codes[(i+1) as usize].parities.to_vec(), 
-            codes[(i+1) as usize].symbols.to_vec()
+            codes[(i+1) as usize].symbols.to_vec(),
+            params
         );
         //println!("decode init ok");   
         let mut sys_symbols_upper: Vec<Symbol> = vec![];
@@ -258,10 +260,11 @@ pub fn modular_code_merkle_roots(
 //symbols are the original block, header_size indicates # of hashes in the header 
 //the variable correct indicates whether the coding is done correctly or incorrectly by malicious block producer
 //Output is the root hashes, and the entire CMT, where each element represents a layer of the tree
-pub fn coded_merkle_roots(symbols: &[SymbolBase], header_size: u32, rate: f32, codes: Vec<Code>, correct: Vec<bool>) 
+pub fn coded_merkle_roots(symbols: &[SymbolBase], params: &CmtParams, codes: Vec<Code>, correct: Vec<bool>) 
 -> (Vec<H256>, Vec<Symbols>) { 
     let start = SystemTime::now();
-    let data = pad(symbols, rate);
+    let (header_size, rate) = (params.header_size, params.rate);
+    let data = pad(symbols, rate, params.base_symbol_size);
     let n = ((data.len() as f32) / rate) as u32;
     let level = ((((n/header_size) as f32).log2()/(rate * (AGGREGATE as f32)).log2()) as u32) + 1;
     //println!("n {} data {} level {} header size {}", n, data.len(), level, header_size);
@@ -272,12 +275,12 @@ pub fn coded_merkle_roots(symbols: &[SymbolBase], header_size: u32, rate: f32, c
     // Construct the base layer
     // Initialize encoder for base layer
     // Here encoder is implemented using peeling decoder
-    let mut base_layer: Decoder = Decoder::new(0 as u32, codes[0].parities.to_vec(), codes[0].symbols.to_vec());
+    let mut base_layer: Decoder = Decoder::new(0 as u32, codes[0].parities.to_vec(), codes[0].symbols.to_vec(), params);
     
     //Perform encoding operation
     let mut sys_symbols_base: Vec<Symbol> = vec![];
     for j in 0..data.len() {
-    	sys_symbols_base.push(Symbol::Base(data[j]));
+    	sys_symbols_base.push(Symbol::Base(data[j].clone()));
     }
 
     //Construct base layer
@@ -291,7 +294,7 @@ pub fn coded_merkle_roots(symbols: &[SymbolBase], header_size: u32, rate: f32, c
     	let new_data: Symbols = hash_aggregate(&tree[i as usize], rate); // data type is Symbols::Upper(Vec<SymbolUp>)
     	// Initialize encoder for layer i+1
         let mut upper_layer: Decoder = Decoder::new((i+1) as u32, 
-        	codes[(i+1) as usize].parities.to_vec(), codes[(i+1) as usize].symbols.to_vec());
+        	codes[(i+1) as usize].parities.to_vec(), codes[(i+1) as usize].symbols.to_vec(), params);
         
         let mut sys_symbols_upper: Vec<Symbol> = vec![];
         //Convert new_data to Vec<Symbol> for encoder
@@ -1,4 +1,5 @@
-use constants::{BASE_SYMBOL_SIZE, AGGREGATE, RATE, HEADER_SIZE, TRANSACTION_SIZE};
+use constants::AGGREGATE;
+use params::CmtParams;
 use std::cmp;
 use std::ops::BitXor;
 use {Symbols, SymbolBase, SymbolUp};
@@ -9,18 +10,20 @@ use serde::{Serialize,Deserialize};
 use big_array::BigArray;
 use std::time::SystemTime;
 use std::collections::HashSet;
+use std::borrow::Borrow;
+use std::sync::Arc;
 use std::thread;
 use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
 use bytes::Bytes;
 use {BlockHeader, Transaction};
+use block::{transactions_from_bytes, ReconstructErr};
 use ser::{deserialize, serialize};
 
 // Symbols on the base layer can have different size as the upper layer
 // The value of symbol is empty before it is decoded
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Symbol {
-    #[serde(with = "BigArray")]
-	Base([u8; BASE_SYMBOL_SIZE]),
+	Base(Vec<u8>),
     #[serde(with = "BigArray")]
 	Upper([u8; 32 * AGGREGATE]),
 	Empty,
@@ -29,7 +32,7 @@ pub enum Symbol {
 impl Symbol{
     pub fn bitxor(&mut self, y: &[u8]) {
         if let Symbol::Base(ref mut x) = *self {
-            for j in 0..BASE_SYMBOL_SIZE {
+            for j in 0..x.len() {
                 x[j] = x[j].bitxor(y[j]);
             }
         } else {
@@ -49,17 +52,45 @@ impl std::fmt::Debug for Symbol {
     }
 }
 
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        match (self, other) {
+            (Symbol::Base(x), Symbol::Base(y)) => x == y,
+            (Symbol::Upper(x), Symbol::Upper(y)) => x[..] == y[..],
+            (Symbol::Empty, Symbol::Empty) => true,
+            _ => false,
+        }
+    }
+}
+
 // a new type indicating types of coding errors
 // NotZero: symbols in a parity equation does not sum up to zero
 // NotHash: decoded symbol does not match its hash
 // Stopped: peeling decoder cannot continue due to absence of degree-one parity node 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum CodingErr{
 	NotZero,
 	NotHash,
 	Stopped,
 } 
 
+// decoding a coded Merkle tree fails on an incorrect coding, or on data that is coded
+// correctly but is not the transactions of the header
+#[derive(Clone, Debug)]
+pub enum TreeDecodeErr {
+	IncorrectCoding(IncorrectCodingProof),
+	Reconstruct(ReconstructErr),
+}
+
+impl From<IncorrectCodingProof> for TreeDecodeErr {
+	fn from(proof: IncorrectCodingProof) -> TreeDecodeErr {
+		TreeDecodeErr::IncorrectCoding(proof)
+	}
+}
+
 // a full node sends an incorrect-coding proof if it detects errors during decoding
+// for NotHash, indices ends with the index of the decoded symbol, which has a Merkle proof but no symbol
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IncorrectCodingProof {
 	pub error_type: CodingErr,
 	pub level: u32,
@@ -71,6 +102,66 @@ pub struct IncorrectCodingProof {
 	pub stop_ratio: f32
 }
 
+impl IncorrectCodingProof {
+	// Verify the proof against the header of the accused block, codes are the decoding codes of all layers.
+	// The symbols must form a parity equation of the code on their layer, a Stopped proof only says
+	// the sender could not decode and proves nothing about the block.
+	pub fn verify<C: Borrow<Code>>(&self, header: &BlockHeader, codes: &[C], params: &CmtParams) -> bool {
+		let lvl = self.level as usize;
+		if codes.len() != params.k_set().len() || lvl >= codes.len() {
+			return false;
+		}
+		let equation = match codes[lvl].borrow().parities.get(self.parity_index as usize) {
+			Some(e) => e,
+			None => return false,
+		};
+		if let CodingErr::NotHash = self.error_type {
+			// the decoded symbol goes last and is not one of the known symbols
+			match self.indices.split_last() {
+				Some((missing, known)) if !known.contains(missing) => (),
+				_ => return false,
+			}
+		}
+		let mut claimed = self.indices.clone();
+		let mut expected = equation.clone();
+		claimed.sort();
+		expected.sort();
+		if claimed != expected {
+			return false;
+		}
+
+		// a Merkle proof holds one symbol from each layer above
+		let mut merkle_proofs: Vec<Vec<SymbolUp>> = vec![];
+		for proof in self.proofs.iter() {
+			if proof.len() != codes.len() - 1 - lvl {
+				return false;
+			}
+			match proof.iter().map(symbol_to_up).collect::<Option<Vec<SymbolUp>>>() {
+				Some(p) => merkle_proofs.push(p),
+				None => return false,
+			}
+		}
+		let symbols = if lvl == 0 {
+			let mut base = vec![];
+			for symbol in self.symbols.iter() {
+				match symbol {
+					Symbol::Base(s) => base.push(s.clone()),
+					_ => return false,
+				}
+			}
+			Symbols::Base(base)
+		} else {
+			match self.symbols.iter().map(symbol_to_up).collect::<Option<Vec<SymbolUp>>>() {
+				Some(up) => Symbols::Upper(up),
+				None => return false,
+			}
+		};
+		let index: Vec<u32> = self.indices.iter().map(|i| *i as u32).collect();
+		let block_size = params.num_sys_symbols() as u32;
+		header.verify_incorrect_coding(symbols, self.level, index, block_size, merkle_proofs, self.error_type, params)
+	}
+}
+
 //A code is specified by its parity-check matrix, which is represented by parities or symbols vectors
 #[derive(Clone)]
 pub struct Code {
@@ -82,6 +173,7 @@ pub struct Code {
 // contains a decoder for each layer of CMT
 // hashes are hashes of the coded symbols on the last (top) layer
 pub struct TreeDecoder {
+	pub params: CmtParams,
 	pub n: u64, //block length of code on the base layer of the tree
 	pub height: u32,
 	pub decoders: Vec<Decoder>,
@@ -95,10 +187,7 @@ pub struct Decoder {
 	pub k: u64, // # of systematic symbols
 	pub p: u64, // # of parity check equations
 
-	pub code: Code, //code shall not change during decoding
-
-	pub parities: Vec<Vec<u64>>, // vector of length p, each element is a vector indicating the variable nodes connected to a parity node
-	pub symbols: Vec<Vec<u64>>, // vector of length n, each element is a vector indicating the parity nodes connected to a variable node
+	pub code: Arc<Code>, //code shall not change during decoding, it is shared by all decoders of the layer
 
     pub parities_set: Vec<HashSet<u64>>,
 
@@ -112,10 +201,8 @@ pub struct Decoder {
 }
 
 //Convert decoded symbols of the current layer to the hashes of the previous layer
+//hashes are aggregated modularly, hash h of the previous layer is slot h / k of symbol h % k
 fn symbol_to_hash(symbols: &Vec<Symbol>) -> Vec<H256> {
-    let reduce_factor = ((
-            AGGREGATE as f32) * RATE) as u64;
-
 	let number_of_hashes = symbols.len() * AGGREGATE; 
 	let mut previous_hashes = vec![H256::default();number_of_hashes];
 
@@ -124,7 +211,7 @@ fn symbol_to_hash(symbols: &Vec<Symbol>) -> Vec<H256> {
 	for i in 0..symbols.len() {
 		//convert symbols[i] to a vector of hashes
 		let mut symbol_in_hash = [H256::default(); AGGREGATE];
-		if let Symbol::Upper(symbol_in_bytes) = symbols[i] {
+		if let Symbol::Upper(symbol_in_bytes) = &symbols[i] {
 			for j in 0..AGGREGATE {
 				let mut h = [0u8; 32];
 				h.copy_from_slice(&symbol_in_bytes[j*32..(j*32+32)]);
@@ -134,33 +221,20 @@ fn symbol_to_hash(symbols: &Vec<Symbol>) -> Vec<H256> {
 		symbols_in_hashes.push(symbol_in_hash);
 	}
     
-    //number of systematic symbols on the previous level
-	let k = ((previous_hashes.len() as f32) * RATE) as u64;
-
+	let k = symbols.len();
 	for index in 0..previous_hashes.len() {
-		let mut hash_index = 0;
-
-        // current symbol is a systematic symbol
-		if (index as u64) <= k - 1 {
-			hash_index = (index as u64) % reduce_factor;
-		}
-		// current symbol is a parity symbol
-		else {
-			hash_index = ((index as u64) - k) % ((AGGREGATE as u64) - reduce_factor) + reduce_factor;
-		}
-
-		previous_hashes[index] = symbols_in_hashes[next_index(index as u64, k, reduce_factor) as usize][hash_index as usize];
+		previous_hashes[index] = symbols_in_hashes[index % k][index / k];
 	}
 
 	previous_hashes
 }
 
 //return if a symbol is equal to zero or not (every byte equals to 0u8)
-fn symbol_equal_to_zero(symbol: Symbol) -> bool {
+fn symbol_equal_to_zero(symbol: &Symbol) -> bool {
 	let mut flag = true;
 	match symbol {
 		Symbol::Base(decoded) => {
-			for j in 0..BASE_SYMBOL_SIZE {
+			for j in 0..decoded.len() {
 				if decoded[j] != 0u8 {
 					flag = false;
 					break;
@@ -180,13 +254,23 @@ fn symbol_equal_to_zero(symbol: Symbol) -> bool {
 	flag
 }
 
-//index of the parent symbol on the coded Merkle tree
+//index of the parent symbol on the coded Merkle tree, see block::next_index
 fn next_index(index: u64, k: u64, reduce_factor: u64) -> u64 {
-	if index <= k - 1 {
-		index / reduce_factor
-	}
-	else {
-		(index - k) / ((AGGREGATE as u64) - reduce_factor)
+	index % (k / reduce_factor)
+}
+
+//convert an upper symbol to the hashes it aggregates
+pub fn symbol_to_up(symbol: &Symbol) -> Option<SymbolUp> {
+	if let Symbol::Upper(bytes) = symbol {
+		let mut up = [H256::default(); AGGREGATE];
+		for j in 0..AGGREGATE {
+			let mut h = [0u8; 32];
+			h.copy_from_slice(&bytes[j*32..(j*32+32)]);
+			up[j] = H256::from(h);
+		}
+		Some(up)
+	} else {
+		None
 	}
 }
 
@@ -209,19 +293,28 @@ fn remove_one_item(vector: &Vec<u64>, item: &u64) -> Vec<u64> {
 	new_vec
 }
 
+//convert the hashes aggregated in an upper symbol to a symbol for decoding and transport
+pub fn up_to_symbol(up: &SymbolUp) -> Symbol {
+	let mut bytes = [0u8; 32 * AGGREGATE];
+	for j in 0..AGGREGATE {
+		bytes[j*32..(j*32+32)].copy_from_slice(&up[j][..]);
+	}
+	Symbol::Upper(bytes)
+}
+
 //IncorrectCodingProof
 pub fn check_incorrect_coding(i: usize, decoder: &mut Decoder) -> Result<(), (usize, u64, Vec<Symbol>, Vec<u64>) > {
     for j in 0..decoder.p {
         if decoder.parity_degree[j as usize] == 0 { //all symbols associated to this parity are known
-            if !symbol_equal_to_zero(decoder.parity_values[j as usize]) {
+            if !symbol_equal_to_zero(&decoder.parity_values[j as usize]) {
                 //construct incorrect coding proof
                 let error_indices = decoder.code.parities[j as usize].clone();
                 let mut error_symbols: Vec<Symbol> = vec![];
     
                 for t in error_indices.iter() {
-                    error_symbols.push(decoder.symbol_values[*t as usize]);
+                    error_symbols.push(decoder.symbol_values[*t as usize].clone());
                 }
-                println!("NotZero incorrect coding detected on layer {} for parity equation #{}.",i,j);
+                debug!("NotZero incorrect coding detected on layer {} for parity equation #{}.",i,j);
                 return Err((i,j as u64, error_symbols, error_indices));
             } 
         }
@@ -304,7 +397,7 @@ impl TreeDecoder {
                 //hash_proof = self.hashes[(i-1) as usize].clone();
                 return Ok(());	
             } else {
-                println!("Coded Merkle tree successfully decoded.");
+                debug!("Coded Merkle tree successfully decoded.");
                 return Ok(()); //Entire coded Merkle tree is decoded
             }							
         }
@@ -386,7 +479,51 @@ impl TreeDecoder {
         symbols_all_levels: Vec<Vec<Symbol>>, 
         indices_all_levels: Vec<Vec<u64>>, 
         header: BlockHeader,
-    ) -> Result<(Vec<Transaction>), IncorrectCodingProof> {
+    ) -> Result<Vec<Transaction>, TreeDecodeErr> {
+		self.receive(&symbols_all_levels, &indices_all_levels);
+		let data = self.decode()?;
+		// the block is coded correctly, but its data may still not be the transactions of the header
+		match transactions_from_bytes(&data, &header.merkle_root_hash) {
+			Ok(transactions) => {
+				debug!("Coded Merkle tree successfully decoded {}.", transactions.len());
+				Ok(transactions)
+			},
+			Err(e) => {
+				debug!("Coded Merkle tree decoded, but the transactions do not match the header: {:?}.", e);
+				Err(TreeDecodeErr::Reconstruct(e))
+			},
+		}
+	}
+
+	//Take in received symbols of all levels and update the parities they are connected to.
+	//Symbols already known are skipped, so symbols can be passed in as they arrive.
+	//Returns the number of new symbols
+	pub fn receive(&mut self, symbols_all_levels: &Vec<Vec<Symbol>>, indices_all_levels: &Vec<Vec<u64>>) -> usize {
+		let mut num_new = 0;
+		let levels = symbols_all_levels.iter().zip(indices_all_levels.iter());
+		for (decoder, (symbols, indices)) in self.decoders.iter_mut().zip(levels) {
+			let (new_symbols, new_symbol_indices, _) = decoder.symbol_update_from_reception(symbols, indices);
+			num_new += new_symbols.len();
+			decoder.parity_update(new_symbols, new_symbol_indices);
+		}
+		num_new
+	}
+
+	//Data of the systematic symbols on the base layer, complete once the base layer is decoded
+	pub fn base_data(&self) -> Vec<u8> {
+		let base_decoder = &self.decoders[0];
+		let mut bytes: Vec<u8> = vec![];
+		for symbol in base_decoder.symbol_values[0..base_decoder.k as usize].iter() {
+			if let Symbol::Base(s) = symbol {
+				bytes.extend_from_slice(s);
+			}
+		}
+		bytes
+	}
+
+	//Decode with the symbols received so far and return the data of the block, see Block::reconstruct.
+	//A Stopped proof means more symbols are needed and decoding continues from where it stopped after the next receive
+	pub fn decode(&mut self) -> Result<Vec<u8>, IncorrectCodingProof> {
 		//hashes of the symbols being decoded. For top layer, they are stored in the header
 		let mut hash_proof = self.hashes[(self.height - 1) as usize].clone();
 
@@ -394,16 +531,12 @@ impl TreeDecoder {
 
 		//Iterate decoding starting from the top level of coded Merkle tree
 		for i in (0..self.height as usize).rev() {
-			let received_symbols = symbols_all_levels[i as usize].clone();
-			let received_indices = indices_all_levels[i as usize].clone();
 			//Here the variable decoded is used for indicating layer i gets decoded
             let mut decoder = &mut self.decoders[i as usize];
-			let (mut new_symbols, 
-                 mut new_symbol_indices, 
-                 mut decoded) = decoder.symbol_update_from_reception(&received_symbols, &received_indices);
+			let mut decoded = decoder.num_decoded_symbols == decoder.n;
 
-			//Update the parities using the received symbols
-			let mut progress = decoder.parity_update(new_symbols, new_symbol_indices);
+			//parities were updated when the symbols were received
+			let mut progress = decoder.degree_1_parities.len() != 0;
 
 			//parity nodes are updated, now check if there is any incorrect coding
             match check_incorrect_coding(i, decoder) {
@@ -429,29 +562,7 @@ impl TreeDecoder {
 				    hash_proof = self.hashes[(i-1) as usize].clone();
 				    continue;	
 				} else {
-                    let base_decoder = &self.decoders[i];
-                    let symbols = base_decoder.symbol_values[0..base_decoder.k as usize].to_vec();
-
-                    let mut bytes: Vec<u8> = vec![];                   
-                    for symbol in symbols.clone() {
-                        if let Symbol::Base(s) = symbol {
-                            bytes.extend_from_slice(&s[0..BASE_SYMBOL_SIZE]);
-                        }
-                    }
-
-                    let mut transactions_byte: Vec<Bytes> = vec![];                   
-                    let mut transactions: Vec<Transaction> = vec![];
-                    let num_trans = bytes.len() / TRANSACTION_SIZE as usize; //TODO magic
-                    for d in 0..(num_trans-1) {
-                        let l = d*TRANSACTION_SIZE as usize;
-                        let u = (d+1)*TRANSACTION_SIZE as usize;
-                        let b: Bytes = bytes[l..u].into();
-                        let t: Transaction = deserialize(&b as &[u8]).unwrap();
-                        transactions_byte.push(b);
-                        transactions.push(t);
-                    }
-                    println!("Coded Merkle tree successfully decoded {}.", transactions_byte.len());
-                    return Ok(transactions);
+                    return Ok(self.base_data());
 				}							
 			}
 
@@ -486,30 +597,7 @@ impl TreeDecoder {
 				                    decoded = finished;
 				                    break;
 				                } else { //base layer decoded
-                                    let base_decoder = &self.decoders[i];
-                                    let symbols = base_decoder.symbol_values[0..base_decoder.k as usize].to_vec();
-
-                                    let mut bytes: Vec<u8> = vec![];                   
-                                    for symbol in symbols.clone() {
-                                        if let Symbol::Base(s) = symbol {
-                                            bytes.extend_from_slice(&s[0..BASE_SYMBOL_SIZE]);
-                                        }
-                                    }
-
-                                    let mut transactions_byte: Vec<Bytes> = vec![];                   
-                                    let mut transactions: Vec<Transaction> = vec![];
-                                    let num_trans = bytes.len() / TRANSACTION_SIZE as usize; //TODO magic
-                                    for d in 0..(num_trans-1) {
-                                        let l = d*TRANSACTION_SIZE as usize;
-                                        let u = (d+1)*TRANSACTION_SIZE as usize;
-                                        let b: Bytes = bytes[l..u].into();
-                                        let t: Transaction = deserialize(&b as &[u8]).unwrap();
-                                        transactions_byte.push(b);
-                                        transactions.push(t);
-                                    }
-
-                                    println!("Coded Merkle tree successfully decoded {}.", transactions_byte.len());
-                                    return Ok(transactions);                   
+                                    return Ok(self.base_data());                   
 				                } 				                
 				            } else { //decoding for layer i needs to continue 
 				            	continue;
@@ -532,7 +620,7 @@ impl TreeDecoder {
 					}
 					let stopping_ratio = (stopping_set.len() as f32) / (self.decoders[i as usize].n as f32);
 
-					println!("Hitting a stopping set at layer {}. Decoding failed with a stopping ratio of {}.", i, stopping_ratio);
+					debug!("Hitting a stopping set at layer {}. Decoding failed with a stopping ratio of {}.", i, stopping_ratio);
 					//panic!("Hitting a stopping set at layer {}. Decoding failured.", i);
 					return Err(self.generate_incorrect_coding_proof(CodingErr::Stopped, i as u32, 
 						    0u64, vec![], vec![], stopping_set, stopping_ratio));
@@ -543,30 +631,7 @@ impl TreeDecoder {
 				if i > 0 {
 					continue;
 				} else {
-                    let base_decoder = &self.decoders[i];
-                    let symbols = base_decoder.symbol_values[0..base_decoder.k as usize].to_vec();
-
-                    let mut bytes: Vec<u8> = vec![];                   
-                    for symbol in symbols.clone() {
-                        if let Symbol::Base(s) = symbol {
-                            bytes.extend_from_slice(&s[0..BASE_SYMBOL_SIZE]);
-                        }
-                    }
-
-                    let mut transactions_byte: Vec<Bytes> = vec![];                   
-                    let mut transactions: Vec<Transaction> = vec![];
-                    let num_trans = bytes.len() / TRANSACTION_SIZE as usize; //TODO magic
-                    for d in 0..(num_trans-1) {
-                        let l = d*TRANSACTION_SIZE as usize;
-                        let u = (d+1)*TRANSACTION_SIZE as usize;
-                        let b: Bytes = bytes[l..u].into();
-                        let t: Transaction = deserialize(&b as &[u8]).unwrap();
-                        transactions_byte.push(b);
-                        transactions.push(t);
-                    }
-
-                    println!("Coded Merkle tree successfully decoded {}.", transactions_byte.len());
-                    return Ok(transactions);                   
+                    return Ok(self.base_data());                   
 				}
 			} 
 		}
@@ -575,20 +640,21 @@ impl TreeDecoder {
 	}
 
     //Initialize the tree decoder
-	pub fn new(codes: Vec<Code>, header_hash: &Vec<H256>) -> Self {
+	pub fn new(codes: &[Arc<Code>], header_hash: &Vec<H256>, params: &CmtParams) -> Self {
 		let num_layers = codes.len();
 		let base_length: u64 = codes[0].symbols.len() as u64;
 		let mut decs: Vec<Decoder> = vec![];
 		let mut hash_list: Vec<Vec<H256>> = vec![];
 		for i in 0..num_layers {
 			let code = &codes[i];
-			let dec: Decoder = Decoder::new(i as u32, code.parities.to_vec(), code.symbols.to_vec());
+			let dec: Decoder = Decoder::with_code(i as u32, code.clone(), params);
 			decs.push(dec);
 			hash_list.push(vec![H256::default();code.symbols.len()]);
 		}
 		hash_list[num_layers-1] = header_hash.to_vec();
 
 		TreeDecoder {
+			params: params.clone(),
 			n: base_length,
 			height: num_layers as u32,
 			decoders: decs,
@@ -603,7 +669,7 @@ impl TreeDecoder {
 		let mut proof = Vec::<Symbol>::new();
 		let mut moving_index = index;
 		let mut moving_k = self.decoders[lvl].k;
-		let reduce_factor = ((AGGREGATE as f32) * RATE) as u64;
+		let reduce_factor = self.params.reduce_factor();
 		for i in lvl..((self.height - 1) as usize) {
 			moving_index = next_index(moving_index, moving_k, reduce_factor);
             proof.push(self.decoders[i+1].symbol_values[moving_index as usize].clone());
@@ -640,19 +706,24 @@ pub enum Message {
 
 impl Decoder {
 	// Initialize the decoder for a layer of CMT 
-	pub fn new(level: u32, parities: Vec<Vec<u64>>, symbols: Vec<Vec<u64>>) -> Self {
-		let n: u64 = symbols.len() as u64; //number of coded symbols
-		let p: u64 = parities.len() as u64; //number of parity nodes
-		let k: u64 = ((n as f32) * RATE) as u64; //number of systematic symbols
+	pub fn new(level: u32, parities: Vec<Vec<u64>>, symbols: Vec<Vec<u64>>, params: &CmtParams) -> Self {
+		Decoder::with_code(level, Arc::new(Code {parities: parities, symbols: symbols}), params)
+	}
+
+	// Initialize the decoder for a layer with a code shared with other decoders
+	pub fn with_code(level: u32, code: Arc<Code>, params: &CmtParams) -> Self {
+		let n: u64 = code.symbols.len() as u64; //number of coded symbols
+		let p: u64 = code.parities.len() as u64; //number of parity nodes
+		let k: u64 = ((n as f32) * params.rate) as u64; //number of systematic symbols
 
         let mut parities_set: Vec<HashSet<u64>> = vec![];
-        for parity in parities.iter() {
-            parities_set.push(parity.clone().into_iter().collect());
+        for parity in code.parities.iter() {
+            parities_set.push(parity.iter().cloned().collect());
         }
 
 		let mut parity_deg = vec![0u32; p as usize]; //number of variable nodes a parity node is connected to, this changes during peeling decoding
 		for i in 0..(p as usize) {
-			parity_deg[i] = parities[i].len() as u32; 
+			parity_deg[i] = code.parities[i].len() as u32; 
 		}
 
 		let mut parity_val = Vec::<Symbol>::new(); //values of parity nodes
@@ -661,7 +732,7 @@ impl Decoder {
 		match level {
 			0 => {
 				for _ in 0..p {
-					parity_val.push(Symbol::Base([0u8; BASE_SYMBOL_SIZE]));
+					parity_val.push(Symbol::Base(vec![0u8; params.base_symbol_size]));
 				}
 			},
 			_ => {
@@ -677,8 +748,7 @@ impl Decoder {
 
 		Decoder {
 			level: level, n: n, k: k, p: p,
-			code: Code {parities: parities.clone(), symbols: symbols.clone()},
-			parities: parities, symbols: symbols,
+			code: code,
             parities_set:  parities_set,
 			symbol_values: symbol_val,
 			parity_values: parity_val,
@@ -758,10 +828,10 @@ impl Decoder {
         let start = SystemTime::now();
 		for i in 0..length {
 			let (s, idx) = (&symbols[i], symbol_indices[i].clone());
-			let parity_list = &self.symbols[idx as usize]; // subset of parity nodes that will be affected by symbol s
+			let parity_list = &self.code.symbols[idx as usize]; // subset of parity nodes that will be affected by symbol s
 			for parity in parity_list.iter() {
 				//Update the value of each parity node symbol s connects to
-                senders[(*parity%(num_thread as u64)) as usize].send(Message::Data(*s, *parity as usize));
+                senders[(*parity%(num_thread as u64)) as usize].send(Message::Data(s.clone(), *parity as usize));
 
 				self.parity_degree[*parity as usize] -= 1;
 				if self.parity_degree[*parity as usize] == 1 {
@@ -791,7 +861,7 @@ impl Decoder {
                         for k in 0..u {
                             let idx: usize = j + k*num_thread ;
                             if idx < p {
-                                self.parity_values[idx] = parities[idx];
+                                self.parity_values[idx] = parities[idx].clone();
                             }
                         }
                     },
@@ -817,7 +887,7 @@ impl Decoder {
         let start = SystemTime::now();
 		for i in 0..length {
 			let (s, idx) = (&symbols[i], symbol_indices[i].clone());
-			let parity_list = &self.symbols[idx as usize]; // subset of parity nodes that will be affected by symbol s
+			let parity_list = &self.code.symbols[idx as usize]; // subset of parity nodes that will be affected by symbol s
 			for parity in parity_list.iter() {
 				//Update the value of each parity node symbol s connects to
                 match s {
@@ -875,17 +945,18 @@ impl Decoder {
 
         for i in 0..self.degree_1_parities.len() {
         	let parity = self.degree_1_parities[i].clone();
-        	if self.parities[parity as usize].len() > 0 {
-        		let symbol_idx = self.parities[parity as usize][0];
+        	// parities_set only holds the symbols of the parity node that are not known yet
+        	if self.parities_set[parity as usize].len() > 0 {
+        		let symbol_idx = *(self.parities_set[parity as usize].iter().next().unwrap());
         		// The only symbol connected to this parity node has not been decoded yet
         		if let Symbol::Empty = self.symbol_values[symbol_idx as usize] {
-        			self.symbol_values[symbol_idx as usize] = self.parity_values[parity as usize]; //Symbol decoded
+        			self.symbol_values[symbol_idx as usize] = self.parity_values[parity as usize].clone(); //Symbol decoded
 
         			//now check if the decoded symbol matches its hash
         			let mut computed_hash = H256::default();
-        			match self.symbol_values[symbol_idx as usize] {
-        				Symbol::Base(decoded_sym) => {computed_hash = dhash256(&decoded_sym);},
-        				Symbol::Upper(decoded_sym) => {computed_hash = dhash256(&decoded_sym);},
+        			match &self.symbol_values[symbol_idx as usize] {
+        				Symbol::Base(decoded_sym) => {computed_hash = dhash256(decoded_sym);},
+        				Symbol::Upper(decoded_sym) => {computed_hash = dhash256(decoded_sym);},
         				_ => {}
         			}
         			if computed_hash == hashes[symbol_idx as usize] {
@@ -896,13 +967,13 @@ impl Decoder {
         			    symbols.push(self.parity_values[parity as usize].clone());
                         symbol_indices.push(symbol_idx.clone());
                     } else {//coding is done incorrectly, return an incorrect-coding message
-                    	println!("NotHash incorrect coding detected on layer {} for parity equation #{}.",self.level,parity);
+                    	debug!("NotHash incorrect coding detected on layer {} for parity equation #{}.",self.level,parity);
                     	// Preparing info for constructing incorrect-coding proof
                     	let index_set: Vec<u64> = self.code.parities[parity as usize].clone();
                     	let mut correct_index_set: Vec<u64> = remove_one_item(&index_set, &symbol_idx);
                     	let mut symbols_in_proof: Vec<Symbol> = vec![];
                     	for j in 0..correct_index_set.len() {
-                    		symbols_in_proof.push(self.symbol_values[j]);
+                    		symbols_in_proof.push(self.symbol_values[correct_index_set[j] as usize].clone());
                     	}
                     	correct_index_set.push(symbol_idx);
                     	return Err((self.level, parity, correct_index_set, symbols_in_proof));
@@ -931,7 +1002,7 @@ impl Decoder {
                 //println!("greater than 0 {}", self.parities[parity as usize].len());
         		let symbol_idx = *(self.parities_set[parity as usize].iter().last().unwrap());
         		if let Symbol::Empty = self.symbol_values[symbol_idx as usize] {
-        			self.symbol_values[symbol_idx as usize] = self.parity_values[parity as usize]; //Symbol decoded
+        			self.symbol_values[symbol_idx as usize] = self.parity_values[parity as usize].clone(); //Symbol decoded
         			self.num_decoded_symbols += 1; 
         			if symbol_idx < self.k {
                         self.num_decoded_sys_symbols += 1;
@@ -960,7 +1031,7 @@ impl Decoder {
 				if keep_peeling {continue;} //new degree-1 parity created
 			}
 			// this part is unreachable during encoding
-			println!("{} out of {} symbols are decoded.", self.num_decoded_symbols, self.n);
+			debug!("{} out of {} symbols are decoded.", self.num_decoded_symbols, self.n);
 			return self.num_decoded_symbols == self.n;
 		}
 	}
@@ -992,16 +1063,17 @@ impl Decoder {
 		let mut output_symbols = self.symbol_values.clone();
 		if !correct { // flip the 1st parity symbol (kth symbol overall)
 			if self.level == 0 { //This is base layer
-			    let mut parity = [0u8; BASE_SYMBOL_SIZE];
-			    if let Symbol::Base(sym) = self.symbol_values[self.k as usize] {
-			    	for l in 0..BASE_SYMBOL_SIZE {
+			    let mut parity = vec![];
+			    if let Symbol::Base(sym) = &self.symbol_values[self.k as usize] {
+			    	parity = vec![0u8; sym.len()];
+			    	for l in 0..sym.len() {
 					    parity[l] = sym[l].bitxor(255u8);
 					}
 			    }
 			    output_symbols[self.k as usize] = Symbol::Base(parity);
 			} else { //This is higher layer
 			    let mut parity_up = [0u8; 32 * AGGREGATE];
-			    if let Symbol::Upper(sym_up) = self.symbol_values[self.k as usize] {
+			    if let Symbol::Upper(sym_up) = &self.symbol_values[self.k as usize] {
 			    	for l in 0..(32 * AGGREGATE) {
 					    parity_up[l] = sym_up[l].bitxor(255u8);
 					}
@@ -1013,6 +1085,195 @@ impl Decoder {
 	}
 }
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use block::Block;
+	use fixtures::{params, code_set, coded_block};
+
+	// decode from the given base symbols, upper layers are sampled in full
+	fn decode(block: &Block, base_idx: Vec<u32>) -> Result<Vec<Transaction>, IncorrectCodingProof> {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let header = block.block_header.clone();
+		let (symbols, indices) = block.sample_vec(base_idx);
+		let decoding: Vec<Arc<Code>> = decoding.into_iter().map(Arc::new).collect();
+		let mut decoder = TreeDecoder::new(&decoding, &header.coded_merkle_roots_hashes, &params);
+		match decoder.run_tree_decoder(symbols, indices, header) {
+			Ok(transactions) => Ok(transactions),
+			Err(TreeDecodeErr::IncorrectCoding(proof)) => Err(proof),
+			Err(TreeDecodeErr::Reconstruct(e)) => panic!("decoded data is not the block: {:?}", e),
+		}
+	}
+
+	#[test]
+	fn data_not_matching_the_header_is_an_error() {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let decoding: Vec<Arc<Code>> = decoding.into_iter().map(Arc::new).collect();
+		let block = coded_block(vec![true, true]);
+		let mut header = block.block_header.clone();
+		header.merkle_root_hash = H256::from(1);
+		let (symbols, indices) = block.sample_vec((0..32).collect());
+		let mut decoder = TreeDecoder::new(&decoding, &header.coded_merkle_roots_hashes, &params);
+		match decoder.run_tree_decoder(symbols, indices, header) {
+			Err(TreeDecodeErr::Reconstruct(ReconstructErr::MerkleRoot)) => (),
+			r => panic!("unexpected {:?}", r.map(|t| t.len())),
+		}
+	}
+
+	#[test]
+	fn decoding_continues_with_symbols_received_later() {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let decoding: Vec<Arc<Code>> = decoding.into_iter().map(Arc::new).collect();
+		let block = coded_block(vec![true, true]);
+		let mut decoder = TreeDecoder::new(&decoding, &block.block_header.coded_merkle_roots_hashes, &params);
+
+		let (symbols, indices) = block.sample_vec(vec![0, 1, 2, 3]);
+		decoder.receive(&symbols, &indices);
+		assert_eq!(decoder.decode().unwrap_err().error_type, CodingErr::Stopped);
+
+		// symbols already held are skipped, upper layers were sampled in full
+		let missing = (32 - decoder.decoders[0].num_decoded_symbols) as usize;
+		let (symbols, indices) = block.sample_vec((2..32).collect());
+		assert_eq!(decoder.receive(&symbols, &indices), missing);
+		assert_eq!(decoder.receive(&symbols, &indices), 0);
+		let data = decoder.decode().unwrap();
+		let mut full = TreeDecoder::new(&decoding, &block.block_header.coded_merkle_roots_hashes, &params);
+		let (symbols, indices) = block.sample_vec((0..32).collect());
+		full.receive(&symbols, &indices);
+		assert_eq!(data, full.decode().unwrap());
+		assert_eq!(data.len(), 256);
+		assert!(Arc::ptr_eq(&decoder.decoders[0].code, &decoding[0]));
+	}
+
+	#[test]
+	fn upper_layer_holds_hashes_of_layer_below() {
+		let params = params();
+		let block = coded_block(vec![true, true]);
+		let (symbols, indices) = block.sample_vec((0..32).collect());
+		let mut sys = vec![Symbol::Empty; 4];
+		for (s, i) in symbols[1].iter().zip(indices[1].iter()) {
+			if *i < 4 {
+				sys[*i as usize] = s.clone();
+			}
+		}
+		let hashes: Vec<H256> = symbols[0].iter().map(|s| match s {
+			Symbol::Base(b) => dhash256(b),
+			_ => unreachable!(),
+		}).collect();
+		assert_eq!(symbol_to_hash(&sys), hashes);
+
+		for (s, i) in symbols[0].iter().zip(indices[0].iter()) {
+			if let Symbol::Base(b) = s {
+				let (proof, _) = block.merkle_proof(0, *i as u32);
+				assert!(block.block_header.verify_base(b, *i as u32, 8, &proof, &params));
+			}
+		}
+	}
+
+	#[test]
+	fn sampled_symbols_verify_with_their_proofs() {
+		let params = params();
+		let block = coded_block(vec![true, true]);
+		let header = &block.block_header;
+		let (symbols, indices) = block.sample_vec(vec![0, 9, 30]);
+		let proofs = block.sample_proofs(&indices);
+		for l in 0..symbols.len() {
+			for j in 0..symbols[l].len() {
+				assert!(header.verify_symbol(&symbols[l][j], l as u32, indices[l][j] as u32, &proofs[l][j], &params));
+			}
+		}
+		assert!(proofs[1].iter().all(|p| p.is_empty()));
+
+		let mut garbage = symbols[0][1].clone();
+		if let Symbol::Base(ref mut s) = garbage {
+			s[3] ^= 1;
+		}
+		assert!(!header.verify_symbol(&garbage, 0, indices[0][1] as u32, &proofs[0][1], &params));
+		// a valid symbol claimed at another index or layer
+		assert!(!header.verify_symbol(&symbols[0][0], 0, indices[0][1] as u32, &proofs[0][0], &params));
+		assert!(!header.verify_symbol(&symbols[1][0], 0, indices[1][0] as u32, &proofs[0][0], &params));
+		assert!(!header.verify_symbol(&symbols[0][0], 0, indices[0][0] as u32, &[], &params));
+	}
+
+	#[test]
+	fn not_zero_proof_on_base_layer() {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let block = coded_block(vec![false, true]);
+		let proof = decode(&block, (0..32).collect()).unwrap_err();
+		assert_eq!(proof.error_type, CodingErr::NotZero);
+		assert_eq!(proof.level, 0);
+		// the first parity symbol is flipped by the encoder
+		assert!(proof.indices.contains(&8));
+		assert!(proof.verify(&block.block_header, &decoding, &params));
+
+		// proofs are gossiped, they must survive serialization
+		let bytes = serde_json::to_string(&proof).unwrap();
+		let received: IncorrectCodingProof = serde_json::from_str(&bytes).unwrap();
+		assert!(received.verify(&block.block_header, &decoding, &params));
+	}
+
+	#[test]
+	fn not_hash_proof_when_flipped_symbol_is_decoded() {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let block = coded_block(vec![false, true]);
+		let proof = decode(&block, (0..32).filter(|i| *i != 8).collect()).unwrap_err();
+		assert_eq!(proof.error_type, CodingErr::NotHash);
+		assert_eq!(proof.level, 0);
+		assert_eq!(proof.indices.last(), Some(&8));
+		assert_eq!(proof.symbols.len() + 1, proof.indices.len());
+		assert!(proof.verify(&block.block_header, &decoding, &params));
+	}
+
+	#[test]
+	fn not_zero_proof_on_top_layer() {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let block = coded_block(vec![true, false]);
+		let proof = decode(&block, (0..32).collect()).unwrap_err();
+		assert_eq!(proof.error_type, CodingErr::NotZero);
+		assert_eq!(proof.level, 1);
+		assert!(proof.proofs.iter().all(|p| p.is_empty()));
+		assert!(proof.verify(&block.block_header, &decoding, &params));
+	}
+
+	#[test]
+	fn rejects_forged_proofs() {
+		let params = params();
+		let decoding = code_set(&params).decoding;
+		let block = coded_block(vec![false, true]);
+		let proof = decode(&block, (0..32).collect()).unwrap_err();
+
+		// an honest block has different roots
+		let honest = coded_block(vec![true, true]);
+		assert!(!proof.verify(&honest.block_header, &decoding, &params));
+
+		let mut tampered = proof.clone();
+		if let Symbol::Base(ref mut s) = tampered.symbols[0] {
+			s[0] ^= 1;
+		}
+		assert!(!tampered.verify(&block.block_header, &decoding, &params));
+
+		let mut partial = proof.clone();
+		partial.indices.pop();
+		partial.symbols.pop();
+		partial.proofs.pop();
+		assert!(!partial.verify(&block.block_header, &decoding, &params));
+
+		let mut wrong_parity = proof.clone();
+		wrong_parity.parity_index = (wrong_parity.parity_index + 1) % (decoding[0].parities.len() as u64);
+		assert!(!wrong_parity.verify(&block.block_header, &decoding, &params));
+
+		let mut stopped = proof.clone();
+		stopped.error_type = CodingErr::Stopped;
+		assert!(!stopped.verify(&block.block_header, &decoding, &params));
+	}
+}
+
 // #[cfg(test)]
 // mod tests {
 // 	use rand::thread_rng;
@@ -0,0 +1,72 @@
+// Parameters and blocks shared by the tests of this crate and of the nodes built on it.
+
+use block::Block;
+use block_header::BlockHeader;
+use bytes::Bytes;
+use code_registry::{CodeRegistry, CodeSet};
+use compact::Compact;
+use hash::H256;
+use params::CmtParams;
+use transaction::{Transaction, TransactionInput, TransactionOutput, OutPoint};
+
+// 8 systematic symbols on the base layer, 32 coded symbols are hashed into a top layer of 16
+pub fn params() -> CmtParams {
+	CmtParams { block_size: 256, base_symbol_size: 32, header_size: 16, ..CmtParams::default() }
+}
+
+// the codes shipped with the node
+const LDPC_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../src/LDPC_codes");
+
+pub fn code_set(params: &CmtParams) -> CodeSet {
+	CodeRegistry::new(LDPC_DIR).code_set(params).unwrap()
+}
+
+// a registry that knows the code set of params
+pub fn code_registry(params: &CmtParams) -> CodeRegistry {
+	let mut registry = CodeRegistry::new(LDPC_DIR);
+	registry.code_set(params).unwrap();
+	registry
+}
+
+// 76 bytes, three fit into a block of params()
+pub fn transaction(value: u64) -> Transaction {
+	Transaction {
+		version: 1,
+		inputs: vec![TransactionInput {
+			previous_output: OutPoint::default(),
+			script_sig: Bytes::new_with_len(8),
+			sequence: 0,
+			script_witness: vec![],
+		}],
+		outputs: vec![TransactionOutput { value, script_pubkey: Bytes::new_with_len(8) }],
+		lock_time: 0,
+	}
+}
+
+fn header(nonce: u32, code_id: H256) -> BlockHeader {
+	BlockHeader {
+		version: 1,
+		previous_header_hash: H256::default(),
+		merkle_root_hash: H256::default(),
+		time: 4,
+		bits: Compact::from(5),
+		nonce,
+		coded_merkle_roots_hashes: vec![],
+		code_id,
+	}
+}
+
+// a correctly coded block of params()
+pub fn block(nonce: u32, transactions: &Vec<Transaction>) -> Block {
+	let params = params();
+	let set = code_set(&params);
+	let correct = vec![true; set.encoding.len()];
+	Block::new(header(nonce, set.id), transactions, &params, &set.encoding, correct).0
+}
+
+// a block of one default transaction, the layers whose entry in correct is false are coded wrongly
+pub fn coded_block(correct: Vec<bool>) -> Block {
+	let params = params();
+	let set = code_set(&params);
+	Block::new(header(6, set.id), &vec![Transaction::default()], &params, &set.encoding, correct).0
+}
@@ -7,7 +7,7 @@ use transaction::Transaction;
 use merkle_root::merkle_root;
 use indexed_header::IndexedBlockHeader;
 use indexed_transaction::IndexedTransaction;
-use constants::{BLOCK_SIZE, HEADER_SIZE};
+use params::CmtParams;
 use bytes::Bytes;
 
 #[derive(Debug, Clone, Deserializable)]
@@ -40,7 +40,7 @@ impl IndexedBlock {
 	///
 	/// Hashes block header + transactions.
 	pub fn from_raw(block: Block) -> Self {
-		let Block { block_header, transactions, coded_tree, block_size_in_bytes} = block;
+		let Block { block_header, transactions, coded_tree, params } = block;
 		Self::new(
 			IndexedBlockHeader::from_raw(block_header),
 			transactions.into_iter().map(IndexedTransaction::from_raw).collect(),
@@ -53,7 +53,7 @@ impl IndexedBlock {
 
 	pub fn to_raw_block(self) -> Block {
 		let (b, _) = Block::new(self.header.raw, &self.transactions.into_iter().map(|tx| tx.raw).collect(), 
-			BLOCK_SIZE as usize, HEADER_SIZE, &vec![], vec![]);
+			&CmtParams::default(), &vec![], vec![]);
         b
 	}
 
@@ -0,0 +1,320 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+// Random LDPC codes for the coded Merkle tree, written in the format read by cmtda::read_codes.
+// The parity check matrix is drawn from the configuration model with the given variable degree
+// distribution, and check degrees as even as possible. It is then brought into systematic form
+// by Gaussian elimination over GF(2), which is dense, so codes with k beyond a few thousand are slow.
+
+// codes with small stopping sets are drawn again up to this many times
+pub const MAX_ATTEMPTS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdpcSpec {
+	// number of systematic symbols
+	pub k: u64,
+	pub rate: f32,
+	// (degree, fraction of variable nodes), fractions sum to 1
+	pub var_degrees: Vec<(usize, f64)>,
+	pub seed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdpcCode {
+	pub k: u64,
+	pub n: u64,
+	// one row per parity symbol k+i, made of that parity symbol and systematic symbols only
+	pub encode: Vec<Vec<u64>>,
+	// sparse parity equations used by the peeling decoder
+	pub decode: Vec<Vec<u64>>,
+}
+
+// "6" gives a regular code, "3:0.5,8:0.5" an irregular one
+pub fn parse_degrees(s: &str) -> Result<Vec<(usize, f64)>, String> {
+	let mut degrees = vec![];
+	for part in s.split(',') {
+		let mut it = part.split(':');
+		let degree = it.next().unwrap_or("").trim().parse::<usize>()
+			.map_err(|e| format!("invalid degree in {}: {}", s, e))?;
+		let fraction = match it.next() {
+			Some(f) => f.trim().parse::<f64>().map_err(|e| format!("invalid fraction in {}: {}", s, e))?,
+			None => 1.0,
+		};
+		if it.next().is_some() || degree == 0 || fraction <= 0.0 {
+			return Err(format!("invalid degree distribution {}", s));
+		}
+		degrees.push((degree, fraction));
+	}
+	let total: f64 = degrees.iter().map(|(_, f)| f).sum();
+	if (total - 1.0).abs() > 1e-6 {
+		return Err(format!("fractions of {} sum to {}", s, total));
+	}
+	Ok(degrees)
+}
+
+impl LdpcCode {
+	pub fn write(&self, dir: &str) -> std::io::Result<()> {
+		write_rows(&Path::new(dir).join(format!("k={}_encode.txt", self.k)), &self.encode)?;
+		write_rows(&Path::new(dir).join(format!("k={}_decode.txt", self.k)), &self.decode)
+	}
+}
+
+fn write_rows(path: &Path, rows: &[Vec<u64>]) -> std::io::Result<()> {
+	let lines: Vec<String> = rows.iter()
+		.map(|r| r.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(" "))
+		.collect();
+	File::create(path)?.write_all(lines.join("\n").as_bytes())
+}
+
+// erasure is the fraction of symbols erased in each of the trials of the stopping set check
+pub fn generate(spec: &LdpcSpec, erasure: f64, trials: usize) -> Result<LdpcCode, String> {
+	let k = spec.k as usize;
+	let n = ((spec.k as f32) / spec.rate) as usize;
+	if k == 0 || n <= k {
+		return Err(format!("k={} at rate {} leaves no parity symbols", spec.k, spec.rate));
+	}
+	let m = n - k;
+	if let Some((d, _)) = spec.var_degrees.iter().find(|(d, _)| *d > m) {
+		return Err(format!("degree {} exceeds the {} parity checks", d, m));
+	}
+	let mut rng = StdRng::seed_from_u64(spec.seed);
+	for _ in 0..MAX_ATTEMPTS {
+		let rows = random_parities(n, m, &spec.var_degrees, &mut rng);
+		if has_small_stopping_set(&rows, n) {
+			continue;
+		}
+		let code = systematic(&rows, n, k, &mut rng);
+		let passed = (0..trials).all(|_| {
+			let mut erased = vec![false; n];
+			for i in rand::seq::index::sample(&mut rng, n, (erasure * n as f64) as usize).iter() {
+				erased[i] = true;
+			}
+			peel(&code.decode, n, &erased).is_empty()
+		});
+		if passed {
+			return Ok(code);
+		}
+	}
+	Err(format!("no code for k={} passed the stopping set check in {} attempts", spec.k, MAX_ATTEMPTS))
+}
+
+// rows of a parity check matrix from the configuration model, edges drawn twice cancel
+fn random_parities<R: Rng>(n: usize, m: usize, var_degrees: &[(usize, f64)], rng: &mut R) -> Vec<Vec<usize>> {
+	let mut degrees = Vec::with_capacity(n);
+	let mut cumulative = 0.0;
+	for (d, fraction) in var_degrees {
+		cumulative += fraction;
+		let count = std::cmp::min((cumulative * n as f64).round() as usize, n) - degrees.len();
+		degrees.extend(std::iter::repeat(*d).take(count));
+	}
+	let last = var_degrees[var_degrees.len() - 1].0;
+	degrees.resize(n, last);
+	degrees.shuffle(rng);
+
+	let mut sockets: Vec<usize> = vec![];
+	for (v, d) in degrees.iter().enumerate() {
+		sockets.extend(std::iter::repeat(v).take(*d));
+	}
+	sockets.shuffle(rng);
+	let edges = sockets.len();
+	(0..m).map(|j| {
+		let mut row = sockets[edges * j / m..edges * (j + 1) / m].to_vec();
+		row.sort();
+		let mut reduced: Vec<usize> = vec![];
+		for v in row {
+			if reduced.last() == Some(&v) {
+				reduced.pop();
+			} else {
+				reduced.push(v);
+			}
+		}
+		reduced
+	}).collect()
+}
+
+// stopping sets of size one are symbols in no parity, of size two are symbols in the same parities
+fn has_small_stopping_set(rows: &[Vec<usize>], n: usize) -> bool {
+	let mut columns = vec![vec![]; n];
+	for (j, row) in rows.iter().enumerate() {
+		for v in row {
+			columns[*v].push(j);
+		}
+	}
+	if columns.iter().any(|c| c.is_empty()) {
+		return true;
+	}
+	columns.sort();
+	columns.windows(2).any(|w| w[0] == w[1])
+}
+
+// Reduce the parity check matrix to rows of the form parity symbol + systematic symbols. When the
+// matrix is rank deficient, the missing parity symbols get an extra sparse equation each.
+fn systematic<R: Rng>(rows: &[Vec<usize>], n: usize, k: usize, rng: &mut R) -> LdpcCode {
+	let m = n - k;
+	let words = (n + 63) / 64;
+	let get = |row: &[u64], c: usize| row[c / 64] >> (c % 64) & 1 == 1;
+	let xor = |dst: &mut Vec<u64>, src: &[u64]| dst.iter_mut().zip(src).for_each(|(a, b)| *a ^= b);
+
+	let mut dense: Vec<Vec<u64>> = rows.iter().map(|r| {
+		let mut bits = vec![0u64; words];
+		for c in r {
+			bits[c / 64] |= 1 << (c % 64);
+		}
+		bits
+	}).collect();
+	let mut pivots = vec![];
+	for c in 0..n {
+		let r = pivots.len();
+		if r == m {
+			break;
+		}
+		let found = match (r..m).find(|i| get(&dense[*i], c)) {
+			Some(i) => i,
+			None => continue,
+		};
+		dense.swap(r, found);
+		let pivot = dense[r].clone();
+		for (i, row) in dense.iter_mut().enumerate() {
+			if i != r && get(row, c) {
+				xor(row, &pivot);
+			}
+		}
+		pivots.push(c);
+	}
+	dense.truncate(pivots.len());
+
+	let free: Vec<usize> = (0..n).filter(|c| !pivots.contains(c)).collect();
+	let missing: Vec<usize> = free.choose_multiple(rng, m - pivots.len()).cloned().collect();
+	let sys: Vec<usize> = free.iter().filter(|c| !missing.contains(c)).cloned().collect();
+	let check_degree = std::cmp::max(rows.iter().map(|r| r.len()).sum::<usize>() / m, 2);
+	let mut extra = vec![];
+	for e in missing {
+		let mut row: Vec<usize> = sys.choose_multiple(rng, check_degree - 1).cloned().collect();
+		row.push(e);
+		let mut bits = vec![0u64; words];
+		for c in row.iter() {
+			bits[c / 64] |= 1 << (c % 64);
+		}
+		for other in dense.iter_mut() {
+			if get(other, e) {
+				xor(other, &bits);
+			}
+		}
+		dense.push(bits);
+		pivots.push(e);
+		extra.push(row);
+	}
+
+	// systematic symbols keep their order and come first, parity symbol k+i is the pivot of row i
+	let mut perm = vec![0u64; n];
+	for (i, c) in sys.iter().enumerate() {
+		perm[*c] = i as u64;
+	}
+	for (i, c) in pivots.iter().enumerate() {
+		perm[*c] = (k + i) as u64;
+	}
+	let map = |cols: &mut dyn Iterator<Item = usize>| {
+		let mut row: Vec<u64> = cols.map(|c| perm[c]).collect();
+		row.sort();
+		row
+	};
+	let encode = dense.iter().map(|bits| map(&mut (0..n).filter(|c| get(bits, *c)))).collect();
+	let decode = rows.iter().chain(extra.iter())
+		.filter(|r| !r.is_empty())
+		.map(|r| map(&mut r.iter().cloned()))
+		.collect();
+	LdpcCode { k: k as u64, n: n as u64, encode, decode }
+}
+
+// Peeling decoder over the erasure channel, returns the erased symbols it cannot recover.
+// They form a stopping set of the code.
+pub fn peel(parities: &[Vec<u64>], n: usize, erased: &[bool]) -> Vec<u64> {
+	let mut erased = erased.to_vec();
+	let mut columns = vec![vec![]; n];
+	let mut unknown = vec![0usize; parities.len()];
+	for (j, parity) in parities.iter().enumerate() {
+		for s in parity {
+			columns[*s as usize].push(j);
+			if erased[*s as usize] {
+				unknown[j] += 1;
+			}
+		}
+	}
+	let mut ready: Vec<usize> = (0..parities.len()).filter(|j| unknown[*j] == 1).collect();
+	while let Some(j) = ready.pop() {
+		if unknown[j] != 1 {
+			continue;
+		}
+		let s = match parities[j].iter().find(|s| erased[**s as usize]) {
+			Some(s) => *s as usize,
+			None => continue,
+		};
+		erased[s] = false;
+		for p in columns[s].iter() {
+			unknown[*p] -= 1;
+			if unknown[*p] == 1 {
+				ready.push(*p);
+			}
+		}
+	}
+	(0..n).filter(|s| erased[*s]).map(|s| s as u64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn spec(k: u64, degrees: &str, seed: u64) -> LdpcSpec {
+		LdpcSpec { k, rate: 0.25, var_degrees: parse_degrees(degrees).unwrap(), seed }
+	}
+
+	#[test]
+	fn parse_distributions() {
+		assert_eq!(parse_degrees("6"), Ok(vec![(6, 1.0)]));
+		assert_eq!(parse_degrees("3:0.5,8:0.5"), Ok(vec![(3, 0.5), (8, 0.5)]));
+		assert!(parse_degrees("3:0.5,8:0.4").is_err());
+		assert!(parse_degrees("0").is_err());
+		assert!(parse_degrees("x").is_err());
+	}
+
+	#[test]
+	fn encoding_is_systematic_and_consistent() {
+		for (k, degrees) in [(16, "6"), (64, "3:0.5,8:0.5")].iter() {
+			let code = generate(&spec(*k, degrees, 7), 0.1, 10).unwrap();
+			assert_eq!(code.n, 4 * k);
+			assert_eq!(code.encode.len() as u64, code.n - k);
+			for (i, row) in code.encode.iter().enumerate() {
+				assert_eq!(row.iter().filter(|s| **s >= *k).cloned().collect::<Vec<u64>>(), vec![k + i as u64]);
+			}
+
+			// encode a random message bit by bit and check every decoding equation
+			let mut rng = StdRng::seed_from_u64(1);
+			let mut word: Vec<bool> = (0..*k).map(|_| rng.gen()).collect();
+			for row in code.encode.iter() {
+				word.push(row.iter().filter(|s| **s < *k).fold(false, |acc, s| acc ^ word[*s as usize]));
+			}
+			for row in code.decode.iter() {
+				assert!(!row.iter().fold(false, |acc, s| acc ^ word[*s as usize]));
+			}
+		}
+	}
+
+	#[test]
+	fn same_seed_same_code() {
+		let a = generate(&spec(32, "6", 3), 0.1, 5).unwrap();
+		assert_eq!(a, generate(&spec(32, "6", 3), 0.1, 5).unwrap());
+		assert_ne!(a, generate(&spec(32, "6", 4), 0.1, 5).unwrap());
+	}
+
+	#[test]
+	fn peel_stops_at_stopping_set() {
+		// symbols 0 and 1 always appear together
+		let parities = vec![vec![0, 1, 2], vec![0, 1, 3], vec![2, 3]];
+		assert_eq!(peel(&parities, 4, &[true, true, false, false]), vec![0, 1]);
+		assert!(peel(&parities, 4, &[false, false, true, true]).is_empty());
+	}
+}
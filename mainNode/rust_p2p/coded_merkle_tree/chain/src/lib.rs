@@ -7,11 +7,18 @@ extern crate serialization as ser;
 extern crate rand;
 
 extern crate serde;
+extern crate serde_json;
+#[macro_use]
+extern crate log;
 
 #[macro_use]
 extern crate serialization_derive;
 
 pub mod constants;
+pub mod params;
+pub mod ldpc;
+pub mod code_file;
+pub mod code_registry;
 
 pub mod block;
 pub mod block_header;
@@ -20,7 +27,9 @@ pub mod coded_merkle_roots;
 pub mod transaction;
 pub mod decoder;
 pub mod big_array;
-//mod light_client;
+pub mod light_client;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod fixtures;
 
 /// `IndexedBlock` extension
 pub mod read_and_hash;
@@ -32,7 +41,11 @@ pub use primitives::{hash, bytes, bigint, compact};
 pub use rand::distributions::{Distribution, Bernoulli, Uniform};
 
 pub use decoder::{Symbol, Decoder, Code, CodingErr};
-pub use block::Block;
+pub use params::CmtParams;
+pub use code_file::{CodeFile, CodeFileErr};
+pub use code_registry::{CodeRegistry, CodeSet};
+pub use light_client::{LightClient, BaseSample, Availability};
+pub use block::{Block, ReconstructErr};
 pub use block_header::BlockHeader;
 pub use merkle_root::{merkle_root, merkle_node_hash};
 pub use coded_merkle_roots::{Symbols, SymbolBase, SymbolUp};
@@ -1,94 +1,133 @@
-use ser::{deserialize, serialize};
-use {BlockHeader, Transaction};
-use constants::{BASE_SYMBOL_SIZE, AGGREGATE, RATE, SAMPLE_COMPLEXITY};
-use {Symbols, SymbolBase, SymbolUp};
-use bytes::Bytes;
-use coded_merkle_roots::coded_merkle_roots;
-use hash::H256;
-use merkle_root::merkle_root;
-use rand::distributions::{Distribution, Uniform};
+use std::cmp;
+use rand::seq::index;
+use serde::{Serialize, Deserialize};
+use BlockHeader;
+use params::CmtParams;
+use block::Block;
+use decoder::{Symbol, up_to_symbol};
+use Symbols;
+
+// A base symbol served to a light client, together with its Merkle proof.
+// The proof holds one symbol from each upper layer, so sampling the base layer samples the whole tree.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BaseSample {
+	pub index: u32,
+	pub symbol: Symbol,
+	pub proof: Vec<Symbol>,
+}
+
+impl BaseSample {
+	// served by a node holding the coded Merkle tree of the block
+	pub fn from_block(block: &Block, index: u32) -> Option<BaseSample> {
+		let symbol = match block.coded_tree.get(0) {
+			Some(Symbols::Base(syms)) => syms.get(index as usize)?.clone(),
+			_ => return None,
+		};
+		let (proof, _) = block.merkle_proof(0, index);
+		Some(BaseSample {
+			index,
+			symbol: Symbol::Base(symbol),
+			proof: proof.iter().map(up_to_symbol).collect(),
+		})
+	}
+}
+
+// s distinct indices of coded symbols on the base layer, uniformly at random
+pub fn sample_indices(params: &CmtParams, s: u32) -> Vec<u32> {
+	let n = params.num_base_symbols() as usize;
+	let mut rng = rand::thread_rng();
+	index::sample(&mut rng, n, cmp::min(s as usize, n))
+		.into_iter()
+		.map(|i| i as u32)
+		.collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Availability {
+	Available,
+	// requested indices that were not served or whose Merkle proof does not pass
+	Unavailable(Vec<u32>),
+}
 
-#[derive(Debug, PartialEq, Clone, Serializable, Deserializable)]
+// A light client only holds the header of a block. It requests random base symbols with their
+// Merkle proofs and declares the block available if every requested symbol is served and verifies.
 pub struct LightClient {
 	pub block_header: BlockHeader,
+	pub params: CmtParams,
 }
 
 impl LightClient {
-	// We have n coded symbols in the block, and each light node randomly samples s of them
-	pub fn sampling_base(&self, n: u64, s: u32) -> (Vec<u64>, Vec<SymbolBase>, Vec<Vec<SymbolUp>>) {
-		let k = ((n as f32) * RATE) as u32; //number of systematic symbols on the base layer
-
-		let mut index = vec![];//indices of sampled symbols
-		let mut symbols: Vec<SymbolBase> = vec![];//sampled symbols
-		let mut proofs: Vec<Vec<SymbolUp>> = vec![];//Merkle proofs of sampled symbols
+	pub fn new(block_header: BlockHeader, params: &CmtParams) -> Self {
+		LightClient {
+			block_header,
+			params: params.clone(),
+		}
+	}
 
-		let mut rng = rand::thread_rng();
-        let die = Uniform::from(0..n);
+	pub fn sample_indices(&self, s: u32) -> Vec<u32> {
+		sample_indices(&self.params, s)
+	}
 
-        //sample the first symbol
-		let throw = die.sample(&mut rng); //select the first index of the coded symbol to sample
-		let (symbol, proof): (Option<SymbolBase>, Option<Vec<SymbolUp>>) = 
-		match (symbol, proof) {
-			(None, _) => panic!("Symbol No.{} is missing!", throw),
-			(_, None) => panic!("Merkle proof for symbol No.{} is missing!", throw),
-			(Some(s), Some(p)) => {
-				if self.block_header.verify_base(s, throw, k, &p) {
-					index.push(throw);
-					symbols.push(s);
-					proofs.push(p);
-				} else {
-					panic!("Merkle proof for symbol No.{} does not pass!", throw);
-				}
-			},
-		}
+	// check a served symbol against the coded Merkle roots in the header
+	pub fn verify_sample(&self, sample: &BaseSample) -> bool {
+		self.block_header.verify_symbol(&sample.symbol, 0, sample.index, &sample.proof, &self.params)
+	}
 
-		for _ in 1..s {
-			let throw = die.sample(&mut rng);
-			//if !index.contains(&throw) {
-			let (symbol, proof): (Option<SymbolBase>, Option<Vec<SymbolUp>>) = 
-			match (symbol, proof) {
-				(None, _) => panic!("Symbol No.{} is missing!", throw),
-				(_, None) => panic!("Merkle proof for symbol No.{} is missing!", throw),
-				(Some(s), Some(p)) => {
-					if self.block_header.verify_base(s, throw, k, &p) {
-					    index.push(throw);
-					    symbols.push(s);
-					    proofs.push(p);
-					} else {
-					    panic!("Merkle proof for symbol No.{} does not pass!", throw);
-					}
-				},
+	pub fn check_availability(&self, requested: &[u32], samples: &[BaseSample]) -> Availability {
+		let mut missing = vec![];
+		for index in requested.iter() {
+			if !samples.iter().any(|s| s.index == *index && self.verify_sample(s)) {
+				missing.push(*index);
 			}
-			//}
 		}
-
-		(index, symbols, proofs)
+		if missing.is_empty() {
+			Availability::Available
+		} else {
+			Availability::Unavailable(missing)
+		}
 	}
+}
 
-    //Take s samples from each upper layer of coded Merkle tree
-	pub fn sampling_upper(&self, index: Vec<u64>, proofs: Vec<Vec<SymbolUp>>, s: u32) -> (Vec<Vec<u64>>, 
-		Vec<Vec<SymbolUp>>, Vec<Vec<Option<Vec<SymbolUp>>>>) {
-		let height = proofs[0].len() + 1; //height of coded Merkle tree
-		for j in 0..s { //iterate over s samples
-			for i in 1..height {  //iterate over height - 1 upper layers
-				let mut symbol: SymbolUp = proofs[j][i - 1];
-				if i < height - 1 {
-				    let mut proof: Option<Vec<SymbolUp>> = Some(proofs[j][i..(height - 1)].to_vec());
-				} else {
-					let mut proof: Option<Vec<SymbolUp>> = None;
-				}
-	
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use constants::SAMPLE_COMPLEXITY;
+	use fixtures::{params, block, transaction};
+
+	#[test]
+	fn served_samples_make_block_available() {
+		let block = block(1, &vec![transaction(1)]);
+		let client = LightClient::new(block.block_header.clone(), &params());
+		let requested = client.sample_indices(SAMPLE_COMPLEXITY);
+		assert_eq!(requested.len(), SAMPLE_COMPLEXITY as usize);
+		let samples: Vec<BaseSample> = requested.iter().map(|i| BaseSample::from_block(&block, *i).unwrap()).collect();
+		assert_eq!(client.check_availability(&requested, &samples), Availability::Available);
+
+		// all of the base layer can be asked for
+		assert_eq!(client.sample_indices(1000).len(), 32);
+	}
 
-			} 
+	#[test]
+	fn withheld_or_forged_samples_make_block_unavailable() {
+		let block = block(1, &vec![transaction(1)]);
+		let client = LightClient::new(block.block_header.clone(), &params());
+		let requested: Vec<u32> = (0..32).collect();
+		let mut samples: Vec<BaseSample> = requested.iter().map(|i| BaseSample::from_block(&block, *i).unwrap()).collect();
 
+		samples.remove(5);
+		if let Symbol::Base(ref mut s) = samples[0].symbol {
+			s[0] ^= 1;
 		}
-
-
+		samples[1].proof.pop();
+		assert_eq!(client.check_availability(&requested, &samples), Availability::Unavailable(vec![0, 1, 5]));
+
+		// symbols of another block do not match this header
+		let other = self::block(2, &vec![transaction(2)]);
+		let samples: Vec<BaseSample> = requested.iter().map(|i| BaseSample::from_block(&other, *i).unwrap()).collect();
+		match client.check_availability(&requested, &samples) {
+			Availability::Unavailable(missing) => assert!(!missing.is_empty()),
+			Availability::Available => panic!("samples of another block accepted"),
+		}
+		assert!(BaseSample::from_block(&block, 32).is_none());
 	}
-
-
-
-
-
-
-}
\ No newline at end of file
+}
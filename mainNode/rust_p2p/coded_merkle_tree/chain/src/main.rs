@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufWriter, Write};
 use std::str;
 
 extern crate rustc_hex as hex;
@@ -23,89 +23,14 @@ use rand::distributions::{Distribution, Bernoulli, Uniform};
 use chain::block_header::BlockHeader;
 use chain::transaction::{Transaction, TransactionInput, TransactionOutput, OutPoint};
 use chain::block::Block;
-use chain::constants::{BLOCK_SIZE, BASE_SYMBOL_SIZE, AGGREGATE, RATE, HEADER_SIZE, NUMBER_ITERATION};
+use chain::constants::{AGGREGATE, NUMBER_ITERATION};
+use chain::params::CmtParams;
 use chain::coded_merkle_roots::{Symbols, SymbolBase, SymbolUp, coded_merkle_roots};
 use chain::merkle_root::merkle_root;
 use chain::decoder::{Code, Symbol, Decoder, TreeDecoder, CodingErr, IncorrectCodingProof};
+use chain::code_registry::CodeRegistry;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-// obtain a code represented by symbols from the form represented by parities
-fn convert_parity_to_symbol(parities: Vec<Vec<u64>>, n: u64) -> Vec<Vec<u64>> {
-	let mut symbols: Vec<Vec<u64>> = vec![vec![];n as usize];
-	for i in 0..parities.len(){
-		let parity = &parities[i];
-		for s in parity.iter() {
-			symbols[*s as usize].push(i as u64);
-		}
-	}
-	symbols
-}
-
-fn read_code_from_file(k: u64) -> (Code, Code) {
-    //compute number of coded symbols
-	let n = ((k as f32) / RATE ) as u64;
-    
-	//Read encoding matrix
-    let curr_dir = std::env::current_dir().unwrap().to_str().unwrap().to_string();
-	let filename = String::from("LDPC_codes/k=") + &k.to_string() + &String::from("_encode.txt");
-    let filename = curr_dir.clone() + "/" + &filename;
-    // Open the file in read-only mode (ignoring errors).
-    let file = File::open(filename).expect("unable to read file");
-    let reader = BufReader::new(file);
-    
-    //parity equations for encoding
-    let mut parities_encoding: Vec<Vec<u64>> = vec![];
-    for (index, line) in reader.lines().enumerate() {
-        let line = line.unwrap(); // Ignore errors.
-        let parity: Vec<u64> = line.split_whitespace().map(|s| s.parse().unwrap()).collect();
-        parities_encoding.push(parity);
-    }
-
-    //Read decodeing matrix
-    let filename = String::from("LDPC_codes/k=") + &k.to_string() + &String::from("_decode.txt");
-    let filename = curr_dir + "/" + &filename;
-    // Open the file in read-only mode (ignoring errors).
-    let file = File::open(filename).unwrap();
-    let reader = BufReader::new(file);
-    
-    //parity equations for decoding
-    let mut parities_decoding: Vec<Vec<u64>> = vec![];
-    for (index, line) in reader.lines().enumerate() {
-        let line = line.unwrap(); // Ignore errors.
-        let parity: Vec<u64> = line.split_whitespace().map(|s| s.parse().unwrap()).collect();
-        parities_decoding.push(parity);
-    }
-
-    // Read the file line by line. Each line represent a parity equation.
-    // for (index, line) in reader.lines().enumerate() {
-    //     let line = line.unwrap(); // Ignore errors.
-    //     let parity: Vec<u64> = line.split_whitespace().map(|s| s.parse().unwrap()).collect();
-    //     if index < ((n - k) as usize) {
-    //     	parities_encoding.push(parity.clone());
-    //     	parities_decoding.push(parity);
-    //     }
-    //     else {//extra parity equations to help decoding
-    //     	parities_decoding.push(parity); 
-    //     }
-    // }
-     
-    //Generate one code for encoding, and one code for decoding
-    (Code {parities: parities_encoding.clone(), symbols: convert_parity_to_symbol(parities_encoding, n)}, 
-    	Code {parities: parities_decoding.clone(), symbols: convert_parity_to_symbol(parities_decoding, n)})
-}
-
-//Read all codes for all coded Merkle tree layers
-fn read_codes(k_set: Vec<u64>) -> (Vec<Code>, Vec<Code>) {
-	let mut codes_for_encoding: Vec<Code> = vec![];
-	let mut codes_for_decoding: Vec<Code> = vec![];
-	for i in k_set.iter() {
-		let (code_e, code_d) = read_code_from_file(*i);
-		codes_for_encoding.push(code_e);
-		codes_for_decoding.push(code_d);
-	}
-	(codes_for_encoding, codes_for_decoding)
-}
-
 //fn test(block: &Block, num_samples: &Vec<u32>, codes_for_decoding: &Vec<Code>) -> Vec<Result<(), IncorrectCodingProof>> {
 	//let mut decoding_results = vec![];
 	////Try different sample sizes to decode
@@ -143,7 +68,7 @@ fn main() {
 			bits: 5.into(),
 			nonce: 6u32,
 			coded_merkle_roots_hashes: vec![H256::default(); 8],
-            delimitor: vec![],
+            code_id: H256::default(),
 		};
 
 	let header_1 = header.clone(); //header for first test
@@ -152,13 +77,20 @@ fn main() {
     // Create transactions
 	let t = "0100000001a6b97044d03da79c005b20ea9c0e1a6d9dc12d9f7b91a5911c9030a439eed8f5000000004948304502206e21798a42fae0e854281abd38bacd1aeed3ee3738d9e1446618c4571d1090db022100e2ac980643b0b82c0e88ffdfec6b64e3e6ba35e7ba5fdd7d5d6cc8d25c6b241501ffffffff0100f2052a010000001976a914404371705fa9bd789a2fcd52d2c580b65d35549d88ac00000000";
 	let transaction_size = String::from(t).len();
-	let num_transactions = BLOCK_SIZE / (transaction_size as u64);
+	// the reference design is read from the json file given as first argument
+	let params = match std::env::args().nth(1) {
+		Some(path) => CmtParams::from_file(&path).unwrap(),
+		None => CmtParams::default(),
+	};
+	let num_transactions = params.block_size / (transaction_size as u64);
 
 	let transactions: Vec<Transaction> = vec![t.into();num_transactions as usize];
     
-    // number of systematic symbols for the codes on the four layers of CMT
-    let k_set: Vec<u64> = vec![128, 64, 32,16, 8, 4];
-    let (codes_for_encoding, codes_for_decoding) = read_codes(k_set);
+    // codes on each layer of CMT
+    let mut registry = CodeRegistry::new("LDPC_codes");
+    let code_set = registry.code_set(&params).unwrap_or_else(|e| panic!("{}", e));
+    let codes_for_encoding = code_set.encoding;
+    let header_1 = BlockHeader { code_id: code_set.id, ..header_1 };
     println!("codes_for_encoding {} Base symbol len {}", codes_for_encoding.len(), codes_for_encoding[0].symbols.len());
 
     //Start testa
@@ -170,8 +102,7 @@ fn main() {
         let (block, trans_len) = Block::new(
             header_1.clone(), 
             &transactions, 
-            BLOCK_SIZE as usize, 
-            HEADER_SIZE, 
+            &params, 
             &codes_for_encoding, 
             vec![true; codes_for_encoding.len()]
         );
@@ -0,0 +1,169 @@
+use constants::{BLOCK_SIZE, TRANSACTION_SIZE, BASE_SYMBOL_SIZE, AGGREGATE, RATE, HEADER_SIZE, UNDECODABLE_RATIO};
+use decoder::Code;
+use serde::{Serialize, Deserialize};
+use std::fs::File;
+use std::io::BufReader;
+
+// Parameters of the coded Merkle tree, read at start up so that experiments with different
+// block sizes run on the same binary. The number of hashes aggregated into an upper symbol
+// stays AGGREGATE, since it fixes the layout of SymbolUp.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CmtParams {
+	// size of transactions in a block in bytes
+	pub block_size: u64,
+	pub transaction_size: u64,
+	// size of a symbol on the base layer in bytes
+	pub base_symbol_size: usize,
+	// coding rate for code ensemble
+	pub rate: f32,
+	// number of hashes of coded symbols stored in the block header
+	pub header_size: u32,
+	// fraction of coded symbols a node collects before trying to decode
+	pub undecodable_ratio: f32,
+}
+
+impl Default for CmtParams {
+	fn default() -> Self {
+		CmtParams {
+			block_size: BLOCK_SIZE,
+			transaction_size: TRANSACTION_SIZE,
+			base_symbol_size: BASE_SYMBOL_SIZE,
+			rate: RATE,
+			header_size: HEADER_SIZE,
+			undecodable_ratio: UNDECODABLE_RATIO,
+		}
+	}
+}
+
+impl CmtParams {
+	// fields missing from the file keep their default value
+	pub fn from_file(path: &str) -> Result<CmtParams, String> {
+		let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path, e))?;
+		let params: CmtParams = serde_json::from_reader(BufReader::new(file))
+			.map_err(|e| format!("cannot parse {}: {}", path, e))?;
+		params.check()?;
+		Ok(params)
+	}
+
+	// number of hashes of a symbol that stay systematic on the next layer
+	pub fn reduce_factor(&self) -> u64 {
+		((AGGREGATE as f32) * self.rate) as u64
+	}
+
+	// number of systematic symbols on the base layer
+	pub fn num_sys_symbols(&self) -> u64 {
+		let size = self.base_symbol_size as u64;
+		(self.block_size + size - 1) / size
+	}
+
+	// number of coded symbols on the base layer
+	pub fn num_base_symbols(&self) -> u64 {
+		((self.num_sys_symbols() as f32) / self.rate) as u64
+	}
+
+	// number of coded base symbols a node collects before trying to decode
+	pub fn decodable_symbols(&self) -> u64 {
+		((self.num_base_symbols() as f32) * self.undecodable_ratio) as u64
+	}
+
+	// number of systematic symbols on each layer of the tree, from the base layer up
+	// to the layer whose coded symbols are hashed into the header
+	pub fn k_set(&self) -> Vec<u64> {
+		let reduce = self.reduce_factor();
+		let mut k_set = vec![];
+		let mut k = self.num_sys_symbols();
+		loop {
+			k_set.push(k);
+			if ((k as f32) / self.rate) as u64 <= self.header_size as u64 || k < reduce {
+				break;
+			}
+			k /= reduce;
+		}
+		k_set
+	}
+
+	// sanity checks that do not depend on the codes
+	pub fn check(&self) -> Result<(), String> {
+		if self.block_size == 0 || self.base_symbol_size == 0 || self.transaction_size == 0 {
+			return Err("block, symbol and transaction sizes must be positive".to_string());
+		}
+		if !(self.rate > 0.0 && self.rate < 1.0) {
+			return Err(format!("rate {} is not in (0, 1)", self.rate));
+		}
+		let reduce = (AGGREGATE as f32) * self.rate;
+		if reduce < 2.0 || reduce.fract() != 0.0 {
+			return Err(format!("rate {} does not split {} hashes evenly", self.rate, AGGREGATE));
+		}
+		if !(self.undecodable_ratio > 0.0 && self.undecodable_ratio <= 1.0) {
+			return Err(format!("undecodable_ratio {} is not in (0, 1]", self.undecodable_ratio));
+		}
+		let k_set = self.k_set();
+		let top = k_set[k_set.len() - 1];
+		if ((top as f32) / self.rate) as u64 != self.header_size as u64 {
+			return Err(format!("{} base symbols do not reduce to a top layer of {} symbols",
+				self.num_sys_symbols(), self.header_size));
+		}
+		Ok(())
+	}
+
+	// codes must hold one code per layer with the block length of that layer
+	pub fn validate(&self, codes: &[Code]) -> Result<(), String> {
+		self.check()?;
+		let k_set = self.k_set();
+		if codes.len() != k_set.len() {
+			return Err(format!("tree has {} layers but {} codes are loaded", k_set.len(), codes.len()));
+		}
+		for (layer, (code, k)) in codes.iter().zip(k_set.iter()).enumerate() {
+			let n = ((*k as f32) / self.rate) as usize;
+			if code.symbols.len() != n {
+				return Err(format!("code of layer {} has length {}, expected {}", layer, code.symbols.len(), n));
+			}
+			if code.parities.iter().flatten().any(|s| *s as usize >= n) {
+				return Err(format!("code of layer {} refers to a symbol beyond {}", layer, n));
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn default_matches_constants() {
+		let params = CmtParams::default();
+		assert_eq!(params.check(), Ok(()));
+		assert_eq!(params.k_set(), vec![128, 64, 32, 16, 8, 4]);
+		assert_eq!(params.num_base_symbols(), ::constants::NUM_BASE_SYMBOL);
+		assert_eq!(params.reduce_factor(), 2);
+	}
+
+	#[test]
+	fn smaller_blocks_have_fewer_layers() {
+		let params = CmtParams { block_size: 65536, base_symbol_size: 1024, ..CmtParams::default() };
+		assert_eq!(params.check(), Ok(()));
+		assert_eq!(params.k_set(), vec![64, 32, 16, 8, 4]);
+	}
+
+	#[test]
+	fn rejects_bad_params() {
+		assert!(CmtParams { rate: 0.3, ..CmtParams::default() }.check().is_err());
+		assert!(CmtParams { header_size: 24, ..CmtParams::default() }.check().is_err());
+		assert!(CmtParams { base_symbol_size: 0, ..CmtParams::default() }.check().is_err());
+	}
+
+	#[test]
+	fn validate_against_codes() {
+		let params = CmtParams { block_size: 128, base_symbol_size: 32, header_size: 16, ..CmtParams::default() };
+		assert_eq!(params.k_set(), vec![4]);
+		let code = Code { parities: vec![vec![0, 4, 8]; 12], symbols: vec![vec![0]; 16] };
+		assert_eq!(params.validate(&[code.clone()]), Ok(()));
+		assert!(params.validate(&[code.clone(), code.clone()]).is_err());
+		let short = Code { parities: vec![vec![0]], symbols: vec![vec![0]; 8] };
+		assert!(params.validate(&[short]).is_err());
+		let outside = Code { parities: vec![vec![16]], symbols: vec![vec![0]; 16] };
+		assert!(params.validate(&[outside]).is_err());
+	}
+}
@@ -3,7 +3,7 @@ extern crate tiny_http;
 use super::{TxGenSignal};
 use super::mempool::mempool::{Mempool};
 use super::blockchain::blockchain::BlockChain;
-use super::db::blockDb::BlockDb;
+use super::db::blockDb::{BlockDb, PrunePolicy};
 use super::contract::interface::{Message, Handle, Answer};
 use super::contract::interface::Response as ContractResponse;
 use crossbeam::channel::{self, Sender};
@@ -54,6 +54,13 @@ macro_rules! respond_result {
     }};
 }
 
+// side chain selected by the sid query parameter, side chain 0 if it is absent
+fn query_sid(url: &Url) -> Option<usize> {
+    match url.query_pairs().find(|(k, _)| k == "sid") {
+        Some((_, v)) => v.parse::<usize>().ok(),
+        None => Some(0),
+    }
+}
 
 
 impl ApiServer {
@@ -144,10 +151,23 @@ impl ApiServer {
                             respond_result!(request, true, "ok");
                         },
                         "/blockchain/get-curr-state" => {
+                            let sid = match query_sid(&url) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, "sid needs to be numeric");
+                                    return;
+                                },
+                            };
                             println!("before /blockchain/get-curr-state lock" );
                             let chain = rc.chain.lock().expect("api get-curr-state");
                             println!("after /blockchain/get-curr-state lock" );
-                            let state = chain.get_latest_state().expect("/blockchain/get-curr-state empty");
+                            let state = match chain.get_latest_state(sid as u64) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, format!("side chain {} is not tracked", sid));
+                                    return;
+                                },
+                            };
                             drop(chain);
                             respond_result!(request, true, format!("{:?}, {}", state.block_id, state.curr_hash.to_string()));
                             //respond_result!(request, true, format!("{:?}", state));
@@ -158,6 +178,38 @@ impl ApiServer {
                             drop(block_db);
                             respond_result!(request, true, format!("{:?}", num_blocks));
                         },
+                        "/block-db/get-block" => {
+                            let sid = match query_sid(&url) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, "sid needs to be numeric");
+                                    return;
+                                },
+                            };
+                            let pairs: HashMap<_, _> = url.query_pairs().into_owned().collect();
+                            let block_id = match pairs.get("bid").map(|b| b.parse::<u64>()) {
+                                Some(Ok(b)) => b,
+                                Some(Err(_)) => {
+                                    respond_result!(request, false, "bid needs to be numeric");
+                                    return;
+                                },
+                                None => {
+                                    respond_result!(request, false, "missing bid");
+                                    return;
+                                },
+                            };
+                            let block_db = rc.block_db.lock().expect("api gets block db");
+                            let block = block_db.get_sblock(sid as u64, block_id);
+                            drop(block_db);
+                            match block {
+                                // serialized transactions in hex, in block order
+                                Some(block) => {
+                                    let transactions: Vec<String> = block.transactions.iter().map(|t| hex::encode(&t.bytes()[..])).collect();
+                                    respond_result!(request, true, serde_json::to_string(&transactions).unwrap());
+                                },
+                                None => respond_result!(request, false, format!("block {} on side chain {} is not stored", block_id, sid)),
+                            }
+                        },
                         "/block-db/set-block-thresh" => {
                             let mut pairs: HashMap<_, _> = url.query_pairs().into_owned().collect();
                             let thresh = match pairs.get("thresh") {
@@ -175,7 +227,7 @@ impl ApiServer {
                                 },
                             };
                             let mut  block_db = rc.block_db.lock().expect("gets block db");
-                            block_db.thresh = thresh;
+                            block_db.set_prune_policy(PrunePolicy::Blocks(thresh));
                             drop(block_db);
                             respond_result!(request, true, "ok");
                         },
@@ -251,8 +303,15 @@ impl ApiServer {
                         },
                         "/contract/reset-chain" => {
                             info!("reset-chain");
+                            let sid = match query_sid(&url) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, "sid needs to be numeric");
+                                    return;
+                                },
+                            };
                             let handle = Handle {
-                                message: Message::ResetChain(0),
+                                message: Message::ResetChain(sid),
                                 answer_channel: None,
                             };
                             rc.contract_channel.send(handle);
@@ -290,9 +349,16 @@ impl ApiServer {
                             respond_result!(request, true, format!("{}", num_node));
                         },
                         "/contract/get-curr-state" => {
+                            let sid = match query_sid(&url) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, "sid needs to be numeric");
+                                    return;
+                                },
+                            };
                             let (answer_tx, answer_rx) = channel::bounded(1);
                             let handle = Handle {
-                                message: Message::GetCurrState(0),
+                                message: Message::GetCurrState(sid),
                                 answer_channel: Some(answer_tx),
                             };
                             rc.contract_channel.send(handle);
@@ -394,17 +460,31 @@ impl ApiServer {
                             //rc.contract_channel.send(handle);
                         },
                         "/contract/get-all" => {
+                            let sid = match query_sid(&url) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, "sid needs to be numeric");
+                                    return;
+                                },
+                            };
                             let (answer_tx, answer_rx) = channel::bounded(1);
                             let handle = Handle {
-                                message: Message::GetAll(([0u8; 32], 0, 0)),
+                                message: Message::GetAll((sid, 0, 0)),
                                 answer_channel: Some(answer_tx),
                             };
                             rc.contract_channel.send(handle);
                         },
                         "/contract/sync-chain" => {
+                            let sid = match query_sid(&url) {
+                                Some(s) => s,
+                                None => {
+                                    respond_result!(request, false, "sid needs to be numeric");
+                                    return;
+                                },
+                            };
                             let (answer_tx, answer_rx) = channel::bounded(1);
                             let handle = Handle {
-                                message: Message::SyncChain,
+                                message: Message::SyncChain(sid),
                                 answer_channel: Some(answer_tx),
                             };
                             rc.contract_channel.send(handle);
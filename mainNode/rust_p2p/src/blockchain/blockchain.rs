@@ -2,66 +2,468 @@ use super::GENESIS;
 use super::hash::{H256};
 use super::fork::{ForkBuffer};
 use super::block::{Header};
-use std::collections::{HashMap};
-use super::primitive::block::{ContractState};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use super::primitive::block::{ContractState, EthBlkTransaction};
 use super::experiment::snapshot::PERFORMANCE_COUNTER;
+use crate::crypto::{hash, chain_hash};
+use crate::db::blockDb::BlockDb;
 
+// how the local chain relates to a state committed on the contract
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainCheck {
+    Synced,
+    Behind(u64), // local tip block id
+    Ahead(u64), // local tip block id
+    Diverged(u64, H256, H256), // block id, local hash, contract hash
+}
+
+// what bringing the local chain in line with the contract did
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainUpdate {
+    Appended,
+    Synced,
+    Missing(u64, u64), // states after the first up to the second block id are needed, see rebase
+    Revised(u64), // first block id that was replaced
+}
+
+// one hash chain per side chain this node serves, keyed by sid
 pub struct BlockChain {
-    blockchain: Vec<ContractState>,
+    blockchains: HashMap<u64, Vec<ContractState>>,
+    // blocks proven to be incorrectly coded, keyed by (sid, block_id)
+    invalid_blocks: HashSet<(u64, u64)>,
+    // side nodes that proposed an incorrectly coded block
+    faulty_proposers: HashSet<SocketAddr>,
+    // number of symbols failing their Merkle proof, keyed by the peer that sent them
+    bad_peers: HashMap<SocketAddr, usize>,
+    // hash of the decoded block header of appended states, keyed by (sid, block_id)
+    block_hashes: HashMap<(u64, u64), H256>,
+    // appended states are written to the block database when set
+    block_db: Option<Arc<Mutex<BlockDb>>>,
+    // proposed blocks not committed yet, keyed by sid
+    forks: HashMap<u64, ForkBuffer>,
 }
 
 impl BlockChain {
     pub fn new() -> BlockChain {
-        let genesis = ContractState::default();
+        let genesis = ContractState::genesis(0);
         //PERFORMANCE_COUNTER.record_chain_update();
+        let mut blockchains = HashMap::new();
+        blockchains.insert(0, vec![genesis]);
+        let mut forks = HashMap::new();
+        forks.insert(0, ForkBuffer::new());
         BlockChain {
-            blockchain: vec![genesis],
+            blockchains: blockchains,
+            invalid_blocks: HashSet::new(),
+            faulty_proposers: HashSet::new(),
+            bad_peers: HashMap::new(),
+            block_hashes: HashMap::new(),
+            block_db: None,
+            forks: forks,
         } 
     }
 
+    // load the chains stored in block_db, states appended later are written back to it
+    pub fn restore(block_db: Arc<Mutex<BlockDb>>, sids: &[u64]) -> BlockChain {
+        let mut chain = BlockChain::new();
+        for sid in sids {
+            chain.add_side_chain(*sid);
+        }
+        let db = block_db.lock().unwrap();
+        for sid in chain.side_chains() {
+            for (state, block_hash) in db.get_states(sid) {
+                if state.block_id != chain.get_height(sid) {
+                    warn!("stored chain {} misses block {}, drop states from block {}", sid, chain.get_height(sid), state.block_id);
+                    break;
+                }
+                if let Some(h) = block_hash {
+                    chain.block_hashes.insert((sid, state.block_id), h);
+                }
+                chain.chain_mut(sid).push(state);
+            }
+            if chain.get_height(sid) > 1 {
+                info!("restored local chain {} up to block {}", sid, chain.get_height(sid) - 1);
+            }
+        }
+        drop(db);
+        chain.block_db = Some(block_db);
+        chain
+    }
+
+    // start tracking side chain sid, no-op if it is already tracked
+    pub fn add_side_chain(&mut self, sid: u64) {
+        self.blockchains.entry(sid).or_insert(vec![ContractState::genesis(sid)]);
+        self.forks.entry(sid).or_insert(ForkBuffer::new());
+    }
+
+    pub fn side_chains(&self) -> Vec<u64> {
+        let mut sids: Vec<u64> = self.blockchains.keys().cloned().collect();
+        sids.sort();
+        sids
+    }
+
+    fn chain_mut(&mut self, sid: u64) -> &mut Vec<ContractState> {
+        self.blockchains.get_mut(&sid).expect(&format!("side chain {} is not tracked", sid))
+    }
+
+    fn push(&mut self, state: &ContractState) {
+        if let Some(db) = &self.block_db {
+            let mut db = db.lock().unwrap();
+            let block_hash = db.get_header(state.sid, state.block_id).map(|h| hash(&h));
+            db.insert_state(state, block_hash);
+            drop(db);
+            if let Some(h) = block_hash {
+                self.block_hashes.insert((state.sid, state.block_id), h);
+            }
+        }
+        self.chain_mut(state.sid).push(state.clone());
+        if let Some(forks) = self.forks.get_mut(&state.sid) {
+            forks.confirm(&state.curr_hash, state.block_id as usize);
+        }
+    }
+
     // input must be consistent with previous block
     pub fn insert(&mut self, contract_state: &ContractState) {
-        self.blockchain.push(contract_state.clone());
+        self.push(contract_state);
     }
 
     // TODO redundent to insert, remove insert later
     pub fn append(&mut self, eth_state: &ContractState) {
-        self.blockchain.push(eth_state.clone());
+        self.push(eth_state);
     }
 
-    pub fn update(&mut self, eth_state: &ContractState) {
-        let curr_state = self.blockchain.last();
+    // eth_state is the current state of the contract
+    pub fn update(&mut self, eth_state: &ContractState) -> ChainUpdate {
+        let curr_state = self.get_latest_state(eth_state.sid);
         let curr_state = curr_state.expect("blockchain:update is empty");
         if eth_state.block_id == curr_state.block_id + 1 {
-            self.blockchain.push(eth_state.clone());
-        } else if eth_state.block_id > curr_state.block_id + 1 {
+            self.push(eth_state);
+            return ChainUpdate::Appended;
+        }
+        match self.check_state(eth_state) {
+            ChainCheck::Synced => ChainUpdate::Synced,
             // local chain is missing blocks
-        } else if eth_state.block_id == curr_state.block_id {
-            println!("local chain already synced");
-        } else {
-            panic!("local chain screw up, it is greater than eth chain");
+            ChainCheck::Behind(tip) => ChainUpdate::Missing(tip, eth_state.block_id),
+            // the contract was reset to one of the local blocks
+            ChainCheck::Ahead(_) => {
+                self.revise(eth_state.sid, eth_state.block_id as usize + 1, vec![]);
+                ChainUpdate::Revised(eth_state.block_id + 1)
+            },
+            // the contract was reset and extended again, the fork point is unknown
+            ChainCheck::Diverged(..) => ChainUpdate::Missing(0, eth_state.block_id),
+        }
+    }
+
+    // states is the contract chain up to its current block, or the part of it after the local tip
+    pub fn rebase(&mut self, sid: u64, states: &[ContractState]) -> ChainUpdate {
+        self.rebase_states(sid, states, true)
+    }
+
+    // history is the whole contract chain of sid. Each state must follow from the one before
+    // through its committed header, only the verified part is taken and its headers are stored
+    // so the block hashes are known
+    pub fn catch_up(&mut self, sid: u64, history: &[EthBlkTransaction]) -> ChainUpdate {
+        let mut prev = ContractState::genesis(sid);
+        let mut states = Vec::with_capacity(history.len());
+        for entry in history.iter() {
+            let state = &entry.contract_state;
+            if state.sid != sid || state.block_id != prev.block_id + 1 || chain_hash(prev.curr_hash, &entry.header) != state.curr_hash {
+                warn!("contract history of side chain {} does not verify at block {}, taken up to block {}", sid, state.block_id, prev.block_id);
+                return self.rebase_states(sid, &states, false);
+            }
+            if let Some(db) = &self.block_db {
+                db.lock().unwrap().insert_header(sid, state.block_id, &entry.header);
+            }
+            states.push(state.clone());
+            prev = state.clone();
         }
+        self.rebase_states(sid, &states, true)
     }
 
-    // block_id itself is changed
-    pub fn revise(&mut self, block_id: usize, states: Vec<ContractState>) {
+    // a complete contract chain that is shorter than the local one means the contract was reset
+    fn rebase_states(&mut self, sid: u64, states: &[ContractState], complete: bool) -> ChainUpdate {
+        let mut result = ChainUpdate::Synced;
+        for (i, state) in states.iter().enumerate() {
+            match self.check_state(state) {
+                ChainCheck::Synced | ChainCheck::Ahead(_) => (),
+                ChainCheck::Behind(tip) if state.block_id == tip + 1 => {
+                    self.push(state);
+                    result = ChainUpdate::Appended;
+                },
+                ChainCheck::Behind(tip) => return ChainUpdate::Missing(tip, state.block_id),
+                ChainCheck::Diverged(block_id, _, _) => {
+                    self.revise(sid, block_id as usize, states[i..].to_vec());
+                    return ChainUpdate::Revised(block_id);
+                },
+            }
+        }
+        if let (true, Some(first), Some(last)) = (complete, states.first(), states.last()) {
+            if first.block_id == 1 && self.get_height(sid) > last.block_id + 1 {
+                // the whole contract chain is shorter, it was reset
+                self.revise(sid, last.block_id as usize + 1, vec![]);
+                return ChainUpdate::Revised(last.block_id + 1);
+            }
+        }
+        result
+    }
 
+    // block_id itself is changed, the local blocks from block_id on are replaced by states
+    pub fn revise(&mut self, sid: u64, block_id: usize, states: Vec<ContractState>) {
+        let mut chain = self.blockchains.get(&sid).cloned().unwrap_or(vec![ContractState::genesis(sid)]);
+        chain.truncate(cmp::max(block_id, 1));
+        chain.extend(states);
+        warn!("local chain {} revised from block {}, now at block {}", sid, block_id, chain.len() - 1);
+        self.replace(sid, chain);
     }
 
-    pub fn replace(&mut self, chain: Vec<ContractState>) {
-        self.blockchain = chain;
+    pub fn replace(&mut self, sid: u64, chain: Vec<ContractState>) {
+        // hashes of blocks that stay the same are kept
+        let old = self.blockchains.get(&sid).cloned().unwrap_or(vec![]);
+        self.block_hashes.retain(|(s, b), _| *s != sid || old.get(*b as usize) == chain.get(*b as usize));
+        if let Some(db) = &self.block_db {
+            let mut db = db.lock().unwrap();
+            db.remove_states(sid);
+            for state in chain.iter().filter(|s| s.block_id > 0) {
+                db.insert_state(state, self.block_hashes.get(&(sid, state.block_id)).cloned());
+            }
+        }
+        if let Some(tip) = chain.last() {
+            self.forks.entry(sid).or_insert(ForkBuffer::new()).confirm(&tip.curr_hash, tip.block_id as usize);
+        }
+        self.blockchains.insert(sid, chain);
         //PERFORMANCE_COUNTER.store_chain_depth(self.blockchain.len());
     }
 
+    // track a proposed block, keyed by the hash the contract stores once it commits the block
+    pub fn propose(&mut self, sid: u64, block_id: u64, prev_hash: H256, header: &[u8]) -> H256 {
+        let proposal = Header {
+            hash: chain_hash(prev_hash, header),
+            nonce: H256::default(),
+            height: block_id as usize,
+            root: hash(header),
+            prev_hash,
+        };
+        let forks = self.forks.entry(sid).or_insert(ForkBuffer::new());
+        forks.insert(&proposal, block_id as usize);
+        forks.prune_below(block_id as usize);
+        proposal.hash
+    }
+
+    // number of proposals seen for block_id
+    pub fn num_proposals(&self, sid: u64, block_id: u64) -> usize {
+        self.forks.get(&sid).map(|f| f.num_at(block_id as usize)).unwrap_or(0)
+    }
+
+    // proposals on top of the committed tip, the longest branch is the canonical one
+    pub fn pending_chain(&self, sid: u64) -> Vec<H256> {
+        match (self.forks.get(&sid), self.get_latest_state(sid)) {
+            (Some(forks), Some(tip)) => forks.longest_from(&tip.curr_hash),
+            _ => vec![],
+        }
+    }
+
+    // mark a block as invalid after an incorrect-coding proof is verified, returns false if it was already marked
+    pub fn mark_invalid(&mut self, sid: u64, block_id: u64, proposer: Option<SocketAddr>) -> bool {
+        if let Some(addr) = proposer {
+            self.faulty_proposers.insert(addr);
+        }
+        self.invalid_blocks.insert((sid, block_id))
+    }
+
+    pub fn is_invalid(&self, sid: u64, block_id: u64) -> bool {
+        self.invalid_blocks.contains(&(sid, block_id))
+    }
+
+    // the contract was reset, blocks from block_id on are committed anew
+    pub fn clear_invalid(&mut self, sid: u64, block_id: u64) {
+        self.invalid_blocks.retain(|(s, b)| *s != sid || *b < block_id);
+    }
+
+    pub fn is_faulty_proposer(&self, addr: &SocketAddr) -> bool {
+        self.faulty_proposers.contains(addr)
+    }
+
+    pub fn record_bad_peer(&mut self, peer: SocketAddr, num_symbols: usize) {
+        *self.bad_peers.entry(peer).or_insert(0) += num_symbols;
+    }
+
+    // number of invalid symbols received from peer so far
+    pub fn bad_symbols_from(&self, peer: &SocketAddr) -> usize {
+        *self.bad_peers.get(peer).unwrap_or(&0)
+    }
+
     // block id should start at 0, so is consistent with height
-    pub fn get_height(&self) -> u64 {
-        self.blockchain.len() as u64
+    pub fn get_height(&self, sid: u64) -> u64 {
+        self.blockchains.get(&sid).map(|c| c.len()).unwrap_or(0) as u64
     }
 
-    pub fn get_latest_state(&self) -> Option<ContractState> {
-        match self.blockchain.last() {
+    // compare the local chain with a state read from the contract
+    pub fn check_state(&self, state: &ContractState) -> ChainCheck {
+        let chain = match self.blockchains.get(&state.sid) {
+            Some(c) => c,
+            None => return ChainCheck::Behind(0),
+        };
+        let tip = chain.len() as u64 - 1;
+        if state.block_id > tip {
+            return ChainCheck::Behind(tip);
+        }
+        let local = chain[state.block_id as usize].curr_hash;
+        if local != state.curr_hash {
+            ChainCheck::Diverged(state.block_id, local, state.curr_hash)
+        } else if state.block_id < tip {
+            ChainCheck::Ahead(tip)
+        } else {
+            ChainCheck::Synced
+        }
+    }
+
+    // hash of the decoded block header, if the block was decoded or sampled by this node
+    pub fn get_block_hash(&self, sid: u64, block_id: u64) -> Option<H256> {
+        self.block_hashes.get(&(sid, block_id)).cloned()
+    }
+
+    pub fn get_latest_state(&self, sid: u64) -> Option<ContractState> {
+        match self.blockchains.get(&sid).and_then(|c| c.last()) {
             Some(c) => Some(c.clone()),
             None => None,
         }
     }
+
+    // block 0 is the genesis state
+    pub fn get_state(&self, sid: u64, block_id: u64) -> Option<ContractState> {
+        self.blockchains.get(&sid).and_then(|c| c.get(block_id as usize)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::blockDb::PrunePolicy;
+    use std::env;
+    use std::fs;
+
+    fn state(sid: u64, block_id: u64, b: u8) -> ContractState {
+        ContractState { sid, curr_hash: H256([b; 32]), block_id }
+    }
+
+    fn open_db(name: &str) -> (std::path::PathBuf, Arc<Mutex<BlockDb>>) {
+        let path = env::temp_dir().join(format!("chain_db_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let db = Arc::new(Mutex::new(BlockDb::new(&path, PrunePolicy::default())));
+        (path, db)
+    }
+
+    // side chain 1 at block 2, the header of block 1 is known
+    fn chain_at_two(db: &Arc<Mutex<BlockDb>>) -> BlockChain {
+        db.lock().unwrap().insert_header(1, 1, &[7]);
+        let mut chain = BlockChain::restore(db.clone(), &[1]);
+        chain.append(&state(1, 1, 1));
+        chain.update(&state(1, 2, 2));
+        chain
+    }
+
+    #[test]
+    fn restored_chain_keeps_appended_states() {
+        let (path, db) = open_db("restore");
+        {
+            let mut chain = chain_at_two(&db);
+            chain.append(&state(0, 1, 9));
+        }
+        let mut chain = BlockChain::restore(db.clone(), &[1]);
+        assert_eq!(chain.get_height(1), 3);
+        assert_eq!(chain.get_latest_state(1), Some(state(1, 2, 2)));
+        assert_eq!(chain.get_latest_state(0), Some(state(0, 1, 9)));
+        assert_eq!(chain.get_block_hash(1, 1), Some(hash(&[7])));
+        assert_eq!(chain.get_block_hash(1, 2), None);
+
+        assert_eq!(chain.check_state(&state(1, 2, 2)), ChainCheck::Synced);
+        assert_eq!(chain.check_state(&state(1, 1, 1)), ChainCheck::Ahead(2));
+        assert_eq!(chain.check_state(&state(1, 3, 3)), ChainCheck::Behind(2));
+        assert_eq!(chain.check_state(&state(1, 2, 5)), ChainCheck::Diverged(2, H256([2; 32]), H256([5; 32])));
+
+        // a dropped chain stays dropped after a restart
+        chain.replace(1, vec![ContractState::genesis(1)]);
+        drop(chain);
+        let chain = BlockChain::restore(db.clone(), &[1]);
+        assert_eq!(chain.get_height(1), 1);
+        assert_eq!(chain.get_height(0), 2);
+        drop(chain);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn proposals_are_pruned_once_one_is_committed() {
+        let (path, db) = open_db("proposals");
+        let mut chain = chain_at_two(&db);
+        let proposal = chain.propose(1, 3, H256([2; 32]), b"header");
+        chain.propose(1, 3, H256([2; 32]), b"other");
+        assert_eq!(proposal, chain_hash(H256([2; 32]), b"header"));
+        assert_eq!(chain.num_proposals(1, 3), 2);
+        assert_eq!(chain.pending_chain(1).len(), 1);
+        assert_eq!(chain.update(&ContractState { sid: 1, curr_hash: proposal, block_id: 3 }), ChainUpdate::Appended);
+        assert_eq!(chain.num_proposals(1, 3), 0);
+        assert_eq!(chain.update(&state(1, 5, 5)), ChainUpdate::Missing(3, 5));
+        drop(chain);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn reset_contract_chain_is_rebased() {
+        let (path, db) = open_db("rebase");
+        let mut chain = chain_at_two(&db);
+        chain.append(&state(1, 3, 3));
+
+        // the contract was reset to block 1 and extended again
+        assert_eq!(chain.update(&state(1, 2, 7)), ChainUpdate::Missing(0, 2));
+        assert_eq!(chain.rebase(1, &[state(1, 1, 1), state(1, 2, 7)]), ChainUpdate::Revised(2));
+        assert_eq!(chain.get_latest_state(1), Some(state(1, 2, 7)));
+        assert_eq!(chain.get_height(1), 3);
+        assert_eq!(chain.get_block_hash(1, 1), Some(hash(&[7])));
+
+        // reset to a local block
+        assert_eq!(chain.update(&state(1, 1, 1)), ChainUpdate::Revised(2));
+        assert_eq!(chain.get_height(1), 2);
+        drop(chain);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn catch_up_takes_only_verified_history() {
+        let (path, db) = open_db("catch_up");
+        let mut chain = BlockChain::restore(db.clone(), &[1]);
+        let mut history = vec![];
+        let mut prev = H256::default();
+        for block_id in 1..4u64 {
+            let header = vec![block_id as u8; 8];
+            prev = chain_hash(prev, &header);
+            history.push(EthBlkTransaction {
+                contract_state: ContractState { sid: 1, curr_hash: prev, block_id },
+                header,
+                ..Default::default()
+            });
+        }
+        let mut tampered = history.clone();
+        tampered[1].header = vec![9; 8];
+
+        assert_eq!(chain.catch_up(1, &tampered), ChainUpdate::Appended);
+        assert_eq!(chain.get_height(1), 2);
+        assert_eq!(chain.get_block_hash(1, 1), Some(hash(&[1; 8])));
+        assert_eq!(chain.get_block_hash(1, 2), None);
+
+        assert_eq!(chain.catch_up(1, &history), ChainUpdate::Appended);
+        assert_eq!(chain.get_latest_state(1), Some(history[2].contract_state.clone()));
+        assert_eq!(chain.get_block_hash(1, 3), Some(hash(&[3; 8])));
+
+        // an unverified prefix is not taken for a reset contract chain
+        assert_eq!(chain.catch_up(1, &tampered[..2]), ChainUpdate::Synced);
+        assert_eq!(chain.get_height(1), 4);
+        drop(chain);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
 }
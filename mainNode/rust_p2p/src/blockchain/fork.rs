@@ -6,6 +6,9 @@ use petgraph::graph::{NodeIndex};
 use petgraph::stable_graph::{StableGraph};
 use petgraph::visit::EdgeRef;
 
+// proposals this many blocks below the newest one are dropped
+pub const FORK_WINDOW: usize = 16;
+
 pub struct ForkBuffer {
     pub graph: StableGraph::<Header, ()>, 
     pub hash_node: HashMap<H256, NodeIndex>,
@@ -51,9 +54,7 @@ impl ForkBuffer {
                         self.leaf_hashes.remove(prev_hash);
                     },
                     None =>  {
-                        let mut nodes = self.prev_hash_to_nodes.entry(*prev_hash).or_insert(vec![node]);
-                        (*nodes).push(node);
-
+                        self.prev_hash_to_nodes.entry(*prev_hash).or_insert(vec![]).push(node);
                     },
                 }
                 //  connect to its children
@@ -88,6 +89,7 @@ impl ForkBuffer {
                 for e in in_edge {
                     let src_n = e.source();
                     parent_hash = self.graph[src_n].hash;
+                    num_in += 1;
                 }
 
                 if num_in == 0 {
@@ -124,7 +126,8 @@ impl ForkBuffer {
 
         for leaf_hash in &self.leaf_hashes {
             let leaf_chain = self.get_parent_hashes(*leaf_hash);     
-            let head_hash = &leaf_chain[0];
+            // contact height is recorded for the first block of a fork
+            let head_hash = leaf_chain.last().unwrap();
             match self.hash_contact_height.get(head_hash) {
                 None => (),
                 Some(contact_height) => {
@@ -145,12 +148,160 @@ impl ForkBuffer {
     }
 
 
-    pub fn remove(&mut self, hash: &H256) {
-        match self.hash_node.get(hash) {
-            None => (),
-            Some(node) => {
-                 
+    pub fn len(&self) -> usize {
+        self.hash_node.len()
+    }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.hash_node.contains_key(hash)
+    }
+
+    // number of competing proposals for block height
+    pub fn num_at(&self, height: usize) -> usize {
+        self.graph.node_indices().filter(|n| self.graph[*n].height == height).count()
+    }
+
+    // longest chain of proposals extending tip_hash, lowest block first,
+    // ties go to the branch with the smaller hash so every node picks the same one
+    pub fn longest_from(&self, tip_hash: &H256) -> Vec<H256> {
+        let roots: Vec<NodeIndex> = match self.hash_node.get(tip_hash) {
+            Some(node) => self.graph.neighbors_directed(*node, Direction::Outgoing).collect(),
+            None => self.prev_hash_to_nodes.get(tip_hash).cloned().unwrap_or(vec![]),
+        };
+        self.longest_of(roots)
+    }
+
+    fn longest_of(&self, nodes: Vec<NodeIndex>) -> Vec<H256> {
+        let mut longest: Vec<H256> = vec![];
+        for node in nodes {
+            let mut chain = vec![self.graph[node].hash];
+            chain.extend(self.longest_of(self.graph.neighbors_directed(node, Direction::Outgoing).collect()));
+            if chain.len() > longest.len() || (chain.len() == longest.len() && chain[0].0 < longest[0].0) {
+                longest = chain;
             }
-        } 
+        }
+        longest
+    }
+
+    // a block is committed at height, proposals up to it are history and
+    // proposals for the next height must extend it
+    pub fn confirm(&mut self, hash: &H256, height: usize) {
+        let committed: Vec<H256> = self.graph.node_indices()
+            .map(|n| &self.graph[n])
+            .filter(|h| h.height <= height)
+            .map(|h| h.hash)
+            .collect();
+        for h in committed.iter() {
+            self.remove(h);
+        }
+        let stale: Vec<H256> = self.graph.node_indices()
+            .map(|n| &self.graph[n])
+            .filter(|h| h.height == height + 1 && h.prev_hash != *hash)
+            .map(|h| h.hash)
+            .collect();
+        for h in stale.iter() {
+            self.remove_subtree(h);
+        }
+    }
+
+    // drop proposals FORK_WINDOW or more blocks below height
+    pub fn prune_below(&mut self, height: usize) {
+        let old: Vec<H256> = self.graph.node_indices()
+            .map(|n| &self.graph[n])
+            .filter(|h| h.height + FORK_WINDOW <= height)
+            .map(|h| h.hash)
+            .collect();
+        for h in old.iter() {
+            self.remove(h);
+        }
+    }
+
+    fn remove_subtree(&mut self, hash: &H256) {
+        let children: Vec<H256> = match self.hash_node.get(hash) {
+            None => return,
+            Some(node) => self.graph.neighbors_directed(*node, Direction::Outgoing).map(|n| self.graph[n].hash).collect(),
+        };
+        self.remove(hash);
+        for c in children.iter() {
+            self.remove_subtree(c);
+        }
+    }
+
+    // children of a removed block wait for it again, as if it had not arrived yet
+    pub fn remove(&mut self, hash: &H256) {
+        let node = match self.hash_node.remove(hash) {
+            None => return,
+            Some(node) => node,
+        };
+        let children: Vec<NodeIndex> = self.graph.neighbors_directed(node, Direction::Outgoing).collect();
+        let parent = self.graph.neighbors_directed(node, Direction::Incoming).next();
+        let header = self.graph.remove_node(node).unwrap();
+        if !children.is_empty() {
+            self.prev_hash_to_nodes.insert(*hash, children);
+        }
+        match parent {
+            Some(p) => {
+                if self.graph.neighbors_directed(p, Direction::Outgoing).next().is_none() {
+                    self.leaf_hashes.insert(self.graph[p].hash);
+                }
+            },
+            None => {
+                let mut empty = false;
+                if let Some(nodes) = self.prev_hash_to_nodes.get_mut(&header.prev_hash) {
+                    nodes.retain(|n| *n != node);
+                    empty = nodes.is_empty();
+                }
+                if empty {
+                    self.prev_hash_to_nodes.remove(&header.prev_hash);
+                }
+            },
+        }
+        self.leaf_hashes.remove(hash);
+        self.hash_contact_height.remove(hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(hash: u8, prev: u8, height: usize) -> Header {
+        Header {
+            hash: H256([hash; 32]),
+            nonce: H256::default(),
+            height,
+            root: H256::default(),
+            prev_hash: H256([prev; 32]),
+        }
+    }
+
+    #[test]
+    fn longest_proposal_chain_from_confirmed_tip() {
+        let mut forks = ForkBuffer::new();
+        // 0 is the committed tip, 1 <- 2 <- 4 competes with 3
+        forks.insert(&header(2, 1, 2), 2);
+        forks.insert(&header(1, 0, 1), 1);
+        forks.insert(&header(3, 0, 1), 1);
+        forks.insert(&header(4, 2, 3), 3);
+        assert_eq!(forks.get_parent(&H256([4; 32])), Some(H256([2; 32])));
+        assert_eq!(forks.num_at(1), 2);
+        assert_eq!(forks.longest_from(&H256([0; 32])), vec![H256([1; 32]), H256([2; 32]), H256([4; 32])]);
+
+        // the contract commits 3, the branch through 1 is stale
+        forks.confirm(&H256([3; 32]), 1);
+        assert_eq!(forks.len(), 0);
+        assert!(forks.longest_from(&H256([3; 32])).is_empty());
+
+        forks.insert(&header(5, 3, 2), 2);
+        forks.insert(&header(6, 3, 2), 2);
+        assert_eq!(forks.longest_from(&H256([3; 32])), vec![H256([5; 32])]);
+        forks.insert(&header(7, 6, 3), 3);
+        assert_eq!(forks.longest_from(&H256([3; 32])), vec![H256([6; 32]), H256([7; 32])]);
+        forks.confirm(&H256([6; 32]), 2);
+        assert_eq!(forks.longest_from(&H256([6; 32])), vec![H256([7; 32])]);
+
+        forks.prune_below(3 + FORK_WINDOW);
+        assert_eq!(forks.len(), 0);
+        assert!(forks.prev_hash_to_nodes.is_empty());
     }
 }
@@ -0,0 +1,535 @@
+// Minimal solidity ABI codec for the ScaleChain contract. Function and event descriptions come
+// from the abi.json shipped next to this file, so encoding does not depend on the working
+// directory or on an external ethabi binary.
+use web3::types::{Address, H256, U256};
+use crypto::sha3::Sha3;
+use crypto::digest::Digest;
+use serde::{Deserialize};
+use std::collections::HashMap;
+use std::fmt;
+
+const WORD: usize = 32;
+
+lazy_static! {
+    pub static ref SCALECHAIN_ABI: Abi = {
+        Abi::from_json(include_bytes!("abi.json")).expect("malformed contract abi.json")
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    Address,
+    Uint(usize),
+    Bool,
+    Bytes,
+    String,
+    FixedBytes(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Address(Address),
+    Uint(U256),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    String(String),
+    FixedBytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiError {
+    Json(String),
+    UnknownFunction(String),
+    UnknownEvent(String),
+    UnsupportedType(String),
+    ArgCount { expected: usize, got: usize },
+    TypeMismatch(usize),
+    InvalidData(String),
+}
+
+impl fmt::Display for AbiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AbiError::Json(e) => write!(f, "unable to parse abi: {}", e),
+            AbiError::UnknownFunction(name) => write!(f, "function {} not in abi", name),
+            AbiError::UnknownEvent(name) => write!(f, "event {} not in abi", name),
+            AbiError::UnsupportedType(t) => write!(f, "unsupported abi type {}", t),
+            AbiError::ArgCount { expected, got } => write!(f, "expected {} arguments, got {}", expected, got),
+            AbiError::TypeMismatch(i) => write!(f, "argument {} does not match its abi type", i),
+            AbiError::InvalidData(e) => write!(f, "invalid abi data: {}", e),
+        }
+    }
+}
+
+impl ParamType {
+    pub fn parse(s: &str) -> Result<ParamType, AbiError> {
+        match s {
+            "address" => Ok(ParamType::Address),
+            "bool" => Ok(ParamType::Bool),
+            "bytes" => Ok(ParamType::Bytes),
+            "string" => Ok(ParamType::String),
+            "uint" => Ok(ParamType::Uint(256)),
+            _ if s.starts_with("uint") => {
+                match s[4..].parse::<usize>() {
+                    Ok(n) if n > 0 && n <= 256 && n % 8 == 0 => Ok(ParamType::Uint(n)),
+                    _ => Err(AbiError::UnsupportedType(s.to_string())),
+                }
+            },
+            _ if s.starts_with("bytes") => {
+                match s[5..].parse::<usize>() {
+                    Ok(n) if n > 0 && n <= 32 => Ok(ParamType::FixedBytes(n)),
+                    _ => Err(AbiError::UnsupportedType(s.to_string())),
+                }
+            },
+            _ => Err(AbiError::UnsupportedType(s.to_string())),
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String => true,
+            _ => false,
+        }
+    }
+
+    fn canonical(&self) -> String {
+        match self {
+            ParamType::Address => "address".to_string(),
+            ParamType::Uint(n) => format!("uint{}", n),
+            ParamType::Bool => "bool".to_string(),
+            ParamType::Bytes => "bytes".to_string(),
+            ParamType::String => "string".to_string(),
+            ParamType::FixedBytes(n) => format!("bytes{}", n),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonParam {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    indexed: bool,
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<JsonParam>,
+    #[serde(default)]
+    outputs: Vec<JsonParam>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub inputs: Vec<ParamType>,
+    pub outputs: Vec<ParamType>,
+}
+
+impl Function {
+    pub fn signature(&self) -> String {
+        let inputs: Vec<String> = self.inputs.iter().map(|p| p.canonical()).collect();
+        format!("{}({})", self.name, inputs.join(","))
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        let hash = keccak(&self.signature());
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&hash[..4]);
+        selector
+    }
+
+    // selector followed by the encoded arguments, ready to be used as transaction data
+    pub fn encode_input(&self, args: &[Token]) -> Result<Vec<u8>, AbiError> {
+        let mut data = self.selector().to_vec();
+        data.extend(encode(&self.inputs, args)?);
+        Ok(data)
+    }
+
+    pub fn decode_input(&self, data: &[u8]) -> Result<Vec<Token>, AbiError> {
+        if data.len() < 4 || data[..4] != self.selector() {
+            return Err(AbiError::InvalidData(format!("selector mismatch for {}", self.name)));
+        }
+        decode(&self.inputs, &data[4..])
+    }
+
+    pub fn decode_output(&self, data: &[u8]) -> Result<Vec<Token>, AbiError> {
+        decode(&self.outputs, data)
+    }
+}
+
+// Only static types may be indexed, the topic of an indexed dynamic value is its hash and
+// cannot be decoded back.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub name: String,
+    pub inputs: Vec<ParamType>,
+    pub indexed: Vec<bool>,
+}
+
+impl Event {
+    pub fn signature(&self) -> String {
+        let inputs: Vec<String> = self.inputs.iter().map(|p| p.canonical()).collect();
+        format!("{}({})", self.name, inputs.join(","))
+    }
+
+    pub fn topic(&self) -> H256 {
+        H256::from(keccak(&self.signature()))
+    }
+
+    fn split(&self) -> (Vec<ParamType>, Vec<ParamType>) {
+        let mut indexed = vec![];
+        let mut data = vec![];
+        for (param, is_indexed) in self.inputs.iter().zip(self.indexed.iter()) {
+            if *is_indexed {
+                indexed.push(param.clone());
+            } else {
+                data.push(param.clone());
+            }
+        }
+        (indexed, data)
+    }
+
+    // topics and data of a log carrying args, the way the contract emits it
+    pub fn encode_log(&self, args: &[Token]) -> Result<(Vec<H256>, Vec<u8>), AbiError> {
+        if args.len() != self.inputs.len() {
+            return Err(AbiError::ArgCount { expected: self.inputs.len(), got: args.len() });
+        }
+        let mut topics = vec![self.topic()];
+        let mut data_tokens = vec![];
+        for (i, ((param, is_indexed), token)) in self.inputs.iter().zip(self.indexed.iter()).zip(args.iter()).enumerate() {
+            if !*is_indexed {
+                data_tokens.push(token.clone());
+            } else if param.is_dynamic() {
+                return Err(AbiError::UnsupportedType(param.canonical()));
+            } else {
+                topics.push(H256::from_slice(&encode_static(i, param, token)?));
+            }
+        }
+        let (_, data_params) = self.split();
+        Ok((topics, encode(&data_params, &data_tokens)?))
+    }
+
+    // args in declaration order
+    pub fn decode_log(&self, topics: &[H256], data: &[u8]) -> Result<Vec<Token>, AbiError> {
+        let (indexed_params, data_params) = self.split();
+        if topics.first() != Some(&self.topic()) {
+            return Err(AbiError::InvalidData(format!("topic mismatch for {}", self.name)));
+        }
+        if topics.len() != indexed_params.len() + 1 {
+            return Err(AbiError::InvalidData(format!("{} topics for {}", topics.len(), self.name)));
+        }
+        if let Some(p) = indexed_params.iter().find(|p| p.is_dynamic()) {
+            return Err(AbiError::UnsupportedType(p.canonical()));
+        }
+        let topic_bytes: Vec<u8> = topics[1..].iter().flat_map(|t| t.as_bytes().to_vec()).collect();
+        let mut indexed_tokens = decode(&indexed_params, &topic_bytes)?.into_iter();
+        let mut data_tokens = decode(&data_params, data)?.into_iter();
+        let mut tokens = Vec::with_capacity(self.inputs.len());
+        for is_indexed in self.indexed.iter() {
+            let next = if *is_indexed { indexed_tokens.next() } else { data_tokens.next() };
+            // both lists were decoded from the same split of the inputs
+            tokens.push(next.expect("decoded token count matches the event inputs"));
+        }
+        Ok(tokens)
+    }
+}
+
+pub struct Abi {
+    functions: HashMap<String, Function>,
+    events: HashMap<String, Event>,
+}
+
+impl Abi {
+    pub fn from_json(bytes: &[u8]) -> Result<Abi, AbiError> {
+        let entries: Vec<JsonEntry> = serde_json::from_slice(bytes)
+            .map_err(|e| AbiError::Json(e.to_string()))?;
+        let mut functions = HashMap::new();
+        let mut events = HashMap::new();
+        for entry in entries.into_iter() {
+            let inputs = entry.inputs.iter()
+                .map(|p| ParamType::parse(&p.kind))
+                .collect::<Result<Vec<ParamType>, AbiError>>()?;
+            match entry.kind.as_str() {
+                "function" => {
+                    let outputs = entry.outputs.iter()
+                        .map(|p| ParamType::parse(&p.kind))
+                        .collect::<Result<Vec<ParamType>, AbiError>>()?;
+                    functions.insert(entry.name.clone(), Function {
+                        name: entry.name,
+                        inputs,
+                        outputs,
+                    });
+                },
+                "event" => {
+                    let indexed = entry.inputs.iter().map(|p| p.indexed).collect();
+                    events.insert(entry.name.clone(), Event {
+                        name: entry.name,
+                        inputs,
+                        indexed,
+                    });
+                },
+                _ => (),
+            }
+        }
+        Ok(Abi { functions, events })
+    }
+
+    pub fn function(&self, name: &str) -> Result<&Function, AbiError> {
+        self.functions.get(name).ok_or(AbiError::UnknownFunction(name.to_string()))
+    }
+
+    pub fn event(&self, name: &str) -> Result<&Event, AbiError> {
+        self.events.get(name).ok_or(AbiError::UnknownEvent(name.to_string()))
+    }
+}
+
+fn keccak(s: &str) -> [u8; 32] {
+    let mut hasher = Sha3::keccak256();
+    hasher.input_str(s);
+    let mut hash = [0u8; 32];
+    hasher.result(&mut hash);
+    hash
+}
+
+fn pad_right(bytes: &[u8]) -> Vec<u8> {
+    let mut padded = bytes.to_vec();
+    let rem = bytes.len() % WORD;
+    if rem != 0 {
+        padded.extend(vec![0u8; WORD - rem]);
+    }
+    padded
+}
+
+fn uint_word(value: U256) -> Vec<u8> {
+    let mut word = [0u8; WORD];
+    value.to_big_endian(&mut word);
+    word.to_vec()
+}
+
+fn encode_dynamic(bytes: &[u8]) -> Vec<u8> {
+    let mut out = uint_word(U256::from(bytes.len()));
+    out.extend(pad_right(bytes));
+    out
+}
+
+fn encode_static(index: usize, param: &ParamType, token: &Token) -> Result<Vec<u8>, AbiError> {
+    match (param, token) {
+        (ParamType::Address, Token::Address(a)) => {
+            let mut word = vec![0u8; WORD - 20];
+            word.extend_from_slice(a.as_bytes());
+            Ok(word)
+        },
+        (ParamType::Uint(n), Token::Uint(v)) => {
+            if *n < 256 && v.bits() > *n {
+                return Err(AbiError::TypeMismatch(index));
+            }
+            Ok(uint_word(*v))
+        },
+        (ParamType::Bool, Token::Bool(b)) => Ok(uint_word(U256::from(*b as u8))),
+        (ParamType::FixedBytes(n), Token::FixedBytes(b)) if b.len() == *n => Ok(pad_right(b)),
+        _ => Err(AbiError::TypeMismatch(index)),
+    }
+}
+
+// encode a parameter list (without selector) using the standard head/tail layout
+pub fn encode(params: &[ParamType], tokens: &[Token]) -> Result<Vec<u8>, AbiError> {
+    if params.len() != tokens.len() {
+        return Err(AbiError::ArgCount { expected: params.len(), got: tokens.len() });
+    }
+    let head_len = params.len() * WORD;
+    let mut head: Vec<u8> = Vec::with_capacity(head_len);
+    let mut tail: Vec<u8> = Vec::new();
+    for (i, (param, token)) in params.iter().zip(tokens.iter()).enumerate() {
+        let dynamic = match (param, token) {
+            (ParamType::Bytes, Token::Bytes(b)) => Some(encode_dynamic(b)),
+            (ParamType::String, Token::String(s)) => Some(encode_dynamic(s.as_bytes())),
+            (p, _) if p.is_dynamic() => return Err(AbiError::TypeMismatch(i)),
+            _ => None,
+        };
+        match dynamic {
+            Some(encoded) => {
+                head.extend(uint_word(U256::from(head_len + tail.len())));
+                tail.extend(encoded);
+            },
+            None => head.extend(encode_static(i, param, token)?),
+        }
+    }
+    head.extend(tail);
+    Ok(head)
+}
+
+fn read_word(data: &[u8], offset: usize) -> Result<&[u8], AbiError> {
+    match offset.checked_add(WORD) {
+        Some(end) if end <= data.len() => Ok(&data[offset..end]),
+        _ => Err(AbiError::InvalidData(format!("word at {} out of range", offset))),
+    }
+}
+
+// uints decoded from calldata may hold any 256 bit value
+pub fn uint_to_usize(value: &U256) -> Result<usize, AbiError> {
+    if value.bits() > 32 {
+        return Err(AbiError::InvalidData(format!("{} does not fit in usize", value)));
+    }
+    Ok(value.low_u32() as usize)
+}
+
+pub fn uint_to_u64(value: &U256) -> Result<u64, AbiError> {
+    if value.bits() > 64 {
+        return Err(AbiError::InvalidData(format!("{} does not fit in u64", value)));
+    }
+    Ok(value.low_u64())
+}
+
+fn read_usize(data: &[u8], offset: usize) -> Result<usize, AbiError> {
+    let word = read_word(data, offset)?;
+    uint_to_usize(&U256::from_big_endian(word))
+}
+
+fn read_dynamic(data: &[u8], offset: usize) -> Result<Vec<u8>, AbiError> {
+    let len = read_usize(data, offset)?;
+    let start = offset.checked_add(WORD);
+    match start.and_then(|s| s.checked_add(len)) {
+        Some(end) if end <= data.len() => Ok(data[end - len..end].to_vec()),
+        _ => Err(AbiError::InvalidData(format!("{} bytes at {} out of range", len, offset))),
+    }
+}
+
+pub fn decode(params: &[ParamType], data: &[u8]) -> Result<Vec<Token>, AbiError> {
+    let mut tokens = Vec::with_capacity(params.len());
+    for (i, param) in params.iter().enumerate() {
+        let word = read_word(data, i * WORD)?;
+        let token = match param {
+            ParamType::Address => {
+                if word[..WORD - 20].iter().any(|b| *b != 0) {
+                    return Err(AbiError::InvalidData(format!("dirty address at {}", i)));
+                }
+                Token::Address(Address::from_slice(&word[WORD - 20..]))
+            },
+            ParamType::Uint(_) => Token::Uint(U256::from_big_endian(word)),
+            ParamType::Bool => Token::Bool(word[WORD - 1] != 0),
+            ParamType::FixedBytes(n) => Token::FixedBytes(word[..*n].to_vec()),
+            ParamType::Bytes => Token::Bytes(read_dynamic(data, read_usize(data, i * WORD)?)?),
+            ParamType::String => {
+                let bytes = read_dynamic(data, read_usize(data, i * WORD)?)?;
+                match String::from_utf8(bytes) {
+                    Ok(s) => Token::String(s),
+                    Err(_) => return Err(AbiError::InvalidData(format!("non utf8 string at {}", i))),
+                }
+            },
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> Address {
+        Address::from_slice(&hex::decode("e5c10a1e39fa1faf25e4fd5ce2c4e2ec5a7ab926").unwrap())
+    }
+
+    fn round_trip(name: &str, args: Vec<Token>, expected: &str) {
+        let function = SCALECHAIN_ABI.function(name).unwrap();
+        let data = function.encode_input(&args).unwrap();
+        assert_eq!(hex::encode(&data), expected);
+        assert_eq!(function.decode_input(&data).unwrap(), args);
+    }
+
+    #[test]
+    fn submit_vote() {
+        round_trip("submitVote", vec![
+            Token::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+            Token::Uint(U256::from(0)),
+            Token::Uint(U256::from(1)),
+            Token::Uint(U256::from(11)),
+            Token::Uint(U256::from(12)),
+            Token::Uint(U256::from(6)),
+        ], "17d7acda00000000000000000000000000000000000000000000000000000000000000c000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000b000000000000000000000000000000000000000000000000000000000000000c00000000000000000000000000000000000000000000000000000000000000060000000000000000000000000000000000000000000000000000000000000004deadbeef00000000000000000000000000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn add_scale_node() {
+        round_trip("addScaleNode", vec![
+            Token::Address(addr()),
+            Token::String("127.0.0.1:40001".to_string()),
+            Token::Uint(U256::from(1)),
+            Token::Uint(U256::from(2)),
+            Token::Uint(U256::from(3)),
+            Token::Uint(U256::from(4)),
+        ], "96477f7f000000000000000000000000e5c10a1e39fa1faf25e4fd5ce2c4e2ec5a7ab92600000000000000000000000000000000000000000000000000000000000000c00000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000f3132372e302e302e313a34303030310000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn add_side_node() {
+        round_trip("addSideNode", vec![
+            Token::Uint(U256::from(0)),
+            Token::Address(addr()),
+            Token::String("127.0.0.1:40002".to_string()),
+        ], "328c0f140000000000000000000000000000000000000000000000000000000000000000000000000000000000000000e5c10a1e39fa1faf25e4fd5ce2c4e2ec5a7ab9260000000000000000000000000000000000000000000000000000000000000060000000000000000000000000000000000000000000000000000000000000000f3132372e302e302e313a34303030320000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn delete_side_node() {
+        round_trip("deleteSideNode", vec![
+            Token::Uint(U256::from(0)),
+            Token::Uint(U256::from(3)),
+        ], "d3884acf00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003");
+    }
+
+    #[test]
+    fn reset_side_chain() {
+        round_trip("resetSideChain", vec![Token::Uint(U256::from(2))],
+            "baba3dab0000000000000000000000000000000000000000000000000000000000000002");
+    }
+
+    #[test]
+    fn send_block_params() {
+        let params = [ParamType::String, ParamType::Bytes, ParamType::Uint(256)];
+        let data = hex::decode("000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000070000000000000000000000000000000000000000000000000000000000000004303066660000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000020102000000000000000000000000000000000000000000000000000000000000").unwrap();
+        let tokens = vec![
+            Token::String("00ff".to_string()),
+            Token::Bytes(vec![1, 2]),
+            Token::Uint(U256::from(7)),
+        ];
+        assert_eq!(decode(&params, &data).unwrap(), tokens);
+        assert_eq!(encode(&params, &tokens).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let function = SCALECHAIN_ABI.function("resetSideChain").unwrap();
+        assert_eq!(function.encode_input(&[]).unwrap_err(), AbiError::ArgCount { expected: 1, got: 0 });
+        assert_eq!(function.encode_input(&[Token::Bool(true)]).unwrap_err(), AbiError::TypeMismatch(0));
+        assert!(function.decode_input(&[0xba, 0xba, 0x3d, 0xab, 0]).is_err());
+        assert!(SCALECHAIN_ABI.function("sendBlock").is_err());
+    }
+
+    #[test]
+    fn vote_submitted_log() {
+        let event = SCALECHAIN_ABI.event("VoteSubmitted").unwrap();
+        assert_eq!(event.signature(), "VoteSubmitted(uint256,uint256,bytes)");
+        let args = vec![
+            Token::Uint(U256::from(2)),
+            Token::Uint(U256::from(7)),
+            Token::Bytes(vec![0xde, 0xad]),
+        ];
+        let (topics, data) = event.encode_log(&args).unwrap();
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics[1], H256::from_low_u64_be(2));
+        assert_eq!(hex::encode(&data), "000000000000000000000000000000000000000000000000000000000000000700000000000000000000000000000000000000000000000000000000000000400000000000000000000000000000000000000000000000000000000000000002dead000000000000000000000000000000000000000000000000000000000000");
+        assert_eq!(event.decode_log(&topics, &data).unwrap(), args);
+
+        let reset = SCALECHAIN_ABI.event("SideChainReset").unwrap();
+        assert!(reset.decode_log(&topics, &data).is_err());
+        assert!(event.decode_log(&topics[..1], &data).is_err());
+        assert!(SCALECHAIN_ABI.event("submitVote").is_err());
+    }
+}
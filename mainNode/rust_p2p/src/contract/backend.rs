@@ -0,0 +1,452 @@
+use super::primitive::block::{EthBlkTransaction, ContractState};
+use super::interface::Error;
+use super::contract::Account;
+use super::history::ChainHistory;
+use super::gas::{self, Fee, GWEI};
+use super::utils::*;
+
+use web3::contract::Contract as EthContract;
+use web3::contract::Options as EthOption;
+use web3::types::{Address, Bytes, H256, U256, TransactionReceipt, CallRequest, H160, BlockId, BlockNumber, FilterBuilder};
+use web3::futures::Future;
+use ethereum_tx_sign::RawTransaction;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+
+const ETH_CHAIN_ID: u32 = 42;
+// ethereum blocks covered by one eth_getLogs request, the sync thread applies one page per round
+const SYNC_PAGE_SIZE: u64 = 1000;
+// pause between sync rounds once the head is reached or after a failed round
+const SYNC_INTERVAL_MS: u64 = 2000;
+
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub tx_hash: H256,
+    pub gas_used: Option<U256>,
+    pub success: bool,
+}
+
+impl From<TransactionReceipt> for Receipt {
+    fn from(r: TransactionReceipt) -> Self {
+        Receipt {
+            tx_hash: r.transaction_hash,
+            gas_used: r.gas_used,
+            // pre byzantium receipts carry no status
+            success: r.status.map(|s| s.as_u64() == 1).unwrap_or(true),
+        }
+    }
+}
+
+// A write to the contract. It is kept by the caller so that a transaction that does not get
+// mined can be sent again under the same nonce.
+#[derive(Debug, Clone)]
+pub enum Call {
+    AddScaleNode(Address, String, U256, U256, U256, U256),
+    AddSideNode(U256, Address, String),
+    DeleteSideNode(U256, U256),
+    // hex encoded header, sid, bid, sigx, sigy, bitset
+    SubmitVote(String, U256, U256, U256, U256, U256),
+    ResetChain(usize),
+    AddSideChain,
+    // empty call under the nonce of a dropped transaction, it reverts but lets later ones be mined
+    FillNonce,
+}
+
+// nonce and fee of a transaction, chosen by the caller
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxParams {
+    pub nonce: U256,
+    pub fee: Fee,
+}
+
+// Everything the node needs from the ScaleChain contract. Writes return the hash of the
+// submitted transaction, its outcome is observed through transaction_receipt.
+pub trait ContractBackend: Send {
+    fn get_curr_state(&self, sid: usize) -> Result<ContractState, Error>;
+    fn count_scale_nodes(&self) -> Result<usize, Error>;
+    fn get_scale_node(&self, index: usize) -> Result<Address, Error>;
+    fn get_scale_id(&self, addr: Address) -> Result<U256, Error>;
+    fn get_scale_pub_key(&self, addr: Address) -> Result<(U256, U256, U256, U256), Error>;
+    fn count_side_chains(&self) -> Result<usize, Error>;
+    fn get_all(&self, sid: usize, start: usize, end: usize) -> Result<Vec<EthBlkTransaction>, Error>;
+
+    // nonce of the next transaction from this account, counting the pending ones
+    fn transaction_count(&self) -> Result<U256, Error>;
+    fn gas_price(&self) -> Result<U256, Error>;
+    fn block_number(&self) -> Result<u64, Error>;
+    fn send(&self, call: &Call, tx: &TxParams) -> Result<H256, Error>;
+    fn transaction_receipt(&self, tx_hash: H256) -> Result<Option<Receipt>, Error>;
+}
+
+pub struct Web3Backend {
+    contract: EthContract<web3::transports::Http>,
+    web3: web3::api::Web3<web3::transports::Http>,
+    my_account: Account,
+    contract_address: Address,
+    // None when no deployment block is known and history cannot be rebuilt
+    sync: Option<Arc<Mutex<HistorySync>>>,
+}
+
+// Side chain history replayed so far. next_block is the first ethereum block not yet fully
+// applied and last_log the (block, index) of the last log applied from it, so a round that
+// fails part way resumes after the last good log.
+struct HistorySync {
+    next_block: u64,
+    last_log: Option<(u64, u64)>,
+    history: ChainHistory,
+    // the replayed history disagrees with the contract, reported by get_all until resolved
+    inconsistent: Option<String>,
+    // the ethereum head was reached and the tips checked at least once
+    caught_up: bool,
+    // next_block and history as of the last time the tips matched the contract
+    checkpoint: Option<(u64, ChainHistory)>,
+}
+
+// Rebuilds history from contract logs on its own thread, readers only take the lock to copy
+// what they need.
+struct HistorySyncer {
+    contract: EthContract<web3::transports::Http>,
+    web3: web3::api::Web3<web3::transports::Http>,
+    contract_address: Address,
+    // block the contract was deployed in, replay starts over from it when nothing else is left
+    start: u64,
+    sync: Arc<Mutex<HistorySync>>,
+}
+
+fn rpc_err(e: web3::error::Error) -> Error {
+    Error::Rpc(format!("{:?}", e))
+}
+
+fn query_err(e: web3::contract::Error) -> Error {
+    Error::Rpc(format!("{:?}", e))
+}
+
+fn abi_err(e: super::abi::AbiError) -> Error {
+    Error::Encoding(e.to_string())
+}
+
+fn get_blk_id(contract: &EthContract<web3::transports::Http>, sid: usize, block: Option<BlockId>) -> Result<U256, Error> {
+    contract
+        .query("getBlockID", (U256::from(sid),), None, EthOption::default(), block)
+        .wait()
+        .map_err(query_err)
+}
+
+fn get_curr_hash(contract: &EthContract<web3::transports::Http>, sid: usize, block: Option<BlockId>) -> Result<H256, Error> {
+    contract
+        .query("getCurrentHash", (U256::from(sid),), None, EthOption::default(), block)
+        .wait()
+        .map_err(query_err)
+}
+
+fn get_state_at(contract: &EthContract<web3::transports::Http>, sid: usize, block: Option<BlockId>) -> Result<ContractState, Error> {
+    let hash = get_curr_hash(contract, sid, block)?;
+    let blk_id = get_blk_id(contract, sid, block)?;
+    Ok(ContractState {
+        sid: sid as u64,
+        curr_hash: hash.into(),
+        block_id: blk_id.as_u64(),
+    })
+}
+
+fn count_side_chains_at(contract: &EthContract<web3::transports::Http>, block: Option<BlockId>) -> Result<usize, Error> {
+    let cnt: U256 = contract
+        .query("sideChainsCount", (), None, EthOption::default(), block)
+        .wait()
+        .map_err(query_err)?;
+    Ok(cnt.as_usize())
+}
+
+impl HistorySyncer {
+    fn start(self) {
+        thread::Builder::new()
+            .name("history_sync".to_string())
+            .spawn(move || loop {
+                match self.sync_page() {
+                    Ok(false) => (),
+                    Ok(true) => thread::sleep(Duration::from_millis(SYNC_INTERVAL_MS)),
+                    Err(e) => {
+                        warn!("contract history sync failed, retrying: {:?}", e);
+                        thread::sleep(Duration::from_millis(SYNC_INTERVAL_MS));
+                    },
+                }
+            })
+            .unwrap();
+    }
+
+    // apply the logs of at most SYNC_PAGE_SIZE ethereum blocks, true once the head is reached
+    fn sync_page(&self) -> Result<bool, Error> {
+        let latest = self.web3.eth().block_number().wait().map(|n| n.as_u64()).map_err(rpc_err)?;
+        let (from, last_log) = {
+            let sync = self.sync.lock().unwrap();
+            (sync.next_block, sync.last_log)
+        };
+        if from > latest {
+            return Ok(true);
+        }
+        let to = std::cmp::min(from + SYNC_PAGE_SIZE - 1, latest);
+        let filter = FilterBuilder::default()
+            .address(vec![self.contract_address])
+            .from_block(BlockNumber::Number(from.into()))
+            .to_block(BlockNumber::Number(to.into()))
+            .build();
+        let logs = self.web3.eth().logs(filter).wait().map_err(rpc_err)?;
+
+        let mut applied = 0;
+        {
+            let mut sync = self.sync.lock().unwrap();
+            for log in logs.iter() {
+                if log.removed == Some(true) {
+                    continue;
+                }
+                let position = match (log.block_number, log.log_index) {
+                    (Some(b), Some(i)) => (b.as_u64(), i.as_u64()),
+                    _ => return Err(Error::Rpc("mined log without position".to_string())),
+                };
+                if last_log.map_or(false, |l| position <= l) {
+                    continue;
+                }
+                if let Err(e) = sync.history.apply(&log.topics, &log.data.0) {
+                    // the same log would fail again, an earlier one was taken from a dropped fork
+                    self.resync(&mut sync, format!("log {:?} does not apply: {:?}", position, e));
+                    return Err(e);
+                }
+                sync.last_log = Some(position);
+                applied += 1;
+            }
+            sync.next_block = to + 1;
+        }
+        if applied > 0 {
+            info!("contract history synced to eth block {}", to);
+        }
+        if to < latest {
+            return Ok(false);
+        }
+        self.check_tips(to, applied > 0)?;
+        Ok(true)
+    }
+
+    // drop what was replayed since the tips last matched the contract, or all of it when that
+    // point itself turned out wrong, and replay from there
+    fn resync(&self, sync: &mut HistorySync, reason: String) {
+        let (next_block, history) = sync.checkpoint.take().unwrap_or_else(|| (self.start, ChainHistory::new()));
+        warn!("contract history {}, replaying from eth block {}", reason, next_block);
+        sync.next_block = next_block;
+        sync.last_log = None;
+        sync.history = history;
+        sync.inconsistent = Some(reason);
+        sync.caught_up = false;
+    }
+
+    // compare the replayed tips with the contract state at ethereum block at, changed when logs
+    // were applied since the last check
+    fn check_tips(&self, at: u64, changed: bool) -> Result<(), Error> {
+        let block = Some(BlockId::Number(BlockNumber::Number(at.into())));
+        let count = count_side_chains_at(&self.contract, block)?;
+        let mut states = Vec::with_capacity(count);
+        for sid in 0..count {
+            states.push(get_state_at(&self.contract, sid, block)?);
+        }
+        let mut sync = self.sync.lock().unwrap();
+        let mismatch = states.into_iter()
+            .find(|state| sync.history.tip(state.sid as usize) != *state)
+            .map(|state| format!("replayed state {:?} differs from contract state {:?}",
+                sync.history.tip(state.sid as usize), state));
+        if let Some(reason) = mismatch {
+            self.resync(&mut sync, reason);
+            return Ok(());
+        }
+        sync.checkpoint = match sync.checkpoint.take() {
+            Some((_, history)) if !changed => Some((at + 1, history)),
+            _ => Some((at + 1, sync.history.clone())),
+        };
+        sync.inconsistent = None;
+        sync.caught_up = true;
+        Ok(())
+    }
+}
+
+impl Web3Backend {
+    // start_block is the block the contract was deployed in, or an earlier one. History sync
+    // starts there on a thread of its own, without it get_all is unsupported
+    pub fn new(account: Account, abi: &[u8], rpc_url: &str, contract_address: &Address, start_block: Option<u64>) -> Web3Backend {
+        let (eloop, http) = web3::transports::Http::new(rpc_url).unwrap();
+        eloop.into_remote();
+        let web3 = web3::api::Web3::new(http);
+        let contract = EthContract::from_json(
+            web3.eth(),
+            contract_address.clone(),
+            abi
+            ).unwrap();
+        let sync = start_block.map(|start| {
+            let sync = Arc::new(Mutex::new(HistorySync {
+                next_block: start,
+                last_log: None,
+                history: ChainHistory::new(),
+                inconsistent: None,
+                caught_up: false,
+                checkpoint: None,
+            }));
+            HistorySyncer {
+                contract: EthContract::from_json(web3.eth(), contract_address.clone(), abi).unwrap(),
+                web3: web3.clone(),
+                contract_address: contract_address.clone(),
+                start,
+                sync: sync.clone(),
+            }.start();
+            sync
+        });
+        Web3Backend {
+            contract,
+            web3,
+            my_account: account,
+            contract_address: contract_address.clone(),
+            sync,
+        }
+    }
+
+    fn _estimate_gas(&self, data: Vec<u8>) -> Result<U256, Error> {
+        let call_request = CallRequest {
+            from: Some(H160::from(self.my_account.address.0)),
+            to: H160::from(self.contract_address.0),
+            gas_price: Some(U256::from(GWEI)),
+            gas: Some(U256::zero()),
+            data: Some(Bytes::from(data)),
+            value: Some(U256::zero())
+        };
+
+        self.web3.eth()
+            .estimate_gas(call_request, None)
+            .wait()
+            .map_err(rpc_err)
+    }
+
+    // sign with the node account and send, gas is estimated when not given
+    fn _send_call(&self, data: Vec<u8>, gas: Option<U256>, params: &TxParams) -> Result<H256, Error> {
+        let gas = match gas {
+            Some(g) => g,
+            None => self._estimate_gas(data.clone())?,
+        };
+        let signed_tx = match params.fee {
+            Fee::Legacy(gas_price) => {
+                let tx = RawTransaction {
+                    nonce: _convert_u256(params.nonce),
+                    to: Some(ethereum_types::H160::from(self.contract_address.0)),
+                    value: ethereum_types::U256::zero(),
+                    gas_price: _convert_u256(gas_price),
+                    gas: _convert_u256(gas),
+                    data,
+                };
+                let key = _get_key_as_H256(self.my_account.private_key.clone());
+                tx.sign(&key, &ETH_CHAIN_ID)
+            },
+            Fee::Eip1559 { max_fee, priority_fee } => {
+                let key = _get_key_as_vec(self.my_account.private_key.clone());
+                _sign_eip1559_tx(ETH_CHAIN_ID as u64, params.nonce, priority_fee, max_fee, gas, self.contract_address, &data, &key)
+            },
+        };
+        self.web3.eth()
+            .send_raw_transaction(Bytes::from(signed_tx))
+            .wait()
+            .map_err(rpc_err)
+    }
+}
+
+impl ContractBackend for Web3Backend {
+    fn get_curr_state(&self, sid: usize) -> Result<ContractState, Error> {
+        get_state_at(&self.contract, sid, None)
+    }
+
+    fn count_scale_nodes(&self) -> Result<usize, Error> {
+        let cnt: U256 = self.contract
+            .query("scaleNodesCount", (), None, EthOption::default(), None)
+            .wait()
+            .map_err(query_err)?;
+        Ok(cnt.as_usize())
+    }
+
+    fn get_scale_node(&self, index: usize) -> Result<Address, Error> {
+        self.contract
+            .query("getScaleNode", (U256::from(index), ), None, EthOption::default(), None)
+            .wait()
+            .map_err(query_err)
+    }
+
+    fn get_scale_id(&self, addr: Address) -> Result<U256, Error> {
+        self.contract
+            .query("getScaleID", (addr), None, EthOption::default(), None)
+            .wait()
+            .map_err(query_err)
+    }
+
+    fn get_scale_pub_key(&self, addr: Address) -> Result<(U256, U256, U256, U256), Error> {
+        self.contract
+            .query("getScalePubKey", (addr), None, EthOption::default(), None)
+            .wait()
+            .map_err(query_err)
+    }
+
+    fn count_side_chains(&self) -> Result<usize, Error> {
+        count_side_chains_at(&self.contract, None)
+    }
+
+    // [start, end) of side chain sid, end 0 means up to the tip. History comes from the sync
+    // thread and only reaches as far as it has replayed the contract logs
+    fn get_all(&self, sid: usize, start: usize, end: usize) -> Result<Vec<EthBlkTransaction>, Error> {
+        let sync = match self.sync {
+            Some(ref s) => s.lock().unwrap(),
+            None => return Err(Error::Unsupported("getAll without the contract deployment block".to_string())),
+        };
+        if let Some(ref reason) = sync.inconsistent {
+            return Err(Error::Inconsistent(reason.clone()));
+        }
+        if !sync.caught_up {
+            return Err(Error::Syncing(sync.next_block.saturating_sub(1)));
+        }
+        Ok(sync.history.get(sid, start, end))
+    }
+
+    fn transaction_count(&self) -> Result<U256, Error> {
+        self.web3.eth()
+            .transaction_count(self.my_account.address, Some(BlockNumber::Pending))
+            .wait()
+            .map_err(rpc_err)
+    }
+
+    fn gas_price(&self) -> Result<U256, Error> {
+        self.web3.eth().gas_price().wait().map_err(rpc_err)
+    }
+
+    fn block_number(&self) -> Result<u64, Error> {
+        self.web3.eth().block_number().wait().map(|n| n.as_u64()).map_err(rpc_err)
+    }
+
+    fn send(&self, call: &Call, tx: &TxParams) -> Result<H256, Error> {
+        let data = match call {
+            Call::AddScaleNode(address, ip_addr, x1, x2, y1, y2) => {
+                _encode_addScaleNode(*address, ip_addr.clone(), *x1, *x2, *y1, *y2).map_err(abi_err)?
+            },
+            Call::AddSideNode(sid, address, ip_addr) => {
+                _encode_addSideNode(*sid, *address, ip_addr.clone()).map_err(abi_err)?
+            },
+            Call::DeleteSideNode(sid, tid) => _encode_deleteSideNode(*sid, *tid).map_err(abi_err)?,
+            Call::SubmitVote(header, sid, bid, sigx, sigy, bitset) => {
+                _encode_submitVote(header.clone(), *sid, *bid, *sigx, *sigy, *bitset).map_err(abi_err)?
+            },
+            Call::ResetChain(sid) => _encode_resetSideChain(U256::from(*sid)).map_err(abi_err)?,
+            Call::AddSideChain => _encode_addSideChain().map_err(abi_err)?,
+            Call::FillNonce => vec![],
+        };
+        self._send_call(data, gas::gas_limit(call), tx)
+    }
+
+    fn transaction_receipt(&self, tx_hash: H256) -> Result<Option<Receipt>, Error> {
+        self.web3.eth()
+            .transaction_receipt(tx_hash)
+            .wait()
+            .map(|r| r.map(Receipt::from))
+            .map_err(rpc_err)
+    }
+}
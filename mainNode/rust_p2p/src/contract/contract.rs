@@ -1,53 +1,61 @@
-use super::primitive::block::{EthBlkTransaction, ContractState, Block};
-use super::primitive::hash::{H256};
+use super::primitive::block::{EthBlkTransaction, ContractState};
 use super::network::message::{ServerSignal, TaskRequest};
-use super::network::message::Message as ServerMessage;
-use super::mempool::mempool::{Mempool};
-use super::blockchain::blockchain::{BlockChain};
-use super::db::blockDb::{BlockDb};
 use super::interface::{Handle, Message, Response, Answer};
+use super::backend::{ContractBackend, Web3Backend, Call, TxParams, Receipt};
+use super::gas::{GasPolicy, Fee, GWEI};
 use super::utils::*;
+use crate::crypto::keystore;
 
-use web3::contract::Contract as EthContract;
-use web3::contract::Options as EthOption;
-use web3::types::{Address, Bytes, U256, TransactionReceipt, CallRequest, H160};
-use web3::futures::Future;
+use web3::types::{Address, H256, U256};
 
-use crypto::digest::Digest;
-use crypto::sha2::Sha256;
+use std::{thread, time};
+use std::collections::{VecDeque, HashMap};
+use std::fs::{self, File};
 
-use std::sync::{Arc, Mutex};
-use std::{time};
-use std::fs::{self, File, OpenOptions};
-use std::io::{Write, BufReader, BufRead, Error};
-
-use crossbeam::channel::{self, Sender, Receiver};
+use crossbeam::channel::{self, Sender, Receiver, RecvTimeoutError, TryRecvError};
 use mio_extras::channel as Mio_channel;
 use serde::{Serialize, Deserialize};
-use ethereum_tx_sign::RawTransaction;
 
 use mio_extras::channel::Sender as MioSender;
 use crate::experiment::snapshot::PERFORMANCE_COUNTER;
 
-//use requests::{ToJson};
 use log::{info, warn, error};
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
-
-const ETH_CHAIN_ID: u32 = 42;
-
+use std::time::{Instant};
+
+// how often the contract thread checks receipts of pending transactions
+const POLL_INTERVAL_MS: u64 = 200;
+
+// A transaction that was sent but has no receipt yet. Every resubmission reuses the nonce,
+// so whichever of tx_hashes gets mined settles it.
+struct PendingTx {
+    call: Call,
+    tx: TxParams,
+    // every version sent with the fee it offered
+    tx_hashes: Vec<(H256, Fee)>,
+    submitted: Instant,
+    // ethereum block number when the latest version was sent
+    sent_block: u64,
+    resubmits: usize,
+    answer_channel: Option<Sender<Answer>>,
+}
 
 pub struct Contract {
-    contract: EthContract<web3::transports::Http>,
+    backend: Box<dyn ContractBackend>,
     my_account: Account,
     key: BLSKey,
     contract_state: ContractState,
     contract_handle: Receiver<Handle>,
     performer_sender: Sender<TaskRequest>,
     server_control_sender: MioSender<ServerSignal>,
-    web3: web3::api::Web3<web3::transports::Http>,
     ip_addr: String,
-    rpc_url: String,
-    contract_address: Address,
+    // next nonce to use, None when it has to be fetched from the chain again
+    next_nonce: Option<U256>,
+    pending: VecDeque<PendingTx>,
+    gas_policy: GasPolicy,
+    // last state pushed and the subscribers of each watched side chain
+    watchers: HashMap<usize, (Option<ContractState>, Vec<Sender<ContractState>>)>,
+    // ethereum block the watched states were read at
+    watched_block: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -64,35 +72,54 @@ impl Contract {
         key: BLSKey,
         performer_sender: Sender<TaskRequest>,
         server_control_sender: MioSender<ServerSignal>,
-        contract_handle: Receiver<Handle>, 
+        contract_handle: Receiver<Handle>,
         ip_addr: String,
         abi_path: String,
         rpc_url: &str,
         contract_address: &Address,
+        start_block: Option<u64>,
+        gas_policy: GasPolicy,
     ) -> Contract {
-        let (eloop, http) = web3::transports::Http::new(rpc_url).unwrap();
-        eloop.into_remote();
-        let web3 = web3::api::Web3::new(http);
         let json_bytes = fs::read(&abi_path).expect("Unable to read abi file");
-        let contract = EthContract::from_json(
-            web3.eth(), 
-            contract_address.clone(), 
-            &json_bytes.clone()
-            ).unwrap();
-        let contract = Contract{
-            contract,
+        let backend = Web3Backend::new(account.clone(), &json_bytes, rpc_url, contract_address, start_block);
+        Contract::with_backend(
+            Box::new(backend),
+            account,
             key,
             performer_sender,
             server_control_sender,
-            my_account: account, 
-            contract_state: ContractState::genesis(),
             contract_handle,
-            web3,
             ip_addr,
-            rpc_url: rpc_url.to_string(),
-            contract_address: contract_address.clone(),
-        };
-        return contract;
+            gas_policy,
+        )
+    }
+
+    // used by tests and offline clusters to run against e.g. a mock contract
+    pub fn with_backend(
+        backend: Box<dyn ContractBackend>,
+        account: Account,
+        key: BLSKey,
+        performer_sender: Sender<TaskRequest>,
+        server_control_sender: MioSender<ServerSignal>,
+        contract_handle: Receiver<Handle>,
+        ip_addr: String,
+        gas_policy: GasPolicy,
+    ) -> Contract {
+        Contract{
+            backend,
+            key,
+            performer_sender,
+            server_control_sender,
+            my_account: account,
+            contract_state: ContractState::genesis(0),
+            contract_handle,
+            ip_addr,
+            next_nonce: None,
+            pending: VecDeque::new(),
+            gas_policy,
+            watchers: HashMap::new(),
+            watched_block: None,
+        }
     }
 
     pub fn instance(
@@ -100,125 +127,157 @@ impl Contract {
         rpc_url: &str,
         contract_address: &Address,
     ) -> Contract {
-        let (eloop, http) = web3::transports::Http::new(rpc_url).unwrap();
-        eloop.into_remote();
-        let web3 = web3::api::Web3::new(http);
-        let json_bytes = fs::read("./scripts/abi.json").expect("Unable to read abi file");
-        let contract = EthContract::from_json(
-            web3.eth(), 
-            contract_address.clone(), 
-            &json_bytes.clone()
-            ).unwrap();
+        let backend = Web3Backend::new(account.clone(), include_bytes!("abi.json"), rpc_url, contract_address, None);
         let (performer_sender, performer_receiver) = channel::unbounded();
         let (server_control_sender, server_control_receiver) = Mio_channel::channel();
         let (contract_handle_sender, contract_handle_receiver) = channel::unbounded();
         let ip_addr = "127.0.0.1".to_owned();
+        // one-shot commands never sign votes, the public key is enough
         let key_path = "./keyfile/node0";
-        let key_file = File::open(key_path).unwrap();
-        let key_str: BLSKeyStr = match serde_json::from_reader(key_file) {
+        let key: BLSKey = match keystore::read_bls_public_key(key_path) {
             Ok(k) => k,
             Err(e) => {
-                panic!("unable to deser keyfile");
+                panic!("unable to read keyfile {}", e);
             }
         };
-        let key: BLSKey = BLSKey::new(key_str);
-        let contract = Contract{
-            contract,
+        Contract::with_backend(
+            Box::new(backend),
+            account.clone(),
             key,
             performer_sender,
             server_control_sender,
-            my_account: account.clone(), 
-            contract_state: ContractState::genesis(),
-            contract_handle: contract_handle_receiver,
-            web3,
+            contract_handle_receiver,
             ip_addr,
-            rpc_url: rpc_url.to_owned(),
-            contract_address: contract_address.clone(),
-        };
-        return contract;
+            GasPolicy::default(),
+        )
     }
 
     pub fn start(mut self) {
         let _ = std::thread::spawn(move || {
+            let interval = time::Duration::from_millis(POLL_INTERVAL_MS);
             loop {
-                match self.contract_handle.recv() {
-                    //let _ = std::thread::spawn(move || {
-                        Ok(handle) => {
-                            match handle.message {
-                                Message::SendBlock(block) => {
-                                    self.send_block(block);
-                                },
-                                Message::SubmitVote(header, sid, bid, sigx, sigy, bitset) => {
-                                    self.submit_vote(header, sid, bid, sigx, sigy, bitset);
-                                   // let header = _generate_random_header();
-                                   // let (sigx, sigy) = _sign_bls(header.clone(), "node1".to_string());
-                                   // let (sigx2, sigy2) = _sign_bls(header.clone(), "node2".to_string());
-                                  //  let (sigx3, sigy3) = _sign_bls(header.clone(), "node3".to_string());
-                                  //  let (sigx, sigy) = _aggregate_sig(sigx, sigy, sigx2, sigy2);
-                                 //   let (sigx, sigy) = _aggregate_sig(sigx, sigy, sigx3, sigy3);
-                                  //  self.submit_vote(header, U256::from_dec_str(sigx.as_ref()).unwrap(), U256::from_dec_str(sigy.as_ref()).unwrap(), U256::from(26))
-                                },
-                                Message::AddScaleNode(id, ip) => {
-                                    let file = File::open(format!("accounts/account{}", id)).unwrap();
-                                    let key_file = File::open(format!("keyfile/node{}", id)).unwrap();
-                                    let account: Account = serde_json::from_reader(file).expect("deser account");
-                                    let key_str: BLSKeyStr = serde_json::from_reader(key_file).expect("deser key file");
-                                    let key = BLSKey::new(key_str);
-                                    self.add_scale_node(account.address, ip, key.pkx1, key.pkx2, key.pky1, key.pky2);
-                                },
-                                Message::CountScaleNodes => {
-                                    self.count_scale_nodes(handle);
-                                },
-                                Message::GetCurrState(sid) => {
-                                    self.get_curr_state(handle, sid);
-                                },
-                                Message::GetScaleNodes => {
-                                    self.get_scale_nodes(handle);
-                                },
-                                Message::GetTxReceipt(tx_hash) => {
-                                    self.get_tx_receipt(tx_hash);
-                                },
-                                Message::GetAll((_init_hash, start, end)) => {
-                                    self.get_all(handle, start, end);
-                                },
-                                Message::SyncChain => {
-                                    self.sync_etherchain(handle);
-                                },
-                                Message::EstimateGas(block) => {
-                                    self.estimate_gas(block);
-                                }
-                                Message::ResetChain(sid) => {
-                                    self.reset_chain(sid);
-                                },
-                                Message::AddSideNode(sid) => {
-                                    //self.add_side_node(sid);
-                                },
-                                //...
-                                _ => {
-                                    warn!("Unrecognized Message");
-                                }
-                            };
-                        },
-                        Err(e) => {
-                            panic!("contract query channel");
-                        }, 
-                    //});
+                match self.contract_handle.recv_timeout(interval) {
+                    Ok(handle) => {
+                        match handle.message {
+                            Message::SubmitVote(header, sid, bid, sigx, sigy, bitset) => {
+                                self.submit(Call::SubmitVote(header, sid, bid, sigx, sigy, bitset), handle.answer_channel);
+                            },
+                            Message::AddScaleNode(id, ip) => {
+                                self.add_scale_node_by_id(&id, ip, handle.answer_channel);
+                            },
+                            Message::CountScaleNodes => {
+                                self.count_scale_nodes(handle);
+                            },
+                            Message::GetCurrState(sid) => {
+                                self.get_curr_state(handle, sid);
+                            },
+                            Message::GetScaleNodes => {
+                                self.get_scale_nodes(handle);
+                            },
+                            Message::GetTxReceipt(tx_hash) => {
+                                self.get_receipt(handle, tx_hash);
+                            },
+                            Message::GetAll((sid, start, end)) => {
+                                self.get_all(handle, sid, start, end);
+                            },
+                            Message::SyncChain(sid) => {
+                                self.sync_etherchain(handle, sid);
+                            },
+                            Message::ResetChain(sid) => {
+                                self.submit(Call::ResetChain(sid), handle.answer_channel);
+                            },
+                            Message::GetScalePubKey(scale_id) => {
+                                self.get_scale_pub_key(handle, scale_id);
+                            },
+                            Message::AddSideNode(sid) => {
+                                //self.add_side_node(sid);
+                            },
+                            Message::AddSideChain => {
+                                self.submit(Call::AddSideChain, handle.answer_channel);
+                            },
+                            Message::CountSideChains => {
+                                self.count_side_chains(handle);
+                            },
+                            Message::WatchState(sid, state_sink) => {
+                                self.watch_state(sid, state_sink);
+                            },
+                            //...
+                            _ => {
+                                warn!("Unrecognized Message");
+                            }
+                        };
+                    },
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        panic!("contract query channel");
+                    },
                 }
+                self.poll_pending();
+                self.poll_watched();
             }
         });
     }
 
+    pub fn watch_state(&mut self, sid: usize, state_sink: Sender<ContractState>) {
+        let state = match self.backend.get_curr_state(sid) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!("unable to get state of side chain {} {:?}", sid, e);
+                None
+            }
+        };
+        if let Some(s) = state {
+            state_sink.send(s);
+        }
+        let watched = self.watchers.entry(sid).or_insert((None, vec![]));
+        if watched.0.is_none() {
+            watched.0 = state;
+        }
+        watched.1.push(state_sink);
+    }
+
+    // push the watched states that changed since the last ethereum block
+    pub fn poll_watched(&mut self) {
+        if self.watchers.is_empty() {
+            return;
+        }
+        let curr_block = match self.backend.block_number() {
+            Ok(b) => b,
+            Err(_) => return,
+        };
+        if self.watched_block == Some(curr_block) {
+            return;
+        }
+        self.watched_block = Some(curr_block);
+        for (sid, (last, sinks)) in self.watchers.iter_mut() {
+            let state = match self.backend.get_curr_state(*sid) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("unable to get state of side chain {} {:?}", sid, e);
+                    continue;
+                }
+            };
+            if *last == Some(state) {
+                continue;
+            }
+            *last = Some(state);
+            // subscribers that went away are dropped
+            sinks.retain(|sink| sink.send(state).is_ok());
+        }
+    }
+
     pub fn get_curr_state(&self, handle: Handle, sid: usize) {
-        let curr_state = self._get_curr_state(sid);
-        let response = Response::GetCurrState(curr_state);
-        let answer = Answer::Success(response);
+        let answer = match self.backend.get_curr_state(sid) {
+            Ok(curr_state) => Answer::Success(Response::GetCurrState(curr_state)),
+            Err(e) => Answer::Fail(format!("{:?}", e)),
+        };
         handle.answer_channel.unwrap().send(answer);
     }
 
     pub fn get_prev_blocks(&self, start: usize, end: usize) -> Vec<EthBlkTransaction> {
         unimplemented!()
     }
-     
+
     pub fn get_scale_nodes(&self, handle: Handle) {
         let n = self._count_scale_nodes();
         let mut nodes = Vec::new();
@@ -236,6 +295,21 @@ impl Contract {
 
     }
 
+    // public key registered for the scale node at index scale_id, as used by submitVote
+    pub fn get_scale_pub_key(&self, handle: Handle, scale_id: usize) {
+        let answer = if scale_id < self._count_scale_nodes() {
+            let address = self._get_scale_node(scale_id);
+            let (x1, x2, y1, y2) = self._get_scale_pub_key(address);
+            Answer::Success(Response::ScalePubKey(x1, x2, y1, y2))
+        } else {
+            Answer::Fail(format!("scale node {} not registered", scale_id))
+        };
+        match handle.answer_channel.as_ref() {
+            Some(ch) => (*ch).send(answer).unwrap(),
+            None => panic!("contract get scale pub key without answer channel"),
+        }
+    }
+
     pub fn get_address(&self) -> Address {
         self.my_account.address.clone()
     }
@@ -251,245 +325,236 @@ impl Contract {
         }
     }
 
-    pub fn add_scale_node(&self, address: Address, ip_addr: String, x1: U256, x2: U256, y1: U256, y2: U256) {
-        let nonce = self._transaction_count();
-        let function_abi = _encode_addScaleNode(address, ip_addr.clone() , x1, x2, y1, y2);
-        let gas = self._estimate_gas(function_abi.clone());
-
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000),
-            gas: _convert_u256(gas),
-            data: function_abi
+    pub fn count_side_chains(&self, handle: Handle) {
+        let answer = match self.backend.count_side_chains() {
+            Ok(num_chains) => Answer::Success(Response::CountSideChains(num_chains)),
+            Err(e) => Answer::Fail(format!("{:?}", e)),
         };
-
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
-        match self._send_transaction(signed_tx) {
-            Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    println!("tx_hash = {:?}", tx_hash);
-                }
-            },
-            Err(e) => {
-                info!("{:?} Error send_transaction {:?}", ip_addr, e);
-            }
+        if let Some(ch) = handle.answer_channel {
+            ch.send(answer);
         }
-        
     }
 
-    pub fn add_side_node(&self, sid: U256, address: Address, ip_addr: String) {
-        let nonce = self._transaction_count();
-        let function_abi = _encode_addSideNode(sid, address, ip_addr);
-        let gas = self._estimate_gas(function_abi.clone());
+    // the blocking writes below are for one-shot commands that do not run the contract thread
+    pub fn add_scale_node(&mut self, address: Address, ip_addr: String, x1: U256, x2: U256, y1: U256, y2: U256) -> bool {
+        self.submit_and_wait(Call::AddScaleNode(address, ip_addr, x1, x2, y1, y2))
+    }
 
-        println!("{:?}", gas);
+    pub fn add_side_node(&mut self, sid: U256, address: Address, ip_addr: String) -> bool {
+        self.submit_and_wait(Call::AddSideNode(sid, address, ip_addr))
+    }
 
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000),
-            gas: _convert_u256(gas),
-            data: function_abi
-        };
+    pub fn delete_side_node(&mut self, sid: U256, tid: U256) -> bool {
+        self.submit_and_wait(Call::DeleteSideNode(sid, tid))
+    }
 
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
+    pub fn reset_chain(&mut self, sid: usize) -> bool {
+        self.submit_and_wait(Call::ResetChain(sid))
+    }
 
-        match self._send_transaction(signed_tx) {
-            Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    println!("tx_hash = {:?}", tx_hash);
-                }
+    pub fn add_side_chain(&mut self) -> bool {
+        self.submit_and_wait(Call::AddSideChain)
+    }
+
+    fn take_nonce(&mut self) -> Result<U256, super::interface::Error> {
+        let nonce = match self.next_nonce {
+            Some(n) => n,
+            None => self.backend.transaction_count()?,
+        };
+        self.next_nonce = Some(nonce + 1);
+        Ok(nonce)
+    }
+
+    // register the scale node whose account and key files are numbered id, a missing or broken
+    // file is answered as a failure
+    fn add_scale_node_by_id(&mut self, id: &str, ip: String, answer_channel: Option<Sender<Answer>>) {
+        let keys = keystore::read_address(format!("accounts/account{}", id))
+            .and_then(|address| keystore::read_bls_public_key(format!("keyfile/node{}", id)).map(|key| (address, key)));
+        match keys {
+            Ok((address, key)) => {
+                let call = Call::AddScaleNode(address, ip, key.pkx1, key.pkx2, key.pky1, key.pky2);
+                self.submit(call, answer_channel);
             },
             Err(e) => {
-                info!("Error send_transaction {:?}", e);
-            }
+                warn!("unable to read the keys of scale node {}: {}", id, e);
+                if let Some(ch) = answer_channel {
+                    ch.send(Answer::Fail(format!("unable to read the keys of scale node {}: {}", id, e)));
+                }
+            },
         }
-        
     }
 
-    pub fn delete_side_node(&self, sid: U256, tid: U256) {
-        let nonce = self._transaction_count();
-        let function_abi = _encode_deleteSideNode(sid, tid);
-        //let gas = self._estimate_gas(function_abi.clone());
-
-        //println!("{:?}", gas);
+    // send call under the next local nonce, the outcome is answered once it is mined
+    pub fn submit(&mut self, call: Call, answer_channel: Option<Sender<Answer>>) {
+        let sent = self.gas_policy.fee(self.backend.as_ref()).and_then(|fee| {
+            let nonce = self.take_nonce()?;
+            let tx = TxParams { nonce, fee };
+            self.backend.send(&call, &tx).map(|tx_hash| (tx, tx_hash))
+        });
+        match sent {
+            Ok((tx, tx_hash)) => {
+                self.pending.push_back(PendingTx {
+                    call,
+                    tx,
+                    tx_hashes: vec![(tx_hash, tx.fee)],
+                    submitted: Instant::now(),
+                    sent_block: self.backend.block_number().unwrap_or(0),
+                    resubmits: 0,
+                    answer_channel,
+                });
+            },
+            Err(e) => {
+                info!("{:?} Error send_transaction {:?}", self.my_account.address, e);
+                // the nonce may or may not be used now, ask the chain next time
+                self.next_nonce = None;
+                if let Some(ch) = answer_channel {
+                    ch.send(Answer::Fail(format!("{:?}", e)));
+                }
+            },
+        }
+    }
 
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000),
-            gas: ethereum_types::U256::from(750000),
-            data: function_abi
-        };
+    pub fn submit_and_wait(&mut self, call: Call) -> bool {
+        let (answer_tx, answer_rx) = channel::bounded(1);
+        self.submit(call, Some(answer_tx));
+        loop {
+            match answer_rx.try_recv() {
+                Ok(Answer::Success(_)) => return true,
+                Ok(Answer::Fail(_)) | Err(TryRecvError::Disconnected) => return false,
+                Err(TryRecvError::Empty) => (),
+            }
+            thread::sleep(time::Duration::from_millis(POLL_INTERVAL_MS));
+            self.poll_pending();
+        }
+    }
 
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
-        match self._send_transaction(signed_tx) {
-            Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    println!("tx_hash = {:?}", tx_hash);
+    // settle mined transactions and replace the ones stuck for gas_policy.bump_blocks
+    pub fn poll_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let curr_block = self.backend.block_number().ok();
+        let mut still_pending = VecDeque::new();
+        while let Some(mut p) = self.pending.pop_front() {
+            let receipt = p.tx_hashes.iter()
+                .filter_map(|(h, _)| self.backend.transaction_receipt(*h).ok().and_then(|r| r))
+                .next();
+            if let Some(receipt) = receipt {
+                self.finish(p, receipt);
+            } else if curr_block.map_or(true, |b| b < p.sent_block + self.gas_policy.bump_blocks) {
+                still_pending.push_back(p);
+            } else if p.resubmits < self.gas_policy.max_bumps {
+                self.resubmit(&mut p);
+                still_pending.push_back(p);
+            } else {
+                warn!("Transaction with nonce {} dropped after {} resubmissions", p.tx.nonce, p.resubmits);
+                if let Some(ch) = p.answer_channel.take() {
+                    ch.send(Answer::Fail(format!("transaction with nonce {} not mined", p.tx.nonce)));
+                }
+                // later transactions wait behind the nonce, without them it is just used again
+                if self.pending.iter().chain(still_pending.iter()).any(|q| q.tx.nonce > p.tx.nonce) {
+                    still_pending.push_back(self.fill_nonce(p));
+                } else {
+                    self.next_nonce = None;
                 }
-            },
-            Err(e) => {
-                info!("Error send_transaction {:?}", e);
             }
         }
+        self.pending = still_pending;
     }
 
-    pub fn submit_vote(&self, str_block: String, sid: U256, bid: U256, sigx: U256, sigy: U256, bitset: U256) {
-        let nonce = self._transaction_count();
-        let private_key = _get_key_as_vec(self.my_account.private_key.clone());
-        let function_abi = _encode_submitVote(str_block, sid, bid, sigx, sigy, bitset);
-       // let gas = self._estimate_gas(function_abi.clone());
-        //  println!("{:?}", gas);
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000)*20, //20 Gwei
-            gas: ethereum_types::U256::from(950000),
-            data: function_abi
+    // take the nonce of dropped p with an empty call at a fee that replaces its last version,
+    // the versions of p are still watched in case one gets mined after all
+    fn fill_nonce(&self, p: PendingTx) -> PendingTx {
+        let tx = TxParams {
+            nonce: p.tx.nonce,
+            fee: p.tx.fee.bump(self.gas_policy.bump_percent),
         };
-
-
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        //info!("key {:?}", key);
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
-        //info!("ETH_CHAIN_ID {} signed tx {:?}", ETH_CHAIN_ID, signed_tx);
-
-        //let tx_hash = self._send_transaction_ori(signed_tx);
-        //if self.get_tx_receipt(tx_hash) {
-            //println!("success tx_hash = {:?}", tx_hash);
-        //}
-        let start = SystemTime::now();
-        match self._send_transaction(signed_tx) {
+        let mut tx_hashes = p.tx_hashes;
+        match self.backend.send(&Call::FillNonce, &tx) {
             Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    println!("block id {}. tx_hash = {:?}", bid.as_usize(), tx_hash);
-                }
+                info!("fill nonce {} with max gas price {} as {:?}", tx.nonce, tx.fee.max_price(), tx_hash);
+                tx_hashes.push((tx_hash, tx.fee));
             },
-            Err(e) => {
-                info!("{:?} Error send_transaction {:?}", self.my_account.address , e);
-            }
+            Err(e) => warn!("unable to fill nonce {} {:?}", tx.nonce, e),
+        }
+        PendingTx {
+            call: Call::FillNonce,
+            tx,
+            tx_hashes,
+            submitted: Instant::now(),
+            sent_block: self.backend.block_number().unwrap_or(p.sent_block),
+            resubmits: 0,
+            answer_channel: None,
         }
-        PERFORMANCE_COUNTER.record_submit_block_stop(bid.as_usize());
-        info!("*******Submitted block time {:?}", start.elapsed());
     }
 
-    pub fn send_block(&self, block: Block)  {
-        let str_block= _block_to_str(block.clone());
-        let nonce = self._transaction_count();
-        let blk_id = self._get_blk_id(0);
-        let private_key = _get_key_as_vec(self.my_account.private_key.clone());
-        let signature = _sign_block(str_block.as_str(), &private_key);
-        let function_abi = _encode_sendBlock(str_block, signature, blk_id + 1);
-
-
-        let gas = self._estimate_gas(function_abi.clone());
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000) *20,
-            gas: _convert_u256(gas),
-            data: function_abi
+    // replacement by fee, same nonce with every price component raised
+    fn resubmit(&self, p: &mut PendingTx) {
+        let tx = TxParams {
+            nonce: p.tx.nonce,
+            fee: p.tx.fee.bump(self.gas_policy.bump_percent),
         };
-
-
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
-        match self._send_transaction(signed_tx) {
+        p.resubmits += 1;
+        p.sent_block = self.backend.block_number().unwrap_or(p.sent_block);
+        match self.backend.send(&p.call, &tx) {
             Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    let curr_state = self._get_curr_state(0);
-                    // update local blockchain
-                    // broadcast to peers
-                    info!("broadcast to peer");
-                    self.send_p2p(curr_state, block);
-                } else {
-                    warn!("get_tx_receipt fail");
-                }
+                info!("resubmit nonce {} with max gas price {} as {:?}", tx.nonce, tx.fee.max_price(), tx_hash);
+                p.tx = tx;
+                p.tx_hashes.push((tx_hash, tx.fee));
             },
-            Err(e) => {
-                info!("Error send_transaction {:?}", e);
-            }
+            // usually an earlier version got mined meanwhile, the next poll sees its receipt
+            Err(e) => warn!("unable to resubmit nonce {} {:?}", tx.nonce, e),
         }
-
-        
     }
 
-    pub fn estimate_gas(&self, block: Block) -> U256 {
-        let mut file = OpenOptions::new().append(true).open("gas_history.csv").unwrap();
-        let str_block= _block_to_str(block.clone());
-        let nonce = self._transaction_count();
-        let blk_id = self._get_blk_id(0);
-        let private_key = _get_key_as_vec(self.my_account.private_key.clone());
-        let signature = _sign_block(str_block.as_str(), &private_key);
-        let function_abi = _encode_sendBlock(str_block, signature, blk_id + 1);
-        let gas = self._estimate_gas(function_abi.clone());
-        file.write_all(format!("{}\n ", gas).as_bytes());
-        return gas;
+    fn finish(&self, p: PendingTx, receipt: Receipt) {
+        // the price of the version that got mined, an earlier one may beat its bumps
+        if let Some((_, fee)) = p.tx_hashes.iter().find(|(h, _)| *h == receipt.tx_hash) {
+            PERFORMANCE_COUNTER.record_gas_update((fee.max_price() / U256::from(GWEI)).as_usize());
+        }
+        if !receipt.success {
+            warn!("Transaction {:?} reverted", receipt.tx_hash);
+        }
+        match &p.call {
+            Call::SubmitVote(_, _, bid, _, _, _) => {
+                if receipt.success {
+                    println!("block id {}. tx_hash = {:?}", bid.as_usize(), receipt.tx_hash);
+                }
+                PERFORMANCE_COUNTER.record_submit_block_stop(bid.as_usize());
+                info!("*******Submitted block time {:?}", p.submitted.elapsed());
+            },
+            Call::FillNonce => info!("nonce {} taken by {:?}", p.tx.nonce, receipt.tx_hash),
+            _ => {
+                if receipt.success {
+                    println!("tx_hash = {:?}", receipt.tx_hash);
+                }
+            },
+        }
+        let answer = if receipt.success {
+            Answer::Success(Response::TxReceipt(receipt))
+        } else {
+            Answer::Fail(format!("transaction {:?} reverted", receipt.tx_hash))
+        };
+        if let Some(ch) = p.answer_channel {
+            ch.send(answer);
+        }
     }
 
-    fn send_p2p(&self, curr_state: ContractState, block: Block) {
-        let main_block = EthBlkTransaction {
-            contract_state: curr_state,
-            block: block,
+    pub fn get_receipt(&self, handle: Handle, tx_hash: H256) {
+        let answer = match self.backend.transaction_receipt(tx_hash) {
+            Ok(Some(receipt)) => Answer::Success(Response::TxReceipt(receipt)),
+            Ok(None) => Answer::Fail(format!("no receipt for {:?}", tx_hash)),
+            Err(e) => Answer::Fail(format!("{:?}", e)),
         };
-
-        let server_message = ServerMessage::SyncBlock(main_block);
-        let p2p_message = ServerSignal::ServerBroadcast(server_message);
-        self.server_control_sender.send(p2p_message); 
-    }
-
-    pub fn get_tx_receipt(&self, tx_hash: web3::types::H256) -> bool {
-        let now = time::Instant::now();
-        let mut receipt = self._transaction_receipt(tx_hash.clone());
-        while receipt.is_none() {
-            receipt = self._transaction_receipt(tx_hash.clone());
-            match receipt {
-                Some(ref r) => {
-                    match r.gas_used {
-                        Some(g) => {
-                            let gas = g.as_usize();
-                            PERFORMANCE_COUNTER.record_gas_update(gas);
-                            break;
-                        },
-                        None => {},
-                    }
-                },
-                None => {},
-            }
-            
-            if now.elapsed().as_secs() > 60 {
-                warn!("Transaction TimeOut");
-                return false;
-            }
+        if let Some(ch) = handle.answer_channel {
+            ch.send(answer);
         }
-        return true;
     }
 
-    // pull function to get updated, return number of state change, 0 for no change 
-    pub fn sync_etherchain(&self, handle: Handle) {
-        let mut transactions = self._get_all([0 as u8;32], 0, std::usize::MAX);
-        let states: Vec<ContractState> = transactions.
-            iter().
-            map(|item| {
-                item.contract_state.clone()   
-            }).collect();
-        let chain_len: usize = states.len();
-
-        let blocks: Vec<Block> = transactions.
-            into_iter().
-            map(|item| {
-                item.block   
-            }).collect();
+    // pull function to get updated, return the number of side chain blocks on the contract
+    pub fn sync_etherchain(&self, handle: Handle, sid: usize) {
+        let transactions = self._get_all(sid, 0, 0);
+        let chain_len: usize = transactions.len();
 
         let response = Response::SyncChain(chain_len);
         let answer = Answer::Success(response);
@@ -497,272 +562,209 @@ impl Contract {
     }
 
     // [start, end)
-    pub fn get_all(&self, handle: Handle, start: usize, end: usize) {
-        let transactions = self._get_all([0 as u8; 32], 0, std::usize::MAX);
-        let req_transactions: Vec<EthBlkTransaction> = if end != 0 {
-            transactions[start..end].to_vec()  
-        } else {
-            transactions[start..].to_vec()
-        };
-
+    pub fn get_all(&self, handle: Handle, sid: usize, start: usize, end: usize) {
+        let req_transactions = self._get_all(sid, start, end);
         let response = Response::GetAll(req_transactions);
         let answer = Answer::Success(response);
         handle.answer_channel.unwrap().send(answer);
     }
 
-    pub fn _get_all(&self, init_hash: [u8;32], start: usize, end: usize) -> (Vec<EthBlkTransaction>) {
-        let mut curr_hash = init_hash;
-        let func_sig = "ae8d0145";
-        let mut block_list: Vec<Block> = Vec::new();
-        let mut state_list: Vec<ContractState> = Vec::new();
-        let request_url = format!("https://api-ropsten.etherscan.io/api?module=account&action=txlist&address={address}&startblock={start}&endblock={end}&sort=asc&apikey={apikey}",
-                                  address = "0xE5C10A1E39fA1fAF25E4fD5ce2C4e2ec5A7aB926",
-                                  start = start,
-                                  end = end,
-                                  apikey = "UGEFW13C4HVZ9GGH5GWIRHQHYYPYKX7FCX");
-
-        //let response = requests::get(request_url).unwrap();
-        //let data = response.json().unwrap();
-       
-        //let resp = reqwest::blocking::get(request_url)?
-          //.json::<HashMap<String, String>>()?;
-        
-        
-        //let txs = data["result"].clone();
-
-        let mut transactions: Vec<EthBlkTransaction> = vec![];
-
-        //for tx in txs.members() {
-            //if tx["input"].as_str().unwrap().len() < 10 {continue;}
-            //let sig = &tx["input"].as_str().unwrap()[2..10];
-            //let isError = tx["isError"].as_str().unwrap().parse::<i32>().unwrap();
-            //if sig == func_sig && isError == 0 {
-                //let input = &tx["input"].as_str().unwrap()[10..];
-                //let (block_ser, block_id) = _decode_sendBlock(input);
-                
-                //let mut hasher = Sha256::new();
-                //hasher.input_str(&block_ser);
-                //let mut block_hash = [0u8;32];
-                //hasher.result(&mut block_hash);
-                //let concat_str = [curr_hash, block_hash].concat();
-                //let mut hasher = Sha256::new();
-                //hasher.input(&concat_str);
-                //hasher.result(&mut curr_hash);
-
-                //let state = ContractState{
-                    //curr_hash: H256(curr_hash),
-                    //block_id,
-                //};
-                //let block = match hex::decode(&block_ser) {
-                    //Ok(bytes) => {
-                        //match bincode::deserialize(&bytes[..]) {
-                            //Ok(block) => block,
-                            //Err(e) => {
-                                //let mut block = Block::default();
-                                //block.header.height = block_id;
-                                //block.update_hash();
-                                //block
-                            //},
-                        //}
-                    //},
-                    //Err(e) => {
-                        //let mut block = Block::default();
-                        //block.header.height = block_id;
-                        //block.update_hash();
-                        //block
-                    //}
-                //};
-                
-                //transactions.push(
-                    //EthBlkTransaction{
-                        //contract_state: state,
-                        //block: block,
-                    //}
-                 //);
-            //} 
-        //}
-
-        return transactions;
-    }
-
-    fn reset_side_node(&self, sid: usize)  {
-        let nonce = self._transaction_count();
-        let function_abi = _encode_resetSideChain(U256::from(sid));
-        let gas = self._estimate_gas(function_abi.clone());
-
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000),
-            gas: _convert_u256(gas),
-            data: function_abi
-        };
-        let now = time::Instant::now();
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
-
-        match self._send_transaction(signed_tx) {
-            Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    println!("tx_hash = {:?}", tx_hash);
-                }
-            },
+    pub fn _get_all(&self, sid: usize, start: usize, end: usize) -> Vec<EthBlkTransaction> {
+        match self.backend.get_all(sid, start, end) {
+            Ok(transactions) => transactions,
             Err(e) => {
-                info!("Error send_transaction {:?}", e);
+                warn!("unable to get contract history {:?}", e);
+                vec![]
             }
         }
     }
 
-    pub fn reset_chain(&self, sid: usize)  {
-        let nonce = self._transaction_count();
-        let function_abi = _encode_resetSideChain(U256::from(sid));
-        let gas = self._estimate_gas(function_abi.clone());
-
-        //println!("{:?}", gas);
-
-        let tx = RawTransaction {
-            nonce: _convert_u256(nonce),
-            to: Some(ethereum_types::H160::from(self.contract_address.0)),
-            value: ethereum_types::U256::zero(),
-            gas_price: ethereum_types::U256::from(1000000000),
-            gas: _convert_u256(gas),
-            data: function_abi
-        };
-        let now = time::Instant::now();
-        let key = _get_key_as_H256(self.my_account.private_key.clone());
-        let signed_tx = tx.sign(&key, &ETH_CHAIN_ID);
-        match self._send_transaction(signed_tx) {
-            Ok(tx_hash) => {
-                if self.get_tx_receipt(tx_hash) {
-                    println!("tx_hash = {:?}", tx_hash);
-                }
-            },
+    pub fn _get_curr_state(&self, sid: usize) -> ContractState {
+        match self.backend.get_curr_state(sid) {
+            Ok(state) => state,
             Err(e) => {
-                info!("Error send_transaction {:?}", e);
+                println!("_get_curr_state recv ERROR {:?}", e);
+                ContractState::genesis(sid as u64)
             }
         }
-
-
-        
     }
 
-    fn _get_blk_id(&self, sid: usize) -> U256 {
-        self.contract
-            .query("getBlockID", (web3::types::U256::from(sid),), None, EthOption::default(), None)
-            .wait()
-            .unwrap()
+    // unlike _get_curr_state, a failed query is not taken for the genesis state
+    pub fn _fetch_curr_state(&self, sid: usize) -> Result<ContractState, super::interface::Error> {
+        self.backend.get_curr_state(sid)
     }
 
-    fn _get_side_node_id(&self, sid: usize, addr: Address) -> U256 {
-        self.contract
-            .query("getSideNodeID", (web3::types::U256::from(sid), addr), None, EthOption::default(), None)
-            .wait()
-            .unwrap()
+    // unlike _get_all, a history that is still syncing or inconsistent is an error
+    pub fn _fetch_all(&self, sid: usize, start: usize, end: usize) -> Result<Vec<EthBlkTransaction>, super::interface::Error> {
+        self.backend.get_all(sid, start, end)
     }
 
-    fn _transaction_count(&self) -> U256 {
-        self.web3.eth()
-            .transaction_count(self.my_account.address, None)
-            .wait()
-            .unwrap()
-    }
-
-    fn _get_curr_hash(&self, sid: usize) -> web3::types::H256 {
-        match self.contract
-            .query("getCurrentHash", (web3::types::U256::from(sid),), None, EthOption::default(), None)
-            .wait()
-        {
-            Ok(h) => h,
-            Err(e) => {
-                println!("_get_curr_hash recv ERROR");
-                web3::types::H256::default()
-            }
-        }
-    }
-
-    pub fn _get_curr_state(&self, sid: usize) -> ContractState {
-        let hash = self._get_curr_hash(sid);
-        let blk_id = self._get_blk_id(sid);
-        ContractState {
-            curr_hash: hash.into(),
-            block_id: blk_id.as_u64(),
-        }
+    pub fn _count_scale_nodes(&self) -> usize {
+        self.backend.count_scale_nodes().unwrap()
     }
 
-    pub fn _count_scale_nodes(&self) -> usize {
-        let cnt: U256 = self.contract
-            .query("scaleNodesCount", (), None, EthOption::default(), None)
-            .wait()
-            .unwrap();
-        cnt.as_usize()
+    pub fn _count_side_chains(&self) -> usize {
+        self.backend.count_side_chains().unwrap()
     }
 
     pub fn _get_scale_node(&self, index: usize) -> Address {
-        self.contract
-            .query("getScaleNode", (web3::types::U256::from(index), ), None, EthOption::default(), None)
-            .wait()
-            .unwrap()
+        self.backend.get_scale_node(index).unwrap()
     }
 
     pub fn _get_scale_id(&self, addr: Address) -> Option<U256> {
-        match self.contract
-            .query("getScaleID", (addr), None, EthOption::default(), None)
-            .wait() {
-            Ok(u) => Some(u),
-            Err(e) => None,
-        }
+        self.backend.get_scale_id(addr).ok()
     }
 
     pub fn _get_scale_pub_key(&self, addr: Address) -> (U256, U256, U256, U256) {
-        self.contract
-            .query("getScalePubKey", (addr), None, EthOption::default(), None)
-            .wait()
-            .unwrap()
-
-    }
-
-    fn _send_transaction_ori(&self, signed_tx: Vec<u8>) -> web3::types::H256 {
-        self.web3.eth()
-            .send_raw_transaction(Bytes::from(signed_tx))
-            .wait()
-            .unwrap()
-    }
-
-    fn _send_transaction(&self, signed_tx: Vec<u8>) -> Result<web3::types::H256, web3::error::Error> {
-        self.web3.eth()
-            .send_raw_transaction(Bytes::from(signed_tx))
-            .wait() 
+        self.backend.get_scale_pub_key(addr).unwrap()
     }
+}
 
-    fn _transaction_receipt(&self, tx_hash: web3::types::H256) -> Option<TransactionReceipt> {
-        match self.web3.eth()
-            .transaction_receipt(tx_hash)
-            .wait()
-            {
-                Ok(t) => t,
-                Err(_) => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mock::MockBackend;
+    use crate::crypto::bls::SecretKey;
+
+    fn mock_contract(backend: MockBackend) -> Contract {
+        let (performer_sender, _) = channel::unbounded();
+        let (server_control_sender, _) = Mio_channel::channel();
+        let (_, contract_handle) = channel::unbounded();
+        let key = BLSKey {
+            sk: U256::zero(),
+            pkx1: U256::zero(),
+            pkx2: U256::zero(),
+            pky1: U256::zero(),
+            pky2: U256::zero(),
+        };
+        Contract::with_backend(
+            Box::new(backend),
+            Account::default(),
+            key,
+            performer_sender,
+            server_control_sender,
+            contract_handle,
+            "127.0.0.1".to_owned(),
+            GasPolicy::default(),
+        )
+    }
+
+    #[test]
+    fn back_to_back_submissions() {
+        let admin = MockBackend::new(Address::from_low_u64_be(100));
+        let mut contract = mock_contract(admin.clone());
+        let mut answers = vec![];
+        for i in 0..3u64 {
+            let (answer_tx, answer_rx) = channel::bounded(1);
+            let call = Call::AddSideNode(U256::zero(), Address::from_low_u64_be(i), format!("127.0.0.1:{}", 40000 + i));
+            contract.submit(call, Some(answer_tx));
+            answers.push(answer_rx);
+        }
+        assert_eq!(contract.next_nonce, Some(U256::from(3)));
+        assert_eq!(contract.pending.len(), 3);
+
+        contract.poll_pending();
+        assert!(contract.pending.is_empty());
+        for answer_rx in answers {
+            match answer_rx.try_recv() {
+                Ok(Answer::Success(Response::TxReceipt(receipt))) => assert!(receipt.success),
+                _ => panic!("submission not answered"),
             }
-            
-    }
+        }
+        assert_eq!(admin.transaction_count().unwrap(), U256::from(3));
+    }
+
+    #[test]
+    fn nonce_of_a_dropped_transaction_is_filled() {
+        let admin = MockBackend::new(Address::from_low_u64_be(100));
+        let mut contract = mock_contract(admin.clone());
+        contract.gas_policy.bump_blocks = 0;
+        contract.gas_policy.max_bumps = 0;
+        // the first transaction never makes it, the second waits behind its nonce
+        admin.evict_next(1);
+        let mut answers = vec![];
+        for i in 0..2u64 {
+            let (answer_tx, answer_rx) = channel::bounded(1);
+            let call = Call::AddSideNode(U256::zero(), Address::from_low_u64_be(i), format!("127.0.0.1:{}", 40000 + i));
+            contract.submit(call, Some(answer_tx));
+            answers.push(answer_rx);
+        }
+        assert_eq!(admin.transaction_count().unwrap(), U256::zero());
 
-    fn _estimate_gas(&self, data: Vec<u8>) -> U256 {
-        let call_request = CallRequest {
-            from: Some(H160::from(self.my_account.address.0)),
-            to: H160::from(self.contract_address.0),
-            gas_price: Some(U256::from(1000000000u64)),
-            gas: Some(U256::zero()),
-            data: Some(Bytes::from(data)),
-            value: Some(U256::zero())
-        };
+        contract.poll_pending();
+        match answers[0].try_recv() {
+            Ok(Answer::Fail(_)) => (),
+            _ => panic!("dropped transaction not answered"),
+        }
+        match answers[1].try_recv() {
+            Ok(Answer::Success(Response::TxReceipt(receipt))) => assert!(receipt.success),
+            _ => panic!("transaction behind the dropped one not mined"),
+        }
+        assert_eq!(admin.transaction_count().unwrap(), U256::from(2));
+        assert_eq!(contract.next_nonce, Some(U256::from(2)));
+
+        // the empty call reverted under the dropped nonce
+        assert_eq!(contract.pending.len(), 1);
+        contract.poll_pending();
+        assert!(contract.pending.is_empty());
+    }
+
+    #[test]
+    fn scale_node_without_key_files_is_answered() {
+        let admin = MockBackend::new(Address::from_low_u64_be(100));
+        let mut contract = mock_contract(admin.clone());
+        let (answer_tx, answer_rx) = channel::bounded(1);
+        contract.add_scale_node_by_id("no-such-node", "127.0.0.1:40001".to_string(), Some(answer_tx));
+        match answer_rx.try_recv() {
+            Ok(Answer::Fail(_)) => (),
+            _ => panic!("missing key files not answered"),
+        }
+        assert!(contract.pending.is_empty());
+        assert_eq!(admin.transaction_count().unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn failed_send_resyncs_nonce() {
+        let admin = MockBackend::new(Address::from_low_u64_be(100));
+        let mut contract = mock_contract(admin.clone());
+        let (answer_tx, answer_rx) = channel::bounded(1);
+        contract.submit(Call::DeleteSideNode(U256::zero(), U256::from(5)), Some(answer_tx));
+        match answer_rx.try_recv() {
+            Ok(Answer::Fail(_)) => (),
+            _ => panic!("failed submission not answered"),
+        }
+        assert_eq!(contract.next_nonce, None);
+        assert!(contract.pending.is_empty());
+
+        assert!(contract.add_side_node(U256::zero(), Address::from_low_u64_be(1), String::new()));
+        assert_eq!(contract.next_nonce, Some(U256::one()));
+    }
+
+    #[test]
+    fn watchers_get_changed_states() {
+        let admin = MockBackend::new(Address::from_low_u64_be(100));
+        let sk = SecretKey::from_u256(U256::from(1007)).unwrap();
+        let (x1, x2, y1, y2) = sk.public_key().to_u256();
+        admin.add_scale_node(Address::from_low_u64_be(1), String::new(), x1, x2, y1, y2).unwrap();
+        let mut contract = mock_contract(admin.clone());
+
+        let (state_tx, state_rx) = channel::unbounded();
+        contract.watch_state(0, state_tx);
+        assert_eq!(state_rx.try_recv(), Ok(ContractState::genesis(0)));
+        contract.poll_watched();
+        assert!(state_rx.try_recv().is_err());
+
+        let header = vec![3u8; 16];
+        let (x, y) = sk.sign(&header).to_u256();
+        admin.connect(Address::from_low_u64_be(1))
+            .submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, U256::one() << 1)
+            .unwrap();
+        contract.poll_watched();
+        assert_eq!(state_rx.try_recv().map(|s| s.block_id), Ok(1));
 
-        let gas =
-            self.web3.eth()
-                .estimate_gas(call_request, None)
-                .wait()
-                .unwrap();
-        gas
+        // a new ethereum block leaving the side chain alone pushes nothing
+        admin.add_side_chain().unwrap();
+        contract.poll_watched();
+        assert!(state_rx.try_recv().is_err());
     }
-
-
 }
@@ -0,0 +1,162 @@
+// How contract transactions are priced. The policy is picked on the command line, e.g.
+// fixed:20 (Gwei), oracle:150 (percent of eth_gasPrice) or eip1559:40:2 (max and priority fee in Gwei).
+use super::backend::{Call, ContractBackend};
+use super::interface::Error;
+
+use web3::types::U256;
+use std::str::FromStr;
+
+pub const GWEI: u64 = 1000000000;
+// nodes only accept a replacement when every price component rises by at least 10%
+pub const MIN_BUMP_PERCENT: u64 = 110;
+
+// fee of a single transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fee {
+    Legacy(U256),
+    Eip1559 { max_fee: U256, priority_fee: U256 },
+}
+
+impl Fee {
+    pub fn bump(&self, percent: u64) -> Fee {
+        let raise = |v: U256| std::cmp::max(v * percent / 100, v + 1);
+        match *self {
+            Fee::Legacy(price) => Fee::Legacy(raise(price)),
+            Fee::Eip1559 { max_fee, priority_fee } => Fee::Eip1559 {
+                max_fee: raise(max_fee),
+                priority_fee: raise(priority_fee),
+            },
+        }
+    }
+
+    // upper bound of the price paid per unit of gas
+    pub fn max_price(&self) -> U256 {
+        match *self {
+            Fee::Legacy(price) => price,
+            Fee::Eip1559 { max_fee, .. } => max_fee,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GasPrice {
+    Fixed(U256),
+    // eth_gasPrice scaled by a percentage
+    Oracle(u64),
+    Eip1559 { max_fee: U256, priority_fee: U256 },
+}
+
+impl FromStr for GasPrice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GasPrice, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let num = |i: usize| -> Result<u64, String> {
+            parts.get(i)
+                .ok_or(format!("missing value in gas policy {}", s))?
+                .parse::<u64>()
+                .map_err(|e| format!("invalid gas policy {}: {}", s, e))
+        };
+        let gwei = |v: u64| U256::from(v) * U256::from(GWEI);
+        match (parts[0], parts.len()) {
+            ("fixed", 2) => Ok(GasPrice::Fixed(gwei(num(1)?))),
+            ("oracle", 2) => Ok(GasPrice::Oracle(num(1)?)),
+            ("eip1559", 3) => {
+                let max_fee = gwei(num(1)?);
+                let priority_fee = gwei(num(2)?);
+                if priority_fee > max_fee {
+                    return Err(format!("priority fee above max fee in {}", s));
+                }
+                Ok(GasPrice::Eip1559 { max_fee, priority_fee })
+            },
+            _ => Err(format!("unknown gas policy {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GasPolicy {
+    pub price: GasPrice,
+    // a transaction not mined after this many blocks is replaced with a higher fee
+    pub bump_blocks: u64,
+    pub bump_percent: u64,
+    pub max_bumps: usize,
+}
+
+impl Default for GasPolicy {
+    fn default() -> Self {
+        GasPolicy {
+            price: GasPrice::Fixed(U256::from(20 * GWEI)),
+            bump_blocks: 4,
+            bump_percent: 125,
+            max_bumps: 4,
+        }
+    }
+}
+
+impl GasPolicy {
+    // a smaller bump would make every replacement get rejected
+    pub fn set_bump_percent(&mut self, percent: u64) -> Result<(), String> {
+        if percent < MIN_BUMP_PERCENT {
+            return Err(format!("bump percent {} is below {}, nodes reject such replacements", percent, MIN_BUMP_PERCENT));
+        }
+        self.bump_percent = percent;
+        Ok(())
+    }
+
+    pub fn fee(&self, backend: &dyn ContractBackend) -> Result<Fee, Error> {
+        match self.price {
+            GasPrice::Fixed(price) => Ok(Fee::Legacy(price)),
+            GasPrice::Oracle(percent) => Ok(Fee::Legacy(backend.gas_price()? * percent / 100)),
+            GasPrice::Eip1559 { max_fee, priority_fee } => Ok(Fee::Eip1559 { max_fee, priority_fee }),
+        }
+    }
+}
+
+// gas limit of a call, None lets the node estimate it
+pub fn gas_limit(call: &Call) -> Option<U256> {
+    match call {
+        Call::SubmitVote(..) => Some(U256::from(950000)),
+        Call::DeleteSideNode(..) => Some(U256::from(750000)),
+        // estimation fails on a call that reverts
+        Call::FillNonce => Some(U256::from(50000)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_policies() {
+        assert_eq!("fixed:20".parse::<GasPrice>(), Ok(GasPrice::Fixed(U256::from(20 * GWEI))));
+        assert_eq!("oracle:150".parse::<GasPrice>(), Ok(GasPrice::Oracle(150)));
+        assert_eq!("eip1559:40:2".parse::<GasPrice>(), Ok(GasPrice::Eip1559 {
+            max_fee: U256::from(40 * GWEI),
+            priority_fee: U256::from(2 * GWEI),
+        }));
+        assert!("eip1559:2:40".parse::<GasPrice>().is_err());
+        assert!("fixed".parse::<GasPrice>().is_err());
+        assert!("auction:3".parse::<GasPrice>().is_err());
+    }
+
+    #[test]
+    fn bump_percent_below_replacement_minimum_is_rejected() {
+        let mut policy = GasPolicy::default();
+        assert!(policy.set_bump_percent(105).is_err());
+        assert_eq!(policy.bump_percent, 125);
+        assert_eq!(policy.set_bump_percent(MIN_BUMP_PERCENT), Ok(()));
+        assert_eq!(policy.bump_percent, 110);
+    }
+
+    #[test]
+    fn bump_raises_every_component() {
+        assert_eq!(Fee::Legacy(U256::from(1000)).bump(125), Fee::Legacy(U256::from(1250)));
+        // tiny prices still go up
+        assert_eq!(Fee::Legacy(U256::from(1)).bump(110), Fee::Legacy(U256::from(2)));
+        let fee = Fee::Eip1559 { max_fee: U256::from(400), priority_fee: U256::from(20) }.bump(110);
+        assert_eq!(fee, Fee::Eip1559 { max_fee: U256::from(440), priority_fee: U256::from(22) });
+        assert_eq!(fee.max_price(), U256::from(440));
+    }
+}
@@ -0,0 +1,168 @@
+// Side chain history rebuilt from the VoteSubmitted and SideChainReset logs of the ScaleChain
+// contract. Logs are replayed in order and the hash chain is recomputed the same way the
+// contract does.
+use super::primitive::block::{EthBlkTransaction, ContractState, Block};
+use super::interface::Error;
+use super::abi::{Token, SCALECHAIN_ABI, uint_to_usize, uint_to_u64};
+use super::crypto::hash;
+use web3::types::H256;
+
+#[derive(Clone)]
+pub struct ChainHistory {
+    chains: Vec<Vec<EthBlkTransaction>>,
+}
+
+impl ChainHistory {
+    pub fn new() -> ChainHistory {
+        ChainHistory {
+            chains: vec![vec![]],
+        }
+    }
+
+    fn chain_mut(&mut self, sid: usize) -> &mut Vec<EthBlkTransaction> {
+        if sid >= self.chains.len() {
+            self.chains.resize(sid + 1, vec![]);
+        }
+        &mut self.chains[sid]
+    }
+
+    pub fn tip(&self, sid: usize) -> ContractState {
+        match self.chains.get(sid).and_then(|c| c.last()) {
+            Some(t) => t.contract_state.clone(),
+            None => ContractState::genesis(sid as u64),
+        }
+    }
+
+    // [start, end) of side chain sid, end 0 means up to the tip
+    pub fn get(&self, sid: usize, start: usize, end: usize) -> Vec<EthBlkTransaction> {
+        let chain = match self.chains.get(sid) {
+            Some(c) => c,
+            None => return vec![],
+        };
+        let end = if end == 0 || end > chain.len() { chain.len() } else { end };
+        let start = std::cmp::min(start, end);
+        chain[start..end].to_vec()
+    }
+
+    // topics and data of a log emitted by the contract, logs of other events are ignored.
+    // A log that fails to apply leaves the history as it was
+    pub fn apply(&mut self, topics: &[H256], data: &[u8]) -> Result<(), Error> {
+        let submitted = SCALECHAIN_ABI.event("VoteSubmitted").map_err(abi_err)?;
+        let reset = SCALECHAIN_ABI.event("SideChainReset").map_err(abi_err)?;
+
+        match topics.first() {
+            Some(t) if *t == submitted.topic() => {
+                match submitted.decode_log(topics, data).map_err(abi_err)?.as_slice() {
+                    [Token::Uint(sid), Token::Uint(bid), Token::Bytes(header)] => {
+                        self.append(uint_to_usize(sid).map_err(abi_err)?, uint_to_u64(bid).map_err(abi_err)?, header)
+                    },
+                    _ => Err(Error::Encoding("unexpected VoteSubmitted params".to_string())),
+                }
+            },
+            Some(t) if *t == reset.topic() => {
+                match reset.decode_log(topics, data).map_err(abi_err)?.as_slice() {
+                    [Token::Uint(sid)] => {
+                        self.chain_mut(uint_to_usize(sid).map_err(abi_err)?).clear();
+                        Ok(())
+                    },
+                    _ => Err(Error::Encoding("unexpected SideChainReset params".to_string())),
+                }
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn append(&mut self, sid: usize, bid: u64, header: &[u8]) -> Result<(), Error> {
+        let tip = self.tip(sid);
+        if bid != tip.block_id + 1 {
+            return Err(Error::Inconsistent(format!(
+                "side chain {} jumps from block {} to {}", sid, tip.block_id, bid)));
+        }
+        let prev: [u8; 32] = tip.curr_hash.into();
+        let header_hash: [u8; 32] = hash(header).into();
+        self.chain_mut(sid).push(EthBlkTransaction {
+            contract_state: ContractState {
+                sid: sid as u64,
+                curr_hash: hash(&[prev, header_hash].concat()),
+                block_id: bid,
+            },
+            block: Block::default(),
+            header: header.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+fn abi_err(e: super::abi::AbiError) -> Error {
+    Error::Encoding(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::backend::ContractBackend;
+    use super::super::mock::MockBackend;
+    use crate::crypto::bls::{SecretKey, Signature};
+    use web3::types::{Address, U256};
+
+    fn vote_log(header: &[u8], sid: u64, bid: u64) -> (Vec<H256>, Vec<u8>) {
+        let args = [Token::Uint(U256::from(sid)), Token::Uint(U256::from(bid)), Token::Bytes(header.to_vec())];
+        SCALECHAIN_ABI.event("VoteSubmitted").unwrap().encode_log(&args).unwrap()
+    }
+
+    fn reset_log(sid: u64) -> (Vec<H256>, Vec<u8>) {
+        SCALECHAIN_ABI.event("SideChainReset").unwrap().encode_log(&[Token::Uint(U256::from(sid))]).unwrap()
+    }
+
+    fn apply(history: &mut ChainHistory, log: (Vec<H256>, Vec<u8>)) -> Result<(), Error> {
+        history.apply(&log.0, &log.1)
+    }
+
+    #[test]
+    fn replay_matches_contract() {
+        let admin = MockBackend::new(Address::from_low_u64_be(100));
+        let sk = SecretKey::from_u256(U256::from(1007)).unwrap();
+        let (x1, x2, y1, y2) = sk.public_key().to_u256();
+        admin.add_scale_node(Address::from_low_u64_be(1), "127.0.0.1:40001".to_string(), x1, x2, y1, y2).unwrap();
+        let node = admin.connect(Address::from_low_u64_be(1));
+
+        let mut history = ChainHistory::new();
+        for bid in 1..4u64 {
+            let header = vec![bid as u8; 40];
+            let (x, y) = Signature::aggregate(&[sk.sign(&header)]).to_u256();
+            node.submit_vote(&hex::encode(&header), U256::zero(), U256::from(bid), x, y, U256::from(2)).unwrap();
+            apply(&mut history, vote_log(&header, 0, bid)).unwrap();
+        }
+
+        assert_eq!(history.tip(0), admin.get_curr_state(0).unwrap());
+        assert_eq!(history.get(0, 0, 0), admin.get_all(0, 0, 0).unwrap());
+        assert_eq!(history.get(0, 1, 2).len(), 1);
+    }
+
+    #[test]
+    fn reset_and_unrelated_logs() {
+        let mut history = ChainHistory::new();
+        apply(&mut history, vote_log(&[1, 2, 3], 0, 1)).unwrap();
+        apply(&mut history, vote_log(&[1, 2, 3], 1, 1)).unwrap();
+        history.apply(&[H256::from_low_u64_be(9)], &[]).unwrap();
+        history.apply(&[], &[]).unwrap();
+        assert_eq!(history.tip(0).block_id, 1);
+
+        apply(&mut history, reset_log(0)).unwrap();
+        assert_eq!(history.tip(0), ContractState::genesis(0));
+        assert_eq!(history.tip(1).block_id, 1);
+        apply(&mut history, vote_log(&[4, 5], 0, 1)).unwrap();
+        assert_eq!(history.get(0, 0, 0).len(), 1);
+    }
+
+    #[test]
+    fn gap_is_reported() {
+        let mut history = ChainHistory::new();
+        apply(&mut history, vote_log(&[1], 0, 1)).unwrap();
+        match apply(&mut history, vote_log(&[2], 0, 3)) {
+            Err(Error::Inconsistent(_)) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(history.tip(0).block_id, 1);
+    }
+}
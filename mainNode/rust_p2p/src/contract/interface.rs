@@ -1,6 +1,7 @@
 use crossbeam::channel::{Sender};
-use super::primitive::block::{EthBlkTransaction, ContractState, Block};
-use web3::types::{Address, H256, TransactionReceipt, U256};
+use super::primitive::block::{EthBlkTransaction, ContractState};
+use web3::types::{Address, H256, U256};
+use super::backend::Receipt;
 
 #[derive(Clone)]
 pub struct Handle {
@@ -9,14 +10,15 @@ pub struct Handle {
 }
 #[derive(Clone)]
 pub enum Response {
-    SendBlock,
     GetCurrState(ContractState),
     CountScaleNode(usize), 
+    CountSideChains(usize),
     AddScaleNode,
     ScaleNodesList(Vec<Address>),
-    TxReceipt(TransactionReceipt),
+    TxReceipt(Receipt),
     GetAll(Vec<EthBlkTransaction>),
     SyncChain(usize),
+    ScalePubKey(U256, U256, U256, U256),
 }
 #[derive(Clone)]
 pub enum Answer {
@@ -25,21 +27,32 @@ pub enum Answer {
 }
 #[derive(Clone)]
 pub enum Message {
-    SendBlock(Block),
     GetCurrState(usize),
     CountScaleNodes,
     AddScaleNode(String, String),
     GetScaleNodes,
     GetTxReceipt(H256),
-    GetAll(([u8;32], usize, usize)), //inithash, start, end
-    SyncChain,
-    EstimateGas(Block),
+    GetAll((usize, usize, usize)), //sid, start, end
+    SyncChain(usize),
     SubmitVote(String, U256, U256, U256, U256, U256),
     ResetChain(usize),
     AddSideNode(usize),
+    AddSideChain,
+    CountSideChains,
+    GetScalePubKey(usize),
+    // the current state of side chain sid is sent now and again whenever a new ethereum block changes it
+    WatchState(usize, Sender<ContractState>),
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     TimeOut,
     ConnectionFail,
+    Rpc(String),
+    Encoding(String),
+    Reverted(String),
+    Unsupported(String),
+    Inconsistent(String),
+    // history is only replayed up to this ethereum block, the head is not reached yet
+    Syncing(u64),
 }
@@ -0,0 +1,581 @@
+// In-memory stand-in for ScaleChain.sol. Several MockBackends connected to the same chain
+// behave like nodes sharing one contract, so a cluster can run without an Ethereum node.
+use super::primitive::block::{EthBlkTransaction, ContractState, Block};
+use super::primitive::hash::H256 as StateHash;
+use super::interface::Error;
+use super::backend::{ContractBackend, Receipt, Call, TxParams};
+use super::gas::GWEI;
+use super::crypto::hash;
+use super::crypto::bls::{PublicKey, Signature};
+
+use web3::types::{Address, H256, U256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const NUM_TOKEN_RINGS: usize = 10;
+// gas reported in receipts, roughly what the real contract uses
+const MOCK_GAS: u64 = 21000;
+
+// same values as the contract P2(), registered for the admin node
+const G2_GEN: [&str; 4] = [
+    "11559732032986387107991004021392285783925812861821192530917403151452391805634",
+    "10857046999023057135944570762232829481370756359578518086990519993285655852781",
+    "4082367875863433681332203403145435568316851327593401208105741076214120093531",
+    "8495653923123431417604973247489272438418190587263600148770280649306958101930",
+];
+
+struct ScaleNode {
+    eth_addr: Address,
+    pub_key: (U256, U256, U256, U256),
+}
+
+pub struct MockChain {
+    scale_nodes: Vec<ScaleNode>,
+    scale_id: HashMap<Address, usize>,
+    curr_hash: Vec<StateHash>,
+    block_id: Vec<u64>,
+    signers: Vec<Vec<U256>>,
+    token_rings: Vec<Vec<(Address, String)>>,
+    history: Vec<Vec<EthBlkTransaction>>,
+    receipts: HashMap<H256, Receipt>,
+    nonces: HashMap<Address, u64>,
+    num_tx: u64,
+    // sends still to be taken without ever being mined, like a node evicting them
+    evict: usize,
+    // transactions above the next nonce of their sender, mined once the gap is filled
+    queued: HashMap<(Address, U256), (Call, H256)>,
+    num_unmined: u64,
+    // minimum number of signers in a vote, the contract itself has none
+    threshold: usize,
+}
+
+impl MockChain {
+    fn new(admin: Address) -> MockChain {
+        let g2 = |i: usize| U256::from_dec_str(G2_GEN[i]).unwrap();
+        let mut scale_id = HashMap::new();
+        scale_id.insert(admin, 0);
+        MockChain {
+            scale_nodes: vec![ScaleNode {
+                eth_addr: admin,
+                pub_key: (g2(0), g2(1), g2(2), g2(3)),
+            }],
+            scale_id,
+            curr_hash: vec![StateHash::default()],
+            block_id: vec![0],
+            signers: vec![vec![]],
+            token_rings: vec![vec![]; NUM_TOKEN_RINGS],
+            history: vec![vec![]],
+            receipts: HashMap::new(),
+            nonces: HashMap::new(),
+            num_tx: 0,
+            evict: 0,
+            queued: HashMap::new(),
+            num_unmined: 0,
+            threshold: 0,
+        }
+    }
+
+    fn id_of(&self, addr: &Address) -> usize {
+        *self.scale_id.get(addr).unwrap_or(&0)
+    }
+
+    fn is_scale_node(&self, addr: &Address) -> bool {
+        self.scale_nodes[self.id_of(addr)].eth_addr == *addr
+    }
+
+    fn check_sid(&self, sid: usize) -> Result<(), Error> {
+        if sid < self.block_id.len() {
+            Ok(())
+        } else {
+            Err(Error::Reverted(format!("side chain {} does not exist", sid)))
+        }
+    }
+
+    // every accepted write gets a hash and a successful receipt
+    fn commit(&mut self) -> H256 {
+        self.num_tx += 1;
+        let tx_hash = H256::from_low_u64_be(self.num_tx);
+        self.receipts.insert(tx_hash, Receipt {
+            tx_hash,
+            gas_used: Some(U256::from(MOCK_GAS)),
+            success: true,
+        });
+        tx_hash
+    }
+
+    // a transaction that reverts still uses its nonce
+    fn revert(&mut self) -> H256 {
+        let tx_hash = self.commit();
+        self.receipts.get_mut(&tx_hash).unwrap().success = false;
+        tx_hash
+    }
+
+    // hash of a transaction taken but not mined, kept apart from the hashes commit hands out
+    fn unmined(&mut self) -> H256 {
+        self.num_unmined += 1;
+        let mut tx_hash = H256::from_low_u64_be(self.num_unmined);
+        tx_hash.0[0] = 0xff;
+        tx_hash
+    }
+
+    fn submit_vote(&mut self, sender: &Address, header: &[u8], sid: usize, bid: u64, sigx: U256, sigy: U256, bitset: U256) -> Result<(), Error> {
+        if !self.is_scale_node(sender) {
+            return Err(Error::Reverted("sender is not a scale node".to_string()));
+        }
+        self.check_sid(sid)?;
+        if bid != self.block_id[sid] + 1 {
+            return Err(Error::Reverted(format!("block id {} != {}", bid, self.block_id[sid] + 1)));
+        }
+        if bitset.bit(0) {
+            return Err(Error::Reverted("bit 0 of the bitset is reserved".to_string()));
+        }
+        let mut keys = vec![];
+        for j in 1..bitset.bits() {
+            if bitset.bit(j) {
+                let node = self.scale_nodes.get(j)
+                    .ok_or(Error::Reverted(format!("scale node {} does not exist", j)))?;
+                let (x1, x2, y1, y2) = node.pub_key;
+                keys.push(PublicKey::from_u256(x1, x2, y1, y2)
+                    .map_err(|_| Error::Reverted(format!("invalid key of scale node {}", j)))?);
+            }
+        }
+        if keys.len() < self.threshold {
+            return Err(Error::Reverted(format!("{} signers below threshold {}", keys.len(), self.threshold)));
+        }
+        let sig = Signature::from_u256(sigx, sigy)
+            .map_err(|_| Error::Reverted("invalid signature point".to_string()))?;
+        if !sig.verify_aggregate(header, &keys) {
+            return Err(Error::Reverted("pairing check failed".to_string()));
+        }
+
+        let prev: [u8; 32] = self.curr_hash[sid].into();
+        let header_hash: [u8; 32] = hash(header).into();
+        self.curr_hash[sid] = hash(&[prev, header_hash].concat());
+        self.signers[sid].push(bitset);
+        self.block_id[sid] += 1;
+        self.history[sid].push(EthBlkTransaction {
+            contract_state: ContractState {
+                sid: sid as u64,
+                curr_hash: self.curr_hash[sid],
+                block_id: self.block_id[sid],
+            },
+            block: Block::default(),
+            header: header.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct MockBackend {
+    chain: Arc<Mutex<MockChain>>,
+    sender: Address,
+}
+
+impl MockBackend {
+    // deploy a fresh contract administered by admin, the returned backend sends as admin
+    pub fn new(admin: Address) -> MockBackend {
+        MockBackend {
+            chain: Arc::new(Mutex::new(MockChain::new(admin))),
+            sender: admin,
+        }
+    }
+
+    // another account talking to the same contract
+    pub fn connect(&self, sender: Address) -> MockBackend {
+        MockBackend {
+            chain: self.chain.clone(),
+            sender,
+        }
+    }
+
+    pub fn set_threshold(&self, threshold: usize) {
+        self.chain.lock().unwrap().threshold = threshold;
+    }
+
+    // the next n transactions sent are taken but never mined
+    pub fn evict_next(&self, n: usize) {
+        self.chain.lock().unwrap().evict = n;
+    }
+
+    fn execute(&self, call: &Call) -> Result<H256, Error> {
+        let tx_hash = match call {
+            Call::AddScaleNode(address, ip_addr, x1, x2, y1, y2) => {
+                self.add_scale_node(*address, ip_addr.clone(), *x1, *x2, *y1, *y2)?
+            },
+            Call::AddSideNode(sid, address, ip_addr) => self.add_side_node(*sid, *address, ip_addr.clone())?,
+            Call::DeleteSideNode(sid, tid) => self.delete_side_node(*sid, *tid)?,
+            Call::SubmitVote(header, sid, bid, sigx, sigy, bitset) => {
+                self.submit_vote(header, *sid, *bid, *sigx, *sigy, *bitset)?
+            },
+            Call::ResetChain(sid) => self.reset_chain(*sid)?,
+            Call::AddSideChain => self.add_side_chain()?,
+            // the contract has no fallback function
+            Call::FillNonce => self.chain.lock().unwrap().revert(),
+        };
+        *self.chain.lock().unwrap().nonces.entry(self.sender).or_insert(0) += 1;
+        Ok(tx_hash)
+    }
+
+    // queued transactions of the sender follow once their nonce is next, each under the hash it was sent as
+    fn mine_queued(&self) -> Result<(), Error> {
+        loop {
+            let nonce = self.transaction_count()?;
+            let queued = self.chain.lock().unwrap().queued.remove(&(self.sender, nonce));
+            let (call, tx_hash) = match queued {
+                Some(q) => q,
+                None => return Ok(()),
+            };
+            let mined = self.execute(&call);
+            let mut chain = self.chain.lock().unwrap();
+            let receipt = match mined {
+                Ok(h) => chain.receipts.remove(&h).unwrap(),
+                Err(_) => {
+                    *chain.nonces.entry(self.sender).or_insert(0) += 1;
+                    let h = chain.revert();
+                    chain.receipts.remove(&h).unwrap()
+                },
+            };
+            chain.receipts.insert(tx_hash, Receipt { tx_hash, ..receipt });
+        }
+    }
+
+    pub fn add_side_chain(&self) -> Result<H256, Error> {
+        let mut chain = self.chain.lock().unwrap();
+        chain.block_id.push(0);
+        chain.curr_hash.push(StateHash::default());
+        chain.signers.push(vec![]);
+        chain.history.push(vec![]);
+        Ok(chain.commit())
+    }
+
+    pub fn get_signers(&self, sid: usize, bid: usize) -> Option<U256> {
+        let chain = self.chain.lock().unwrap();
+        chain.signers.get(sid).and_then(|s| s.get(bid)).cloned()
+    }
+
+    pub fn add_scale_node(&self, address: Address, _ip_addr: String, x1: U256, x2: U256, y1: U256, y2: U256) -> Result<H256, Error> {
+        let mut chain = self.chain.lock().unwrap();
+        if !chain.is_scale_node(&self.sender) {
+            return Err(Error::Reverted("sender is not a scale node".to_string()));
+        }
+        if address == chain.scale_nodes[0].eth_addr || chain.id_of(&address) != 0 {
+            return Err(Error::Reverted("scale node already registered".to_string()));
+        }
+        chain.scale_nodes.push(ScaleNode {
+            eth_addr: address,
+            pub_key: (x1, x2, y1, y2),
+        });
+        let id = chain.scale_nodes.len() - 1;
+        chain.scale_id.insert(address, id);
+        Ok(chain.commit())
+    }
+
+    pub fn add_side_node(&self, sid: U256, address: Address, ip_addr: String) -> Result<H256, Error> {
+        let mut chain = self.chain.lock().unwrap();
+        if sid >= U256::from(NUM_TOKEN_RINGS) {
+            return Err(Error::Reverted(format!("token ring {} does not exist", sid)));
+        }
+        chain.token_rings[sid.as_usize()].push((address, ip_addr));
+        Ok(chain.commit())
+    }
+
+    pub fn delete_side_node(&self, sid: U256, tid: U256) -> Result<H256, Error> {
+        let mut chain = self.chain.lock().unwrap();
+        if sid >= U256::from(NUM_TOKEN_RINGS) || tid >= U256::from(chain.token_rings[sid.as_usize()].len()) {
+            return Err(Error::Reverted(format!("side node {} {} does not exist", sid, tid)));
+        }
+        chain.token_rings[sid.as_usize()].remove(tid.as_usize());
+        Ok(chain.commit())
+    }
+
+    pub fn submit_vote(&self, header: &str, sid: U256, bid: U256, sigx: U256, sigy: U256, bitset: U256) -> Result<H256, Error> {
+        let header = hex::decode(header).map_err(|e| Error::Encoding(format!("{:?}", e)))?;
+        if sid.bits() > 64 || bid.bits() > 64 {
+            return Err(Error::Reverted("id out of range".to_string()));
+        }
+        let mut chain = self.chain.lock().unwrap();
+        chain.submit_vote(&self.sender, &header, sid.as_usize(), bid.as_u64(), sigx, sigy, bitset)?;
+        Ok(chain.commit())
+    }
+
+    pub fn reset_chain(&self, sid: usize) -> Result<H256, Error> {
+        let mut chain = self.chain.lock().unwrap();
+        chain.check_sid(sid)?;
+        chain.block_id[sid] = 0;
+        chain.curr_hash[sid] = StateHash::default();
+        chain.signers[sid].clear();
+        chain.history[sid].clear();
+        Ok(chain.commit())
+    }
+}
+
+impl ContractBackend for MockBackend {
+    fn get_curr_state(&self, sid: usize) -> Result<ContractState, Error> {
+        let chain = self.chain.lock().unwrap();
+        chain.check_sid(sid)?;
+        Ok(ContractState {
+            sid: sid as u64,
+            curr_hash: chain.curr_hash[sid],
+            block_id: chain.block_id[sid],
+        })
+    }
+
+    fn count_scale_nodes(&self) -> Result<usize, Error> {
+        Ok(self.chain.lock().unwrap().scale_nodes.len())
+    }
+
+    fn get_scale_node(&self, index: usize) -> Result<Address, Error> {
+        let chain = self.chain.lock().unwrap();
+        chain.scale_nodes.get(index)
+            .map(|n| n.eth_addr)
+            .ok_or(Error::Reverted(format!("scale node {} does not exist", index)))
+    }
+
+    fn get_scale_id(&self, addr: Address) -> Result<U256, Error> {
+        Ok(U256::from(self.chain.lock().unwrap().id_of(&addr)))
+    }
+
+    fn get_scale_pub_key(&self, addr: Address) -> Result<(U256, U256, U256, U256), Error> {
+        let chain = self.chain.lock().unwrap();
+        Ok(chain.scale_nodes[chain.id_of(&addr)].pub_key)
+    }
+
+    fn count_side_chains(&self) -> Result<usize, Error> {
+        Ok(self.chain.lock().unwrap().block_id.len())
+    }
+
+    // [start, end) of side chain sid, end 0 means up to the tip
+    fn get_all(&self, sid: usize, start: usize, end: usize) -> Result<Vec<EthBlkTransaction>, Error> {
+        let chain = self.chain.lock().unwrap();
+        chain.check_sid(sid)?;
+        let history = &chain.history[sid];
+        let end = if end == 0 || end > history.len() { history.len() } else { end };
+        let start = std::cmp::min(start, end);
+        Ok(history[start..end].to_vec())
+    }
+
+    fn transaction_count(&self) -> Result<U256, Error> {
+        let chain = self.chain.lock().unwrap();
+        Ok(U256::from(*chain.nonces.get(&self.sender).unwrap_or(&0)))
+    }
+
+    fn gas_price(&self) -> Result<U256, Error> {
+        Ok(U256::from(GWEI))
+    }
+
+    // every accepted transaction is mined in a block of its own
+    fn block_number(&self) -> Result<u64, Error> {
+        Ok(self.chain.lock().unwrap().num_tx)
+    }
+
+    // a transaction under the next nonce is mined right away, one above it waits for the gap
+    // to be filled
+    fn send(&self, call: &Call, tx: &TxParams) -> Result<H256, Error> {
+        let nonce = self.transaction_count()?;
+        if tx.nonce < nonce {
+            return Err(Error::Rpc(format!("nonce {} is used, next is {}", tx.nonce, nonce)));
+        }
+        {
+            let mut chain = self.chain.lock().unwrap();
+            if chain.evict > 0 {
+                chain.evict -= 1;
+                return Ok(chain.unmined());
+            }
+            if tx.nonce > nonce {
+                let tx_hash = chain.unmined();
+                chain.queued.insert((self.sender, tx.nonce), (call.clone(), tx_hash));
+                return Ok(tx_hash);
+            }
+        }
+        let tx_hash = self.execute(call)?;
+        self.mine_queued()?;
+        Ok(tx_hash)
+    }
+
+    fn transaction_receipt(&self, tx_hash: H256) -> Result<Option<Receipt>, Error> {
+        Ok(self.chain.lock().unwrap().receipts.get(&tx_hash).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::bls::SecretKey;
+
+    fn address(i: u64) -> Address {
+        Address::from_low_u64_be(i)
+    }
+
+    // admin plus n registered scale nodes with keys 1..=n
+    fn setup(n: u64) -> (MockBackend, Vec<SecretKey>) {
+        let admin = MockBackend::new(address(100));
+        let mut keys = vec![];
+        for i in 1..=n {
+            let sk = SecretKey::from_u256(U256::from(i * 1000 + 7)).unwrap();
+            let (x1, x2, y1, y2) = sk.public_key().to_u256();
+            admin.add_scale_node(address(i), format!("127.0.0.1:{}", 40000 + i), x1, x2, y1, y2).unwrap();
+            keys.push(sk);
+        }
+        (admin, keys)
+    }
+
+    fn vote(keys: &[SecretKey], ids: &[usize], header: &[u8]) -> (U256, U256, U256) {
+        let sigs: Vec<Signature> = ids.iter().map(|i| keys[i - 1].sign(header)).collect();
+        let (x, y) = Signature::aggregate(&sigs).to_u256();
+        let bitset = ids.iter().fold(U256::zero(), |acc, i| acc | (U256::one() << *i));
+        (x, y, bitset)
+    }
+
+    #[test]
+    fn vote_advances_hash_chain() {
+        let (admin, keys) = setup(3);
+        let node = admin.connect(address(1));
+        let header = vec![1u8, 2, 3, 4];
+        let (x, y, bitset) = vote(&keys, &[1, 2, 3], &header);
+
+        let tx_hash = node.submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, bitset).unwrap();
+        assert!(node.transaction_receipt(tx_hash).unwrap().unwrap().success);
+
+        let state = admin.get_curr_state(0).unwrap();
+        let header_hash: [u8; 32] = hash(&header).into();
+        let expected = hash(&[[0u8; 32], header_hash].concat());
+        assert_eq!(state, ContractState { sid: 0, curr_hash: expected, block_id: 1 });
+        assert_eq!(admin.get_signers(0, 0), Some(bitset));
+        assert_eq!(admin.get_all(0, 0, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn vote_requires_next_block_id() {
+        let (admin, keys) = setup(2);
+        let node = admin.connect(address(2));
+        let header = vec![9u8; 16];
+        let (x, y, bitset) = vote(&keys, &[1, 2], &header);
+        let err = node.submit_vote(&hex::encode(&header), U256::zero(), U256::from(2), x, y, bitset);
+        assert!(err.is_err());
+        assert_eq!(admin.get_curr_state(0).unwrap().block_id, 0);
+    }
+
+    #[test]
+    fn vote_rejects_bad_signature_and_outsiders() {
+        let (admin, keys) = setup(3);
+        let header = vec![7u8; 16];
+        let (x, y, _) = vote(&keys, &[1, 2], &header);
+        // claims a signer that did not sign
+        let bitset = (U256::one() << 1) | (U256::one() << 2) | (U256::one() << 3);
+        assert!(admin.connect(address(1)).submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, bitset).is_err());
+
+        let (x, y, bitset) = vote(&keys, &[1, 2], &header);
+        assert!(admin.connect(address(50)).submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, bitset).is_err());
+        assert!(admin.connect(address(1)).submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, bitset).is_ok());
+    }
+
+    #[test]
+    fn vote_below_threshold() {
+        let (admin, keys) = setup(3);
+        admin.set_threshold(3);
+        let header = vec![5u8; 16];
+        let (x, y, bitset) = vote(&keys, &[1, 2], &header);
+        assert!(admin.submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, bitset).is_err());
+    }
+
+    #[test]
+    fn registration_and_reset() {
+        let (admin, keys) = setup(2);
+        assert_eq!(admin.count_scale_nodes().unwrap(), 3);
+        assert_eq!(admin.get_scale_node(2).unwrap(), address(2));
+        assert_eq!(admin.get_scale_id(address(2)).unwrap(), U256::from(2));
+        assert_eq!(admin.get_scale_pub_key(address(1)).unwrap(), keys[0].public_key().to_u256());
+        // duplicates and outsiders cannot register
+        assert!(admin.add_scale_node(address(1), String::new(), U256::zero(), U256::zero(), U256::zero(), U256::zero()).is_err());
+        assert!(admin.connect(address(60)).add_scale_node(address(61), String::new(), U256::zero(), U256::zero(), U256::zero(), U256::zero()).is_err());
+
+        let header = vec![3u8; 16];
+        let (x, y, bitset) = vote(&keys, &[1, 2], &header);
+        admin.submit_vote(&hex::encode(&header), U256::zero(), U256::one(), x, y, bitset).unwrap();
+        admin.reset_chain(0).unwrap();
+        assert_eq!(admin.get_curr_state(0).unwrap(), ContractState::genesis(0));
+    }
+
+    // a side node proposes, scale nodes sign, one of them submits the vote through its contract
+    // thread and a main node following the contract commits the proposal, all on one MockBackend
+    #[test]
+    fn offline_cluster_proposes_votes_and_finalizes() {
+        use super::super::contract::{Contract, Account};
+        use super::super::gas::GasPolicy;
+        use super::super::interface::{Answer, Response};
+        use super::super::utils::BLSKey;
+        use crate::blockchain::blockchain::{BlockChain, ChainUpdate};
+        use crossbeam::channel;
+        use mio_extras::channel as mio_channel;
+
+        let (admin, keys) = setup(3);
+        let contract_of = |sender: Address| {
+            let (performer_sender, _) = channel::unbounded();
+            let (server_control_sender, _) = mio_channel::channel();
+            let (_, contract_handle) = channel::unbounded();
+            let key = BLSKey { sk: U256::zero(), pkx1: U256::zero(), pkx2: U256::zero(), pky1: U256::zero(), pky2: U256::zero() };
+            let account = Account { address: sender, private_key: String::new() };
+            Contract::with_backend(Box::new(admin.connect(sender)), account, key, performer_sender,
+                server_control_sender, contract_handle, "127.0.0.1".to_owned(), GasPolicy::default())
+        };
+        let mut aggregator = contract_of(address(1));
+        let mut main_contract = contract_of(address(200));
+        let mut side_chain = BlockChain::new();
+        let mut main_chain = BlockChain::new();
+        let (state_tx, state_rx) = channel::unbounded();
+        main_contract.watch_state(0, state_tx);
+        assert_eq!(state_rx.try_recv(), Ok(ContractState::genesis(0)));
+
+        for block_id in 1..=2u64 {
+            // propose
+            let header = vec![block_id as u8; 16];
+            let prev_hash = side_chain.get_latest_state(0).unwrap().curr_hash;
+            let proposal = side_chain.propose(0, block_id, prev_hash, &header);
+            assert_eq!(main_chain.propose(0, block_id, prev_hash, &header), proposal);
+            assert_eq!(main_chain.pending_chain(0), vec![proposal]);
+
+            // vote, every partial signature is checked before it is aggregated
+            let sigs: Vec<Signature> = keys.iter().map(|sk| sk.sign(&header)).collect();
+            assert!(sigs.iter().zip(keys.iter()).all(|(sig, sk)| sig.verify(&header, &sk.public_key())));
+            let (x, y) = Signature::aggregate(&sigs).to_u256();
+            let bitset = (1..=3).fold(U256::zero(), |acc, i| acc | (U256::one() << i));
+            let (answer_tx, answer_rx) = channel::bounded(1);
+            let call = Call::SubmitVote(hex::encode(&header), U256::zero(), U256::from(block_id), x, y, bitset);
+            aggregator.submit(call, Some(answer_tx));
+            aggregator.poll_pending();
+            match answer_rx.try_recv() {
+                Ok(Answer::Success(Response::TxReceipt(receipt))) => assert!(receipt.success),
+                _ => panic!("vote on block {} not mined", block_id),
+            }
+
+            // finalize, the pushed state commits the proposal on both nodes
+            main_contract.poll_watched();
+            let state = state_rx.try_recv().unwrap();
+            assert_eq!(state, ContractState { sid: 0, curr_hash: proposal, block_id });
+            assert_eq!(main_chain.update(&state), ChainUpdate::Appended);
+            assert_eq!(side_chain.update(&state), ChainUpdate::Appended);
+            assert!(main_chain.pending_chain(0).is_empty());
+        }
+        assert_eq!(main_chain.get_height(0), 3);
+        assert_eq!(admin.get_all(0, 0, 0).unwrap().len(), 2);
+        assert_eq!(admin.get_signers(0, 1), Some((1..=3).fold(U256::zero(), |acc, i| acc | (U256::one() << i))));
+    }
+
+    #[test]
+    fn side_chains_are_independent() {
+        let (admin, keys) = setup(2);
+        admin.add_side_chain().unwrap();
+        assert_eq!(admin.count_side_chains().unwrap(), 2);
+
+        let header = vec![4u8; 16];
+        let (x, y, bitset) = vote(&keys, &[1, 2], &header);
+        admin.submit_vote(&hex::encode(&header), U256::one(), U256::one(), x, y, bitset).unwrap();
+        assert_eq!(admin.get_curr_state(1).unwrap().block_id, 1);
+        assert_eq!(admin.get_curr_state(1).unwrap().sid, 1);
+        assert_eq!(admin.get_curr_state(0).unwrap(), ContractState::genesis(0));
+        assert_eq!(admin.get_all(1, 0, 0).unwrap().len(), 1);
+        assert!(admin.get_all(0, 0, 0).unwrap().is_empty());
+        assert!(admin.get_all(2, 0, 0).is_err());
+    }
+}
@@ -1,5 +1,10 @@
+pub mod abi;
+pub mod backend;
 pub mod contract;
+pub mod gas;
+pub mod history;
 pub mod interface;
+pub mod mock;
 pub mod utils;
 
 use super::primitive;
@@ -7,4 +12,5 @@ use super::network;
 use super::mempool;
 use super::blockchain;
 use super::db;
+use super::crypto;
 
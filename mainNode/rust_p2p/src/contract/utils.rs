@@ -1,4 +1,3 @@
-use std::process::Command;
 use web3::types::{Address, U256};
 
 use crypto::sha3::Sha3;
@@ -7,6 +6,7 @@ use secp256k1::{Secp256k1, SecretKey};
 use crate::primitive::block::Block;
 use bincode::{deserialize};
 use serde::{Serialize, Deserialize};
+use super::abi::{SCALECHAIN_ABI, AbiError, ParamType, Token, decode, uint_to_usize};
 
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -39,142 +39,58 @@ impl BLSKey {
     }
 }
 
-pub fn _encode_sendBlock(block: String, signature: String, new_blk_id: U256) -> Vec<u8> {
-    let command = format!("./ethabi encode function --lenient ./abi.json sendBlock -p {} -p {} -p {}", block, signature, new_blk_id);
-    //println!("command {}", command.clone());
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
-}
-
-pub fn _encode_addScaleNode(address: Address, ip_addr: String, x1: U256, x2: U256, y1: U256, y2: U256) -> Vec<u8> {
-    let addr = hex::encode(address.as_bytes());
-    //let addr = addr.replace("0x", "");
-    let command = format!("./ethabi encode function --lenient ./abi.json addScaleNode -p {} -p {} -p {} -p {} -p {} -p {}", addr, ip_addr, x1, x2, y1, y2);
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-    //println!("{:?}", output);
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
+fn _encode_call(name: &str, args: Vec<Token>) -> Result<Vec<u8>, AbiError> {
+    SCALECHAIN_ABI.function(name)?.encode_input(&args)
 }
 
-pub fn _encode_addSideNode(sid: U256, address: Address, ip_addr: String) -> Vec<u8> {
-    let addr = hex::encode(address.as_bytes());
-    //let addr = addr.replace("0x", "");
-    let command = format!("./ethabi encode function --lenient ./abi.json addSideNode -p {} -p {} -p {}", sid, addr, ip_addr);
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-   // println!("{:?}", output);
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
+pub fn _encode_addScaleNode(address: Address, ip_addr: String, x1: U256, x2: U256, y1: U256, y2: U256) -> Result<Vec<u8>, AbiError> {
+    _encode_call("addScaleNode", vec![
+        Token::Address(address),
+        Token::String(ip_addr),
+        Token::Uint(x1),
+        Token::Uint(x2),
+        Token::Uint(y1),
+        Token::Uint(y2),
+    ])
 }
 
-pub fn _encode_deleteSideNode(sid: U256, tid: U256) -> Vec<u8> {
-    let command = format!("./ethabi encode function --lenient ./abi.json deleteSideNode -p {} -p {}", sid, tid);
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-   // println!("{:?}", output);
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
+pub fn _encode_addSideNode(sid: U256, address: Address, ip_addr: String) -> Result<Vec<u8>, AbiError> {
+    _encode_call("addSideNode", vec![Token::Uint(sid), Token::Address(address), Token::String(ip_addr)])
 }
 
-
-pub fn _encode_submitVote(block: String, sid: U256, bid: U256, sigx: U256, sigy: U256, bitset: U256) -> Vec<u8> {
-    let command = format!("./ethabi encode function --lenient ./abi.json submitVote -p {:?} -p {} -p {} -p {} -p {} -p {}", block, sid, bid, sigx, sigy, bitset);
-    //println!("command {}", command.clone());
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
+pub fn _encode_deleteSideNode(sid: U256, tid: U256) -> Result<Vec<u8>, AbiError> {
+    _encode_call("deleteSideNode", vec![Token::Uint(sid), Token::Uint(tid)])
 }
 
-pub fn _encode_resetSideChain(sid: U256) -> Vec<u8> {
-    let command = format!("./ethabi encode function --lenient ./abi.json resetSideChain -p {}", sid);
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-    //println!("{:?}", output);
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
+// block is the hex encoded header, it goes on chain as raw bytes
+pub fn _encode_submitVote(block: String, sid: U256, bid: U256, sigx: U256, sigy: U256, bitset: U256) -> Result<Vec<u8>, AbiError> {
+    let header = hex::decode(block.trim_start_matches("0x")).map_err(|e| AbiError::InvalidData(e.to_string()))?;
+    _encode_call("submitVote", vec![
+        Token::Bytes(header),
+        Token::Uint(sid),
+        Token::Uint(bid),
+        Token::Uint(sigx),
+        Token::Uint(sigy),
+        Token::Uint(bitset),
+    ])
 }
 
-pub fn _encode_resetSideNode(sid: U256) -> Vec<u8> {
-    
-
-    let command = format!("./ethabi encode function --lenient ./abi.json deleteSideNode -p {}", sid, );
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-    //println!("{:?}", output);
-
-    let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    return function_abi;
+pub fn _encode_resetSideChain(sid: U256) -> Result<Vec<u8>, AbiError> {
+    _encode_call("resetSideChain", vec![Token::Uint(sid)])
 }
 
-pub fn _decode_sendBlock(input: &str) -> (String, usize) {
-    let command = format!("./ethabi decode params -t string -t bytes -t uint256 {}", input);
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-    let params = std::str::from_utf8(&output.stdout).unwrap().split("\n");
-    let params: Vec<&str> = params.collect();
-    //println!("ethabu output {:?}", params);
-    let block = params[0].replace("string ", "");
-    let block_id = params[2].replace("uint256 ", "");
-    let block_id = usize::from_str_radix(&block_id, 16).unwrap();
-    (block, block_id)
+pub fn _encode_addSideChain() -> Result<Vec<u8>, AbiError> {
+    _encode_call("addSideChain", vec![])
 }
 
-pub fn _sign_bls(msg: String, key_file: String, bin_path: &str) -> (String, String) {
-    let command = bin_path.to_string() + &format!( "/sign -msg={} -key={}", msg, key_file);
-    //info!("command {}", command.clone());
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-
-    //let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    let sig = std::str::from_utf8(&output.stdout).unwrap().split("\n");
-    let sig: Vec<&str> = sig.collect();
-    return (sig[0].to_string(), sig[1].to_string());
-
-}
-
-//pub fn _aggregate_sig(x1: String, y1: String, x2: String, y2: String)-> (String, String) {
-    //let command = format!("./aggregate -x1={} -y1={} -x2={} -y2={}", x1, y1, x2, y2);
-    //println!("command {}", command.clone());
-    //let output = Command::new("sh").arg("-c")
-        //.arg(command)
-        //.output().unwrap();
-
-    ////let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    //let sig = std::str::from_utf8(&output.stdout).unwrap().split("\n");
-    //let sig: Vec<&str> = sig.collect();
-    //return (sig[0].to_string(), sig[1].to_string());
-//}
-
-pub fn _aggregate_sig(x1: String, y1: String, x2: String, y2: String, bin_dir: &str)-> (String, String) {
-    let command = bin_dir.to_string() + &format!("/aggregate -x1={} -y1={} -x2={} -y2={}", x1, y1, x2, y2);
-    //info!("command {}", command.clone());
-    let output = Command::new("sh").arg("-c")
-        .arg(command)
-        .output().unwrap();
-
-    //let function_abi = hex::decode(std::str::from_utf8(&output.stdout).unwrap().trim()).unwrap();
-    let sig = std::str::from_utf8(&output.stdout).unwrap().split("\n");
-    let sig: Vec<&str> = sig.collect();
-    return (sig[0].to_string(), sig[1].to_string());
+// input is the hex encoded calldata without the function selector
+pub fn _decode_sendBlock(input: &str) -> Result<(String, usize), AbiError> {
+    let data = hex::decode(input).map_err(|e| AbiError::InvalidData(e.to_string()))?;
+    let params = [ParamType::String, ParamType::Bytes, ParamType::Uint(256)];
+    match decode(&params, &data)?.as_slice() {
+        [Token::String(block), _, Token::Uint(block_id)] => Ok((block.clone(), uint_to_usize(block_id)?)),
+        _ => Err(AbiError::InvalidData("unexpected sendBlock params".to_string())),
+    }
 }
 
 pub fn _hash_message(message: &[u8], result: &mut [u8]) {
@@ -221,6 +137,79 @@ pub fn _sign_block(block: &str, private_key: &[u8]) -> String {
     return format!("{}{}{}", hex::encode(r), hex::encode(s), hex::encode([v.to_i32() as u8 + 27]));
 }
 
+fn _rlp_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    if data.len() == 1 && data[0] < 0x80 {
+        out.push(data[0]);
+    } else {
+        _rlp_length(out, data.len(), 0x80);
+        out.extend_from_slice(data);
+    }
+}
+
+fn _rlp_uint(out: &mut Vec<u8>, value: U256) {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(32);
+    _rlp_bytes(out, &bytes[first..]);
+}
+
+fn _rlp_list(out: &mut Vec<u8>, items: &[u8]) {
+    _rlp_length(out, items.len(), 0xc0);
+    out.extend_from_slice(items);
+}
+
+fn _rlp_length(out: &mut Vec<u8>, len: usize, offset: u8) {
+    if len <= 55 {
+        out.push(offset + len as u8);
+    } else {
+        let bytes = (len as u64).to_be_bytes();
+        let first = bytes.iter().position(|b| *b != 0).unwrap();
+        out.push(offset + 55 + (8 - first) as u8);
+        out.extend_from_slice(&bytes[first..]);
+    }
+}
+
+// typed (0x02) transaction of EIP-1559, ethereum-tx-sign only knows legacy ones
+pub fn _sign_eip1559_tx(
+    chain_id: u64,
+    nonce: U256,
+    priority_fee: U256,
+    max_fee: U256,
+    gas: U256,
+    to: Address,
+    data: &[u8],
+    private_key: &[u8],
+) -> Vec<u8> {
+    let mut fields = vec![];
+    _rlp_uint(&mut fields, U256::from(chain_id));
+    _rlp_uint(&mut fields, nonce);
+    _rlp_uint(&mut fields, priority_fee);
+    _rlp_uint(&mut fields, max_fee);
+    _rlp_uint(&mut fields, gas);
+    _rlp_bytes(&mut fields, to.as_bytes());
+    _rlp_uint(&mut fields, U256::zero());
+    _rlp_bytes(&mut fields, data);
+    // empty access list
+    _rlp_list(&mut fields, &[]);
+
+    let mut unsigned = vec![0x02];
+    _rlp_list(&mut unsigned, &fields);
+    let mut hash = [0u8; 32];
+    hash_header(&unsigned, &mut hash);
+
+    let secp = Secp256k1::new();
+    let sk = SecretKey::from_slice(private_key).unwrap();
+    let msg = secp256k1::Message::from_slice(&hash).unwrap();
+    let (v, sig) = secp.sign_recoverable(&msg, &sk).serialize_compact();
+    _rlp_uint(&mut fields, U256::from(v.to_i32() as u64));
+    _rlp_uint(&mut fields, U256::from_big_endian(&sig[0..32]));
+    _rlp_uint(&mut fields, U256::from_big_endian(&sig[32..64]));
+
+    let mut signed = vec![0x02];
+    _rlp_list(&mut signed, &fields);
+    signed
+}
+
 pub fn _convert_u256(value: U256) -> ethereum_types::U256 {
     let U256(ref arr) = value;
     let mut ret = [0; 4];
@@ -290,3 +279,116 @@ pub fn _count_sig(x: usize) -> usize {
     }
     cnt
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::abi::encode;
+
+    fn rlp_bytes(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        _rlp_bytes(&mut out, data);
+        out
+    }
+
+    fn rlp_uint(v: u64) -> Vec<u8> {
+        let mut out = vec![];
+        _rlp_uint(&mut out, U256::from(v));
+        out
+    }
+
+    #[test]
+    fn send_block_with_oversized_block_id_is_an_error() {
+        // sendBlock is not in the v2 abi, its params are laid out by hand
+        let params = [ParamType::String, ParamType::Bytes, ParamType::Uint(256)];
+        let calldata = |block_id: U256| encode(&params, &[
+            Token::String("header".to_string()),
+            Token::Bytes(vec![0x0a, 0x0b]),
+            Token::Uint(block_id),
+        ]).unwrap();
+        assert_eq!(_decode_sendBlock(&hex::encode(calldata(U256::from(7)))).unwrap(), ("header".to_string(), 7));
+        match _decode_sendBlock(&hex::encode(calldata(U256::max_value()))) {
+            Err(AbiError::InvalidData(_)) => (),
+            other => panic!("decoded {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rlp_encoding() {
+        assert_eq!(rlp_bytes(b"dog"), b"\x83dog".to_vec());
+        assert_eq!(rlp_bytes(b""), vec![0x80]);
+        assert_eq!(rlp_bytes(&[0x7f]), vec![0x7f]);
+        assert_eq!(rlp_uint(0), vec![0x80]);
+        assert_eq!(rlp_uint(15), vec![0x0f]);
+        assert_eq!(rlp_uint(1024), vec![0x82, 0x04, 0x00]);
+        let long = vec![0x61u8; 56];
+        assert_eq!(rlp_bytes(&long)[..2], [0xb8, 56]);
+
+        let mut items = rlp_bytes(b"cat");
+        items.extend(rlp_bytes(b"dog"));
+        let mut list = vec![];
+        _rlp_list(&mut list, &items);
+        assert_eq!(list, hex::decode("c88363617483646f67").unwrap());
+    }
+
+    // (payload, encoded length) of the item at the start of data
+    fn rlp_item(data: &[u8]) -> (&[u8], usize) {
+        let b = data[0];
+        let (offset, short) = if b < 0x80 {
+            return (&data[..1], 1);
+        } else if b < 0xc0 {
+            (0x80, 0xb7)
+        } else {
+            (0xc0, 0xf7)
+        };
+        if b <= short {
+            let len = (b - offset) as usize;
+            (&data[1..1 + len], 1 + len)
+        } else {
+            let len_len = (b - short) as usize;
+            let len = data[1..1 + len_len].iter().fold(0usize, |acc, x| acc * 256 + *x as usize);
+            (&data[1 + len_len..1 + len_len + len], 1 + len_len + len)
+        }
+    }
+
+    #[test]
+    fn eip1559_signature_recovers_sender() {
+        let key = [7u8; 32];
+        let tx = _sign_eip1559_tx(42, U256::from(3), U256::from(2), U256::from(40), U256::from(21000), Address::from_low_u64_be(9), &[1, 2, 3], &key);
+        assert_eq!(tx[0], 0x02);
+        let (body, len) = rlp_item(&tx[1..]);
+        assert_eq!(len, tx.len() - 1);
+
+        let mut items = vec![];
+        let mut pos = 0;
+        while pos < body.len() {
+            let (payload, len) = rlp_item(&body[pos..]);
+            items.push((pos, payload));
+            pos += len;
+        }
+        assert_eq!(items.len(), 12);
+        assert_eq!(items[0].1, &[42u8][..]);
+        assert_eq!(items[5].1, Address::from_low_u64_be(9).as_bytes());
+        assert_eq!(items[7].1, &[1u8, 2, 3][..]);
+
+        // signing payload is the type byte and the first nine fields
+        let mut unsigned = vec![0x02];
+        _rlp_list(&mut unsigned, &body[..items[9].0]);
+        let mut hash = [0u8; 32];
+        hash_header(&unsigned, &mut hash);
+
+        let word = |b: &[u8]| {
+            let mut w = [0u8; 32];
+            w[32 - b.len()..].copy_from_slice(b);
+            w
+        };
+        let v = if items[9].1.is_empty() { 0 } else { items[9].1[0] as i32 };
+        let rec_id = secp256k1::recovery::RecoveryId::from_i32(v).unwrap();
+        let compact = [word(items[10].1), word(items[11].1)].concat();
+        let sig = secp256k1::recovery::RecoverableSignature::from_compact(&compact, rec_id).unwrap();
+        let msg = secp256k1::Message::from_slice(&hash).unwrap();
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&key).unwrap();
+        assert_eq!(secp.recover(&msg, &sig).unwrap(), secp256k1::PublicKey::from_secret_key(&secp, &sk));
+    }
+}
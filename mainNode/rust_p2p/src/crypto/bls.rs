@@ -0,0 +1,276 @@
+// BLS signatures over BN254 (alt_bn128), compatible with ScaleChain.sol: signatures live in
+// G1, public keys in G2, and hashToG1(msg) = keccak256(msg) * P1. Points cross the contract
+// boundary as U256 coordinates, G2 coordinates ordered (x_imag, x_real, y_imag, y_real).
+use bn::{Fr, Fq, Fq2, G1, G2, AffineG1, AffineG2, Group, Gt, pairing};
+use crypto::sha3::Sha3;
+use crypto::digest::Digest;
+use web3::types::U256;
+use std::fmt;
+
+// order of G1 and G2
+const CURVE_ORDER: &str = "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlsError {
+    InvalidScalar,
+    InvalidPoint,
+}
+
+impl fmt::Display for BlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlsError::InvalidScalar => write!(f, "scalar is not in the curve order"),
+            BlsError::InvalidPoint => write!(f, "coordinates are not a point on the curve"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SecretKey(Fr);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PublicKey(G2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signature(G1);
+
+fn u256_to_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn fq_from_u256(value: U256) -> Result<Fq, BlsError> {
+    Fq::from_slice(&u256_to_bytes(value)).map_err(|_| BlsError::InvalidPoint)
+}
+
+fn fq_to_u256(value: Fq) -> U256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes).expect("32 bytes buffer");
+    U256::from_big_endian(&bytes)
+}
+
+// reduce an arbitrary 256 bit scalar, as the ecMul precompile does implicitly
+fn fr_from_u256(value: U256) -> Fr {
+    let order = U256::from_dec_str(CURVE_ORDER).unwrap();
+    Fr::from_slice(&u256_to_bytes(value % order)).expect("reduced scalar")
+}
+
+pub fn hash_to_g1(message: &[u8]) -> G1 {
+    let mut hasher = Sha3::keccak256();
+    hasher.input(message);
+    let mut digest = [0u8; 32];
+    hasher.result(&mut digest);
+    G1::one() * fr_from_u256(U256::from_big_endian(&digest))
+}
+
+impl SecretKey {
+    pub fn from_u256(sk: U256) -> Result<SecretKey, BlsError> {
+        let order = U256::from_dec_str(CURVE_ORDER).unwrap();
+        if sk.is_zero() || sk >= order {
+            return Err(BlsError::InvalidScalar);
+        }
+        Ok(SecretKey(fr_from_u256(sk)))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(G2::one() * self.0)
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature(hash_to_g1(message) * self.0)
+    }
+}
+
+impl PublicKey {
+    pub fn from_u256(x1: U256, x2: U256, y1: U256, y2: U256) -> Result<PublicKey, BlsError> {
+        if x1.is_zero() && x2.is_zero() && y1.is_zero() && y2.is_zero() {
+            return Ok(PublicKey(G2::zero()));
+        }
+        let x = Fq2::new(fq_from_u256(x2)?, fq_from_u256(x1)?);
+        let y = Fq2::new(fq_from_u256(y2)?, fq_from_u256(y1)?);
+        match AffineG2::new(x, y) {
+            Ok(p) => Ok(PublicKey(G2::from(p))),
+            Err(_) => Err(BlsError::InvalidPoint),
+        }
+    }
+
+    pub fn to_u256(&self) -> (U256, U256, U256, U256) {
+        match AffineG2::from_jacobian(self.0) {
+            Some(p) => (
+                fq_to_u256(p.x().imaginary()),
+                fq_to_u256(p.x().real()),
+                fq_to_u256(p.y().imaginary()),
+                fq_to_u256(p.y().real()),
+            ),
+            None => (U256::zero(), U256::zero(), U256::zero(), U256::zero()),
+        }
+    }
+
+    pub fn aggregate(keys: &[PublicKey]) -> PublicKey {
+        PublicKey(keys.iter().fold(G2::zero(), |acc, k| acc + k.0))
+    }
+}
+
+impl Signature {
+    pub fn from_u256(x: U256, y: U256) -> Result<Signature, BlsError> {
+        if x.is_zero() && y.is_zero() {
+            return Ok(Signature(G1::zero()));
+        }
+        match AffineG1::new(fq_from_u256(x)?, fq_from_u256(y)?) {
+            Ok(p) => Ok(Signature(G1::from(p))),
+            Err(_) => Err(BlsError::InvalidPoint),
+        }
+    }
+
+    // signatures travel between scale nodes as decimal strings
+    pub fn from_dec_str(x: &str, y: &str) -> Result<Signature, BlsError> {
+        let x = U256::from_dec_str(x).map_err(|_| BlsError::InvalidPoint)?;
+        let y = U256::from_dec_str(y).map_err(|_| BlsError::InvalidPoint)?;
+        Signature::from_u256(x, y)
+    }
+
+    pub fn to_u256(&self) -> (U256, U256) {
+        match AffineG1::from_jacobian(self.0) {
+            Some(p) => (fq_to_u256(p.x()), fq_to_u256(p.y())),
+            None => (U256::zero(), U256::zero()),
+        }
+    }
+
+    pub fn to_dec_str(&self) -> (String, String) {
+        let (x, y) = self.to_u256();
+        (x.to_string(), y.to_string())
+    }
+
+    pub fn aggregate(sigs: &[Signature]) -> Signature {
+        Signature(sigs.iter().fold(G1::zero(), |acc, s| acc + s.0))
+    }
+
+    // e(-sig, P2) * e(H(m), pk) == 1, the check performed by verifyBLS
+    pub fn verify(&self, message: &[u8], key: &PublicKey) -> bool {
+        pairing(-self.0, G2::one()) * pairing(hash_to_g1(message), key.0) == Gt::one()
+    }
+
+    // verify an aggregate against individual signer keys, mirroring submitVote
+    pub fn verify_aggregate(&self, message: &[u8], keys: &[PublicKey]) -> bool {
+        let h = hash_to_g1(message);
+        keys.iter().fold(pairing(-self.0, G2::one()), |acc, k| acc * pairing(h, k.0)) == Gt::one()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SK: &str = "20250646353110123449699264563083966954462832061600201718859862147640241933469";
+    const PK: [&str; 4] = [
+        "20463107601567689379043894111161309101793221010918959701183133962198446200961",
+        "8494783984622751197528201792130574051194294223045816959893047927598081273021",
+        "19637338210540423249416917512715549470433934050274842576656764765006930548599",
+        "21025453705579530374080523368188600003975435295195183377042484907526591511696",
+    ];
+    const G2_GEN: [&str; 4] = [
+        "11559732032986387107991004021392285783925812861821192530917403151452391805634",
+        "10857046999023057135944570762232829481370756359578518086990519993285655852781",
+        "4082367875863433681332203403145435568316851327593401208105741076214120093531",
+        "8495653923123431417604973247489272438418190587263600148770280649306958101930",
+    ];
+    const FIELD_MODULUS: &str = "21888242871839275222246405745257275088696311157297823662689037894645226208583";
+
+    fn dec(s: &str) -> U256 {
+        U256::from_dec_str(s).unwrap()
+    }
+
+    fn g1(x: U256, y: U256) -> G1 {
+        G1::from(AffineG1::new(fq_from_u256(x).unwrap(), fq_from_u256(y).unwrap()).unwrap())
+    }
+
+    fn g2(c: [U256; 4]) -> G2 {
+        let x = Fq2::new(fq_from_u256(c[1]).unwrap(), fq_from_u256(c[0]).unwrap());
+        let y = Fq2::new(fq_from_u256(c[3]).unwrap(), fq_from_u256(c[2]).unwrap());
+        G2::from(AffineG2::new(x, y).unwrap())
+    }
+
+    // ecMul with the full 256 bit scalar, no reduction
+    fn ec_mul(p: G1, s: U256) -> G1 {
+        let mut acc = G1::zero();
+        for i in (0..256).rev() {
+            acc = acc + acc;
+            if s.bit(i) {
+                acc = acc + p;
+            }
+        }
+        acc
+    }
+
+    // ScaleChain.sol verifyBLS, written against raw contract inputs
+    fn contract_verify_bls(message: &[u8], sigx: U256, sigy: U256, pkx1: U256, pkx2: U256, pky1: U256, pky2: U256) -> bool {
+        let mut hasher = Sha3::keccak256();
+        hasher.input(message);
+        let mut digest = [0u8; 32];
+        hasher.result(&mut digest);
+        let h = ec_mul(g1(U256::from(1), U256::from(2)), U256::from_big_endian(&digest));
+        let neg_sig = g1(sigx, (dec(FIELD_MODULUS) - sigy) % dec(FIELD_MODULUS));
+        let p2 = g2([dec(G2_GEN[0]), dec(G2_GEN[1]), dec(G2_GEN[2]), dec(G2_GEN[3])]);
+        let pk = g2([pkx1, pkx2, pky1, pky2]);
+        pairing(neg_sig, p2) * pairing(h, pk) == Gt::one()
+    }
+
+    #[test]
+    fn public_key_matches_keyfile() {
+        let sk = SecretKey::from_u256(dec(SK)).unwrap();
+        let (x1, x2, y1, y2) = sk.public_key().to_u256();
+        assert_eq!((x1, x2, y1, y2), (dec(PK[0]), dec(PK[1]), dec(PK[2]), dec(PK[3])));
+        assert_eq!(PublicKey::from_u256(x1, x2, y1, y2).unwrap(), sk.public_key());
+    }
+
+    #[test]
+    fn known_signature() {
+        let sk = SecretKey::from_u256(dec(SK)).unwrap();
+        let sig = sk.sign(&[0xde, 0xad, 0xbe, 0xef]);
+        let (x, y) = sig.to_dec_str();
+        assert_eq!(x, "6809085898419283355157248721470797696214422771585023355422864165896929390623");
+        assert_eq!(y, "17038698966392192161013584970080894491723956472688210412791568941884390392140");
+        assert_eq!(Signature::from_dec_str(&x, &y).unwrap(), sig);
+    }
+
+    #[test]
+    fn sign_passes_contract_verification() {
+        let sk = SecretKey::from_u256(dec(SK)).unwrap();
+        let message = b"scalechain block header";
+        let sig = sk.sign(message);
+        let (sigx, sigy) = sig.to_u256();
+        let (x1, x2, y1, y2) = sk.public_key().to_u256();
+        assert!(sig.verify(message, &sk.public_key()));
+        assert!(contract_verify_bls(message, sigx, sigy, x1, x2, y1, y2));
+        assert!(!contract_verify_bls(b"other header", sigx, sigy, x1, x2, y1, y2));
+    }
+
+    #[test]
+    fn aggregate_passes_contract_verification() {
+        let message = b"scalechain block header";
+        let keys: Vec<SecretKey> = [dec(SK), U256::from(7), U256::from(123456789)]
+            .iter()
+            .map(|s| SecretKey::from_u256(*s).unwrap())
+            .collect();
+        let sigs: Vec<Signature> = keys.iter().map(|k| k.sign(message)).collect();
+        let pks: Vec<PublicKey> = keys.iter().map(|k| k.public_key()).collect();
+        let agg_sig = Signature::aggregate(&sigs);
+        let agg_pk = PublicKey::aggregate(&pks);
+        let (sigx, sigy) = agg_sig.to_u256();
+        let (x1, x2, y1, y2) = agg_pk.to_u256();
+
+        assert!(agg_sig.verify(message, &agg_pk));
+        assert!(agg_sig.verify_aggregate(message, &pks));
+        assert!(!agg_sig.verify_aggregate(message, &pks[..2]));
+        assert!(contract_verify_bls(message, sigx, sigy, x1, x2, y1, y2));
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(SecretKey::from_u256(U256::zero()).err(), Some(BlsError::InvalidScalar));
+        assert_eq!(SecretKey::from_u256(dec(CURVE_ORDER)).err(), Some(BlsError::InvalidScalar));
+        assert_eq!(Signature::from_u256(U256::from(1), U256::from(3)).err(), Some(BlsError::InvalidPoint));
+        assert_eq!(Signature::from_u256(dec(FIELD_MODULUS), U256::from(2)).err(), Some(BlsError::InvalidPoint));
+    }
+}
@@ -0,0 +1,569 @@
+// Encrypted key files. Ethereum accounts use the Web3 Secret Storage v3 format, so they can be
+// exchanged with geth and other wallets. BLS keys use the same crypto section around the 32 byte
+// big endian secret, next to the public key in the clear so that other tools can register a
+// node without its passphrase. Plaintext files of the old format are still accepted.
+use crypto::aes::{self, KeySize};
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::scrypt::{scrypt, ScryptParams};
+use crypto::sha2::Sha256;
+use crypto::sha3::Sha3;
+use crypto::symmetriccipher::SynchronousStreamCipher;
+use crypto::util::fixed_time_eq;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use web3::types::{Address, U256};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use log::warn;
+
+use super::bls;
+use crate::contract::contract::Account;
+use crate::contract::utils::{BLSKey, BLSKeyStr, _get_key_as_vec};
+
+pub const PASSPHRASE_ENV: &str = "SCALE_PASSPHRASE";
+// scrypt cost of newly written files, n = 2^18, r = 8, p = 1 as geth does
+pub const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// bounds on scrypt parameters read from key files, ScryptParams panics outside of them and
+// n * r * 128 bytes are allocated
+const MAX_SCRYPT_LOG_N: u32 = 24;
+const MAX_SCRYPT_R: u32 = 32;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const DKLEN: usize = 32;
+const BLS_KEYSTORE_TYPE: &str = "bn254-bls";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeystoreError {
+    Io(String),
+    Format(String),
+    Unsupported(String),
+    WrongPassphrase,
+    KeyMismatch,
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "io error: {}", e),
+            KeystoreError::Format(e) => write!(f, "malformed key file: {}", e),
+            KeystoreError::Unsupported(e) => write!(f, "unsupported key file: {}", e),
+            KeystoreError::WrongPassphrase => write!(f, "wrong passphrase"),
+            KeystoreError::KeyMismatch => write!(f, "decrypted key does not match the public part of the file"),
+        }
+    }
+}
+
+fn io_err(e: std::io::Error) -> KeystoreError {
+    KeystoreError::Io(e.to_string())
+}
+
+fn format_err<E: fmt::Display>(e: E) -> KeystoreError {
+    KeystoreError::Format(e.to_string())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+// scrypt uses n, r, p and pbkdf2 uses c, prf
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KdfParams {
+    pub dklen: usize,
+    pub salt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prf: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CryptoJson {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    pub mac: String,
+}
+
+// Web3 Secret Storage v3
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EthKeystore {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    pub crypto: CryptoJson,
+    pub id: String,
+    pub version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlsKeystore {
+    #[serde(rename = "type")]
+    pub key_type: String,
+    pub pkx1: String,
+    pub pkx2: String,
+    pub pky1: String,
+    pub pky2: String,
+    pub crypto: CryptoJson,
+    pub id: String,
+    pub version: u32,
+}
+
+// Passphrase of the key files, taken from the --passphrase_file file, then from SCALE_PASSPHRASE,
+// then asked for on the terminal. It is resolved once and reused for every file.
+pub struct Passphrase {
+    file: Option<String>,
+    cached: Option<String>,
+}
+
+impl Passphrase {
+    pub fn new(file: Option<&str>) -> Passphrase {
+        Passphrase {
+            file: file.map(|f| f.to_string()),
+            cached: None,
+        }
+    }
+
+    pub fn get(&mut self) -> Result<String, KeystoreError> {
+        if let Some(ref p) = self.cached {
+            return Ok(p.clone());
+        }
+        let passphrase = if let Some(ref file) = self.file {
+            let content = fs::read_to_string(file).map_err(io_err)?;
+            content.trim_end_matches(|c| c == '\n' || c == '\r').to_string()
+        } else if let Ok(p) = std::env::var(PASSPHRASE_ENV) {
+            p
+        } else {
+            rpassword::prompt_password_stderr("Key file passphrase: ").map_err(io_err)?
+        };
+        self.cached = Some(passphrase.clone());
+        Ok(passphrase)
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen::<u8>()).collect()
+}
+
+fn uuid_v4() -> String {
+    let mut b = random_bytes(16);
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let h = hex::encode(&b);
+    format!("{}-{}-{}-{}-{}", &h[0..8], &h[8..12], &h[12..16], &h[16..20], &h[20..32])
+}
+
+fn keccak256(input: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3::keccak256();
+    hasher.input(input);
+    let mut out = [0u8; 32];
+    hasher.result(&mut out);
+    out
+}
+
+fn derive_key(passphrase: &str, params: &KdfParams, kdf: &str) -> Result<Vec<u8>, KeystoreError> {
+    let salt = hex::decode(&params.salt).map_err(format_err)?;
+    if params.dklen < 32 {
+        return Err(KeystoreError::Unsupported(format!("dklen {}", params.dklen)));
+    }
+    let mut key = vec![0u8; params.dklen];
+    match kdf {
+        "scrypt" => {
+            let (n, r, p) = match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) => (n, r, p),
+                _ => return Err(KeystoreError::Format("scrypt needs n, r and p".to_string())),
+            };
+            let log_n = scrypt_log_n(n, r, p)?;
+            let params = ScryptParams::new(log_n, r, p);
+            scrypt(passphrase.as_bytes(), &salt, &params, &mut key);
+        },
+        "pbkdf2" => {
+            if params.prf.as_ref().map(|p| p.as_str()) != Some("hmac-sha256") {
+                return Err(KeystoreError::Unsupported(format!("pbkdf2 prf {:?}", params.prf)));
+            }
+            let c = params.c.ok_or(KeystoreError::Format("pbkdf2 needs c".to_string()))?;
+            if c == 0 {
+                return Err(KeystoreError::Format("pbkdf2 c is 0".to_string()));
+            }
+            let mut mac = Hmac::new(Sha256::new(), passphrase.as_bytes());
+            pbkdf2(&mut mac, &salt, c, &mut key);
+        },
+        other => return Err(KeystoreError::Unsupported(format!("kdf {}", other))),
+    }
+    Ok(key)
+}
+
+// log2 of n if n, r and p are in the range ScryptParams takes and within the memory bound
+fn scrypt_log_n(n: u64, r: u32, p: u32) -> Result<u8, KeystoreError> {
+    if !n.is_power_of_two() || n < 2 {
+        return Err(KeystoreError::Format(format!("scrypt n {} is not a power of two", n)));
+    }
+    let log_n = n.trailing_zeros();
+    if r == 0 || p == 0 {
+        return Err(KeystoreError::Format(format!("scrypt r {} and p {} must be positive", r, p)));
+    }
+    // scrypt requires n < 2^(128 * r / 8)
+    if log_n >= 16 * r {
+        return Err(KeystoreError::Format(format!("scrypt n {} is too large for r {}", n, r)));
+    }
+    if log_n > MAX_SCRYPT_LOG_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P || n * r as u64 * 128 > MAX_SCRYPT_MEMORY {
+        return Err(KeystoreError::Unsupported(format!("scrypt n {}, r {}, p {}", n, r, p)));
+    }
+    Ok(log_n as u8)
+}
+
+fn aes_128_ctr(key: &[u8], iv: &[u8], input: &[u8]) -> Vec<u8> {
+    let mut cipher = aes::ctr(KeySize::KeySize128, &key[..16], iv);
+    let mut output = vec![0u8; input.len()];
+    cipher.process(input, &mut output);
+    output
+}
+
+pub fn encrypt(secret: &[u8], passphrase: &str, scrypt_log_n: u8) -> CryptoJson {
+    let salt = random_bytes(32);
+    let iv = random_bytes(16);
+    let params = KdfParams {
+        dklen: DKLEN,
+        salt: hex::encode(&salt),
+        n: Some(1u64 << scrypt_log_n),
+        r: Some(SCRYPT_R),
+        p: Some(SCRYPT_P),
+        c: None,
+        prf: None,
+    };
+    let key = derive_key(passphrase, &params, "scrypt").expect("valid scrypt parameters");
+    let ciphertext = aes_128_ctr(&key, &iv, secret);
+    let mac = keccak256(&[&key[16..32], &ciphertext[..]].concat());
+    CryptoJson {
+        cipher: "aes-128-ctr".to_string(),
+        cipherparams: CipherParams { iv: hex::encode(&iv) },
+        ciphertext: hex::encode(&ciphertext),
+        kdf: "scrypt".to_string(),
+        kdfparams: params,
+        mac: hex::encode(&mac),
+    }
+}
+
+pub fn decrypt(crypto: &CryptoJson, passphrase: &str) -> Result<Vec<u8>, KeystoreError> {
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(KeystoreError::Unsupported(format!("cipher {}", crypto.cipher)));
+    }
+    let key = derive_key(passphrase, &crypto.kdfparams, &crypto.kdf)?;
+    let ciphertext = hex::decode(&crypto.ciphertext).map_err(format_err)?;
+    let iv = hex::decode(&crypto.cipherparams.iv).map_err(format_err)?;
+    let mac = hex::decode(&crypto.mac).map_err(format_err)?;
+    if iv.len() != 16 {
+        return Err(KeystoreError::Format(format!("iv of {} bytes", iv.len())));
+    }
+    let expected = keccak256(&[&key[16..32], &ciphertext[..]].concat());
+    if !fixed_time_eq(&expected, &mac) {
+        return Err(KeystoreError::WrongPassphrase);
+    }
+    Ok(aes_128_ctr(&key, &iv, &ciphertext))
+}
+
+// ethereum address of a secp256k1 private key
+pub fn address_of(private_key: &[u8]) -> Result<Address, KeystoreError> {
+    let secp = secp256k1::Secp256k1::new();
+    let sk = secp256k1::SecretKey::from_slice(private_key).map_err(format_err)?;
+    let pk = secp256k1::PublicKey::from_secret_key(&secp, &sk).serialize_uncompressed();
+    Ok(Address::from_slice(&keccak256(&pk[1..])[12..]))
+}
+
+fn read_json(path: &Path) -> Result<serde_json::Value, KeystoreError> {
+    let content = fs::read(path).map_err(io_err)?;
+    serde_json::from_slice(&content).map_err(format_err)
+}
+
+fn is_encrypted(json: &serde_json::Value) -> bool {
+    json.get("crypto").is_some()
+}
+
+// key files are written once and never overwritten, with permissions for the owner only
+fn write_new(path: &Path, content: &str) -> Result<(), KeystoreError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(io_err)?;
+    file.write_all(content.as_bytes()).map_err(io_err)
+}
+
+pub fn load_account<P: AsRef<Path>>(path: P, passphrase: &mut Passphrase) -> Result<Account, KeystoreError> {
+    let json = read_json(path.as_ref())?;
+    if !is_encrypted(&json) {
+        warn!("{:?} holds a plaintext private key, convert it with keygen", path.as_ref());
+        return serde_json::from_value(json).map_err(format_err);
+    }
+    let keystore: EthKeystore = serde_json::from_value(json).map_err(format_err)?;
+    if keystore.version != 3 {
+        return Err(KeystoreError::Unsupported(format!("version {}", keystore.version)));
+    }
+    let private_key = decrypt(&keystore.crypto, &passphrase.get()?)?;
+    let address = address_of(&private_key)?;
+    if let Some(ref a) = keystore.address {
+        let stored: Address = a.trim_start_matches("0x").parse().map_err(format_err)?;
+        if stored != address {
+            return Err(KeystoreError::KeyMismatch);
+        }
+    }
+    Ok(Account {
+        address,
+        private_key: format!("0x{}", hex::encode(&private_key)),
+    })
+}
+
+// address of an account file, no passphrase needed
+pub fn read_address<P: AsRef<Path>>(path: P) -> Result<Address, KeystoreError> {
+    let json = read_json(path.as_ref())?;
+    let address = json.get("address")
+        .and_then(|a| a.as_str())
+        .ok_or(KeystoreError::Format("missing address".to_string()))?;
+    address.trim_start_matches("0x").parse().map_err(format_err)
+}
+
+pub fn save_account<P: AsRef<Path>>(path: P, account: &Account, passphrase: &str, scrypt_log_n: u8) -> Result<(), KeystoreError> {
+    let private_key = _get_key_as_vec(account.private_key.clone());
+    let keystore = EthKeystore {
+        address: Some(hex::encode(account.address.as_bytes())),
+        crypto: encrypt(&private_key, passphrase, scrypt_log_n),
+        id: uuid_v4(),
+        version: 3,
+    };
+    write_new(path.as_ref(), &serde_json::to_string_pretty(&keystore).map_err(format_err)?)
+}
+
+pub fn new_account() -> Account {
+    loop {
+        let private_key = random_bytes(32);
+        if let Ok(address) = address_of(&private_key) {
+            return Account {
+                address,
+                private_key: format!("0x{}", hex::encode(&private_key)),
+            };
+        }
+    }
+}
+
+fn bls_key_from_json(json: serde_json::Value, passphrase: &mut Passphrase) -> Result<BLSKey, KeystoreError> {
+    if !is_encrypted(&json) {
+        let key_str: BLSKeyStr = serde_json::from_value(json).map_err(format_err)?;
+        return Ok(BLSKey::new(key_str));
+    }
+    let keystore: BlsKeystore = serde_json::from_value(json).map_err(format_err)?;
+    if keystore.key_type != BLS_KEYSTORE_TYPE {
+        return Err(KeystoreError::Unsupported(format!("key type {}", keystore.key_type)));
+    }
+    let secret = decrypt(&keystore.crypto, &passphrase.get()?)?;
+    if secret.len() != 32 {
+        return Err(KeystoreError::Format(format!("secret of {} bytes", secret.len())));
+    }
+    let sk = U256::from_big_endian(&secret);
+    let public_key = bls::SecretKey::from_u256(sk).map_err(format_err)?.public_key().to_u256();
+    let key = BLSKey::new(BLSKeyStr {
+        sk: sk.to_string(),
+        pkx1: keystore.pkx1,
+        pkx2: keystore.pkx2,
+        pky1: keystore.pky1,
+        pky2: keystore.pky2,
+    });
+    if public_key != (key.pkx1, key.pkx2, key.pky1, key.pky2) {
+        return Err(KeystoreError::KeyMismatch);
+    }
+    Ok(key)
+}
+
+pub fn load_bls_key<P: AsRef<Path>>(path: P, passphrase: &mut Passphrase) -> Result<BLSKey, KeystoreError> {
+    let json = read_json(path.as_ref())?;
+    if !is_encrypted(&json) {
+        warn!("{:?} holds a plaintext BLS key, convert it with keygen", path.as_ref());
+    }
+    bls_key_from_json(json, passphrase)
+}
+
+// public part of a BLS key file, the secret is left zero
+pub fn read_bls_public_key<P: AsRef<Path>>(path: P) -> Result<BLSKey, KeystoreError> {
+    let json = read_json(path.as_ref())?;
+    let field = |name: &str| -> Result<U256, KeystoreError> {
+        let v = json.get(name)
+            .and_then(|v| v.as_str())
+            .ok_or(KeystoreError::Format(format!("missing {}", name)))?;
+        U256::from_dec_str(v).map_err(|e| KeystoreError::Format(format!("{} {:?}", name, e)))
+    };
+    Ok(BLSKey {
+        sk: U256::zero(),
+        pkx1: field("pkx1")?,
+        pkx2: field("pkx2")?,
+        pky1: field("pky1")?,
+        pky2: field("pky2")?,
+    })
+}
+
+pub fn save_bls_key<P: AsRef<Path>>(path: P, key: &BLSKey, passphrase: &str, scrypt_log_n: u8) -> Result<(), KeystoreError> {
+    let mut secret = [0u8; 32];
+    key.sk.to_big_endian(&mut secret);
+    let keystore = BlsKeystore {
+        key_type: BLS_KEYSTORE_TYPE.to_string(),
+        pkx1: key.pkx1.to_string(),
+        pkx2: key.pkx2.to_string(),
+        pky1: key.pky1.to_string(),
+        pky2: key.pky2.to_string(),
+        crypto: encrypt(&secret, passphrase, scrypt_log_n),
+        id: uuid_v4(),
+        version: 1,
+    };
+    write_new(path.as_ref(), &serde_json::to_string_pretty(&keystore).map_err(format_err)?)
+}
+
+pub fn new_bls_key() -> BLSKey {
+    loop {
+        // below 2^254, so under the curve order
+        let mut bytes = random_bytes(32);
+        bytes[0] &= 0x3f;
+        let sk = U256::from_big_endian(&bytes);
+        if let Ok(secret) = bls::SecretKey::from_u256(sk) {
+            let (pkx1, pkx2, pky1, pky2) = secret.public_key().to_u256();
+            return BLSKey { sk, pkx1, pkx2, pky1, pky2 };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap scrypt so the tests stay fast
+    const TEST_LOG_N: u8 = 10;
+
+    fn passphrase(p: &str) -> Passphrase {
+        Passphrase {
+            file: None,
+            cached: Some(p.to_string()),
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("scale-keystore-{}-{}", name, uuid_v4()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    // pbkdf2 test vector of the Web3 Secret Storage definition
+    #[test]
+    fn decrypts_reference_vector() {
+        let keystore: EthKeystore = serde_json::from_str(r#"{
+            "crypto" : {
+                "cipher" : "aes-128-ctr",
+                "cipherparams" : { "iv" : "6087dab2f9fdbbfaddc31a909735c1e6" },
+                "ciphertext" : "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf" : "pbkdf2",
+                "kdfparams" : {
+                    "c" : 262144,
+                    "dklen" : 32,
+                    "prf" : "hmac-sha256",
+                    "salt" : "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac" : "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version" : 3
+        }"#).unwrap();
+        let key = decrypt(&keystore.crypto, "testpassword").unwrap();
+        assert_eq!(hex::encode(&key), "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+        assert_eq!(decrypt(&keystore.crypto, "wrong"), Err(KeystoreError::WrongPassphrase));
+    }
+
+    #[test]
+    fn account_round_trip() {
+        let path = temp_path("account");
+        let account = new_account();
+        save_account(&path, &account, "secret", TEST_LOG_N).unwrap();
+        // never overwrites an existing key file
+        assert!(save_account(&path, &account, "secret", TEST_LOG_N).is_err());
+
+        assert_eq!(read_address(&path).unwrap(), account.address);
+        let loaded = load_account(&path, &mut passphrase("secret")).unwrap();
+        assert_eq!(loaded.address, account.address);
+        assert_eq!(_get_key_as_vec(loaded.private_key), _get_key_as_vec(account.private_key));
+        assert_eq!(load_account(&path, &mut passphrase("other")).unwrap_err(), KeystoreError::WrongPassphrase);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bls_key_round_trip() {
+        let path = temp_path("bls");
+        let key = new_bls_key();
+        save_bls_key(&path, &key, "secret", TEST_LOG_N).unwrap();
+
+        let public = read_bls_public_key(&path).unwrap();
+        assert_eq!((public.pkx1, public.pkx2, public.pky1, public.pky2), (key.pkx1, key.pkx2, key.pky1, key.pky2));
+        let loaded = load_bls_key(&path, &mut passphrase("secret")).unwrap();
+        assert_eq!(loaded.sk, key.sk);
+        assert_eq!(load_bls_key(&path, &mut passphrase("other")).unwrap_err(), KeystoreError::WrongPassphrase);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bad_kdf_parameters_are_errors() {
+        let mut crypto = encrypt(&[7u8; 32], "secret", TEST_LOG_N);
+        for (n, r, p) in [(1u64 << 10, 0u32, 1u32), (1 << 10, 8, 0), (3, 8, 1), (1 << 20, 1, 1), (1 << 40, 8, 1), (1 << 10, 8, 1 << 20)].iter() {
+            crypto.kdfparams.n = Some(*n);
+            crypto.kdfparams.r = Some(*r);
+            crypto.kdfparams.p = Some(*p);
+            match decrypt(&crypto, "secret") {
+                Err(KeystoreError::Format(_)) | Err(KeystoreError::Unsupported(_)) => (),
+                other => panic!("scrypt n {} r {} p {} gave {:?}", n, r, p, other),
+            }
+        }
+        crypto.kdf = "pbkdf2".to_string();
+        crypto.kdfparams.prf = Some("hmac-sha256".to_string());
+        crypto.kdfparams.c = Some(0);
+        assert!(decrypt(&crypto, "secret").is_err());
+    }
+
+    #[test]
+    fn passphrase_file_wins_over_environment() {
+        let path = temp_path("passphrase");
+        fs::write(&path, "from file\n").unwrap();
+        std::env::set_var(PASSPHRASE_ENV, "from env");
+        let from_file = Passphrase::new(path.to_str()).get();
+        let from_env = Passphrase::new(None).get();
+        std::env::remove_var(PASSPHRASE_ENV);
+        assert_eq!(from_file.unwrap(), "from file");
+        assert_eq!(from_env.unwrap(), "from env");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_files_still_load() {
+        let sk = bls::SecretKey::from_u256(U256::from(1007)).unwrap();
+        let (x1, x2, y1, y2) = sk.public_key().to_u256();
+        let json = serde_json::json!({
+            "sk": "1007",
+            "pkx1": x1.to_string(),
+            "pkx2": x2.to_string(),
+            "pky1": y1.to_string(),
+            "pky2": y2.to_string(),
+        });
+        let key = bls_key_from_json(json, &mut Passphrase::new(None)).unwrap();
+        assert_eq!(key.sk, U256::from(1007));
+        assert_eq!(key.pky2, y2);
+    }
+}
@@ -1,4 +1,7 @@
 extern crate crypto;
+pub mod bls;
+pub mod keystore;
+
 use crypto::sha2::Sha256;
 use crypto::digest::Digest;
 use super::primitive::hash::{H256};
@@ -11,3 +14,23 @@ pub fn hash(input: &[u8]) -> H256 {
     hash
 }
 
+
+// curr_hash the contract stores once header is committed on top of prev_hash
+pub fn chain_hash(prev_hash: H256, header: &[u8]) -> H256 {
+    let prev_hash: [u8; 32] = prev_hash.into();
+    let header_hash: [u8; 32] = hash(header).into();
+    hash(&[prev_hash, header_hash].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_hash_matches_submit_vote() {
+        // curr_hash[sid] = sha256(curr_hash[sid], sha256(block_header)) in ScaleChain.sol
+        let next = chain_hash(H256::default(), b"header");
+        assert_eq!(hex::encode(&next.0), "e6ec11cdfa969aac8396f8dd23c5e91dd7dc83d7f9d7686e026cb131c84a1076");
+        assert_ne!(chain_hash(next, b"header"), next);
+    }
+}
@@ -1,100 +1,582 @@
 use super::hash::{H256};
-use super::block::{Block};
+use super::block::{Block, ContractState};
 use std::sync::{Mutex, Arc};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
 use super::cmtda::H256 as CMTH256;
+use super::cmtda::{BlockHeader, CmtParams, Transaction};
 use chain::block::Block as SBlock;
-use chain::constants::{NUM_BASE_SYMBOL};
 use super::network::message::{Samples};
-use rocksdb::{self, ColumnFamilyDescriptor, Options, SliceTransform, DB};
+use super::network::sampleSet::MergeErr;
+use rocksdb::{self, ColumnFamilyDescriptor, Direction, IteratorMode, Options, DB};
 use bincode::{deserialize, serialize};
 
-const SYMBOL_CF: &str = "SYMBOL";
-const BLOCK_CF: &str = "BLOCK";
+const SYMBOL_CF: &str = "SYMBOL"; // samples of a block, a value for each chunk with the symbols it added
+const BLOCK_CF: &str = "BLOCK"; // decoded blocks
+const HEADER_CF: &str = "HEADER"; // serialized cmt headers
+const STATE_CF: &str = "STATE"; // contract states of the local chains, a chain missing its first states is synced again at restart
+
+// how much of the samples or decoded blocks is kept, oldest blocks are pruned first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrunePolicy {
+    Blocks(usize),
+    Bytes(usize),
+}
+
+impl Default for PrunePolicy {
+    fn default() -> PrunePolicy {
+        PrunePolicy::Blocks(8)
+    }
+}
+
+// decoded blocks, headers and states are served to clients long after the samples are dropped
+pub const DEFAULT_BLOCK_RETENTION: PrunePolicy = PrunePolicy::Blocks(1024);
+
+impl FromStr for PrunePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PrunePolicy, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 2 {
+            return Err(format!("unknown prune policy {}", s));
+        }
+        let num = parts[1].parse::<usize>().map_err(|e| format!("invalid prune policy {}: {}", s, e))?;
+        match parts[0] {
+            "blocks" => Ok(PrunePolicy::Blocks(num)),
+            "bytes" => Ok(PrunePolicy::Bytes(num)),
+            _ => Err(format!("unknown prune policy {}", s)),
+        }
+    }
+}
+
+// a decoded block, the header and transactions are in their wire format
+#[derive(Serialize, Deserialize)]
+struct StoredBlock {
+    header: Vec<u8>,
+    transactions: Vec<u8>,
+    params: CmtParams,
+}
+
+// blocks with values in some column families, oldest first, and the bytes held for each
+struct Retention {
+    policy: PrunePolicy,
+    cfs: &'static [&'static str],
+    record: VecDeque<(u64, u64)>,
+    sizes: HashMap<(u64, u64), usize>,
+}
+
+impl Retention {
+    fn new(policy: PrunePolicy, cfs: &'static [&'static str]) -> Retention {
+        Retention {
+            policy,
+            cfs,
+            record: VecDeque::new(),
+            sizes: HashMap::new(),
+        }
+    }
+
+    // size is the number of bytes now held for the block in one column family, replacing old_size
+    fn update(&mut self, key: (u64, u64), old_size: usize, size: usize) {
+        if !self.sizes.contains_key(&key) {
+            self.record.push_back(key);
+        }
+        let held = self.sizes.entry(key).or_insert(0);
+        *held = (*held + size).saturating_sub(old_size);
+    }
+
+    // bytes of a block removed outside of pruning, the block keeps its place
+    fn release(&mut self, key: (u64, u64), size: usize) {
+        if let Some(held) = self.sizes.get_mut(&key) {
+            *held = held.saturating_sub(size);
+        }
+    }
+
+    // the oldest block while the policy does not hold, the block stored last is always kept
+    fn pop_over(&mut self) -> Option<(u64, u64)> {
+        let over = match self.policy {
+            PrunePolicy::Blocks(n) => self.record.len() > n,
+            PrunePolicy::Bytes(n) => self.record.len() > 1 && self.sizes.values().sum::<usize>() > n,
+        };
+        if !over {
+            return None;
+        }
+        let key = self.record.pop_front()?;
+        self.sizes.remove(&key);
+        Some(key)
+    }
+}
+
+// keys are big endian, so blocks of a side chain are iterated in order
+fn block_key(sid: u64, block_id: u64) -> Vec<u8> {
+    let mut key = sid.to_be_bytes().to_vec();
+    key.extend_from_slice(&block_id.to_be_bytes());
+    key
+}
+
+// chunks of samples are keyed by the order they were stored in after the block
+fn chunk_key(sid: u64, block_id: u64, seq: u32) -> Vec<u8> {
+    let mut key = block_key(sid, block_id);
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+// the block of a key, chunk keys included
+fn parse_key(key: &[u8]) -> (u64, u64) {
+    let mut sid = [0u8; 8];
+    let mut block_id = [0u8; 8];
+    sid.copy_from_slice(&key[0..8]);
+    block_id.copy_from_slice(&key[8..16]);
+    (u64::from_be_bytes(sid), u64::from_be_bytes(block_id))
+}
+
+// all values stored for a block in a column family
+fn block_values(db: &DB, name: &str, sid: u64, block_id: u64) -> Vec<(Box<[u8]>, Box<[u8]>)> {
+    let cf = db.cf_handle(name).unwrap();
+    let start = block_key(sid, block_id);
+    db.iterator_cf(cf, IteratorMode::From(&start, Direction::Forward)).unwrap()
+        .take_while(|(key, _)| parse_key(key) == (sid, block_id))
+        .collect()
+}
+
+// indices of the symbols stored for a block, so a chunk is checked without reading the stored ones
+struct HeldSamples {
+    header: Vec<u8>,
+    chunks: u32,
+    idx: Vec<HashSet<u64>>,
+}
+
+impl HeldSamples {
+    fn new(header: Vec<u8>, num_layer: usize) -> HeldSamples {
+        HeldSamples {
+            header,
+            chunks: 0,
+            idx: vec![HashSet::new(); num_layer],
+        }
+    }
+
+    // the symbols of samples not held yet, which are held from now on, the symbol
+    // received first is kept for an index
+    fn take_new(&mut self, samples: &Samples) -> Result<Samples, MergeErr> {
+        if samples.header != self.header {
+            return Err(MergeErr::Header);
+        }
+        let num_layer = self.idx.len();
+        if samples.symbols.len() != num_layer || samples.idx.len() != num_layer || samples.proofs.len() != num_layer {
+            return Err(MergeErr::Layers(num_layer, samples.symbols.len()));
+        }
+        for l in 0..num_layer {
+            if samples.idx[l].len() != samples.symbols[l].len() || samples.proofs[l].len() != samples.symbols[l].len() {
+                return Err(MergeErr::Malformed(l));
+            }
+        }
+        let mut new = Samples {
+            header: samples.header.clone(),
+            symbols: vec![vec![]; num_layer],
+            idx: vec![vec![]; num_layer],
+            proofs: vec![vec![]; num_layer],
+        };
+        for l in 0..num_layer {
+            for j in 0..samples.idx[l].len() {
+                if self.idx[l].insert(samples.idx[l][j]) {
+                    new.symbols[l].push(samples.symbols[l][j].clone());
+                    new.idx[l].push(samples.idx[l][j]);
+                    new.proofs[l].push(samples.proofs[l][j].clone());
+                }
+            }
+        }
+        Ok(new)
+    }
+}
 
 pub struct BlockDb {
-    pub block_record: VecDeque<u64>, // hack for reducing storage
-    pub thresh: usize,
-    pub num_sample: u64, // used by scale node
-    pub num_block: u64,
+    samples: Retention,
+    blocks: Retention,
+    held: HashMap<(u64, u64), HeldSamples>,
     pub db: rocksdb::DB,
 }
 
 impl BlockDb {
+    // opens the database at path, creating it if needed, samples and blocks stored before are kept
     pub fn new<P: AsRef<std::path::Path>>(
         path: P,
+        prune: PrunePolicy,
     ) -> BlockDb {
-        DB::destroy(&Options::default(), &path).unwrap();
-        //let block_cf = ColumnFamilyDescriptor::new(BLOCK_CF, Options::default());
-        let symbol_cf = ColumnFamilyDescriptor::new(SYMBOL_CF, Options::default());
-        let cfs = vec![symbol_cf];
+        let cfs = [SYMBOL_CF, BLOCK_CF, HEADER_CF, STATE_CF].iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()))
+            .collect::<Vec<_>>();
         let mut opts = Options::default();
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
         let db = DB::open_cf_descriptors(&opts, path, cfs).unwrap();
 
-        BlockDb {
-            block_record: VecDeque::new(),
-            thresh: 8,
-            num_sample: 0,
-            num_block: 0,
+        let mut block_db = BlockDb {
+            samples: Retention::new(prune, &[SYMBOL_CF]),
+            blocks: Retention::new(DEFAULT_BLOCK_RETENTION, &[BLOCK_CF, HEADER_CF, STATE_CF]),
+            held: HashMap::new(),
             db: db,
-        }  
-    }
-    
-    pub fn insert_sblock(&mut self, block_id: u64, sblock: SBlock){
-        self.num_block += 1;
-        //let block_cf = self.db.cf_handle(BLOCK_CF).unwrap();
-        //let serialized = serialize(&sblock).unwrap();
-        //let block_id = serialize(&block_id).unwrap();
-        //self.db.put_cf(block_cf, &block_id, &serialized).unwrap(); 
-    }
-
-    pub fn get_sblock(&mut self, block_id: u64) -> Option<SBlock>{
-        //let block_cf = self.db.cf_handle(BLOCK_CF).unwrap();
-        //let block_id = serialize(&block_id).unwrap();
-        //let serialized = self.db.get_pinned_cf(block_cf, &block_id).unwrap();
-        //match serialized {
-            //Some(block) => Some(deserialize(&block).unwrap()),
-            //None => None,
-        //}
-        None
-    }
-
-    // return if there is redundant elements
-    pub fn insert_cmt_sample(&mut self, block_id: u64 , chunk: &Samples) -> bool {
-        let symbol_cf = self.db.cf_handle(SYMBOL_CF).unwrap();
-        let serialized = serialize(&chunk).unwrap();
-        let key = serialize(&block_id).unwrap();
-        self.db.put_cf(symbol_cf, &key, &serialized).unwrap(); 
-        self.num_sample += 1;
-        self.block_record.push_back(block_id);
-        // remove one block for saving storage
-        if self.block_record.len() > self.thresh {
-            let id = self.block_record.pop_front().unwrap();
-            let id = serialize(&id).unwrap();
-            self.db.delete(&id);
-        }
-        info!("curr staroge size {}", self.block_record.len());
+        };
+        block_db.load_sizes();
+        block_db
+    }
+
+    fn load_sizes(&mut self) {
+        for retention in [&mut self.samples, &mut self.blocks].iter_mut() {
+            for name in retention.cfs.iter() {
+                let cf = self.db.cf_handle(name).unwrap();
+                for (key, value) in self.db.iterator_cf(cf, IteratorMode::Start).unwrap() {
+                    retention.update(parse_key(&key), 0, value.len());
+                }
+            }
+            // the order blocks were stored in is not kept, prune the lowest block ids first
+            let mut record: Vec<(u64, u64)> = retention.record.drain(..).collect();
+            record.sort_by_key(|(sid, block_id)| (*block_id, *sid));
+            retention.record = record.into_iter().collect();
+        }
+        info!("block db holds samples of {} and headers, states or decoded blocks of {} blocks", self.get_num_samples(), self.get_num_blocks());
+    }
+
+    // samples kept
+    pub fn set_prune_policy(&mut self, prune: PrunePolicy) {
+        self.samples.policy = prune;
+        self.prune_blocks();
+    }
+
+    // decoded blocks, headers and states kept
+    pub fn set_block_retention(&mut self, keep: PrunePolicy) {
+        self.blocks.policy = keep;
+        self.prune_blocks();
+    }
+
+    // drop the oldest samples and blocks until both policies hold
+    fn prune_blocks(&mut self) {
+        for retention in [&mut self.samples, &mut self.blocks].iter_mut() {
+            while let Some((sid, block_id)) = retention.pop_over() {
+                for name in retention.cfs.iter() {
+                    let cf = self.db.cf_handle(name).unwrap();
+                    for (key, _) in block_values(&self.db, name, sid, block_id) {
+                        self.db.delete_cf(cf, &key).unwrap();
+                    }
+                    if *name == SYMBOL_CF {
+                        self.held.remove(&(sid, block_id));
+                    }
+                }
+                info!("block db pruned {:?} of block {} on side chain {}", retention.cfs, block_id, sid);
+            }
+        }
+    }
+
+    fn put(&mut self, name: &str, key: &[u8], value: &[u8]) {
+        let cf = self.db.cf_handle(name).unwrap();
+        let old_size = self.db.get_pinned_cf(cf, key).unwrap().map(|v| v.len()).unwrap_or(0);
+        self.db.put_cf(cf, key, value).unwrap();
+        for retention in [&mut self.samples, &mut self.blocks].iter_mut() {
+            if retention.cfs.iter().any(|cf| *cf == name) {
+                retention.update(parse_key(key), old_size, value.len());
+            }
+        }
+        self.prune_blocks();
+    }
+
+    fn get(&self, name: &str, sid: u64, block_id: u64) -> Option<Vec<u8>> {
+        let cf = self.db.cf_handle(name).unwrap();
+        self.db.get_pinned_cf(cf, &block_key(sid, block_id)).unwrap().map(|v| v.to_vec())
+    }
+
+    pub fn insert_sblock(&mut self, sid: u64, block_id: u64, sblock: SBlock){
+        let header: Vec<u8> = ser::serialize(&sblock.block_header).into();
+        let stored = StoredBlock {
+            header: header.clone(),
+            transactions: ser::serialize_list::<Transaction, Transaction>(&sblock.transactions).into(),
+            params: sblock.params,
+        };
+        self.insert_header(sid, block_id, &header);
+        self.put(BLOCK_CF, &block_key(sid, block_id), &serialize(&stored).unwrap());
+    }
+
+    // the coded Merkle tree of a decoded block is not stored
+    pub fn get_sblock(&self, sid: u64, block_id: u64) -> Option<SBlock>{
+        let stored: StoredBlock = deserialize(&self.get(BLOCK_CF, sid, block_id)?).unwrap();
+        let block_header: BlockHeader = ser::deserialize(&stored.header as &[u8]).ok()?;
+        let transactions: Vec<Transaction> = ser::Reader::new(&stored.transactions).read_list().ok()?;
+        Some(SBlock {
+            block_header,
+            transactions,
+            coded_tree: vec![],
+            params: stored.params,
+        })
+    }
+
+    pub fn insert_header(&mut self, sid: u64, block_id: u64, header: &[u8]) {
+        self.put(HEADER_CF, &block_key(sid, block_id), header);
+    }
+
+    pub fn get_header(&self, sid: u64, block_id: u64) -> Option<Vec<u8>> {
+        self.get(HEADER_CF, sid, block_id)
+    }
+
+    // a state of a local chain, with the hash of its decoded block header if known
+    pub fn insert_state(&mut self, state: &ContractState, block_hash: Option<H256>) {
+        self.put(STATE_CF, &block_key(state.sid, state.block_id), &serialize(&(state, block_hash)).unwrap());
+    }
+
+    // stored states of side chain sid, in block order
+    pub fn get_states(&self, sid: u64) -> Vec<(ContractState, Option<H256>)> {
+        let cf = self.db.cf_handle(STATE_CF).unwrap();
+        let start = block_key(sid, 0);
+        let mut states = vec![];
+        for (key, value) in self.db.iterator_cf(cf, IteratorMode::From(&start, Direction::Forward)).unwrap() {
+            if parse_key(&key).0 != sid {
+                break;
+            }
+            states.push(deserialize(&value).unwrap());
+        }
+        states
+    }
+
+    pub fn remove_states(&mut self, sid: u64) {
+        let ids: Vec<u64> = self.get_states(sid).iter().map(|(s, _)| s.block_id).collect();
+        let cf = self.db.cf_handle(STATE_CF).unwrap();
+        for block_id in ids {
+            let key = block_key(sid, block_id);
+            let size = self.db.get_pinned_cf(cf, &key).unwrap().map(|v| v.len()).unwrap_or(0);
+            self.db.delete_cf(cf, &key).unwrap();
+            self.blocks.release((sid, block_id), size);
+        }
+    }
+
+    // return false if the chunk holds no symbol that is not stored yet
+    pub fn insert_cmt_sample(&mut self, sid: u64, block_id: u64 , chunk: &Samples) -> bool {
+        let key = (sid, block_id);
+        if !self.held.contains_key(&key) {
+            let stored = self.get_chunks(sid, block_id);
+            if let Some(first) = stored.first() {
+                let mut held = HeldSamples::new(first.header.clone(), first.idx.len());
+                held.chunks = stored.len() as u32;
+                for samples in stored.iter() {
+                    for (l, idx) in samples.idx.iter().enumerate() {
+                        held.idx[l].extend(idx.iter());
+                    }
+                }
+                self.held.insert(key, held);
+            }
+        }
+        // only the symbols not stored yet are written, under a key of their own
+        let (seq, new) = {
+            let held = self.held.entry(key).or_insert_with(|| HeldSamples::new(chunk.header.clone(), chunk.symbols.len()));
+            match held.take_new(chunk) {
+                Ok(ref new) if new.num_symbols() == 0 => (held.chunks, None),
+                Ok(new) => {
+                    held.chunks += 1;
+                    (held.chunks - 1, Some(new))
+                },
+                Err(e) => {
+                    warn!("unable to merge chunk of block {} on side chain {}: {:?}", block_id, sid, e);
+                    (held.chunks, None)
+                },
+            }
+        };
+        let mut new = match new {
+            Some(new) => new,
+            None => {
+                if seq == 0 {
+                    self.held.remove(&key);
+                }
+                return false;
+            },
+        };
+        // the header is stored with the first chunk only
+        if seq == 0 {
+            self.insert_header(sid, block_id, &chunk.header);
+        } else {
+            new.header = vec![];
+        }
+        self.put(SYMBOL_CF, &chunk_key(sid, block_id, seq), &serialize(&new).unwrap());
         true
     }
 
-    pub fn get_chunk(&self, block_id: u64) -> Option<Samples> {
-        let symbol_cf = self.db.cf_handle(SYMBOL_CF).unwrap();
-        let block_id = serialize(&block_id).unwrap();
-        let serialized = self.db.get_pinned_cf(symbol_cf, &block_id).unwrap();
-        match serialized {
-            Some(chunk) => Some(deserialize(&chunk).unwrap()),
-            None => None,
+    fn get_chunks(&self, sid: u64, block_id: u64) -> Vec<Samples> {
+        block_values(&self.db, SYMBOL_CF, sid, block_id).iter()
+            .map(|(_, value)| deserialize(value).unwrap())
+            .collect()
+    }
+
+    // all samples stored for a block, in the order they were received
+    pub fn get_chunk(&self, sid: u64, block_id: u64) -> Option<Samples> {
+        let mut chunks = self.get_chunks(sid, block_id).into_iter();
+        let mut samples = chunks.next()?;
+        for chunk in chunks {
+            for (l, ((symbols, idx), proofs)) in chunk.symbols.into_iter().zip(chunk.idx).zip(chunk.proofs).enumerate() {
+                samples.symbols[l].extend(symbols);
+                samples.idx[l].extend(idx);
+                samples.proofs[l].extend(proofs);
+            }
         }
+        Some(samples)
     }
 
+    // blocks with a header, state or decoded block stored
     pub fn get_num_blocks(&self) -> u64 {
-       self.num_block as u64
+        self.blocks.record.len() as u64
     }
 
+    // used by scale node
     pub fn get_num_samples(&self) -> u64 {
-        self.num_sample as u64
+        self.samples.record.len() as u64
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use crate::cmtda::Symbol;
+
+    fn samples(header: u8, base_idx: &[u64]) -> Samples {
+        Samples {
+            header: vec![header],
+            symbols: vec![base_idx.iter().map(|i| Symbol::Base(vec![*i as u8; 32])).collect()],
+            idx: vec![base_idx.to_vec()],
+            proofs: vec![vec![vec![]; base_idx.len()]],
+        }
+    }
+
+    fn sblock(nonce: u32) -> SBlock {
+        let block_header = BlockHeader {
+            version: 1,
+            previous_header_hash: CMTH256::default(),
+            merkle_root_hash: CMTH256::default(),
+            time: 4u32,
+            bits: 5.into(),
+            nonce,
+            coded_merkle_roots_hashes: vec![CMTH256::default(); 4],
+            code_id: CMTH256::default(),
+        };
+        SBlock {
+            block_header,
+            transactions: vec![Transaction::default(); 3],
+            coded_tree: vec![],
+            params: CmtParams::default(),
+        }
+    }
+
+    fn db_path(name: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("block_db_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn reopened_db_keeps_samples_and_blocks() {
+        let path = db_path("reopen");
+        let state = ContractState { sid: 1, curr_hash: H256::default(), block_id: 1 };
+        {
+            let mut db = BlockDb::new(&path, PrunePolicy::Blocks(8));
+            assert!(db.insert_cmt_sample(1, 1, &samples(7, &[0, 2])));
+            assert!(db.insert_cmt_sample(1, 1, &samples(7, &[2, 3])));
+            assert!(!db.insert_cmt_sample(1, 1, &samples(7, &[3])));
+            db.insert_sblock(1, 2, sblock(9));
+            db.insert_state(&state, Some(H256::default()));
+        }
+        let mut db = BlockDb::new(&path, PrunePolicy::Blocks(8));
+        assert_eq!(db.get_chunk(1, 1).unwrap().idx, vec![vec![0, 2, 3]]);
+        assert!(!db.insert_cmt_sample(1, 1, &samples(7, &[0, 3])));
+        assert_eq!(db.get_header(1, 1), Some(vec![7]));
+        let block = db.get_sblock(1, 2).unwrap();
+        assert_eq!(block.block_header.nonce, 9);
+        assert_eq!(block.transactions.len(), 3);
+        assert_eq!(db.get_header(1, 2), Some(ser::serialize(&sblock(9).block_header).into()));
+        assert_eq!(db.get_states(1), vec![(state, Some(H256::default()))]);
+        assert!(db.get_states(0).is_empty());
+        assert_eq!((db.get_num_samples(), db.get_num_blocks()), (1, 2));
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn oldest_blocks_are_pruned() {
+        let path = db_path("prune");
+        let mut db = BlockDb::new(&path, PrunePolicy::Blocks(2));
+        for block_id in 1..4 {
+            db.insert_cmt_sample(0, block_id, &samples(block_id as u8, &[0]));
+        }
+        assert!(db.get_chunk(0, 1).is_none());
+        assert!(db.get_chunk(0, 3).is_some());
+        // headers outlive the samples
+        assert_eq!(db.get_header(0, 1), Some(vec![1]));
+
+        let size = serialize(&samples(4, &[0, 1])).unwrap().len();
+        db.set_prune_policy(PrunePolicy::Bytes(size));
+        assert_eq!(db.samples.record, vec![(0, 3)]);
+        db.insert_cmt_sample(0, 4, &samples(4, &[0, 1]));
+        assert_eq!(db.samples.record, vec![(0, 4)]);
+        drop(db);
+
+        let db = BlockDb::new(&path, PrunePolicy::Blocks(2));
+        assert_eq!(db.samples.record, vec![(0, 4)]);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn chunks_are_stored_apart() {
+        let path = db_path("chunks");
+        let mut db = BlockDb::new(&path, PrunePolicy::Blocks(8));
+        assert!(db.insert_cmt_sample(0, 1, &samples(7, &[0, 1])));
+        assert!(db.insert_cmt_sample(0, 1, &samples(7, &[1, 2])));
+        assert!(!db.insert_cmt_sample(0, 1, &samples(8, &[3])));
+        // a later chunk holds only the symbols it added, without the header
+        let chunks = db.get_chunks(0, 1);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].header, Vec::<u8>::new());
+        assert_eq!(chunks[1].idx, vec![vec![2]]);
+        let merged = db.get_chunk(0, 1).unwrap();
+        assert_eq!((merged.header, merged.idx), (vec![7], vec![vec![0, 1, 2]]));
+
+        let size = serialize(&samples(7, &[0, 1])).unwrap().len() + serialize(&chunks[1]).unwrap().len();
+        assert_eq!(db.samples.sizes[&(0, 1)], size);
+        drop(db);
+        let db = BlockDb::new(&path, PrunePolicy::Blocks(8));
+        assert_eq!(db.samples.sizes[&(0, 1)], size);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn decoded_blocks_have_their_own_retention() {
+        let path = db_path("retention");
+        let mut db = BlockDb::new(&path, PrunePolicy::Blocks(1));
+        for block_id in 1..4 {
+            db.insert_cmt_sample(0, block_id, &samples(block_id as u8, &[0]));
+            db.insert_sblock(0, block_id, sblock(block_id as u32));
+            db.insert_state(&ContractState { sid: 0, curr_hash: H256::default(), block_id }, None);
+        }
+        assert_eq!((db.get_num_samples(), db.get_num_blocks()), (1, 3));
+        assert!(db.get_chunk(0, 2).is_none());
+        assert_eq!(db.get_sblock(0, 1).unwrap().block_header.nonce, 1);
+
+        db.set_block_retention(PrunePolicy::Blocks(2));
+        assert!(db.get_sblock(0, 1).is_none());
+        assert!(db.get_sblock(0, 2).is_some());
+        // headers and states go with their block
+        assert!(db.get_header(0, 1).is_none());
+        assert_eq!(db.get_states(0).iter().map(|(s, _)| s.block_id).collect::<Vec<_>>(), vec![2, 3]);
+
+        // states rewritten by the chain keep their place
+        db.remove_states(0);
+        db.insert_state(&ContractState { sid: 0, curr_hash: H256::default(), block_id: 2 }, None);
+        assert_eq!(db.blocks.record, vec![(0, 2), (0, 3)]);
+        drop(db);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn parse_prune_policies() {
+        assert_eq!("blocks:8".parse::<PrunePolicy>(), Ok(PrunePolicy::Blocks(8)));
+        assert_eq!("bytes:1000000".parse::<PrunePolicy>(), Ok(PrunePolicy::Bytes(1000000)));
+        assert!("bytes".parse::<PrunePolicy>().is_err());
+        assert!("days:3".parse::<PrunePolicy>().is_err());
     }
-    
 }
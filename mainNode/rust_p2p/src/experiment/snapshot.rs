@@ -51,8 +51,14 @@ pub struct Counter {
 
     block_latency: AtomicUsize,
 
-    gas: AtomicUsize,
-
+    gas: AtomicUsize,   // gas prices of mined transactions in gwei, the max fee under eip1559
+
+    rejected_votes: AtomicUsize,
+    rejected_symbols: AtomicUsize,
+    hash_mismatches: AtomicUsize,
+    targeted_requests: AtomicUsize, // blocks whose chunks were asked again from scale nodes
+    rebroadcasts: AtomicUsize,
+    unavailable_blocks: AtomicUsize,
 }
 
 impl Counter {
@@ -83,6 +89,30 @@ impl Counter {
         self.gas.fetch_add(gas, Ordering::Relaxed);
     }
 
+    pub fn record_rejected_vote(&self) {
+        self.rejected_votes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rejected_symbols(&self, num: usize) {
+        self.rejected_symbols.fetch_add(num, Ordering::Relaxed);
+    }
+
+    pub fn record_hash_mismatch(&self) {
+        self.hash_mismatches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_targeted_request(&self) {
+        self.targeted_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rebroadcast(&self) {
+        self.rebroadcasts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_unavailable_block(&self) {
+        self.unavailable_blocks.fetch_add(1, Ordering::Relaxed);
+    }
+
     // should not be used later
     pub fn store_chain_depth(&self, chain_len: usize) {
         self.chain_depth.store(chain_len, Ordering::Relaxed);
@@ -232,6 +262,12 @@ impl Counter {
             submit_num: self.submit_num.load(Ordering::Relaxed) as usize,
             block_num: self.block_num.load(Ordering::Relaxed) as usize,
             coll_num: self.coll_num.load(Ordering::Relaxed) as usize,
+            rejected_votes: self.rejected_votes.load(Ordering::Relaxed),
+            rejected_symbols: self.rejected_symbols.load(Ordering::Relaxed),
+            hash_mismatches: self.hash_mismatches.load(Ordering::Relaxed),
+            targeted_requests: self.targeted_requests.load(Ordering::Relaxed),
+            rebroadcasts: self.rebroadcasts.load(Ordering::Relaxed),
+            unavailable_blocks: self.unavailable_blocks.load(Ordering::Relaxed),
         }
     }
 }
@@ -263,4 +299,11 @@ pub struct Snapshot {
     submit_num:              usize,
     block_num:               usize,
     coll_num:                usize,
+
+    rejected_votes:          usize,
+    rejected_symbols:        usize,
+    hash_mismatches:         usize,
+    targeted_requests:       usize,
+    rebroadcasts:            usize,
+    unavailable_blocks:      usize,
 }
@@ -12,23 +12,32 @@ use system_rust::network::performer;
 
 use system_rust::network::server;
 use system_rust::mempool::scheduler::{Scheduler, Token};
-use system_rust::db::blockDb::{BlockDb};
-use system_rust::blockchain::blockchain::{BlockChain};
+use system_rust::mempool::slotClock::SlotClock;
+use system_rust::db::blockDb::{BlockDb, PrunePolicy, DEFAULT_BLOCK_RETENTION};
+use system_rust::blockchain::blockchain::{BlockChain, ChainCheck, ChainUpdate};
 use system_rust::mempool::mempool::{Mempool};
 use system_rust::contract::contract::{Contract, Account};
+use system_rust::contract::gas::{GasPolicy, GasPrice};
 use std::sync::{Arc, Mutex};
 use system_rust::api::apiServer::ApiServer;
 use system_rust::experiment::transactionGenerator::{TransactionGenerator};
 use std::net::{SocketAddr};
+use std::collections::HashMap;
 use crossbeam::channel as cbchannel;
 use log::{info, warn, error, debug};
 use system_rust::mainChainManager::{Manager};
-use system_rust::cmtda::{read_codes};
+use system_rust::mainChainManager::lightNode::{LightNode};
+use system_rust::cmtda::{CmtParams, CodeFile, CodeRegistry};
+use chain::code_registry::binary_path;
+use std::path::Path;
 use chain::decoder::{Code};
+use chain::ldpc;
 use system_rust::contract::interface::{Handle, Answer};
 use system_rust::contract::interface::Message as ContractMessage;
 use system_rust::contract::interface::Response as ContractResponse;
-use system_rust::contract::utils::{BLSKey, BLSKeyStr};
+use system_rust::contract::interface::Error as ContractError;
+use system_rust::contract::utils::{BLSKey};
+use system_rust::crypto::keystore::{self, Passphrase};
 use system_rust::primitive::block::{ContractState};
 use web3::types::Address;
 use system_rust::experiment::snapshot::PERFORMANCE_COUNTER;
@@ -40,42 +49,86 @@ fn main() {
         (author: "Bowen Xue.<bx3@uw.edu>")
         (about: "simple blockchain network")
         (@arg known_peer: -c --connect ... [PEER] "Sets ip to connect to")
-        (@arg side_node: -r --side_node ... [SIDE] "Sets side ip to connect to")
+        (@arg side_node: -r --side_node ... [SIDE] "Sets side ip to connect to, SID@IP:PORT for side chains other than 0")
         (@arg peer_addr: -i --p2p [ADDR]  "Sets ip to listen")
         (@arg api_addr: -a --api_addr [ADDR] "Sets port for api")
         (@arg account: -d --account  [ACCOUNT] "Sets account address")
         (@arg contract_addr: -f --contract_addr [ADDR] "Sets ETH contract address")
         (@arg node_url: -u --node_url [HTTP] "Sets ETH node https url")
         (@arg key: -k --key  +takes_value "Sets key address")
+        (@arg passphrase_file: --passphrase_file +takes_value "file holding the key file passphrase, SCALE_PASSPHRASE or a prompt otherwise")
         //(@arg has_token: -t --has_token "Sets init token")
         (@arg scale_id: -s --scale_id  +takes_value "Sets scalechain node")
         (@arg ldpc: -l --ldpc  +takes_value "get ldpc file path")
+        (@arg cmt_config: --cmt_config +takes_value "json file with block size, symbol size, rate, header size and undecodable ratio of the coded Merkle tree")
         (@arg num_scale: -n --num_scale +takes_value "get number scale node")
-        (@arg binary_dir: -b --binary_dir +takes_value "get bls binary")
         (@arg abi_path: -j --abi_path +takes_value "get api_path")
         (@arg num_side: -e --num_side +takes_value "get num side")
         (@arg slot_time: -t --slot_time +takes_value "get slot time")
         (@arg start_time: --start_time +takes_value "contract starting time, measured in UNIX EPOCH")
+        (@arg slot_tolerance: --slot_tolerance +takes_value "milliseconds a proposal is taken before or after its slot, 500 by default")
+        (@arg epoch_slots: --epoch_slots +takes_value "number of slots in an epoch, 32 by default")
+        (@arg contract_block: --contract_block +takes_value "ETH block the contract is deployed at, side chain history is synced from there, without it no catch up")
+        (@arg gas_policy: --gas_policy +takes_value "gas price, fixed:GWEI, oracle:PERCENT of eth_gasPrice or eip1559:MAX_GWEI:PRIORITY_GWEI")
+        (@arg bump_blocks: --bump_blocks +takes_value "replace a transaction with a higher fee if not mined within this many blocks")
+        (@arg bump_percent: --bump_percent +takes_value "percent of the old fee a replacement pays, at least 110, 125 by default")
+        (@arg data_dir: --data_dir +takes_value "directory of the block database, /tmp/db<p2p addr> by default")
+        (@arg prune: --prune +takes_value "samples kept in the block database, blocks:N or bytes:N, blocks:8 by default")
+        (@arg keep_blocks: --keep_blocks +takes_value "decoded blocks, headers and chain states kept in the block database, blocks:N or bytes:N, blocks:1024 by default")
+        (@arg no_hash_check: --no_hash_check "append decoded blocks even if their headers do not match the contract hash chain")
+        (@arg decoders: --decoders +takes_value "number of threads decoding collected blocks, 4 by default")
+        (@arg light: --light "run as a light node, only sample committed blocks for availability")
         (@subcommand addScaleNode =>
             (@arg contract_addr: -f --contract_addr [ADDR] "Sets ETH contract address")
             (@arg node_url: -u --node_url [HTTP] "Sets ETH node https url")
             (@arg account: --account [ACCOUNT] "get account file")
+            (@arg passphrase_file: --passphrase_file +takes_value "file holding the account passphrase")
             (@arg new_account: --new_account +takes_value "get account file")
             (@arg keyfile: --keyfile +takes_value "get key file")
             (@arg ip_addr: --ip_addr +takes_value "get ip_addr")
         )
         (@subcommand getCurrState =>
             (@arg account: --account [ACCOUNT]  "get account file")
+            (@arg passphrase_file: --passphrase_file +takes_value "file holding the account passphrase")
             (@arg contract_addr: -f --contract_addr [ADDR] "Sets ETH contract address")
             (@arg node_url: -u --node_url [HTTP] "Sets ETH node https url")
+            (@arg sid: --sid +takes_value "side chain id, 0 by default")
         )
         (@subcommand resetChain =>
             (@arg account: --account [ACCOUNT]  "get account file")
+            (@arg passphrase_file: --passphrase_file +takes_value "file holding the account passphrase")
             (@arg contract_addr: -f --contract_addr [ADDR] "Sets ETH contract address")
             (@arg node_url: -u --node_url [HTTP] "Sets ETH node https url")
+            (@arg sid: --sid +takes_value "side chain id, 0 by default")
+        )
+        (@subcommand addSideChain =>
+            (@arg account: --account [ACCOUNT]  "get account file")
+            (@arg passphrase_file: --passphrase_file +takes_value "file holding the account passphrase")
+            (@arg contract_addr: -f --contract_addr [ADDR] "Sets ETH contract address")
+            (@arg node_url: -u --node_url [HTTP] "Sets ETH node https url")
+        )
+        (@subcommand keygen =>
+            (@arg account: --account +takes_value "where to write the encrypted account")
+            (@arg keyfile: --keyfile +takes_value "where to write the encrypted BLS key")
+            (@arg passphrase_file: --passphrase_file +takes_value "file holding the new passphrase")
+            (@arg scrypt_log_n: --scrypt_log_n +takes_value "scrypt cost as log2(n), 18 by default")
+        )
+        (@subcommand genLdpc =>
+            (@arg out: --out +takes_value "directory the k=K_encode.txt and k=K_decode.txt files are written to")
+            (@arg cmt_config: --cmt_config +takes_value "generate a code for every layer of this coded Merkle tree config")
+            (@arg k: --k +takes_value "number of systematic symbols, when no config is given")
+            (@arg var_degrees: --var_degrees +takes_value "variable node degrees, DEGREE or DEGREE:FRACTION,..., 6 by default")
+            (@arg seed: --seed +takes_value "random seed, 0 by default")
+            (@arg erasure: --erasure +takes_value "fraction of symbols erased in the stopping set check, 0.1 by default")
+            (@arg trials: --trials +takes_value "number of erasure patterns a code must peel, 10 by default")
+        )
+        (@subcommand packLdpc =>
+            (@arg ldpc: -l --ldpc +takes_value "directory holding k=K_encode.txt and k=K_decode.txt files")
+            (@arg cmt_config: --cmt_config +takes_value "pack the codes of every layer of this coded Merkle tree config")
         )
         (@subcommand getScaleNodes =>
             (@arg account: --account [ACCOUNT]  "get account file")
+            (@arg passphrase_file: --passphrase_file +takes_value "file holding the account passphrase")
             (@arg contract_addr: -f --contract_addr [ADDR] "Sets ETH contract address")
             (@arg node_url: -u --node_url [HTTP] "Sets ETH node https url")
         )
@@ -84,36 +137,32 @@ fn main() {
 
     match matches.subcommand() {
         ("addScaleNode", Some(m)) => {
-            let contract = get_contract_instance(&m);
-            let account: Account = match m.value_of("new_account") {
-                Some(account_path) => {
-                    let file = File::open(account_path).unwrap();
-                    serde_json::from_reader(file).expect("deser account")
-                },
+            let mut contract = get_contract_instance(&m);
+            // only the public parts of the new node are needed
+            let new_address = match m.value_of("new_account") {
+                Some(account_path) => keystore::read_address(account_path).expect("read account"),
                 None => panic!("unable to locate account"),
             };
             let key_path = m.
                 value_of("keyfile").
                 expect("missing key file");           
-            let key_file = File::open(key_path).unwrap();
-            let key_str: BLSKeyStr = match serde_json::from_reader(key_file) {
+            let key: BLSKey = match keystore::read_bls_public_key(key_path) {
                 Ok(k) => k,
                 Err(e) => {
-                    error!("unable to deser keyfile {:?}", key_path);
+                    error!("unable to read keyfile {:?}: {}", key_path, e);
                     return;
                 }
             };
-            let key: BLSKey = BLSKey::new(key_str);
             let ip_addr = m.
                 value_of("ip_addr").
                 unwrap().
                 to_string();
-            info!("get scale id {:?}", account.address);
-            match contract._get_scale_id(account.address.clone()) {
+            info!("get scale id {:?}", new_address);
+            match contract._get_scale_id(new_address.clone()) {
                 Some(i) => {
                     if i.as_usize() == 0 {
                         contract.add_scale_node(
-                            account.address,
+                            new_address,
                             ip_addr,
                             key.pkx1, key.pkx2, 
                             key.pky1, key.pky2
@@ -122,7 +171,7 @@ fn main() {
                 },
                 None => {
                     contract.add_scale_node(
-                        account.address,
+                        new_address,
                         ip_addr,
                         key.pkx1, key.pkx2, 
                         key.pky1, key.pky2
@@ -134,21 +183,112 @@ fn main() {
         },
         ("getCurrState", Some(m)) => {
             let contract = get_contract_instance(&m);
-            let state = contract._get_curr_state(0); 
+            let sid = get_sid(&m);
+            let state = contract._get_curr_state(sid); 
             println!("hash: {:?}\nblock_id: {:?}", state.curr_hash, state.block_id);
             return;
         },
         ("resetChain", Some(m)) => {
-            let contract = get_contract_instance(&m);
-            let mut state = contract._get_curr_state(0); 
+            let mut contract = get_contract_instance(&m);
+            let sid = get_sid(&m);
+            let mut state = contract._get_curr_state(sid); 
             if state.block_id != 0 {
-                contract.reset_chain(0); 
-                state = contract._get_curr_state(0); 
+                contract.reset_chain(sid); 
+                state = contract._get_curr_state(sid); 
             }
             println!("hash: {:?}\nblock_id: {:?}", state.curr_hash, state.block_id);
             assert!(state.block_id==0);
             return;
         },
+        ("addSideChain", Some(m)) => {
+            let mut contract = get_contract_instance(&m);
+            if contract.add_side_chain() {
+                // side chains are numbered in the order they are added
+                println!("sid: {}", contract._count_side_chains() - 1);
+            } else {
+                println!("unable to add side chain");
+            }
+            return;
+        },
+        ("keygen", Some(m)) => {
+            let account_path = m.value_of("account").expect("missing account path");
+            let key_path = m.value_of("keyfile").expect("missing key file path");
+            let log_n = match m.value_of("scrypt_log_n") {
+                Some(n) => n.parse::<u8>().expect("invalid scrypt cost"),
+                None => keystore::SCRYPT_LOG_N,
+            };
+            let passphrase = Passphrase::new(m.value_of("passphrase_file")).get().expect("read passphrase");
+            let account = keystore::new_account();
+            let key = keystore::new_bls_key();
+            if let Err(e) = keystore::save_account(account_path, &account, &passphrase, log_n) {
+                error!("unable to write account {}: {}", account_path, e);
+                return;
+            }
+            if let Err(e) = keystore::save_bls_key(key_path, &key, &passphrase, log_n) {
+                error!("unable to write key file {}: {}", key_path, e);
+                return;
+            }
+            println!("address: {:?}", account.address);
+            println!("bls public key: {} {} {} {}", key.pkx1, key.pkx2, key.pky1, key.pky2);
+            return;
+        },
+        ("genLdpc", Some(m)) => {
+            let out = m.value_of("out").expect("missing output directory");
+            let params = match m.value_of("cmt_config") {
+                Some(path) => CmtParams::from_file(path).unwrap_or_else(|e| panic!("{}", e)),
+                None => CmtParams::default(),
+            };
+            let k_set = match m.value_of("k") {
+                Some(k) => vec![k.parse::<u64>().expect("invalid k")],
+                None => params.k_set(),
+            };
+            let var_degrees = ldpc::parse_degrees(m.value_of("var_degrees").unwrap_or("6")).unwrap_or_else(|e| panic!("{}", e));
+            let seed = m.value_of("seed").unwrap_or("0").parse::<u64>().expect("invalid seed");
+            let erasure = m.value_of("erasure").unwrap_or("0.1").parse::<f64>().expect("invalid erasure fraction");
+            let trials = m.value_of("trials").unwrap_or("10").parse::<usize>().expect("invalid trials");
+            for k in k_set {
+                // layers get different codes from the same seed
+                let spec = ldpc::LdpcSpec { k, rate: params.rate, var_degrees: var_degrees.clone(), seed: seed ^ k };
+                let code = match ldpc::generate(&spec, erasure, trials) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        error!("{}", e);
+                        return;
+                    }
+                };
+                if let Err(e) = code.write(out) {
+                    error!("unable to write code k={} to {}: {}", k, out, e);
+                    return;
+                }
+                let packed = CodeFile::new(code.k, params.rate, code.encode, code.decode)
+                    .and_then(|c| c.write(&binary_path(Path::new(out), k)));
+                if let Err(e) = packed {
+                    error!("unable to write binary code k={} to {}: {}", k, out, e);
+                    return;
+                }
+                println!("k={} written to {}", k, out);
+            }
+            return;
+        },
+        ("packLdpc", Some(m)) => {
+            let dir = m.value_of("ldpc").expect("missing ldpc file path");
+            let params = match m.value_of("cmt_config") {
+                Some(path) => CmtParams::from_file(path).unwrap_or_else(|e| panic!("{}", e)),
+                None => CmtParams::default(),
+            };
+            for k in params.k_set() {
+                let packed = CodeFile::from_text(Path::new(dir), k, params.rate)
+                    .and_then(|c| c.write(&binary_path(Path::new(dir), k)).map(|_| c));
+                match packed {
+                    Ok(c) => println!("k={} hash {:?}", k, c.hash),
+                    Err(e) => {
+                        error!("unable to pack code k={} in {}: {}", k, dir, e);
+                        return;
+                    }
+                }
+            }
+            return;
+        },
         ("getScaleNodes", Some(m)) => {
             let contract = get_contract_instance(&m);
             let num_scale = contract._count_scale_nodes(); 
@@ -201,14 +341,29 @@ fn main() {
 
 
 
-    let bin_path = matches.value_of("binary_dir").expect("missing binary path");
     let abi_path = matches.value_of("abi_path").expect("missing json abi path");
     let key_path = matches.value_of("key").expect("missing key file");
     let ldpc_path = matches.value_of("ldpc").expect("missing ldpc file");
+    let cmt_params = match matches.value_of("cmt_config") {
+        Some(path) => CmtParams::from_file(path).unwrap_or_else(|e| panic!("{}", e)),
+        None => CmtParams::default(),
+    };
     let mut scale_id: u64 = matches.value_of("scale_id").expect("missing scaleid").parse::<u64>().unwrap();
     let mut num_scale: u64 = matches.value_of("num_scale").expect("missing number of scale").parse::<u64>().unwrap();
     let mut slot_time: u64 = matches.value_of("slot_time").expect("missing slot time").parse::<u64>().unwrap();
     let mut start_time: f64 = matches.value_of("start_time").expect("missing starting time").parse::<f64>().unwrap();
+    let contract_block: Option<u64> = matches.value_of("contract_block").map(|b| b.parse::<u64>().expect("invalid contract block"));
+    let mut gas_policy = GasPolicy::default();
+    if let Some(price) = matches.value_of("gas_policy") {
+        gas_policy.price = price.parse::<GasPrice>().unwrap_or_else(|e| panic!("{}", e));
+    }
+    if let Some(blocks) = matches.value_of("bump_blocks") {
+        gas_policy.bump_blocks = blocks.parse::<u64>().expect("invalid bump blocks");
+    }
+    if let Some(percent) = matches.value_of("bump_percent") {
+        let percent = percent.parse::<u64>().expect("invalid bump percent");
+        gas_policy.set_bump_percent(percent).unwrap_or_else(|e| panic!("{}", e));
+    }
     let start_sec: u64 = start_time.floor() as u64;
     let start_millis: u64 = ((start_time - start_time.floor())*1000.0).floor() as u64;
 
@@ -216,6 +371,14 @@ fn main() {
 
     info!("sec    {}", start_sec);
     info!("millis {}", start_millis);
+    let slot_tolerance: u64 = matches.value_of("slot_tolerance").unwrap_or("500").parse::<u64>().expect("invalid slot tolerance");
+    let epoch_slots: u64 = matches.value_of("epoch_slots").unwrap_or("32").parse::<u64>().expect("invalid epoch slots");
+    let slot_clock = SlotClock::new(
+        start_sec * 1000 + start_millis,
+        time::Duration::from_secs(slot_time),
+        epoch_slots,
+        time::Duration::from_millis(slot_tolerance),
+    );
 
     // get neighnors
     let mut neighbors = vec![];
@@ -229,18 +392,22 @@ fn main() {
         }
     }
 
-    let mut sidenodes = vec![];
+    // token ring of every side chain, in the order the nodes were given
+    let mut sidenodes: HashMap<u64, Vec<SocketAddr>> = HashMap::new();
     if let Some(side_nodes) =  matches.values_of("side_node") {
         let side_nodes: Vec<String> = side_nodes.map(|x| x.to_owned()).collect();
         for peer in side_nodes {
-            match peer.parse::<SocketAddr>() {
-                Ok(addr) => sidenodes.push(addr),
-                Err(_) => panic!("parse peer addr error"),
-            }
+            let (sid, addr) = parse_side_node(&peer);
+            sidenodes.entry(sid).or_insert(vec![]).push(addr);
         }
     }
 
-    let num_side = sidenodes.len() as u64;
+    let num_side = sidenodes.values().map(|ring| ring.len()).sum::<usize>() as u64;
+    // a side node proposes for the side chain whose ring it is in
+    let my_sid = sidenodes.iter()
+        .find(|(_, ring)| ring.contains(&p2p_addr))
+        .map(|(sid, _)| *sid)
+        .unwrap_or(0);
 
 
     //let has_token = sidenodes[0] == p2p_addr;
@@ -248,34 +415,49 @@ fn main() {
     //println!("side_nodes {:?}", sidenodes);
 
     let is_scale_node: bool = (scale_id > 0);
+    let is_light_node: bool = matches.is_present("light");
     
     // get accounts
     info!("api socket {:?}", api_socket);
+    let mut passphrase = Passphrase::new(matches.value_of("passphrase_file"));
     let account: Account = match matches.value_of("account") {
-        Some(account_path) => {
-            let file = File::open(account_path).unwrap();
-            serde_json::from_reader(file).expect("deser account")
+        Some(account_path) => match keystore::load_account(account_path, &mut passphrase) {
+            Ok(a) => a,
+            Err(e) => {
+                error!("unable to load account {:?}: {}", account_path, e);
+                return;
+            }
         },
         None => panic!("unable to locate account"),
     };
 
-    let key_file = File::open(key_path).unwrap();
-    let key_str: BLSKeyStr = match serde_json::from_reader(key_file) {
+    let key: BLSKey = match keystore::load_bls_key(key_path, &mut passphrase) {
         Ok(k) => k,
         Err(e) => {
-            error!("unable to deser keyfile {:?}", key_path);
+            error!("unable to load keyfile {:?}: {}", key_path, e);
             return;
         }
     };
-    let key: BLSKey = BLSKey::new(key_str);
     
 
     // roles
-    let block_db_path = "/tmp/db".to_owned() + &matches.
-        value_of("peer_addr").
-        unwrap();
-    let block_db = Arc::new(Mutex::new(BlockDb::new(block_db_path)));
-    let blockchain = Arc::new(Mutex::new(BlockChain::new()));
+    let block_db_path = match matches.value_of("data_dir") {
+        Some(dir) => dir.to_owned(),
+        None => "/tmp/db".to_owned() + &matches.value_of("peer_addr").unwrap(),
+    };
+    let prune = match matches.value_of("prune") {
+        Some(p) => p.parse::<PrunePolicy>().unwrap_or_else(|e| panic!("{}", e)),
+        None => PrunePolicy::default(),
+    };
+    let keep_blocks = match matches.value_of("keep_blocks") {
+        Some(p) => p.parse::<PrunePolicy>().unwrap_or_else(|e| panic!("{}", e)),
+        None => DEFAULT_BLOCK_RETENTION,
+    };
+    let mut block_db = BlockDb::new(block_db_path, prune);
+    block_db.set_block_retention(keep_blocks);
+    let block_db = Arc::new(Mutex::new(block_db));
+    let sids: Vec<u64> = sidenodes.keys().cloned().collect();
+    let blockchain = Arc::new(Mutex::new(BlockChain::restore(block_db.clone(), &sids)));
 
     let (task_sender, task_receiver) =cbchannel::unbounded();
 
@@ -289,14 +471,26 @@ fn main() {
     let (schedule_handle_sender, schedule_handle_receiver) = cbchannel::unbounded();
     let (contract_handle_sender, contract_handle_receiver) = cbchannel::unbounded();
     let (manager_handle_sender, manager_handle_receiver) = cbchannel::unbounded();
-    let k_set: Vec<u64> = vec![128,64,32,16,8,4];//vec![32,16,8];//  //128,64,32,16,8
-    let (codes_for_encoding, codes_for_decoding) = read_codes(k_set.clone(), ldpc_path);
+    let (light_handle_sender, light_handle_receiver) = cbchannel::unbounded();
+    let mut code_registry = CodeRegistry::new(ldpc_path);
+    let code_set = match code_registry.code_set(&cmt_params) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("unable to load ldpc codes from {}: {}", ldpc_path, e);
+            return;
+        }
+    };
+    info!("ldpc code id {:?}", code_set.id);
+    let code_registry = Arc::new(code_registry);
+    let codes_for_encoding = code_set.encoding;
+    let codes_for_decoding = code_set.decoding;
     let mempool = Arc::new(Mutex::new(Mempool::new(
         contract_handle_sender.clone(),
         schedule_handle_sender.clone(),
         p2p_addr.clone(),
         codes_for_encoding.clone(),
         codes_for_decoding.clone(),
+        cmt_params.block_size as usize,
     )));
 
     
@@ -305,7 +499,7 @@ fn main() {
 
     let contract = Contract::new(
         account.clone(),
-        key,
+        key.clone(),
         task_sender.clone(),
         server_handle.control_tx.clone(),
         contract_handle_receiver,
@@ -313,22 +507,98 @@ fn main() {
         abi_path.to_string(),
         rpc_url,
         &contract_addr,
+        contract_block,
+        gas_policy,
     );
 
-    let manager = Manager::new(
-        contract_handle_sender.clone(),
-        blockchain.clone(),
-        mempool.clone(),
-        server_handle.control_tx.clone(),
-        p2p_addr.clone(),
-        manager_handle_receiver,
-        block_db.clone(),
-        codes_for_encoding.clone(),
-        codes_for_decoding.clone(),
-        k_set.clone()
-    );
+    // a restored chain must agree with the contract, otherwise it is dropped and rebuilt
+    {
+        let mut chain = blockchain.lock().unwrap();
+        for sid in chain.side_chains() {
+            let state = match contract._fetch_curr_state(sid as usize) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("unable to check local chain {} against the contract: {:?}", sid, e);
+                    continue;
+                }
+            };
+            match chain.check_state(&state) {
+                ChainCheck::Synced => info!("local chain {} is synced with the contract at block {}", sid, state.block_id),
+                ChainCheck::Behind(tip) => info!("local chain {} is at block {}, the contract at block {}", sid, tip, state.block_id),
+                ChainCheck::Ahead(tip) => {
+                    error!("local chain {} is at block {} but the contract only at block {}, dropping the local chain", sid, tip, state.block_id);
+                    chain.replace(sid, vec![ContractState::genesis(sid)]);
+                },
+                ChainCheck::Diverged(block_id, local, remote) => {
+                    error!("local chain {} diverges from the contract at block {}: local hash {:?}, contract hash {:?}, dropping the local chain",
+                           sid, block_id, local, remote);
+                    chain.replace(sid, vec![ContractState::genesis(sid)]);
+                },
+            }
+        }
+    }
 
-    if scale_id == 0 {
+    // a fresh node replays the side chains from the contract before joining
+    if contract_block.is_some() {
+        let sids = blockchain.lock().unwrap().side_chains();
+        for sid in sids {
+            let history = loop {
+                match contract._fetch_all(sid as usize, 0, 0) {
+                    Ok(h) => break h,
+                    Err(ContractError::Syncing(eth_block)) => {
+                        info!("waiting for contract history, synced to eth block {}", eth_block);
+                        thread::sleep(time::Duration::from_secs(2));
+                    },
+                    Err(e) => {
+                        warn!("unable to catch up local chain {}: {:?}", sid, e);
+                        break vec![];
+                    },
+                }
+            };
+            let mut chain = blockchain.lock().unwrap();
+            match chain.catch_up(sid, &history) {
+                ChainUpdate::Revised(block_id) => warn!("local chain {} replaced from block {} by the contract history", sid, block_id),
+                ChainUpdate::Missing(tip, block_id) => warn!("contract history of side chain {} jumps from block {} to {}", sid, tip, block_id),
+                _ => (),
+            }
+            info!("caught up local chain {} to {:?}", sid, chain.get_latest_state(sid));
+        }
+    }
+
+    // the manager and its decoder threads only run on a side node that collects blocks, other
+    // nodes drop the receiver and chunk replies to them go nowhere
+    if is_light_node {
+        let light_node = LightNode::new(
+            contract_handle_sender.clone(),
+            blockchain.clone(),
+            server_handle.control_tx.clone(),
+            p2p_addr.clone(),
+            light_handle_receiver,
+            slot_time,
+            code_registry.clone(),
+            cmt_params.clone(),
+        );
+        light_node.start();
+    } else if scale_id == 0 {
+        let num_decoders: usize = matches.value_of("decoders").unwrap_or("4").parse::<usize>().expect("invalid number of decoders");
+        let manager = Manager::new(
+            contract_handle_sender.clone(),
+            blockchain.clone(),
+            mempool.clone(),
+            server_handle.control_tx.clone(),
+            p2p_addr.clone(),
+            manager_handle_receiver,
+            block_db.clone(),
+            codes_for_encoding.clone(),
+            codes_for_decoding.iter().cloned().map(Arc::new).collect(),
+            code_set.id,
+            sidenodes.clone(),
+            cmt_params.clone(),
+            !matches.is_present("no_hash_check"),
+            num_scale,
+            time::Duration::from_secs(slot_time),
+            num_decoders,
+        );
         manager.start();
     }
 
@@ -341,15 +611,16 @@ fn main() {
         blockchain.clone(),
         contract_handle_sender.clone(),
         //side_id as u64,
-        sidenodes.clone(),
+        my_sid,
+        sidenodes.get(&my_sid).cloned().unwrap_or(vec![]),
         account.address.clone(),
-        slot_time,
-        start_sec,
-        start_millis,
+        slot_clock.clone(),
         num_scale,
         codes_for_encoding.clone(),
+        code_set.id,
+        cmt_params.clone(),
     );
-    if scale_id == 0 {
+    if scale_id == 0 && !is_light_node {
 
         scheduler.start();
     }
@@ -364,19 +635,19 @@ fn main() {
         schedule_handle_sender.clone(),
         contract_handle_sender.clone(),
         p2p_addr.clone(),
-        key_path.to_string(),
+        key,
         scale_id,
         0,
         server_handle.control_tx.clone(),
         manager_handle_sender.clone(),
+        light_handle_sender.clone(),
         num_scale,
-        bin_path,
         num_side,
         account.address.clone(),
-        slot_time,
         sidenodes.clone(),
-        start_sec,
-        start_millis,
+        slot_clock.clone(),
+        code_registry.clone(),
+        cmt_params.clone(),
     );
     performer.start();
 
@@ -459,10 +730,10 @@ pub fn parse_addr_file(filename: &str) -> Vec<SocketAddr> {
     }
     neighbors
 }
-pub fn sync_chain(contract_channel: cbchannel::Sender<Handle>) -> usize {
+pub fn sync_chain(contract_channel: cbchannel::Sender<Handle>, sid: usize) -> usize {
     let (answer_tx, answer_rx) = cbchannel::bounded(1);
     let handle = Handle {
-        message: ContractMessage::SyncChain,
+        message: ContractMessage::SyncChain(sid),
         answer_channel: Some(answer_tx),
     };
     contract_channel.send(handle);
@@ -489,17 +760,30 @@ pub fn sync_chain(contract_channel: cbchannel::Sender<Handle>) -> usize {
     chain_len
 }
 
-pub fn get_contract_instance(m : &ArgMatches) -> Contract {
-    let account: Account = match m.value_of("account") {
-        Some(account_path) => {
-            let file = File::open(account_path).unwrap();
-            serde_json::from_reader(file).expect("deser account")
-        },
-        None => {
-            let file = File::open("accounts/account1").unwrap();
-            serde_json::from_reader(file).expect("deser account")
-        }
+// SID@IP:PORT, a bare IP:PORT belongs to side chain 0
+pub fn parse_side_node(s: &str) -> (u64, SocketAddr) {
+    let (sid, addr) = match s.find('@') {
+        Some(i) => (s[..i].parse::<u64>().expect("parse side chain id error"), &s[i+1..]),
+        None => (0, s),
     };
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) => (sid, addr),
+        Err(_) => panic!("parse peer addr error"),
+    }
+}
+
+pub fn get_sid(m: &ArgMatches) -> usize {
+    match m.value_of("sid") {
+        Some(sid) => sid.parse::<usize>().expect("invalid side chain id"),
+        None => 0,
+    }
+}
+
+pub fn get_contract_instance(m : &ArgMatches) -> Contract {
+    let account_path = m.value_of("account").unwrap_or("accounts/account1");
+    let mut passphrase = Passphrase::new(m.value_of("passphrase_file"));
+    let account: Account = keystore::load_account(account_path, &mut passphrase)
+        .unwrap_or_else(|e| panic!("unable to load account {}: {}", account_path, e));
     let contract_addr = m.
         value_of("contract_addr").
         expect("missing contract file").
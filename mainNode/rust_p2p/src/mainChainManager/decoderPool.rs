@@ -0,0 +1,127 @@
+use crossbeam::channel::{self, Sender, Receiver, RecvTimeoutError};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::network::message::Samples;
+use super::JobManager;
+
+// a worker with nothing queued looks at every job this often, so deadlines pass without chunks
+const IDLE_CHECK: Duration = Duration::from_millis(50);
+
+struct PoolEntry {
+    chunk_sender: Sender<(SocketAddr, Option<Samples>)>,
+    job: Arc<Mutex<JobManager>>,
+}
+
+// A fixed number of workers run the jobs of all blocks being collected. Jobs are kept by
+// (sid, bid), a job is queued whenever a chunk arrives for it and is done by one worker at a time.
+// A job is queued at most once, the worker taking it steps over every chunk that arrived by then.
+pub struct DecoderPool {
+    jobs: Arc<Mutex<HashMap<(u64, u64), PoolEntry>>>,
+    queue: Sender<(u64, u64)>,
+    queued: Arc<Mutex<HashSet<(u64, u64)>>>,
+}
+
+impl DecoderPool {
+    pub fn new(size: usize) -> DecoderPool {
+        let jobs = Arc::new(Mutex::new(HashMap::new()));
+        let queued = Arc::new(Mutex::new(HashSet::new()));
+        let (queue, queue_sink) = channel::unbounded();
+        for _ in 0..size {
+            let jobs = jobs.clone();
+            let queued = queued.clone();
+            let queue_sink = queue_sink.clone();
+            thread::spawn(move || {
+                work(jobs, queued, queue_sink);
+            });
+        }
+        DecoderPool {
+            jobs,
+            queue,
+            queued,
+        }
+    }
+
+    pub fn insert(&self, key: (u64, u64), chunk_sender: Sender<(SocketAddr, Option<Samples>)>, job: JobManager) {
+        let entry = PoolEntry {
+            chunk_sender,
+            job: Arc::new(Mutex::new(job)),
+        };
+        self.jobs.lock().unwrap().insert(key, entry);
+    }
+
+    // false if no job collects the block
+    pub fn send_chunk(&self, key: (u64, u64), peer: SocketAddr, chunk: Option<Samples>) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.get(&key) {
+            Some(entry) => {
+                let _ = entry.chunk_sender.send((peer, chunk));
+                drop(jobs);
+                if self.queued.lock().unwrap().insert(key) {
+                    let _ = self.queue.send(key);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, key: &(u64, u64)) -> bool {
+        self.jobs.lock().unwrap().contains_key(key)
+    }
+
+    pub fn remove(&self, key: &(u64, u64)) {
+        self.jobs.lock().unwrap().remove(key);
+    }
+
+    pub fn retain<F: Fn(&(u64, u64)) -> bool>(&self, keep: F) {
+        self.jobs.lock().unwrap().retain(|k, _| keep(k));
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.lock().unwrap().len()
+    }
+}
+
+fn work(
+    jobs: Arc<Mutex<HashMap<(u64, u64), PoolEntry>>>,
+    queued: Arc<Mutex<HashSet<(u64, u64)>>>,
+    queue_sink: Receiver<(u64, u64)>,
+) {
+    loop {
+        // a queued job waits for the worker holding it, on an idle check busy jobs are skipped
+        let (keys, wait) = match queue_sink.recv_timeout(IDLE_CHECK) {
+            Ok(key) => {
+                // chunks arriving from now on queue the job again
+                queued.lock().unwrap().remove(&key);
+                (vec![key], true)
+            },
+            Err(RecvTimeoutError::Timeout) => (jobs.lock().unwrap().keys().cloned().collect(), false),
+            // the pool is dropped with the manager
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        for key in keys {
+            let job = match jobs.lock().unwrap().get(&key) {
+                Some(entry) => entry.job.clone(),
+                None => continue,
+            };
+            let done = if wait {
+                job.lock().unwrap().step()
+            } else {
+                match job.try_lock() {
+                    Ok(mut job) => job.step(),
+                    Err(_) => continue,
+                }
+            };
+            if done {
+                let mut jobs = jobs.lock().unwrap();
+                // the block may have been collected again meanwhile
+                if jobs.get(&key).map_or(false, |entry| Arc::ptr_eq(&entry.job, &job)) {
+                    jobs.remove(&key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crossbeam::channel::{self, Sender, Receiver};
+use mio_extras::channel::Sender as MioSender;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use ser::deserialize;
+use crate::blockchain::blockchain::{BlockChain};
+use crate::contract::interface::{Handle, Answer};
+use crate::contract::interface::Message as ContractMessage;
+use crate::contract::interface::Response as ContractResponse;
+use crate::crypto::chain_hash;
+use crate::network::message::{Message, ServerSignal};
+use crate::primitive::block::ContractState;
+use crate::cmtda::{BlockHeader, CmtParams, CodeRegistry, LightClient, BaseSample, sample_indices, SAMPLE_COMPLEXITY};
+
+// next block of a side chain whose availability is being sampled
+struct PendingBlock {
+    state: ContractState,
+    indices: Vec<u32>,
+    client: Option<LightClient>,
+    // requested indices whose symbol passed its Merkle proof
+    served: HashSet<u32>,
+    requested_at: Instant,
+}
+
+impl PendingBlock {
+    fn is_available(&self) -> bool {
+        self.client.is_some() && self.indices.iter().all(|i| self.served.contains(i))
+    }
+}
+
+// A light node follows the side chains without downloading blocks. For every block committed on
+// the contract it samples SAMPLE_COMPLEXITY base symbols from the side nodes and only extends its
+// local chain once all of them are served with a valid Merkle proof.
+pub struct LightNode {
+    contract_handler: Sender<Handle>,
+    chain: Arc<Mutex<BlockChain>>,
+    server_control_sender: MioSender<ServerSignal>,
+    addr: SocketAddr,
+    // (sid, bid, header, samples)
+    sample_sink: Receiver<(u64, u64, Vec<u8>, Vec<BaseSample>)>,
+    // states pushed by the contract watcher
+    state_source: Sender<ContractState>,
+    state_sink: Receiver<ContractState>,
+    // latest state pushed for each side chain
+    committed: HashMap<u64, ContractState>,
+    pending: HashMap<u64, PendingBlock>,
+    // samples not complete by then are requested again
+    timeout: Duration,
+    // blocks encoded with codes not in it are not sampled
+    code_registry: Arc<CodeRegistry>,
+    params: CmtParams,
+}
+
+impl LightNode {
+    pub fn new(
+        contract_handler: Sender<Handle>,
+        chain: Arc<Mutex<BlockChain>>,
+        server_control_sender: MioSender<ServerSignal>,
+        addr: SocketAddr,
+        sample_sink: Receiver<(u64, u64, Vec<u8>, Vec<BaseSample>)>,
+        slot_time: u64,
+        code_registry: Arc<CodeRegistry>,
+        params: CmtParams,
+    ) -> LightNode {
+        let (state_source, state_sink) = channel::unbounded();
+        LightNode {
+            contract_handler,
+            chain,
+            server_control_sender,
+            addr,
+            sample_sink,
+            state_source,
+            state_sink,
+            committed: HashMap::new(),
+            pending: HashMap::new(),
+            timeout: Duration::from_secs(slot_time),
+            code_registry,
+            params,
+        }
+    }
+
+    pub fn start(mut self) {
+        thread::spawn(move || {
+            // the contract pushes the state of each side chain when watched and whenever it changes
+            let side_chains = self.chain.lock().unwrap().side_chains();
+            for sid in side_chains {
+                let handle = Handle {
+                    message: ContractMessage::WatchState(sid as usize, self.state_source.clone()),
+                    answer_channel: None,
+                };
+                self.contract_handler.send(handle);
+            }
+            loop {
+                self.run_once();
+            }
+        });
+        info!("Light node started");
+    }
+
+    // wait for the next state, samples or request timeout and handle it
+    fn run_once(&mut self) {
+        let timeout = self.timeout;
+        let timer = match self.pending.values().map(|p| p.requested_at + timeout).min() {
+            Some(at) => channel::after(at.saturating_duration_since(Instant::now())),
+            None => channel::never(),
+        };
+        crossbeam::select! {
+            recv(self.state_sink) -> state => {
+                let state = state.expect("contract watcher broken");
+                self.committed.insert(state.sid, state.clone());
+                self.advance(state.sid);
+            },
+            recv(self.sample_sink) -> reply => {
+                let (sid, block_id, header, samples) = reply.expect("light node sample sink broken");
+                self.receive_samples(sid, block_id, &header, samples);
+                self.advance(sid);
+            },
+            recv(timer) -> _ => {
+                self.request_again();
+            },
+        }
+    }
+
+    // append the pending block once it is available and sample the next one
+    fn advance(&mut self, sid: u64) {
+        if self.pending.get(&sid).map_or(false, |p| p.is_available()) {
+            let p = self.pending.remove(&sid).unwrap();
+            info!("{:?} block {} on side chain {} is available", self.addr, p.state.block_id, sid);
+            self.chain.lock().unwrap().append(&p.state);
+        }
+        if !self.pending.contains_key(&sid) {
+            if let Some(p) = self.next_block(sid) {
+                self.pending.insert(sid, p);
+            }
+        }
+    }
+
+    // ask side nodes for the next block on top of the local tip, if the contract has committed one
+    fn next_block(&self, sid: u64) -> Option<PendingBlock> {
+        let tip = self.chain.lock().unwrap().get_latest_state(sid)?;
+        let committed = self.committed.get(&sid)?;
+        if committed.block_id <= tip.block_id {
+            return None;
+        }
+        // the contract may have moved several blocks since the tip, only then is it asked
+        let state = if committed.block_id == tip.block_id + 1 {
+            committed.clone()
+        } else {
+            self.fetch_state(sid, tip.block_id)?
+        };
+        if state.block_id != tip.block_id + 1 {
+            return None;
+        }
+        let mut block = PendingBlock {
+            state,
+            indices: vec![],
+            client: None,
+            served: HashSet::new(),
+            requested_at: Instant::now(),
+        };
+        self.request_samples(&mut block);
+        Some(block)
+    }
+
+    // committed state of the block after block_id
+    fn fetch_state(&self, sid: u64, block_id: u64) -> Option<ContractState> {
+        let (answer_tx, answer_rx) = channel::bounded(1);
+        let handle = Handle {
+            message: ContractMessage::GetAll((sid as usize, block_id as usize, block_id as usize + 1)),
+            answer_channel: Some(answer_tx),
+        };
+        self.contract_handler.send(handle);
+        match answer_rx.recv() {
+            Ok(Answer::Success(ContractResponse::GetAll(txs))) => Some(txs.into_iter().next()?.contract_state),
+            Ok(Answer::Fail(reason)) => {
+                warn!("{:?} unable to get states of side chain {}: {}", self.addr, sid, reason);
+                None
+            },
+            Ok(_) => panic!("light node contract get wrong answer"),
+            Err(e) => panic!("light node contract channel broke"),
+        }
+    }
+
+    fn request_samples(&self, block: &mut PendingBlock) {
+        block.indices = sample_indices(&self.params, SAMPLE_COMPLEXITY);
+        block.served.clear();
+        block.requested_at = Instant::now();
+        let message = Message::LightReqSamples(block.state.sid, block.state.block_id, block.indices.clone());
+        self.server_control_sender.send(ServerSignal::ServerBroadcast(message));
+    }
+
+    // each requested symbol is verified once, when it first arrives
+    fn receive_samples(&mut self, sid: u64, block_id: u64, header: &[u8], samples: Vec<BaseSample>) {
+        let block = match self.pending.get_mut(&sid) {
+            Some(b) if b.state.block_id == block_id => b,
+            _ => return,
+        };
+        if block.client.is_none() {
+            // the header must be the one the contract chained on top of our tip
+            let tip = match self.chain.lock().unwrap().get_latest_state(sid) {
+                Some(t) => t,
+                None => return,
+            };
+            if chain_hash(tip.curr_hash, header) != block.state.curr_hash {
+                warn!("{:?} drop samples of block {} on side chain {}, header is not committed", self.addr, block_id, sid);
+                return;
+            }
+            let header: BlockHeader = match deserialize(header) {
+                Ok(h) => h,
+                Err(e) => {
+                    warn!("{:?} malformed header of block {} on side chain {}", self.addr, block_id, sid);
+                    return;
+                }
+            };
+            if self.code_registry.find(&header.code_id).is_none() {
+                warn!("{:?} drop samples of block {} on side chain {}, unknown codes {:?}", self.addr, block_id, sid, header.code_id);
+                return;
+            }
+            block.client = Some(LightClient::new(header, &self.params));
+        }
+        let client = block.client.as_ref().unwrap();
+        for sample in samples {
+            if block.indices.contains(&sample.index) && !block.served.contains(&sample.index) && client.verify_sample(&sample) {
+                block.served.insert(sample.index);
+            }
+        }
+    }
+
+    // sample again the pending blocks that are not complete after the timeout
+    fn request_again(&mut self) {
+        let now = Instant::now();
+        let timeout = self.timeout;
+        let due: Vec<u64> = self.pending.iter()
+            .filter(|(_, p)| p.requested_at + timeout <= now)
+            .map(|(sid, _)| *sid)
+            .collect();
+        for sid in due {
+            let mut block = self.pending.remove(&sid).unwrap();
+            if block.client.is_some() {
+                let missing = block.indices.iter().filter(|i| !block.served.contains(i)).count();
+                warn!("{:?} block {} on side chain {} is unavailable, {} of {} samples missing",
+                      self.addr, block.state.block_id, sid, missing, block.indices.len());
+            } else {
+                warn!("{:?} block {} on side chain {} is unavailable, no side node served it",
+                      self.addr, block.state.block_id, sid);
+            }
+            self.request_samples(&mut block);
+            self.pending.insert(sid, block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::hash::H256;
+    use chain::decoder::Symbol;
+    use chain::fixtures;
+    use ser::serialize;
+
+    #[test]
+    fn pushed_state_is_sampled_and_each_sample_verified_once() {
+        let params = fixtures::params();
+        let block = fixtures::block(1, &vec![fixtures::transaction(1)]);
+        let header: Vec<u8> = serialize(&block.block_header).into();
+        let state = ContractState { sid: 0, curr_hash: chain_hash(H256::default(), &header), block_id: 1 };
+
+        let (contract_handler, contract_handle) = channel::unbounded();
+        let (server_control_sender, server_control_receiver) = mio_extras::channel::channel();
+        let (sample_source, sample_sink) = channel::unbounded();
+        let chain = Arc::new(Mutex::new(BlockChain::new()));
+        let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let registry = Arc::new(fixtures::code_registry(&params));
+        let mut light_node = LightNode::new(contract_handler, chain.clone(), server_control_sender, addr, sample_sink, 10, registry, params.clone());
+
+        // the state of the next block is pushed, the contract is not asked for it
+        light_node.state_source.send(state).unwrap();
+        light_node.run_once();
+        let indices = match server_control_receiver.try_recv() {
+            Ok(ServerSignal::ServerBroadcast(Message::LightReqSamples(0, 1, indices))) => indices,
+            _ => panic!("samples of the pushed block are not requested"),
+        };
+        assert!(contract_handle.try_recv().is_err());
+
+        // a sample with a bad proof does not count, the rest completes the block
+        let mut samples: Vec<BaseSample> = indices.iter().map(|i| BaseSample::from_block(&block, *i).unwrap()).collect();
+        let mut forged = samples[0].clone();
+        forged.symbol = Symbol::Base(vec![0xff; 32]);
+        sample_source.send((0, 1, header.clone(), vec![forged])).unwrap();
+        light_node.run_once();
+        assert!(light_node.pending[&0].served.is_empty());
+        let last = samples.pop().unwrap();
+        sample_source.send((0, 1, header.clone(), samples)).unwrap();
+        light_node.run_once();
+        assert_eq!(light_node.pending[&0].served.len(), indices.len() - 1);
+        assert_eq!(chain.lock().unwrap().get_height(0), 1);
+        sample_source.send((0, 1, header.clone(), vec![last])).unwrap();
+        light_node.run_once();
+        assert_eq!(chain.lock().unwrap().get_latest_state(0), Some(state));
+        assert!(light_node.pending.is_empty());
+    }
+}
@@ -1,24 +1,30 @@
+pub mod lightNode;
+pub mod decoderPool;
+
 use crossbeam::channel::{self, Sender, Receiver, TryRecvError};
 use super::contract::interface::{Handle, Answer};
 use super::primitive::block::ContractState;
-use std::{thread, time};
+use std::thread;
 use mio_extras::channel::Sender as MioSender;
 use super::network::message::{Message, ServerSignal, Samples};
+use super::network::sampleSet::SampleSet;
 use super::contract::interface::Message as ContractMessage;
 use super::contract::interface::Response as ContractResponse;
 use super::contract::utils;
 use std::sync::{Arc, Mutex};
 use std::net::SocketAddr;
-use super::blockchain::blockchain::{BlockChain};
+use super::blockchain::blockchain::{BlockChain, ChainUpdate};
 use super::mempool::mempool::{Mempool};
+use super::mempool::scheduler::{slot_proposer, get_sample_index};
 use std::collections::HashMap;
+use self::decoderPool::DecoderPool;
 use super::db::blockDb::{BlockDb};
 use chain::block::Block as SBlock;
 use chain::decoder::CodingErr;
 use chain::decoder::{Symbol};
 use chain::decoder::{Code, Decoder, TreeDecoder, IncorrectCodingProof};
-use chain::constants::{NUM_BASE_SYMBOL, UNDECODABLE_RATIO};
-use super::cmtda::{read_codes, BlockHeader};
+use super::cmtda::{BlockHeader, CmtParams, ReconstructErr};
+use super::cmtda::H256 as CMTH256;
 use super::cmtda::Transaction as CMTTransaction;
 use primitives::bytes::{Bytes};
 use crypto::sha3::Sha3;
@@ -26,8 +32,10 @@ use crypto::sha2::Sha256;
 use crypto::digest::Digest;
 use super::primitive::hash::H256;
 use ser::{deserialize, serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use super::experiment::snapshot::PERFORMANCE_COUNTER;
+use log::{warn, error};
+use crate::crypto::chain_hash;
 
 pub struct Manager {
     pub contract_handler: Sender<Handle>,
@@ -36,125 +44,330 @@ pub struct Manager {
     pub block_db: Arc<Mutex<BlockDb>>,
     pub server_control_sender: MioSender<ServerSignal>,
     pub addr: SocketAddr,
-    pub manager_sink: Receiver<(u64, Option<Samples>)>,
-    pub chunk_senders: HashMap<u64, Sender<Option<Samples>> >,
+    // (sid, bid, sender, chunk)
+    pub manager_sink: Receiver<(u64, u64, SocketAddr, Option<Samples>)>,
     pub codes_for_encoding: Vec<Code>,
-    pub codes_for_decoding: Vec<Code>,
-    pub k_set: Vec<u64>,
+    // shared by the decoders of all jobs
+    pub codes_for_decoding: Vec<Arc<Code>>,
+    pub code_id: CMTH256,
+    pub sidenodes: HashMap<u64, Vec<SocketAddr>>, // token ring of each side chain
+    pub params: CmtParams,
+    // decoded headers must extend the contract hash chain before they are appended
+    pub check_hash: bool,
+    // states pushed by the contract watcher
+    state_source: Sender<ContractState>,
+    state_sink: Receiver<ContractState>,
+    // number of scale nodes, each stores the base symbols get_sample_index assigns to it
+    pub num_scale: u64,
+    // scale nodes that served their chunk before, by scale id
+    scale_peers: Arc<Mutex<HashMap<u64, SocketAddr>>>,
+    // a job waits this long for chunks before it asks again
+    collect_timeout: Duration,
+    // (sid, bid, result) of finished jobs
+    block_source: Sender<(u64, u64, Collected)>,
+    block_sink: Receiver<(u64, u64, Collected)>,
+    // jobs of the blocks being collected
+    pool: DecoderPool,
+    // blocks being collected and collected blocks are keyed by (sid, bid)
+    register_blocks: HashMap<(u64, u64), ContractState>,
+    // decoded blocks with their serialized header, waiting to be appended in order
+    ready_blocks: HashMap<(u64, u64), (ContractState, Vec<u8>)>,
+    // unavailable blocks with the time they are collected again
+    retries: Vec<(Instant, ContractState)>,
+    // committed state of an incorrectly coded block, the local chain stops before it and
+    // nothing after it is collected until the contract is reset
+    invalid: HashMap<u64, ContractState>,
+    longest_id: HashMap<u64, u64>,
+}
+
+// outcome of a collection job
+pub enum Collected {
+    Decoded(SBlock),
+    Incorrect(IncorrectCodingProof),
+    // correctly coded, but the data is not the transactions of the header
+    Malformed(ReconstructErr),
+    // too few symbols were served after every request
+    Unavailable,
+}
+
+// requests a job makes for its chunks, each waits collect_timeout before the next one
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Round {
+    Broadcast,
+    // ask the scale nodes whose symbols are missing
+    Targeted,
+    Rebroadcast,
 }
 
 pub struct JobManager {
     state: ContractState,
+    // hash the contract held before the block, a header is taken only if it chains from it to state
+    prev_hash: H256,
     addr: SocketAddr,
     server_control_sender: MioSender<ServerSignal>,
-    chunk_receiver: Receiver<Option<Samples>>,
-    block_source: Sender<Result<SBlock, CodingErr>>,
-    codes_for_encoding: Vec<Code>,
-    codes_for_decoding: Vec<Code>,
-    k_set: Vec<u64>,
+    chunk_receiver: Receiver<(SocketAddr, Option<Samples>)>,
+    chain: Arc<Mutex<BlockChain>>,
+    block_source: Sender<(u64, u64, Collected)>,
+    num_scale: u64,
+    scale_peers: Arc<Mutex<HashMap<u64, SocketAddr>>>,
+    timeout: Duration,
+    codes_for_decoding: Vec<Arc<Code>>,
+    code_id: CMTH256,
+    params: CmtParams,
+    // symbols of the first header that passes verification
+    coll: Option<SampleSet>,
+    // fed with the symbols as they are merged, so decoding goes on from where it stopped
+    decoder: Option<TreeDecoder>,
+    round: Round,
+    deadline: Instant,
+    finished: bool,
 }
 
-fn is_sufficient_symbols(tree_idx: &Vec<Vec<u64>>) -> bool {
-    assert!(tree_idx.len() > 0);
-    let len = tree_idx[0].len() as f32;
-    len > NUM_BASE_SYMBOL as f32 * UNDECODABLE_RATIO
+fn is_sufficient_symbols(samples: &SampleSet, params: &CmtParams) -> bool {
+    let len = samples.layer(0).len() as f32;
+    len > params.num_base_symbols() as f32 * params.undecodable_ratio
 }
 
-// currently only handle one layer encoding
-fn collect_cmt_chunks(job_manager: JobManager) {
-    let chunk_receiver = &job_manager.chunk_receiver;
-    let mut coll_symbols: Vec<Vec<Symbol>> = Vec::new();
-    let mut coll_idx: Vec<Vec<u64>> = Vec::new();
-    let mut is_first_recv = true;
-    let start = SystemTime::now();
-    //info!("{:?} start collect cmt for {:?}", job_manager.addr, job_manager.state);
-    loop {
-        // accumulate chunks
-        match chunk_receiver.recv() {
-            // accumulate chunks 
-            Ok(chunk) => {
-                match chunk {
-                    None => (),//info!("does not recv chunk"),
-                    Some(chunk) => {
-                        let elapsed = start.elapsed();
-                        let hash_str = utils::hash_header_hex(&chunk.header as &[u8]);
-                        //info!("{:?} get hash_str  {:?} {:?}", job_manager.addr, hash_str);
-
-                        let header: BlockHeader = deserialize(&chunk.header as &[u8]).unwrap();
-
-                        let num_layer = job_manager.k_set.len();
-                        if is_first_recv {
-                            is_first_recv = false;
-                            coll_symbols = chunk.symbols.clone();
-                            coll_idx = chunk.idx.clone();
-                            //info!("coll_symbols {:?}", coll_symbols.len());
-                            //info!("coll_idx {:?}", coll_idx.len());
-                        } else {
-                            for l in 0..num_layer {
-                                let symbols = &chunk.symbols[l];
-                                let idx = &chunk.idx[l];
-                                let mut c_symbols = &mut coll_symbols[l];
-                                let mut c_idx = &mut coll_idx[l];
-                                let mut j =0;
-                                for i in idx {
-                                    if c_idx.contains(i) {
-                                        //
-                                    } else {
-                                        c_idx.push(*i);
-                                        c_symbols.push(symbols[j]);
-                                    }
-                                    j += 1;
-                                }
-                            }
-                        }
+// scale nodes holding a base symbol that is not collected yet
+fn missing_scale_ids(samples: Option<&SampleSet>, params: &CmtParams, num_scale: u64) -> Vec<u64> {
+    let num_symbol = params.num_base_symbols();
+    (1..num_scale+1)
+        .filter(|scale_id| {
+            get_sample_index(*scale_id, num_symbol, num_scale)
+                .iter()
+                .any(|i| samples.map_or(true, |s| !s.layer(0).contains(*i as u64)))
+        })
+        .collect()
+}
 
-                        if is_sufficient_symbols(&coll_idx) {
-                            // accumulate chunks + currently only handle single layer
-                            let mut decoder: TreeDecoder = TreeDecoder::new(
-                                job_manager.codes_for_decoding.to_vec(), 
-                                &header.coded_merkle_roots_hashes
-                            );
-
-                            //let start = SystemTime::now();
-                            //info!("{:?} before cmt decoding {:?}", job_manager.addr, job_manager.state.block_id);
-                            match decoder.run_tree_decoder(coll_symbols.clone(), coll_idx.clone(), header.clone()) {
-                                Ok(transactions) => {
-                                    //info!("{:?} cmt pass decoding time {:?}", job_manager.addr, start.elapsed());
-                                    // collect all base + reconstruct block
-                                    let mut recon:Vec<u8> = vec![];
-                                    let systematic_symbol_len = job_manager.k_set[0];
-                                    for i in 0..systematic_symbol_len {
-                                        for j in 0..(coll_symbols[0].len()) {
-                                            if coll_idx[0][j] == i as u64 {
-                                                match coll_symbols[0][j] {
-                                                    Symbol::Base(s) => recon.extend_from_slice(&s),
-                                                    _ => unreachable!(),
-                                                }
-                                            }
-                                        }
-                                    }
-                                    //info!("************finish block with {}", job_manager.state.block_id);
-                                    PERFORMANCE_COUNTER.record_coll_block_stop(job_manager.state.block_id as usize);
-                                    
-                                    PERFORMANCE_COUNTER.record_confirmeded_transactions(transactions.len());
-                                    // TODO
-                                    let mut r = SBlock {
-                                        block_header: header.clone(),
-                                        transactions: transactions,
-                                        coded_tree: vec![],
-                                        block_size_in_bytes:0 
-                                    };
-
-                                    job_manager.block_source.send(Ok(r));
-                                    break;
-                                },
-                                Err(proof) => (),
-                            };
-                        }
-                    }
-                }
+// a scale node serves the base symbols assigned to it, so the first one tells which node it is
+fn serving_scale_id(chunk: &Samples, params: &CmtParams, num_scale: u64) -> Option<u64> {
+    let first = *chunk.idx.get(0)?.get(0)?;
+    (1..num_scale+1).find(|scale_id| get_sample_index(*scale_id, params.num_base_symbols(), num_scale).contains(&(first as u32)))
+}
+
+impl JobManager {
+    fn new(
+        state: ContractState,
+        prev_hash: H256,
+        manager: &Manager,
+        chunk_receiver: Receiver<(SocketAddr, Option<Samples>)>,
+    ) -> JobManager {
+        JobManager {
+            state,
+            prev_hash,
+            addr: manager.addr,
+            server_control_sender: manager.server_control_sender.clone(),
+            chunk_receiver,
+            chain: manager.chain.clone(),
+            block_source: manager.block_source.clone(),
+            num_scale: manager.num_scale,
+            scale_peers: manager.scale_peers.clone(),
+            timeout: manager.collect_timeout,
+            codes_for_decoding: manager.codes_for_decoding.clone(),
+            code_id: manager.code_id,
+            params: manager.params.clone(),
+            coll: None,
+            decoder: None,
+            round: Round::Broadcast,
+            deadline: Instant::now() + manager.collect_timeout,
+            finished: false,
+        }
+    }
+
+    // take the chunks received so far and ask again once the deadline passed,
+    // true once the job is done and its result is sent
+    pub fn step(&mut self) -> bool {
+        while !self.finished {
+            match self.chunk_receiver.try_recv() {
+                Ok((peer, chunk)) => self.finished = self.receive_chunk(peer, chunk),
+                Err(TryRecvError::Empty) => break,
+                // the manager dropped the block, the contract was reset
+                Err(TryRecvError::Disconnected) => self.finished = true,
+            }
+        }
+        if !self.finished && Instant::now() >= self.deadline {
+            self.finished = self.request_again();
+        }
+        self.finished
+    }
+
+    // currently only handle one layer encoding, true if the block is decoded or incorrectly coded
+    fn receive_chunk(&mut self, peer: SocketAddr, chunk: Option<Samples>) -> bool {
+        let mut chunk = match chunk {
+            None => return false,//info!("does not recv chunk"),
+            Some(chunk) => chunk,
+        };
+        let hash_str = utils::hash_header_hex(&chunk.header as &[u8]);
+        //info!("{:?} get hash_str  {:?} {:?}", self.addr, hash_str);
+
+        let header: BlockHeader = match deserialize(&chunk.header as &[u8]) {
+            Ok(h) => h,
+            Err(e) => {
+                warn!("{:?} malformed header from {:?} for block {}", self.addr, peer, self.state.block_id);
+                self.chain.lock().unwrap().record_bad_peer(peer, chunk.num_symbols());
+                return false;
             }
-           _ => panic!("{:?} job manager error", job_manager.addr),
+        };
+        if header.code_id != self.code_id {
+            warn!("{:?} block {} is encoded with codes {:?}, not ours", self.addr, self.state.block_id, header.code_id);
+            return false;
+        }
+        // chunks of different headers are never merged, the first one must be committed
+        match &self.coll {
+            Some(set) if set.header() != &chunk.header[..] => {
+                warn!("{:?} {:?} sent chunks of another header for block {}", self.addr, peer, self.state.block_id);
+                return false;
+            },
+            None if chain_hash(self.prev_hash, &chunk.header) != self.state.curr_hash => {
+                warn!("{:?} {:?} sent chunks of a header not committed for block {}", self.addr, peer, self.state.block_id);
+                self.chain.lock().unwrap().record_bad_peer(peer, chunk.num_symbols());
+                return false;
+            },
+            _ => (),
+        }
+        let dropped = chunk.retain_verified(&header, &self.params);
+        if dropped > 0 {
+            warn!("{:?} {:?} sent {} symbols failing their Merkle proof for block {}", self.addr, peer, dropped, self.state.block_id);
+            PERFORMANCE_COUNTER.record_rejected_symbols(dropped);
+            self.chain.lock().unwrap().record_bad_peer(peer, dropped);
+        }
+        if chunk.num_symbols() == 0 {
+            return false;
+        }
+        if let Some(scale_id) = serving_scale_id(&chunk, &self.params, self.num_scale) {
+            self.scale_peers.lock().unwrap().insert(scale_id, peer);
+        }
+
+        let k_set = self.params.k_set();
+        let num_layer = k_set.len();
+        let set = self.coll.get_or_insert_with(|| SampleSet::new(chunk.header.clone(), num_layer));
+        let (symbols, idx) = (chunk.symbols.clone(), chunk.idx.clone());
+        match set.merge(chunk) {
+            Ok(merged) => {
+                // both symbols passed their proof, so the peer cannot be told apart
+                if !merged.conflicts.is_empty() {
+                    warn!("{:?} {:?} sent {} symbols conflicting with collected ones for block {}", 
+                          self.addr, peer, merged.conflicts.len(), self.state.block_id);
+                }
+                if merged.added == 0 {
+                    return false;
+                }
+            },
+            Err(e) => {
+                warn!("{:?} unable to merge chunk from {:?} for block {}: {:?}", self.addr, peer, self.state.block_id, e);
+                return false;
+            },
+        }
+
+        // only the symbols the decoder does not hold yet update its parities
+        let codes = &self.codes_for_decoding;
+        let params = &self.params;
+        let decoder = self.decoder.get_or_insert_with(|| TreeDecoder::new(codes, &header.coded_merkle_roots_hashes, params));
+        decoder.receive(&symbols, &idx);
+        if !is_sufficient_symbols(set, &self.params) {
+            return false;
+        }
+
+        //let start = SystemTime::now();
+        //info!("{:?} before cmt decoding {:?}", self.addr, self.state.block_id);
+        match decoder.decode() {
+            Ok(data) => {
+                //info!("{:?} cmt pass decoding time {:?}", self.addr, start.elapsed());
+                //info!("************finish block with {}", self.state.block_id);
+                PERFORMANCE_COUNTER.record_coll_block_stop(self.state.block_id as usize);
+
+                // the systematic symbols hold the transactions, they must hash to the merkle root in the header
+                let collected = match SBlock::reconstruct(header.clone(), &data, &self.params) {
+                    Ok(block) => {
+                        PERFORMANCE_COUNTER.record_confirmeded_transactions(block.transactions.len());
+                        Collected::Decoded(block)
+                    },
+                    Err(e) => {
+                        warn!("{:?} block {} on side chain {} does not hold the transactions of its header: {:?}",
+                              self.addr, self.state.block_id, self.state.sid, e);
+                        Collected::Malformed(e)
+                    },
+                };
+                self.block_source.send((self.state.sid, self.state.block_id, collected));
+                true
+            },
+            Err(proof) => {
+                // a stopping set only means more symbols are needed
+                if proof.error_type == CodingErr::Stopped {
+                    return false;
+                }
+                if !proof.verify(&header, &self.codes_for_decoding, &self.params) {
+                    warn!("{:?} incorrect-coding proof of block {} does not verify", self.addr, self.state.block_id);
+                    return false;
+                }
+                warn!("{:?} block {} on side chain {} is incorrectly coded, {:?} on layer {}", 
+                      self.addr, self.state.block_id, self.state.sid, proof.error_type, proof.level);
+                let response_msg = Message::IncorrectCoding(
+                    self.state.sid,
+                    self.state.block_id,
+                    set.header().to_vec(),
+                    proof.clone());
+                let signal = ServerSignal::ServerBroadcast(response_msg);
+                self.server_control_sender.send(signal);
+                self.block_source.send((self.state.sid, self.state.block_id, Collected::Incorrect(proof)));
+                true
+            },
+        }
+    }
+
+    // escalate until a request goes out, the scale nodes to target may be unknown,
+    // true once every request timed out and the block is reported unavailable
+    fn request_again(&mut self) -> bool {
+        let mut sent = false;
+        while !sent && self.round != Round::Rebroadcast {
+            self.round = match self.round {
+                Round::Broadcast => Round::Targeted,
+                _ => Round::Rebroadcast,
+            };
+            sent = self.send_request();
+        }
+        if sent {
+            self.deadline = Instant::now() + self.timeout;
+            return false;
+        }
+        let collected = self.coll.as_ref().map_or(0, |s| s.layer(0).len());
+        warn!("{:?} block {} on side chain {} is unavailable, {} of {} base symbols collected", 
+              self.addr, self.state.block_id, self.state.sid, collected, self.params.num_base_symbols());
+        PERFORMANCE_COUNTER.record_unavailable_block();
+        self.block_source.send((self.state.sid, self.state.block_id, Collected::Unavailable));
+        true
+    }
+
+    // ask again for the chunks of the block in the current round, true if a request was sent
+    fn send_request(&self) -> bool {
+        let state = &self.state;
+        match self.round {
+            Round::Broadcast => false,
+            Round::Targeted => {
+                let scale_peers = self.scale_peers.lock().unwrap();
+                let targets: Vec<SocketAddr> = missing_scale_ids(self.coll.as_ref(), &self.params, self.num_scale)
+                    .iter()
+                    .filter_map(|scale_id| scale_peers.get(scale_id).cloned())
+                    .collect();
+                drop(scale_peers);
+                for peer in targets.iter() {
+                    let signal = ServerSignal::ServerUnicast((*peer, Message::ScaleGetAllChunks(state.clone())));
+                    self.server_control_sender.send(signal);
+                }
+                if !targets.is_empty() {
+                    info!("{:?} ask {} scale nodes again for block {} on side chain {}", self.addr, targets.len(), state.block_id, state.sid);
+                    PERFORMANCE_COUNTER.record_targeted_request();
+                }
+                !targets.is_empty()
+            },
+            Round::Rebroadcast => {
+                info!("{:?} broadcast again for block {} on side chain {}", self.addr, state.block_id, state.sid);
+                let signal = ServerSignal::ServerBroadcast(Message::ScaleGetAllChunks(state.clone()));
+                self.server_control_sender.send(signal);
+                PERFORMANCE_COUNTER.record_rebroadcast();
+                true
+            },
         }
     }
 }
@@ -166,228 +379,663 @@ impl Manager {
         mempool: Arc<Mutex<Mempool>>, //TODO for forgetting certain blocks
         server_control_sender: MioSender<ServerSignal>,
         addr: SocketAddr,     
-        manager_sink: Receiver<(u64, Option<Samples>)>,
+        manager_sink: Receiver<(u64, u64, SocketAddr, Option<Samples>)>,
         block_db: Arc<Mutex<BlockDb>>,
         codes_for_encoding: Vec<Code>,
-        codes_for_decoding: Vec<Code>,
-        k_set: Vec<u64>,
+        codes_for_decoding: Vec<Arc<Code>>,
+        code_id: CMTH256,
+        sidenodes: HashMap<u64, Vec<SocketAddr>>,
+        params: CmtParams,
+        check_hash: bool,
+        num_scale: u64,
+        collect_timeout: Duration,
+        num_decoders: usize,
     ) -> Manager {
+        let (state_source, state_sink) = channel::unbounded();
+        let (block_source, block_sink) = channel::unbounded();
         Manager {
             contract_handler: contract_handler,
             chain: chain,
             mempool: mempool,
             server_control_sender: server_control_sender,
             addr: addr,
-            chunk_senders: HashMap::new(),
             manager_sink: manager_sink,
             block_db: block_db,
             codes_for_encoding: codes_for_encoding,
             codes_for_decoding: codes_for_decoding,
-            k_set: k_set,
+            code_id,
+            sidenodes,
+            params,
+            check_hash,
+            num_scale,
+            scale_peers: Arc::new(Mutex::new(HashMap::new())),
+            collect_timeout,
+            state_source,
+            state_sink,
+            block_source,
+            block_sink,
+            pool: DecoderPool::new(num_decoders),
+            register_blocks: HashMap::new(),
+            ready_blocks: HashMap::new(),
+            retries: vec![],
+            invalid: HashMap::new(),
+            longest_id: HashMap::new(),
         }
     }
 
-    // spawn a new thread pulling for update from mainchain 
+    // committed states after block start up to block end
+    fn fetch_states(&self, sid: u64, start: u64, end: u64) -> Vec<ContractState> {
+        let (answer_tx, answer_rx) = channel::bounded(1);
+        let handle = Handle {
+            message: ContractMessage::GetAll((sid as usize, start as usize, end as usize)),
+            answer_channel: Some(answer_tx),
+        };
+        self.contract_handler.send(handle);
+        match answer_rx.recv() {
+            Ok(Answer::Success(ContractResponse::GetAll(txs))) => txs.into_iter().map(|tx| tx.contract_state).collect(),
+            Ok(Answer::Fail(reason)) => {
+                warn!("{:?} unable to get states of side chain {}: {}", self.addr, sid, reason);
+                vec![]
+            },
+            Ok(_) => panic!("manager contract get wrong answer"),
+            Err(e) => panic!("manager contract channel broke"),
+        }
+    }
+
+    // bring the local chain back in line with a contract that was reset to state,
+    // returns the first block that was replaced
+    fn realign(&self, state: &ContractState) -> Option<u64> {
+        let update = self.chain.lock().unwrap().update(state);
+        let update = match update {
+            ChainUpdate::Missing(start, end) => {
+                let states = self.fetch_states(state.sid, start, end);
+                self.chain.lock().unwrap().rebase(state.sid, &states)
+            },
+            u => u,
+        };
+        match update {
+            ChainUpdate::Revised(block_id) => Some(block_id),
+            _ => None,
+        }
+    }
+
+    // spawn a new thread reacting to the contract, chunks and finished jobs
     pub fn start(mut self){
         thread::spawn(move || {
-            let mut blocks_sink: HashMap<u64, Receiver<Result<SBlock, CodingErr>>> = HashMap::new();
-            let mut register_blocks: HashMap<u64, ContractState> = HashMap::new();
-            let mut ready_blocks: HashMap<u64, ContractState> = HashMap::new();
-            let mut longest_id = 0;
-            let mut start = SystemTime::now();
+            // the contract pushes the state of each side chain whenever a new ethereum block changes it
+            let side_chains = self.chain.lock().unwrap().side_chains();
+            for sid in side_chains {
+                let handle = Handle {
+                    message: ContractMessage::WatchState(sid as usize, self.state_source.clone()),
+                    answer_channel: None,
+                };
+                self.contract_handler.send(handle);
+            }
+            loop {
+                self.run_once();
+            }
+        });
+    }
 
-            let check_hash = false;
+    // wait for the next event and handle it
+    fn run_once(&mut self) {
+        let retry = match self.retries.iter().map(|(at, _)| *at).min() {
+            Some(at) => channel::after(at.saturating_duration_since(Instant::now())),
+            None => channel::never(),
+        };
+        crossbeam::select! {
+            recv(self.state_sink) -> state => {
+                let state = state.expect("contract watcher broken");
+                self.on_state(state);
+            },
+            recv(self.manager_sink) -> chunk => {
+                let (sid, block_id, peer, chunk) = chunk.expect("manager sink broken");
+                // the job is gone once its block is done
+                if !self.pool.send_chunk((sid, block_id), peer, chunk)
+                    && !self.ready_blocks.contains_key(&(sid, block_id))
+                    && self.chain.lock().unwrap().get_height(sid) <= block_id {
+                    info!("{:?} Error  no cmt get all request", self.addr);
+                }
+            },
+            recv(retry) -> _ => {
+                self.retry_blocks();
+            },
+            recv(self.block_sink) -> result => {
+                let (sid, block_id, result) = result.expect("block sink broken");
+                self.on_block(sid, block_id, result);
+            },
+        }
+    }
 
-            loop {
-                let mut rm: Vec<u64> = vec![];
-                // check if any threads finish
-                for (block_id, block_sink) in &blocks_sink {
-                    match block_sink.try_recv() {
-                        Err(TryRecvError::Empty) => (),
-                        Err(TryRecvError::Disconnected) => panic!("block sink broken"),
-                        Ok(result) => {
-                            // a thread has finished processing cmt
-                            match result {
-                                Ok(sblock) => {
-                                    //info!("{:?} cmt finishes", self.addr);
-                                    rm.push(*block_id);
-                                    let mut sblock_db = self.block_db.lock().unwrap();
-                                    sblock_db.insert_sblock(*block_id as u64, sblock);
-                                    drop(sblock_db);
-
-                                    // update ready chain
-                                    let state = register_blocks.remove(block_id).expect("get block state");
-                                    ready_blocks.insert(*block_id, state);
-
-                                    // update blockchain
-                                    let mut local_chain = self.chain.lock().unwrap();
-                                    let tip_state = local_chain.get_latest_state().unwrap();
-                                    //info!("{:?} tip_state {:?} longest_id {}", self.addr, tip_state, longest_id);
-
-                                    let mut curr_hash = tip_state.curr_hash.clone();
-                                    // test if update block chain
-                                    for i in (tip_state.block_id+1) .. (longest_id+1) {
-                                        match ready_blocks.get(&i) {
-                                            None => (),//info!("{:?} block {} is missing", self.addr, i),
-                                            Some(s) => {
-                                                ////info!("{:?} db get block {:?}", self.addr, i);
-                                                //let mut sblock_db = self.block_db.lock().unwrap();
-                                                //let header = match sblock_db.get_sblock(*block_id as u64) {
-                                                    //Some(b) => b.block_header.clone(),
-                                                    //None => unreachable!(),
-                                                //};
-                                                //drop(sblock_db);
-
-                                                //let mut hash  = [0u8; 32];
-                                                //let header_bytes = serialize(&header);
-                                                //// get hash in the same way as bls
-                                                //let mut hasher = Sha256::new();;
-                                                //hasher.input(&header_bytes);
-                                                //hasher.result(&mut hash);
-                                                //let hash_str = hex::encode(&hash);
-                                                ////info!("header hash {:?}", hash_str);
-
-                                                ////let mut curr_hash_str: String = hex::encode(&curr_hash.0);
-                                                //let v = [ curr_hash.0, hash].concat();
-                                                //let mut sec_hasher = Sha256::new();
-                                                //let mut hash  = [0u8; 32];
-                                                //sec_hasher.input(&v);
-                                                //sec_hasher.result(&mut hash);
-
-                                                //// compare if smart contract hash equals to local
-                                                //let new_hash = H256(hash);
-                                                ////info!("new hash {:?}", new_hash);
-                                                //if  new_hash != s.curr_hash {
-                                                    //info!("{:?}, inconsistent hash {:?} smart contract {} hash {:?}", 
-                                                          //self.addr,
-                                                          //new_hash, 
-                                                          //i,
-                                                          //s.curr_hash); 
-                                                    //if check_hash{
-                                                        //break; 
-                                                    //}
-                                                //}
-
-                                                //info!("{:?} local chain update to {:?}", self.addr, s);
-
-                                                local_chain.append(s);
-                                                curr_hash = s.curr_hash;
-                                                PERFORMANCE_COUNTER.record_chain_update();
-                                            },
-                                        }
-                                    }
-                                    drop(local_chain);
-                                },
-                                Err(e) => info!("cmt handler error "),
-                            }
+    fn on_state(&mut self, state: ContractState) {
+        let sid = state.sid;
+        let tip_state = match self.chain.lock().unwrap().get_latest_state(sid) {
+            Some(s) => s,
+            None => return,
+        };
+        if tip_state == state {
+            return;
+        }
+        PERFORMANCE_COUNTER.record_block_stop(state.block_id);
+
+        if state.block_id <= tip_state.block_id {
+            // the contract went back, it was reset
+            let first = match self.realign(&state) {
+                Some(b) => b,
+                None => return,
+            };
+            warn!("{:?} side chain {} was reset, local chain revised from block {}", self.addr, sid, first);
+            self.lift_invalid(sid);
+            // blocks from there on are collected again, results of their jobs are ignored
+            let keep = |&(s, b): &(u64, u64)| s != sid || b < first;
+            self.pool.retain(&keep);
+            self.register_blocks.retain(|k, _| keep(k));
+            self.ready_blocks.retain(|k, _| keep(k));
+            self.retries.retain(|(_, s)| keep(&(s.sid, s.block_id)));
+            self.longest_id.insert(sid, state.block_id);
+            return;
+        }
+
+        if let Some(bad) = self.invalid.get(&sid).cloned() {
+            if self.contract_holds(&bad, &state) {
+                return;
+            }
+            warn!("{:?} side chain {} was reset past invalid block {}", self.addr, sid, bad.block_id);
+            self.lift_invalid(sid);
+        }
+
+        //info!("{:?}, update start: mainchain new state {:?} tip_state {:?}", self.addr, state, tip_state);
+        let longest = self.longest_id.entry(sid).or_insert(0);
+        if *longest < state.block_id {
+            *longest = state.block_id;
+        }
+        self.collect_up_to(state);
+    }
+
+    // collect the blocks committed since the local tip up to state that are not collected yet,
+    // the contract may have moved several blocks within one ethereum block
+    fn collect_up_to(&mut self, state: ContractState) {
+        let sid = state.sid;
+        let tip_state = match self.chain.lock().unwrap().get_latest_state(sid) {
+            Some(s) => s,
+            None => return,
+        };
+        let missing: Vec<u64> = (tip_state.block_id+1 ..= state.block_id)
+            .filter(|b| !self.register_blocks.contains_key(&(sid, *b)) && !self.ready_blocks.contains_key(&(sid, *b)))
+            .filter(|b| !self.chain.lock().unwrap().is_invalid(sid, *b))
+            .collect();
+        let states: Vec<ContractState> = if missing.is_empty() {
+            return;
+        } else if missing == vec![state.block_id] {
+            vec![state.clone()]
+        } else {
+            self.fetch_states(sid, missing[0] - 1, state.block_id)
+                .into_iter()
+                .filter(|s| missing.contains(&s.block_id))
+                .collect()
+        };
+        // the history may still be syncing, the rest is fetched again later
+        if states.len() < missing.len() {
+            warn!("{:?} got {} of {} missing states up to block {} on side chain {}, retry in {:?}",
+                  self.addr, states.len(), missing.len(), state.block_id, sid, self.collect_timeout);
+            self.retries.push((Instant::now() + self.collect_timeout, state));
+        }
+        for state in states {
+            self.collect_block(state);
+        }
+    }
+
+    // whether the contract at state still commits the block of bad, taken as so when unknown
+    fn contract_holds(&self, bad: &ContractState, state: &ContractState) -> bool {
+        if state.block_id <= bad.block_id {
+            return state == bad;
+        }
+        match self.fetch_states(bad.sid, bad.block_id - 1, bad.block_id).iter().find(|s| s.block_id == bad.block_id) {
+            Some(s) => s == bad,
+            None => true,
+        }
+    }
+
+    fn lift_invalid(&mut self, sid: u64) {
+        if let Some(bad) = self.invalid.remove(&sid) {
+            self.chain.lock().unwrap().clear_invalid(sid, bad.block_id);
+        }
+    }
+
+    // committed state of the block before state, blocks are collected in order so it is
+    // appended or being collected, the contract is asked otherwise
+    fn prev_state(&self, state: &ContractState) -> Option<ContractState> {
+        if state.block_id == 1 {
+            return Some(ContractState::genesis(state.sid));
+        }
+        let key = (state.sid, state.block_id - 1);
+        if let Some(prev) = self.chain.lock().unwrap().get_state(state.sid, state.block_id - 1) {
+            return Some(prev);
+        }
+        if let Some(prev) = self.register_blocks.get(&key).or(self.ready_blocks.get(&key).map(|(s, _)| s)) {
+            return Some(*prev);
+        }
+        self.fetch_states(state.sid, state.block_id.saturating_sub(2), state.block_id - 1)
+            .into_iter()
+            .find(|s| s.block_id == state.block_id - 1)
+    }
+
+    // get block from scale node network
+    fn collect_block(&mut self, state: ContractState) {
+        let prev = match self.prev_state(&state) {
+            Some(p) => p,
+            None => {
+                warn!("{:?} state before block {} on side chain {} is unknown, retry in {:?}", self.addr, state.block_id, state.sid, self.collect_timeout);
+                self.retries.push((Instant::now() + self.collect_timeout, state));
+                return;
+            },
+        };
+        PERFORMANCE_COUNTER.record_coll_block_update(state.block_id);
+        let (chunk_sender, chunk_receiver) = crossbeam::channel::unbounded();
+        self.register_blocks.insert((state.sid, state.block_id), state.clone());
+        // the pool runs the job whenever chunks of the block arrive
+        let job_manager = JobManager::new(state.clone(), prev.curr_hash, self, chunk_receiver);
+        self.pool.insert((state.sid, state.block_id), chunk_sender, job_manager);
+
+        // broadcast get all chunks
+        let response_msg = Message::ScaleGetAllChunks(state.clone());
+        //info!("{:?} broadcase ScaleGetAllChunks {:?}", self.addr, state);
+        let signal = ServerSignal::ServerBroadcast(response_msg);
+        self.server_control_sender.send(signal);
+    }
+
+    // collect the blocks whose retry is due up to their state, unless the local chain got past them
+    fn retry_blocks(&mut self) {
+        let now = Instant::now();
+        let (due, later): (Vec<_>, Vec<_>) = self.retries.drain(..).partition(|(at, _)| *at <= now);
+        self.retries = later;
+        for (_, state) in due {
+            let chain = self.chain.lock().unwrap();
+            let appended = chain.get_height(state.sid) > state.block_id;
+            let invalid = chain.is_invalid(state.sid, state.block_id);
+            drop(chain);
+            if appended || invalid {
+                continue;
+            }
+            info!("{:?} collect blocks up to {} on side chain {} again", self.addr, state.block_id, state.sid);
+            self.collect_up_to(state);
+        }
+    }
+
+    // a thread has finished processing cmt
+    fn on_block(&mut self, sid: u64, block_id: u64, result: Collected) {
+        let state = match self.register_blocks.remove(&(sid, block_id)) {
+            Some(s) => s,
+            // the block was dropped by a contract reset
+            None => return,
+        };
+        self.pool.remove(&(sid, block_id));
+        match result {
+            Collected::Decoded(sblock) => {
+                //info!("{:?} cmt finishes", self.addr);
+                let header: Vec<u8> = serialize(&sblock.block_header).into();
+                let mut sblock_db = self.block_db.lock().unwrap();
+                sblock_db.insert_sblock(sid, block_id, sblock);
+                drop(sblock_db);
+
+                // update ready chain
+                self.ready_blocks.insert((sid, block_id), (state, header));
+
+                // update blockchain
+                let mut local_chain = self.chain.lock().unwrap();
+                let tip_state = local_chain.get_latest_state(sid).unwrap();
+                let longest = *self.longest_id.get(&sid).unwrap_or(&0);
+                //info!("{:?} tip_state {:?} longest_id {}", self.addr, tip_state, longest_id);
+
+                let mut curr_hash = tip_state.curr_hash.clone();
+                // blocks are appended in order, each decoded header must extend the
+                // hash chain exactly as the contract does in submitVote
+                for i in (tip_state.block_id+1) .. (longest+1) {
+                    let (s, new_hash) = match self.ready_blocks.get(&(sid, i)) {
+                        Some((s, header)) => (*s, chain_hash(curr_hash, header)),
+                        None => break,
+                    };
+                    if new_hash != s.curr_hash {
+                        error!("{:?} decoded header of block {} on side chain {} does not match the contract, local hash {:?} contract hash {:?}",
+                               self.addr, i, sid, new_hash, s.curr_hash);
+                        PERFORMANCE_COUNTER.record_hash_mismatch();
+                        if self.check_hash {
+                            // headers are checked when their chunks arrive, so the contract moved
+                            // on since, the block is collected again once it settles
+                            self.ready_blocks.remove(&(sid, i));
+                            self.retries.push((Instant::now() + self.collect_timeout, s));
+                            break;
                         }
                     }
+
+                    //info!("{:?} local chain update to {:?}", self.addr, s);
+                    local_chain.append(&s);
+                    self.ready_blocks.remove(&(sid, i));
+                    curr_hash = s.curr_hash;
+                    PERFORMANCE_COUNTER.record_chain_update();
                 }
-                // romove finished threads handler
-                for block_id in &rm {
-                    blocks_sink.remove(block_id);
+                drop(local_chain);
+            },
+            Collected::Unavailable => {
+                // the local chain stops before it until the block is collected again
+                warn!("{:?} stop collecting block {} on side chain {}, retry in {:?}", self.addr, block_id, sid, self.collect_timeout);
+                self.retries.push((Instant::now() + self.collect_timeout, state));
+            },
+            // there is no proof to gossip for a malformed block, every node finds out by decoding it
+            Collected::Incorrect(_) | Collected::Malformed(_) => {
+                // the block never becomes ready, so the local chain stops before it until
+                // the contract is reset, the blocks after it are dropped
+                let proposer = self.sidenodes.get(&sid).and_then(|ring| slot_proposer(ring, block_id));
+                let mut local_chain = self.chain.lock().unwrap();
+                local_chain.mark_invalid(sid, block_id, proposer);
+                drop(local_chain);
+                let keep = |&(s, b): &(u64, u64)| s != sid || b < block_id;
+                self.pool.retain(&keep);
+                self.register_blocks.retain(|k, _| keep(k));
+                self.ready_blocks.retain(|k, _| keep(k));
+                self.retries.retain(|(_, s)| keep(&(s.sid, s.block_id)));
+                if self.invalid.get(&sid).map_or(true, |bad| bad.block_id > block_id) {
+                    self.invalid.insert(sid, state);
                 }
+            },
+        }
+    }
+}
 
-                // job distributor to threads sender receiver
-                match self.manager_sink.try_recv() {
-                    Err(TryRecvError::Empty) => (),
-                    Err(TryRecvError::Disconnected) => panic!("manager sink broken"),
-                    Ok((block_id, chunk)) => {
-                        match self.chunk_senders.get(&block_id) {
-                            None => info!("{:?} Error  no cmt get all request", self.addr),
-                            Some(chunk_sender) => chunk_sender.send(chunk).unwrap(),
-                        }
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmtda::CodeSet;
+    use crate::primitive::block::EthBlkTransaction;
+    use chain::fixtures;
+    use crate::db::blockDb::PrunePolicy;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
 
-                let interval = time::Duration::from_millis(80);
-                thread::sleep(interval);
-
-                // check state every  sec
-                //if start.elapsed().unwrap() > time::Duration::from_millis(2000) {
-                    //check current state
-                    //info!("{:?} check smart contract", self.addr);
-                    start = SystemTime::now();
-                    let (answer_tx, answer_rx) = channel::bounded(1);
-                    let handle = Handle {
-                        message: ContractMessage::GetCurrState(0),
-                        answer_channel: Some(answer_tx),
-                    };
-                    self.contract_handler.send(handle);
-                    let mut curr_state: Option<ContractState> = None;
-                    match answer_rx.recv() {
-                        Ok(answer) => {
-                            match answer {
-                                Answer::Success(resp) => {
-                                    match resp {
-                                        ContractResponse::GetCurrState(state) => {
-                                            
-                                            let mut local_chain = self.chain.lock().unwrap();
-                                            let tip_state = local_chain.get_latest_state().expect("blockchain does not have state");
-                                            drop(local_chain);
-                                            // Ask performer to do the task
-                                            if tip_state != state {
-                                                PERFORMANCE_COUNTER.record_block_stop(state.block_id);
-                                                //let mut mempool = self.mempool.lock().unwrap();
-                                                //mempool.remove_block(state.block_id);
-                                                //drop(mempool);
-                                                
-                                                if (false) {
-                                                    // if get correct block from side chain network
-                                                } else {
-                                                    //info!("*********Fetch a new state tip {:?} smart {:?}", tip_state, state);
-                                                    // if task is already handled
-                                                    if self.chunk_senders.contains_key(&state.block_id) {
-                                                        continue;
-                                                    } 
-                                                    //info!("{:?}, update start: mainchain new state {:?} tip_state {:?}", self.addr, state, tip_state);
-                                                    if longest_id < state.block_id {
-                                                        longest_id = state.block_id;
-                                                    }
-                                                    PERFORMANCE_COUNTER.record_coll_block_update(state.block_id);
-
-                                                    // get block from scale node network
-                                                    let (chunk_sender, chunk_receiver) = crossbeam::channel::unbounded();
-                                                    let (block_sender, block_receiver) = crossbeam::channel::unbounded();
-                                                    register_blocks.insert(state.block_id, state.clone());
-                                                    blocks_sink.insert(state.block_id, block_receiver);
-                                                    self.chunk_senders.insert(state.block_id, chunk_sender);
-                                                    let mut job_manager = JobManager {
-                                                        state: state.clone(), 
-                                                        addr: self.addr.clone(),
-                                                        server_control_sender: self.server_control_sender.clone(),
-                                                        chunk_receiver: chunk_receiver,
-                                                        block_source: block_sender,
-                                                        k_set: self.k_set.clone(),
-                                                        codes_for_encoding: self.codes_for_encoding.clone(),
-                                                        codes_for_decoding: self.codes_for_decoding.clone(),
-                                                    };
-
-                                                    // create a new handler for each block
-                                                    thread::spawn(move || {
-                                                        collect_cmt_chunks(job_manager);
-                                                   });
-
-                                                    // broadcast get all chunks
-                                                    let response_msg = Message::ScaleGetAllChunks(state.clone());
-                                                    //info!("{:?} broadcase ScaleGetAllChunks {:?}", self.addr, state);
-                                                    let signal = ServerSignal::ServerBroadcast(response_msg);
-                                                    self.server_control_sender.send(signal);
-                                                }
-                                            }
-                                        },
-                                        _ => panic!("performer contract get wrong answer"), 
-                                    }
-                                },
-                                _ => panic!("fail"),
-                            }
-                        },
-                        Err(e) => panic!("performer contract channel broke"), 
-                    }
-                //}
+    fn manager(
+        code_set: &CodeSet,
+        params: &CmtParams,
+        path: &Path,
+        collect_timeout: Duration,
+    ) -> (Manager, mio_extras::channel::Receiver<ServerSignal>, Sender<(u64, u64, SocketAddr, Option<Samples>)>) {
+        let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let (contract_handler, _contract_handle) = channel::unbounded();
+        let (schedule_handler, _schedule_handle) = channel::unbounded();
+        let (server_control_sender, server_control_receiver) = mio_extras::channel::channel();
+        let (chunk_source, chunk_sink) = channel::unbounded();
+        let chain = Arc::new(Mutex::new(BlockChain::new()));
+        let mempool = Arc::new(Mutex::new(Mempool::new(
+            contract_handler.clone(), schedule_handler, addr, code_set.encoding.clone(), code_set.decoding.clone(), 256)));
+        let manager = Manager::new(
+            contract_handler,
+            chain,
+            mempool,
+            server_control_sender,
+            addr,
+            chunk_sink,
+            Arc::new(Mutex::new(BlockDb::new(path, PrunePolicy::default()))),
+            code_set.encoding.clone(),
+            code_set.decoding.iter().cloned().map(Arc::new).collect(),
+            code_set.id,
+            HashMap::new(),
+            params.clone(),
+            true,
+            2,
+            collect_timeout,
+            2,
+        );
+        (manager, server_control_receiver, chunk_source)
+    }
+
+    #[test]
+    fn block_is_collected_one_event_round_after_state_change() {
+        let params = fixtures::params();
+        let code_set = fixtures::code_set(&params);
+        let transaction = fixtures::transaction(5);
+        let block = fixtures::block(1, &vec![transaction.clone()]);
+        let header: Vec<u8> = serialize(&block.block_header).into();
+        let state = ContractState { sid: 0, curr_hash: chain_hash(H256::default(), &header), block_id: 1 };
+
+        let path = env::temp_dir().join(format!("manager_db_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let (mut manager, server_control_receiver, chunk_source) = manager(&code_set, &params, &path, Duration::from_secs(10));
+        let chain = manager.chain.clone();
+        let addr = manager.addr;
+
+        // the watcher pushes the new state, the chunks are requested right away
+        manager.state_source.send(state).unwrap();
+        manager.run_once();
+        match server_control_receiver.try_recv() {
+            Ok(ServerSignal::ServerBroadcast(Message::ScaleGetAllChunks(s))) => assert_eq!(s, state),
+            _ => panic!("chunks of the new block are not requested"),
+        }
+
+        let base: Vec<u32> = (0..params.num_base_symbols() as u32).collect();
+        let (symbols, idx) = block.sample_vec(base.clone());
+        let proofs = block.sample_proofs(&idx);
+        let samples = Samples { header: header.clone(), symbols, idx, proofs };
+
+        // chunks of a block the contract did not commit are dropped before any is taken
+        let forged = fixtures::block(2, &vec![transaction.clone()]);
+        let (symbols, idx) = forged.sample_vec(base);
+        let proofs = forged.sample_proofs(&idx);
+        let forged = Samples { header: serialize(&forged.block_header).into(), symbols, idx, proofs };
+        let forger: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        chunk_source.send((0, 1, forger, Some(forged.clone()))).unwrap();
+        manager.run_once();
+        chunk_source.send((0, 1, addr, Some(samples))).unwrap();
+        manager.run_once();
+
+        // the job decodes the block and the next round appends it
+        manager.run_once();
+        assert_eq!(chain.lock().unwrap().bad_symbols_from(&forger), forged.num_symbols());
+        assert_eq!(chain.lock().unwrap().get_latest_state(0), Some(state));
+        // the transactions are read back from the decoded symbols
+        assert_eq!(manager.block_db.lock().unwrap().get_sblock(0, 1).unwrap().transactions, vec![transaction]);
+        assert_eq!(manager.pool.len(), 0);
+        assert!(manager.ready_blocks.is_empty());
+        drop(manager);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn chain_waits_at_an_invalid_block_until_the_contract_is_reset() {
+        let params = fixtures::params();
+        let code_set = fixtures::code_set(&params);
+        let path = env::temp_dir().join(format!("manager_invalid_db_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let (mut manager, server_control_receiver, _chunk_source) = manager(&code_set, &params, &path, Duration::from_secs(10));
+        let state = |block_id: u64, b: u8| ContractState { sid: 0, curr_hash: H256([b; 32]), block_id };
+        manager.chain.lock().unwrap().append(&state(1, 1));
+        manager.register_blocks.insert((0, 2), state(2, 2));
+        manager.register_blocks.insert((0, 3), state(3, 3));
+        manager.ready_blocks.insert((0, 4), (state(4, 4), vec![]));
+
+        manager.block_source.send((0, 2, Collected::Malformed(ReconstructErr::MerkleRoot))).unwrap();
+        manager.run_once();
+        assert!(manager.chain.lock().unwrap().is_invalid(0, 2));
+        assert!(manager.register_blocks.is_empty());
+        assert!(manager.ready_blocks.is_empty());
+
+        // the contract still holds the invalid block, nothing is collected
+        manager.state_source.send(state(2, 2)).unwrap();
+        manager.run_once();
+        assert!(server_control_receiver.try_recv().is_err());
+
+        // reset and extended again, the new block 2 is collected
+        manager.state_source.send(state(2, 7)).unwrap();
+        manager.run_once();
+        match server_control_receiver.try_recv() {
+            Ok(ServerSignal::ServerBroadcast(Message::ScaleGetAllChunks(s))) => assert_eq!(s, state(2, 7)),
+            _ => panic!("block after the reset is not collected"),
+        }
+        assert!(!manager.chain.lock().unwrap().is_invalid(0, 2));
+        assert!(manager.invalid.is_empty());
+        drop(manager);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn decoded_block_off_the_contract_chain_is_collected_again_later() {
+        let params = fixtures::params();
+        let code_set = fixtures::code_set(&params);
+        let path = env::temp_dir().join(format!("manager_mismatch_db_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let (mut manager, server_control_receiver, _chunk_source) = manager(&code_set, &params, &path, Duration::from_secs(10));
+        let state = |block_id: u64, b: u8| ContractState { sid: 0, curr_hash: H256([b; 32]), block_id };
+        manager.chain.lock().unwrap().append(&state(1, 1));
+        manager.register_blocks.insert((0, 2), state(2, 2));
+        manager.longest_id.insert(0, 2);
+
+        let block = fixtures::block(1, &vec![fixtures::transaction(5)]);
+        manager.block_source.send((0, 2, Collected::Decoded(block))).unwrap();
+        manager.run_once();
+        assert_eq!(manager.chain.lock().unwrap().get_latest_state(0), Some(state(1, 1)));
+        assert!(manager.ready_blocks.is_empty());
+        // not asked for right away, the retry timer collects it
+        assert!(server_control_receiver.try_recv().is_err());
+        assert_eq!(manager.retries.len(), 1);
+        assert_eq!(manager.retries[0].1, state(2, 2));
+        drop(manager);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn states_the_contract_history_lacks_are_fetched_again() {
+        let params = fixtures::params();
+        let code_set = fixtures::code_set(&params);
+        let path = env::temp_dir().join(format!("manager_syncing_db_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let (mut manager, server_control_receiver, _chunk_source) = manager(&code_set, &params, &path, Duration::from_millis(10));
+        let state = |block_id: u64, b: u8| ContractState { sid: 0, curr_hash: H256([b; 32]), block_id };
+        let contract = vec![state(1, 1), state(2, 2), state(3, 3)];
+
+        // the history is still syncing on the first request
+        let (contract_handler, contract_handle) = channel::unbounded::<Handle>();
+        manager.contract_handler = contract_handler;
+        thread::spawn(move || {
+            for (i, handle) in contract_handle.iter().enumerate() {
+                let answer = match handle.message {
+                    ContractMessage::GetAll(_) if i == 0 => Answer::Fail("syncing".to_string()),
+                    ContractMessage::GetAll((_, start, end)) => Answer::Success(ContractResponse::GetAll(contract.iter()
+                        .filter(|s| s.block_id as usize > start && s.block_id as usize <= end)
+                        .map(|s| EthBlkTransaction { contract_state: *s, ..Default::default() })
+                        .collect())),
+                    _ => panic!("unexpected contract message"),
+                };
+                handle.answer_channel.unwrap().send(answer).unwrap();
             }
         });
+
+        manager.state_source.send(state(3, 3)).unwrap();
+        manager.run_once();
+        assert!(server_control_receiver.try_recv().is_err());
+        assert_eq!(manager.retries.len(), 1);
+        assert_eq!(manager.retries[0].1, state(3, 3));
+
+        manager.run_once();
+        for s in contract {
+            match server_control_receiver.try_recv() {
+                Ok(ServerSignal::ServerBroadcast(Message::ScaleGetAllChunks(r))) => assert_eq!(r, s),
+                _ => panic!("block {} is not collected", s.block_id),
+            }
+        }
+        assert!(manager.retries.is_empty());
+        assert_eq!(manager.pool.len(), 3);
+        drop(manager);
+        fs::remove_dir_all(&path).unwrap();
     }
 
-    
+    #[test]
+    fn unavailable_block_is_collected_again() {
+        let params = fixtures::params();
+        let code_set = fixtures::code_set(&params);
+        let path = env::temp_dir().join(format!("manager_retry_db_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let (mut manager, server_control_receiver, _chunk_source) = manager(&code_set, &params, &path, Duration::from_millis(10));
+        let state = ContractState { sid: 0, curr_hash: H256::default(), block_id: 1 };
+        manager.state_source.send(state).unwrap();
+        manager.run_once();
+        assert!(server_control_receiver.try_recv().is_ok());
+
+        // no state is pushed while the contract waits for the block, the retry is not left to it
+        manager.block_source.send((0, 1, Collected::Unavailable)).unwrap();
+        manager.run_once();
+        assert_eq!(manager.pool.len(), 0);
+        assert_eq!(manager.retries.len(), 1);
+        manager.run_once();
+        match server_control_receiver.try_recv() {
+            Ok(ServerSignal::ServerBroadcast(Message::ScaleGetAllChunks(s))) => assert_eq!(s, state),
+            _ => panic!("unavailable block is not requested again"),
+        }
+        assert!(manager.retries.is_empty());
+        assert_eq!(manager.pool.len(), 1);
+        drop(manager);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn silent_scale_nodes_are_asked_again_then_block_is_unavailable() {
+        let params = fixtures::params();
+        let code_set = fixtures::code_set(&params);
+        let state = ContractState { sid: 0, curr_hash: H256::default(), block_id: 1 };
+        let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let scale_peer: SocketAddr = "127.0.0.1:40002".parse().unwrap();
+        let (server_control_sender, server_control_receiver) = mio_extras::channel::channel();
+        let (chunk_sender, chunk_receiver) = channel::unbounded();
+        let (block_source, block_sink) = channel::unbounded();
+        // only scale node 2 served a chunk before
+        let mut scale_peers = HashMap::new();
+        scale_peers.insert(2, scale_peer);
+        let timeout = Duration::from_millis(10);
+        let mut job_manager = JobManager {
+            state,
+            prev_hash: H256::default(),
+            addr,
+            server_control_sender,
+            chunk_receiver,
+            chain: Arc::new(Mutex::new(BlockChain::new())),
+            block_source,
+            num_scale: 3,
+            scale_peers: Arc::new(Mutex::new(scale_peers)),
+            timeout,
+            codes_for_decoding: code_set.decoding.iter().cloned().map(Arc::new).collect(),
+            code_id: code_set.id,
+            params: params.clone(),
+            coll: None,
+            decoder: None,
+            round: Round::Broadcast,
+            deadline: Instant::now() + timeout,
+            finished: false,
+        };
+        let mut steps = 0;
+        while !job_manager.step() {
+            thread::sleep(timeout / 2);
+            steps += 1;
+            assert!(steps < 100, "job never gives up");
+        }
+        assert!(job_manager.step());
+
+        match server_control_receiver.try_recv() {
+            Ok(ServerSignal::ServerUnicast((peer, Message::ScaleGetAllChunks(s)))) => assert_eq!((peer, s), (scale_peer, state)),
+            _ => panic!("known scale node is not asked again"),
+        }
+        match server_control_receiver.try_recv() {
+            Ok(ServerSignal::ServerBroadcast(Message::ScaleGetAllChunks(s))) => assert_eq!(s, state),
+            _ => panic!("chunks are not broadcast again"),
+        }
+        assert!(server_control_receiver.try_recv().is_err());
+        match block_sink.try_recv() {
+            Ok((0, 1, Collected::Unavailable)) => (),
+            _ => panic!("block is not reported unavailable"),
+        }
+        drop(chunk_sender);
+
+        // every node holding symbols of an empty set is missing, serving nodes are told by their range
+        assert_eq!(missing_scale_ids(None, &params, 3), vec![1, 2, 3]);
+        let base: Vec<u64> = get_sample_index(2, params.num_base_symbols(), 3).into_iter().map(|i| i as u64).collect();
+        let chunk = Samples { header: vec![], symbols: vec![], idx: vec![base], proofs: vec![] };
+        assert_eq!(serving_scale_id(&chunk, &params, 3), Some(2));
+    }
 }
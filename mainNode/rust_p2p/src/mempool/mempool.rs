@@ -15,10 +15,9 @@ use super::cmtda::Block as CMTBlock;
 use super::cmtda::Transaction;
 use super::cmtda::H256 as CMTH256;
 
-use super::cmtda::{BlockHeader, HEADER_SIZE, read_codes};
+use super::cmtda::{BlockHeader};
 use chain::decoder::{Code, Decoder, TreeDecoder, CodingErr, IncorrectCodingProof};
 use chain::decoder::{Symbol};
-use chain::constants::{BLOCK_SIZE, BASE_SYMBOL_SIZE, RATE};
 use primitives::bytes::{Bytes};
 use ser::{deserialize, serialize};
 use std::net::{SocketAddr};
@@ -32,6 +31,8 @@ use std::io::{BufRead, BufReader};
 use crate::mempool::scheduler;
 #[allow(non_upper_case_globals)]
 static algorithm: &'static Algorithm = &SHA256;
+// proposed blocks of a side chain kept for light clients to sample, counted back from the newest
+pub const SAMPLING_WINDOW: u64 = 16;
 
 //struct Sample {
     //symbols: Vec<Vec<Symbols>>,
@@ -66,9 +67,11 @@ pub struct Mempool {
     contract_handler: Sender<Handle>,
     schedule_handler: Sender<scheduler::Signal>,
     returned_blocks: VecDeque<Block>,
-    symbols_by: HashMap<u64, HashMap<u64, (Vec<Vec<Symbol>>, Vec<Vec<u64>>) > >,
-    headers_by: HashMap<u64, BlockHeader>,
-    //block_by: HashMap<u64, CMTBlock>,
+    // keyed by (sid, block_id)
+    symbols_by: HashMap<(u64, u64), HashMap<u64, (Vec<Vec<Symbol>>, Vec<Vec<u64>>) > >,
+    headers_by: HashMap<(u64, u64), BlockHeader>,
+    // coded blocks proposed by this node, kept to serve light client samples
+    block_by: HashMap<(u64, u64), CMTBlock>,
     addr: SocketAddr,
     codes_for_encoding: Vec<Code>,
     codes_for_decoding: Vec<Code>,
@@ -81,16 +84,17 @@ impl Mempool {
         addr: SocketAddr,
         codes_for_encoding: Vec<Code>,
         codes_for_decoding: Vec<Code>,
+        block_size: usize,
     ) -> Mempool {
         
         Mempool {
             transactions: VecDeque::with_capacity(30000), 
-            block_size: BLOCK_SIZE as usize, // in bytes
+            block_size: block_size, // in bytes
             contract_handler: contract_handler,
             schedule_handler: schedule_handler,
             returned_blocks: VecDeque::new(),
             symbols_by: HashMap::new(),
-            //block_by: HashMap::new(),
+            block_by: HashMap::new(),
             addr: addr,
             codes_for_encoding: codes_for_encoding,
             codes_for_decoding: codes_for_decoding,
@@ -122,40 +126,47 @@ impl Mempool {
 
     pub fn insert_symbols(
         &mut self, 
+        sid: u64,
         block_id: u64, 
         block_header: &BlockHeader,
         symbols_by_scale_id: HashMap<u64, (Vec<Vec<Symbol>>, Vec<Vec<u64>>)>
     ) {
-        self.headers_by.insert(block_id, block_header.clone());
-        self.symbols_by.insert(block_id, symbols_by_scale_id);
+        self.headers_by.insert((sid, block_id), block_header.clone());
+        self.symbols_by.insert((sid, block_id), symbols_by_scale_id);
+        self.evict_before(sid, (block_id + 1).saturating_sub(SAMPLING_WINDOW));
     }
 
-    pub fn get_cmt_sample(&mut self, block_id: u64, scale_id: u64) 
-        -> (BlockHeader, Vec<Vec<Symbol>>, Vec<Vec<u64>>) {
-         match self.symbols_by.get(&block_id) {
-            Some(symbols_by) => {
-                match symbols_by.get(&scale_id) {
-                    Some((s, i)) => {
-                        let header = match self.headers_by.get(&block_id) {
-                            Some(h) => h.clone(),
-                            None => {
-                                info!("I don't have cmt header for block id {}", block_id);
-                                unreachable!();
-                            },
-                        };
-                        return (header, s.clone(), i.clone())
-                    },
-                    None => {
-                        info!("I have cmt symbols for block id {}, but not have for scale node {}", block_id, scale_id);
-                        unreachable!();
-                    }
-                }
-            },
-            None => {
-                info!("I don't have cmt symbols for block id {}", block_id);
-                unreachable!();
-            }
-         }
+    pub fn insert_block(&mut self, sid: u64, block_id: u64, block: CMTBlock) {
+        self.block_by.insert((sid, block_id), block);
+        self.evict_before(sid, (block_id + 1).saturating_sub(SAMPLING_WINDOW));
+    }
+
+    // scale nodes sample a block before voting on it, once the contract committed block_id
+    // only light clients still sample it and the symbols sent to scale nodes are dropped
+    pub fn finalize(&mut self, sid: u64, block_id: u64) {
+        self.symbols_by.retain(|(s, b), _| *s != sid || *b > block_id);
+        self.headers_by.retain(|(s, b), _| *s != sid || *b > block_id);
+    }
+
+    // drop everything kept for blocks of side chain sid before block_id
+    fn evict_before(&mut self, sid: u64, block_id: u64) {
+        self.symbols_by.retain(|(s, b), _| *s != sid || *b >= block_id);
+        self.headers_by.retain(|(s, b), _| *s != sid || *b >= block_id);
+        self.block_by.retain(|(s, b), _| *s != sid || *b >= block_id);
+    }
+
+    pub fn get_block(&self, sid: u64, block_id: u64) -> Option<&CMTBlock> {
+        self.block_by.get(&(sid, block_id))
+    }
+
+    // symbols sent to scale node scale_id, with their Merkle proofs. None when the block is
+    // not one this node proposed, or was already evicted
+    pub fn get_cmt_sample(&self, sid: u64, block_id: u64, scale_id: u64)
+        -> Option<(BlockHeader, Vec<Vec<Symbol>>, Vec<Vec<u64>>, Vec<Vec<Vec<Symbol>>>)> {
+        let (s, i) = self.symbols_by.get(&(sid, block_id))?.get(&scale_id)?;
+        let header = self.headers_by.get(&(sid, block_id))?;
+        let block = self.block_by.get(&(sid, block_id))?;
+        Some((header.clone(), s.clone(), i.clone(), block.sample_proofs(i)))
     }
 
     //pub fn sample_cmt(&mut self, 
@@ -293,10 +304,11 @@ impl Mempool {
         self.transactions.len()
     }
 
-    pub fn remove_block(&mut self, block_id: u64) {
-        info!("mempool remove {}", block_id);
-        self.symbols_by.remove(&block_id);
-        self.headers_by.remove(&block_id);
+    pub fn remove_block(&mut self, sid: u64, block_id: u64) {
+        info!("mempool remove {} {}", sid, block_id);
+        self.symbols_by.remove(&(sid, block_id));
+        self.headers_by.remove(&(sid, block_id));
+        self.block_by.remove(&(sid, block_id));
     }
 
     
@@ -6,6 +6,7 @@ use super::cmtda;
 
 pub mod mempool;
 pub mod scheduler;
+pub mod slotClock;
 
 
 
@@ -8,7 +8,7 @@ use super::blockchain::{BlockChain};
 use mio_extras::channel::Sender as MioSender;
 use crossbeam::channel::{Receiver, Sender, self};
 use std::{thread, time};
-use super::cmtda::{BlockHeader, Block, H256, HEADER_SIZE, Transaction, read_codes};
+use super::cmtda::{BlockHeader, Block, H256, Transaction, CmtParams};
 use super::contract::utils;
 use ser::{deserialize, serialize};
 use super::contract::interface::{Handle, Answer};
@@ -17,9 +17,9 @@ use super::contract::interface::Response as ContractResponse;
 use crypto::sha3::Sha3;
 use crypto::digest::Digest;
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use super::slotClock::SlotClock;
 use web3::types::Address;
 use crate::experiment::snapshot::PERFORMANCE_COUNTER;
-use chain::constants::{TRANSACTION_SIZE, BLOCK_SIZE, BASE_SYMBOL_SIZE, RATE, UNDECODABLE_RATIO};
 
 use chain::decoder::{Code, Decoder, TreeDecoder, CodingErr, IncorrectCodingProof};
 use chain::decoder::{Symbol};
@@ -49,15 +49,17 @@ pub struct Scheduler {
     pub handle: Receiver<Signal>,
     pub chain: Arc<Mutex<BlockChain>>, 
     //pub side_id: u64,
-    pub sidenodes: Vec<SocketAddr>,
+    pub sid: u64, // side chain this node proposes blocks for
+    pub sidenodes: Vec<SocketAddr>, // token ring of side chain sid
     pub address: Address,
-    pub slot_time: u64, 
-    pub start_sec: u64, 
-    pub start_millis: u64,
+    pub clock: SlotClock,
     pub prepared_block: Option<BlockHeader>,
+    pub prepared_cmt_block: Option<CMTBlock>,
     pub num_nodes: u64, //scale nodes
     pub symbols_by: Option<HashMap<u64, (Vec<Vec<Symbol>>, Vec<Vec<u64>>)>>,
     pub codes_for_encoding: Vec<Code>,
+    pub code_id: CMTH256, // id of codes_for_encoding, carried in the block header
+    pub params: CmtParams,
 }
 
 impl Scheduler {
@@ -70,13 +72,14 @@ impl Scheduler {
         chain: Arc<Mutex<BlockChain>>,
         contract_handler: Sender<Handle>,
         //side_id: u64,
+        sid: u64,
         sidenodes: Vec<SocketAddr>,
         address: Address,
-        slot_time: u64,
-        start_sec: u64,
-        start_millis: u64,
+        clock: SlotClock,
         num_scale: u64,
-        codes_for_encoding: Vec<Code>
+        codes_for_encoding: Vec<Code>,
+        code_id: CMTH256,
+        params: CmtParams,
     ) -> Scheduler {
         Scheduler {
             addr,
@@ -87,15 +90,17 @@ impl Scheduler {
             handle,
             chain: chain,
             //side_id,
+            sid,
             sidenodes,
             address,
-            slot_time: slot_time,
-            start_sec: start_sec,
-            start_millis: start_millis,
+            clock,
             prepared_block: None,
+            prepared_cmt_block: None,
             num_nodes: num_scale,
             symbols_by: None,
             codes_for_encoding: codes_for_encoding,
+            code_id,
+            params,
         }
     }
 
@@ -166,7 +171,6 @@ impl Scheduler {
             loop {
                 // setup
                 let round = self.sidenodes.len() as u64;
-                let round_millis = round * self.slot_time * 1000;
                 let side_id = self.get_side_id();     
                 // pipelining
                 match &self.prepared_block {
@@ -177,34 +181,21 @@ impl Scheduler {
                             _ => (),
                         }
                     }, 
-                    Some(h) => { 
-                        let (curr_slot, elapsed) = get_curr_slot(self.start_sec, self.start_millis, self.slot_time);
-                        let curr_id = curr_slot % round;
-                        // my slot
-                        if curr_id == side_id {
-
-                            PERFORMANCE_COUNTER.record_token_update(true);
-                            if self.propose_block() {
-                                let (curr_slot, elapsed) = get_curr_slot(self.start_sec, self.start_millis, self.slot_time);
-                                // go over the deadline
-                                if curr_slot%round != side_id {
-                                    PERFORMANCE_COUNTER.record_token_update(false);
-                                    continue;
-                                } else {
-                                    // to next slot
-                                    let target_time = ((side_id+1) * self.slot_time)*1000 - elapsed%round_millis;
-                                    thread::sleep(time::Duration::from_millis(target_time));
-                                    PERFORMANCE_COUNTER.record_token_update(false);
+                    Some(_) => { 
+                        match self.clock.current_slot() {
+                            // my slot
+                            Some(slot) if slot % round == side_id => {
+                                PERFORMANCE_COUNTER.record_token_update(true);
+                                if self.propose_block(slot) {
+                                    // to next slot, no wait if building the block went over the deadline
+                                    thread::sleep(self.clock.until_slot(slot + 1));
                                 }
-                            } 
-                        } else if curr_id < side_id {
-                            PERFORMANCE_COUNTER.record_token_update(false);
-                            let target = side_id*self.slot_time* 1000 - elapsed%round_millis;
-                            thread::sleep(time::Duration::from_millis(target));
-                        } else {
-                            PERFORMANCE_COUNTER.record_token_update(false);
-                            let time_left = round*self.slot_time*1000 - elapsed%round_millis + side_id*self.slot_time*1000;
-                            thread::sleep(time::Duration::from_millis(time_left));
+                                PERFORMANCE_COUNTER.record_token_update(false);
+                            },
+                            _ => {
+                                PERFORMANCE_COUNTER.record_token_update(false);
+                                thread::sleep(self.clock.until_slot(self.my_next_slot()));
+                            }
                         }
                     }
                 }
@@ -268,26 +259,31 @@ impl Scheduler {
 
     pub fn create_cmt_block(&mut self, trans: &Vec<Transaction>) -> Option<BlockHeader> {
         let mut rng = rand::thread_rng();
+        // the block extends the committed tip, whose hash the contract chains the header onto
+        let tip_hash = match self.chain.lock().unwrap().get_latest_state(self.sid) {
+            Some(tip) => tip.curr_hash,
+            None => crate::primitive::hash::H256::default(),
+        };
         let header = BlockHeader {
             version: 1,
-            previous_header_hash: CMTH256::default(),
+            previous_header_hash: CMTH256::from(<[u8; 32]>::from(tip_hash)),
             merkle_root_hash: CMTH256::default(),
             time: 4u32,
             bits: 5.into(),
             nonce: rng.gen(),
             coded_merkle_roots_hashes: vec![CMTH256::default(); 8],
+            code_id: self.code_id,
         };
         let (block, trans_len) = CMTBlock::new(
             header.clone(), 
             &trans, 
-            BLOCK_SIZE as usize, 
-            HEADER_SIZE, 
+            &self.params, 
             &self.codes_for_encoding, 
             vec![true; self.codes_for_encoding.len()]
         );
 
         let cmt_header = block.block_header.clone();
-        let num_symbol = BLOCK_SIZE/(BASE_SYMBOL_SIZE as u64) *((1.0/RATE) as u64);
+        let num_symbol = self.params.num_base_symbols();
         let mut symbols_by: HashMap<u64, (Vec<Vec<Symbol>>, Vec<Vec<u64>>)> = HashMap::new();
 
         // debug
@@ -314,11 +310,12 @@ impl Scheduler {
 
         self.symbols_by = Some(symbols_by);
         self.prepared_block = Some(cmt_header);
+        self.prepared_cmt_block = Some(block);
         Some(header)
     }
 
     pub fn prepare_block(&mut self) -> Option<BlockHeader> {
-        let tx_thresh = BLOCK_SIZE / TRANSACTION_SIZE - 1;
+        let tx_thresh = self.params.block_size / self.params.transaction_size - 1;
         // generate a coded block
         let mut mempool = self.mempool.lock().unwrap();
         let num_tx = mempool.get_num_transaction();
@@ -333,11 +330,10 @@ impl Scheduler {
         header
     }
 
-    pub fn propose_block(&mut self) -> bool {
+    pub fn propose_block(&mut self, slot: u64) -> bool {
         // construct message and broadcast 
-        let (curr_slot, elapsed)= get_curr_slot(self.start_sec, self.start_millis, self.slot_time);
-        let new_block_id =  curr_slot + 1; // a hack, to make sure curr_slot > 0, otherwise block rejected
-        //info!("************start propose block with {}", new_block_id);
+        let new_block_id =  slot + 1; // block ids start at 1, one past the slot
+        info!("propose block {} in epoch {} on side chain {}", new_block_id, self.clock.epoch(slot), self.sid);
         PERFORMANCE_COUNTER.record_block_update(new_block_id);
         PERFORMANCE_COUNTER.record_propose_block_update(new_block_id);
         
@@ -345,12 +341,18 @@ impl Scheduler {
             Some(header) => header.clone(),
             None => panic!("propose block without block ready"),
         };
+        // the chain is extended as the contract commits blocks, tip is the last committed one
+        let committed = self.chain.lock().unwrap().get_height(self.sid).saturating_sub(1);
         let mut mempool = self.mempool.lock().unwrap();
+        mempool.finalize(self.sid, committed);
         let symbols = match self.symbols_by.take() {
             Some(s) => s,
             None => panic!("unable to take symbols in scheduler"),
         };
-        mempool.insert_symbols(new_block_id, &header, symbols);
+        mempool.insert_symbols(self.sid, new_block_id, &header, symbols);
+        if let Some(block) = self.prepared_cmt_block.take() {
+            mempool.insert_block(self.sid, new_block_id, block);
+        }
         drop(mempool);
 
         self.prepared_block = None;
@@ -358,9 +360,12 @@ impl Scheduler {
 
         let header_bytes = serialize(&header);
         let header_message: Vec<u8> = header_bytes.clone().into();
+        let prev_hash = crate::primitive::hash::H256(header.previous_header_hash.clone().take());
+        self.chain.lock().unwrap().propose(self.sid, new_block_id, prev_hash, &header_message);
         let hash_str = utils::hash_header_hex(&header_message);
         let message =  Message::ProposeBlock(
             self.addr, 
+            self.sid,
             new_block_id as u64, 
             header_message); 
         let signal = ServerSignal::ServerBroadcast(message);
@@ -381,12 +386,15 @@ impl Scheduler {
         true 
     }
 
-    pub fn my_next_slot(&self, start_sec: u64, start_millis: u64, slot_time: u64) -> u64 {
-        let (curr_slot, elapsed) = get_curr_slot(self.start_sec, self.start_millis, self.slot_time);
+    pub fn my_next_slot(&self) -> u64 {
         let round = self.sidenodes.len() as u64;
-        let curr_round = curr_slot / round;
         let side_id = self.get_side_id();
-        let mut next_slot = curr_round * round + side_id;
+        let curr_slot = match self.clock.current_slot() {
+            Some(s) => s,
+            // before the start time
+            None => return side_id,
+        };
+        let mut next_slot = curr_slot - curr_slot % round + side_id;
         if next_slot <= curr_slot {
             next_slot += round;
         }
@@ -394,15 +402,14 @@ impl Scheduler {
     }
 }
 
-// return slot and time elapsed as nano
-// precision to millis, return curr_slot
-pub fn get_curr_slot(start_sec: u64, start_millis: u64, slot_time: u64) -> (u64, u64) {
-    let curr_time = time::SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-    let time_elapsed_millis = (curr_time.as_secs() - start_sec) *1000 + curr_time.subsec_millis() as u64 - start_millis;
-    (time_elapsed_millis/(slot_time*1000), time_elapsed_millis)
+// side node of a token ring allowed to propose block_id, block_id is one past the slot it is proposed in
+pub fn slot_proposer(ring: &[SocketAddr], block_id: u64) -> Option<SocketAddr> {
+    if ring.is_empty() || block_id == 0 {
+        return None;
+    }
+    Some(ring[((block_id - 1) % ring.len() as u64) as usize])
 }
 
-
 // scale id starts at 1
 pub fn get_sample_index(scale_id: u64, num_trans: u64, num_node: u64) -> Vec<u32> {
     let num_sample = ((num_trans as f32) / (num_node as f32)).ceil() as u64;
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// wall clock in millis since UNIX EPOCH, swapped for a MockTime in tests
+pub trait TimeSource: Send + Sync {
+    fn now_millis(&self) -> u64;
+}
+
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now_millis(&self) -> u64 {
+        // a clock set before UNIX EPOCH reads as 0, which is before any start time
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() * 1000 + d.subsec_millis() as u64,
+            Err(_) => 0,
+        }
+    }
+}
+
+// time that only moves when told to, clones share the same time
+#[derive(Clone, Default)]
+pub struct MockTime(Arc<AtomicU64>);
+
+impl MockTime {
+    pub fn new(millis: u64) -> MockTime {
+        MockTime(Arc::new(AtomicU64::new(millis)))
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.0.store(millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, d: Duration) {
+        self.0.fetch_add(d.as_secs() * 1000 + d.subsec_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for MockTime {
+    fn now_millis(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Slots of slot_time follow each other from the contract start time and are grouped into
+// epochs of slots_per_epoch. A block id is one past the slot it is proposed in. Clocks of the
+// nodes drift apart, so a proposal is taken up to tolerance before or after its slot.
+#[derive(Clone)]
+pub struct SlotClock {
+    start_millis: u64, // since UNIX EPOCH
+    slot_millis: u64,
+    slots_per_epoch: u64,
+    tolerance_millis: u64,
+    time: Arc<dyn TimeSource>,
+}
+
+impl SlotClock {
+    pub fn new(start_millis: u64, slot_time: Duration, slots_per_epoch: u64, tolerance: Duration) -> SlotClock {
+        SlotClock::with_time_source(start_millis, slot_time, slots_per_epoch, tolerance, Arc::new(SystemTimeSource))
+    }
+
+    pub fn with_time_source(
+        start_millis: u64,
+        slot_time: Duration,
+        slots_per_epoch: u64,
+        tolerance: Duration,
+        time: Arc<dyn TimeSource>,
+    ) -> SlotClock {
+        let slot_millis = to_millis(slot_time);
+        assert!(slot_millis > 0, "slot time must be at least a millisecond");
+        assert!(slots_per_epoch > 0, "an epoch needs at least one slot");
+        SlotClock {
+            start_millis,
+            slot_millis,
+            slots_per_epoch,
+            tolerance_millis: to_millis(tolerance),
+            time,
+        }
+    }
+
+    pub fn slot_time(&self) -> Duration {
+        Duration::from_millis(self.slot_millis)
+    }
+
+    pub fn tolerance(&self) -> Duration {
+        Duration::from_millis(self.tolerance_millis)
+    }
+
+    // None before the start time
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.time.now_millis().checked_sub(self.start_millis).map(Duration::from_millis)
+    }
+
+    pub fn current_slot(&self) -> Option<u64> {
+        self.time.now_millis().checked_sub(self.start_millis).map(|e| e / self.slot_millis)
+    }
+
+    pub fn current_epoch(&self) -> Option<u64> {
+        self.current_slot().map(|s| self.epoch(s))
+    }
+
+    pub fn epoch(&self, slot: u64) -> u64 {
+        slot / self.slots_per_epoch
+    }
+
+    pub fn first_slot(&self, epoch: u64) -> u64 {
+        epoch.saturating_mul(self.slots_per_epoch)
+    }
+
+    // millis since UNIX EPOCH the slot begins at
+    fn slot_begin(&self, slot: u64) -> u64 {
+        self.start_millis.saturating_add(slot.saturating_mul(self.slot_millis))
+    }
+
+    // zero if the slot has begun
+    pub fn until_slot(&self, slot: u64) -> Duration {
+        Duration::from_millis(self.slot_begin(slot).saturating_sub(self.time.now_millis()))
+    }
+
+    // true if now is in the slot of block_id, widened by the tolerance on both sides
+    pub fn accepts(&self, block_id: u64) -> bool {
+        if block_id == 0 {
+            return false;
+        }
+        let now = self.time.now_millis();
+        let begin = self.slot_begin(block_id - 1);
+        let end = self.slot_begin(block_id);
+        now.saturating_add(self.tolerance_millis) >= begin && now <= end.saturating_add(self.tolerance_millis)
+    }
+}
+
+fn to_millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + d.subsec_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: u64 = 1_600_000_000_000;
+
+    fn clock(time: &MockTime) -> SlotClock {
+        SlotClock::with_time_source(START, Duration::from_secs(3), 4, Duration::from_millis(500), Arc::new(time.clone()))
+    }
+
+    #[test]
+    fn slots_and_epochs_follow_the_time_source() {
+        let time = MockTime::new(START - 1000);
+        let clock = clock(&time);
+        assert_eq!(clock.current_slot(), None);
+        assert_eq!(clock.current_epoch(), None);
+        assert_eq!(clock.elapsed(), None);
+        assert_eq!(clock.until_slot(0), Duration::from_millis(1000));
+        assert_eq!(clock.until_slot(2), Duration::from_millis(7000));
+
+        time.set(START);
+        assert_eq!(clock.current_slot(), Some(0));
+        time.advance(Duration::from_millis(14_999));
+        assert_eq!(clock.current_slot(), Some(4));
+        assert_eq!(clock.current_epoch(), Some(1));
+        assert_eq!(clock.until_slot(5), Duration::from_millis(1));
+        assert_eq!(clock.until_slot(4), Duration::from_millis(0));
+        assert_eq!(clock.first_slot(1), 4);
+        assert_eq!(clock.epoch(3), 0);
+
+        // the system clock may be anywhere, but never before UNIX EPOCH
+        assert!(SystemTimeSource.now_millis() > 0);
+    }
+
+    #[test]
+    fn proposals_taken_within_tolerance_of_their_slot() {
+        // slot 0 is [START, START+3000), block 1 is proposed in it
+        let time = MockTime::new(START - 600);
+        let clock = clock(&time);
+        assert!(!clock.accepts(1));
+        time.set(START - 400);
+        assert!(clock.accepts(1));
+        assert!(!clock.accepts(0));
+
+        // a proposer ahead of us sends block 2 before slot 1 began here
+        time.set(START + 2700);
+        assert!(clock.accepts(1));
+        assert!(clock.accepts(2));
+        assert!(!clock.accepts(3));
+
+        // a proposer behind us is still in slot 1
+        time.set(START + 6400);
+        assert!(clock.accepts(2));
+        assert!(clock.accepts(3));
+        assert!(!clock.accepts(1));
+        time.set(START + 6501);
+        assert!(!clock.accepts(2));
+
+        // huge ids do not overflow
+        assert!(!clock.accepts(u64::max_value()));
+    }
+}
@@ -6,41 +6,70 @@ use chain::transaction::Transaction;
 use super::scheduler::Token;
 use std::net::{SocketAddr};
 use chain::{BlockHeader}; 
-use super::cmtda::{Block, H256, BLOCK_SIZE, HEADER_SIZE, read_codes};
+use super::cmtda::{Block, H256, CmtParams};
 use ser::{deserialize, serialize};
 use primitives::bytes::{Bytes};
-use chain::decoder::{Symbol};
+use chain::decoder::{Symbol, IncorrectCodingProof};
+use chain::light_client::BaseSample;
 use chain::big_array::{BigArray};
 use super::primitive::block::ContractState;
 use web3::types::Address;
+use super::sampleSet::{SampleSet, Merged, MergeErr};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Samples {
     pub header: Vec<u8>,
     pub symbols: Vec<Vec<Symbol>>,
     pub idx: Vec<Vec<u64>>,
+    // proofs[l][j] is the Merkle proof of symbols[l][j], one symbol from each layer above l
+    pub proofs: Vec<Vec<Vec<Symbol>>>,
 }
 
 impl Samples {
-    pub fn merge(&mut self, samples: &Samples) -> bool {
-        if self.header != samples.header {
-            return false;
-        }
+    pub fn num_symbols(&self) -> usize {
+        self.symbols.iter().map(|s| s.len()).sum()
+    }
 
-        // has the same number of layers
-        let num_layer = self.symbols.len();
-        if (samples.symbols.len() != num_layer) || 
-           (samples.idx.len() != num_layer) {
-            return false;
+    // keep only symbols that pass their Merkle proof against the coded Merkle roots in header,
+    // return the number of symbols dropped
+    pub fn retain_verified(&mut self, header: &BlockHeader, params: &CmtParams) -> usize {
+        let total = self.num_symbols();
+        let num_layer = params.k_set().len();
+        if self.symbols.len() != num_layer || self.idx.len() != num_layer || self.proofs.len() != num_layer {
+            self.symbols = vec![vec![]; num_layer];
+            self.idx = vec![vec![]; num_layer];
+            self.proofs = vec![vec![]; num_layer];
+            return total;
         }
-
-        // TODO check if they are redundant 
-       
-        // merge
-        for i in 0..num_layer {
-
+        let mut dropped = 0;
+        for l in 0..num_layer {
+            let symbols = std::mem::replace(&mut self.symbols[l], vec![]);
+            let idx = std::mem::replace(&mut self.idx[l], vec![]);
+            let proofs = std::mem::replace(&mut self.proofs[l], vec![]);
+            if idx.len() != symbols.len() || proofs.len() != symbols.len() {
+                dropped += symbols.len();
+                continue;
+            }
+            for ((symbol, i), proof) in symbols.into_iter().zip(idx.into_iter()).zip(proofs.into_iter()) {
+                if i <= std::u32::MAX as u64 && header.verify_symbol(&symbol, l as u32, i as u32, &proof, params) {
+                    self.symbols[l].push(symbol);
+                    self.idx[l].push(i);
+                    self.proofs[l].push(proof);
+                } else {
+                    dropped += 1;
+                }
+            }
         }
-        return true;
+        dropped
+    }
+
+    // add the symbols of samples not held yet, see SampleSet::merge
+    pub fn merge(&mut self, samples: &Samples) -> Result<Merged, MergeErr> {
+        let mut set = SampleSet::new(self.header.clone(), self.symbols.len());
+        set.merge(self.clone())?;
+        let merged = set.merge(samples.clone())?;
+        *self = set.to_samples();
+        Ok(merged)
     }
 }
 
@@ -52,13 +81,16 @@ pub enum Message {
     SyncBlock(EthBlkTransaction),
     SendTransaction(Vec<u8>), 
     PassToken(Token),
-    //ip(pubkey) BlockHeader block_id //sender is client
-    ProposeBlock(SocketAddr, u64, Vec<u8>), 
-    ScaleReqChunks(SocketAddr, u64, u64), //(id, scale_id), // sender is scalenode
-    ScaleReqChunksReply(SocketAddr, u64, Samples),
-    MySign(String, u64, u64, String, String, u64),
+    //ip(pubkey) sid block_id BlockHeader //sender is client
+    ProposeBlock(SocketAddr, u64, u64, Vec<u8>), 
+    ScaleReqChunks(SocketAddr, u64, u64, u64), //(sid, id, scale_id), // sender is scalenode
+    ScaleReqChunksReply(SocketAddr, u64, u64, Samples), // sid, id
+    MySign(String, u64, u64, String, String, u64), // header, sid, bid, sigx, sigy, scale_id
     ScaleGetAllChunks(ContractState), // blockheader
-    ScaleGetAllChunksReply((Option<Samples>, u64)),
+    ScaleGetAllChunksReply((Option<Samples>, u64, u64)), // sid, id
+    IncorrectCoding(u64, u64, Vec<u8>, IncorrectCodingProof), // sid, bid, header, proof
+    LightReqSamples(u64, u64, Vec<u32>), // sid, bid, base symbol indices, sender is light node
+    LightReqSamplesReply(u64, u64, Vec<u8>, Vec<BaseSample>), // sid, bid, header, samples
 }
 
 
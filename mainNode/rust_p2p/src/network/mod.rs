@@ -3,6 +3,7 @@ extern crate log;
 pub mod server;
 pub mod peer;
 pub mod message;
+pub mod sampleSet;
 pub mod performer;
 
 use super::primitive;
@@ -15,5 +16,5 @@ use super::mempool::scheduler;
 use super::cmtda;
 
 
-// should be greater than coded_merkle_tree/chain constants.rs BLOCK_SIZE
+// should be greater than the block_size of the CMT parameters (--cmt_config)
 pub const MSG_BUF_SIZE: usize = 1000_000;//65535;
@@ -6,7 +6,8 @@ use std::sync::{Arc, Mutex};
 use crate::db::blockDb::{BlockDb};
 use crate::blockchain::blockchain::{BlockChain};
 use crate::mempool::mempool::{Mempool};
-use crate::mempool::scheduler::{self, get_curr_slot};
+use crate::mempool::scheduler::{self, slot_proposer};
+use crate::mempool::slotClock::SlotClock;
 
 use super::message::{Message, TaskRequest, PeerHandle, Samples, ServerSignal};
 use super::contract::contract::{Contract};
@@ -19,22 +20,22 @@ use super::primitive::block::ContractState;
 use crossbeam::channel::{self, Sender, Receiver};
 use std::net::{SocketAddr};
 use super::primitive::hash::{H256};
-use super::crypto::hash;
+use super::crypto::{hash, chain_hash};
 use super::primitive::block::{Block, EthBlkTransaction};
 use chain::transaction::Transaction;
 extern crate crypto;
 use crypto::sha2::Sha256;
 use crypto::digest::Digest;
-use super::contract::utils;
+use super::contract::utils::{self, BLSKey};
+use super::crypto::bls::{self, Signature, PublicKey};
 use std::collections::HashMap;
 use web3::types::{U256};
 use core::borrow::BorrowMut;
 use primitives::bytes::{Bytes};
 use ser::{deserialize, serialize};
 use mio_extras::channel::Sender as MioSender;
-use super::cmtda::{BlockHeader};
+use super::cmtda::{BlockHeader, CmtParams, CodeRegistry, BaseSample};
 use hex;
-use chain::constants::{BLOCK_SIZE, BASE_SYMBOL_SIZE, RATE, UNDECODABLE_RATIO};
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
 use web3::types::{Address};
 use crate::experiment::snapshot::PERFORMANCE_COUNTER;
@@ -47,21 +48,23 @@ pub struct Performer {
     scheduler_handler: Sender<scheduler::Signal>,
     contract_handler: Sender<Handle>,
     addr: SocketAddr,
-    proposal_by: HashMap<(SocketAddr, u64), Sender<Samples> >, //TODO cleanup memory
-    key_file: String,
+    proposal_by: HashMap<(SocketAddr, u64, u64), Sender<Samples> >, //TODO cleanup memory
+    bls_key: bls::SecretKey,
     scale_id: u64,
-    agg_sig: Arc<Mutex<HashMap<String, (String, String, usize)>>>,
+    agg_sig: Arc<Mutex<HashMap<(u64, String), (Signature, usize)>>>, // keyed by sid, header
+    scale_pub_keys: HashMap<u64, PublicKey>,
     threshold: u64,
     server_control_sender: MioSender<ServerSignal>,
-    manager_source: Sender<(u64, Option<Samples>)>,
+    manager_source: Sender<(u64, u64, SocketAddr, Option<Samples>)>,
+    light_source: Sender<(u64, u64, Vec<u8>, Vec<BaseSample>)>,
     num_nodes: u64,
-    bin_dir: String,
     num_side: u64,
     address: Address,
-    slot_time: u64,
-    sidenodes: Vec<SocketAddr>,
-    start_sec: u64,
-    start_millis: u64,
+    sidenodes: HashMap<u64, Vec<SocketAddr>>, // token ring of each side chain
+    clock: SlotClock,
+    // codes of received blocks are looked up by the code id in their header
+    code_registry: Arc<CodeRegistry>,
+    params: CmtParams,
 }
 
 pub fn new(
@@ -72,19 +75,19 @@ pub fn new(
     scheduler_handler: Sender<scheduler::Signal>,
     contract_handler: Sender<Handle>,
     addr: SocketAddr,
-    key_file: String,
+    key: BLSKey,
     scale_id: u64,
     threshold: u64,
     server_control_sender: MioSender<ServerSignal>,
-    manager_source: Sender<(u64, Option<Samples>)>,
+    manager_source: Sender<(u64, u64, SocketAddr, Option<Samples>)>,
+    light_source: Sender<(u64, u64, Vec<u8>, Vec<BaseSample>)>,
     num_nodes: u64,
-    bin_dir: &str,
     num_side: u64,
     address: Address,
-    slot_time: u64,
-    sidenodes: Vec<SocketAddr>,
-    start_sec: u64,
-    start_millis: u64,
+    sidenodes: HashMap<u64, Vec<SocketAddr>>,
+    clock: SlotClock,
+    code_registry: Arc<CodeRegistry>,
+    params: CmtParams,
 ) -> Performer {
     Performer {
         task_source,
@@ -95,20 +98,21 @@ pub fn new(
         scheduler_handler: scheduler_handler,
         addr: addr,
         proposal_by: HashMap::new(),
-        key_file,
+        bls_key: bls::SecretKey::from_u256(key.sk).expect("invalid bls secret key"),
         scale_id,
         agg_sig: Arc::new(Mutex::new(HashMap::new())),
+        scale_pub_keys: HashMap::new(),
         threshold,
         server_control_sender: server_control_sender,
         manager_source: manager_source,
+        light_source,
         num_nodes: num_nodes,
-        bin_dir: bin_dir.to_string(),
         num_side: num_side,
         address: address,
-        slot_time: slot_time,
         sidenodes: sidenodes,
-        start_sec: start_sec,
-        start_millis: start_millis,
+        clock,
+        code_registry,
+        params,
     } 
 }
 
@@ -140,31 +144,74 @@ impl Performer {
         return local_hash;
     }
 
-    pub fn decide_node(&self, proposer_addr: &SocketAddr) -> bool {
-        let (curr_slot, elapsed) = get_curr_slot(self.start_sec, self.start_millis, self.slot_time);
-        let curr_id = curr_slot % self.sidenodes.len() as u64;
-        let sender_id = match self.sidenodes.
-            iter().
-            position(|x| x== proposer_addr) 
-        {
-            Some(i) => i as u64,
-            None => return false,
+    // the proposer has to own the slot of block_id in the token ring of the side chain
+    pub fn decide_node(&self, sid: u64, proposer_addr: &SocketAddr, block_id: u64) -> bool {
+        let ring = match self.sidenodes.get(&sid) {
+            Some(r) if !r.is_empty() => r,
+            _ => {
+                warn!("proposal from {:?} for unknown side chain {}", proposer_addr, sid);
+                return false;
+            }
         };
+        match slot_proposer(ring, block_id) {
+            Some(addr) if addr == *proposer_addr => true,
+            expected => {
+                warn!("wrong node {:?} propose block {} on side chain {}, expected {:?}", proposer_addr, block_id, sid, expected);
+                false
+            }
+        }
+    }
 
-        //info!("sender id {:?}; curr id {}", sender_id, curr_id);
+    // registered public key of a scale node, fetched from the contract once and cached. Only
+    // scale nodes 1..=num_nodes sign, the contract is not asked about any other id
+    fn get_scale_pub_key(&mut self, scale_id: u64) -> Option<PublicKey> {
+        if let Some(key) = self.scale_pub_keys.get(&scale_id) {
+            return Some(*key);
+        }
+        if scale_id == 0 || scale_id > self.num_nodes {
+            return None;
+        }
+        let (answer_tx, answer_rx) = channel::bounded(1);
+        let handle = Handle {
+            message: ContractMessage::GetScalePubKey(scale_id as usize),
+            answer_channel: Some(answer_tx),
+        };
+        self.contract_handler.send(handle);
 
-        if sender_id  == curr_id {
-            true
-        } else {
-            warn!("wrong node {:?} {} propose, curr_id {}", proposer_addr, sender_id, curr_id);
-            false
+        let key = match answer_rx.recv() {
+            Ok(Answer::Success(ContractResponse::ScalePubKey(x1, x2, y1, y2))) => {
+                match PublicKey::from_u256(x1, x2, y1, y2) {
+                    Ok(key) => key,
+                    Err(e) => {
+                        warn!("scale node {} registered an invalid public key: {}", scale_id, e);
+                        return None;
+                    }
+                }
+            },
+            Ok(Answer::Fail(reason)) => {
+                warn!("unable to get public key of scale node {}: {}", scale_id, reason);
+                return None;
+            },
+            Ok(_) => panic!("performer contract get wrong answer"),
+            Err(e) => panic!("performer to contract handler channel broke"),
+        };
+        self.scale_pub_keys.insert(scale_id, key);
+        Some(key)
+    }
+
+    // whether header is the one committed as block block_id of side chain sid, the contract
+    // chains sha256 hashes of the committed headers
+    fn is_committed_header(&self, sid: u64, block_id: u64, header: &[u8]) -> bool {
+        match committed_states(&self.chain, &self.contract_handler, sid, block_id) {
+            Some((prev, state)) => chain_hash(prev.curr_hash, header) == state.curr_hash,
+            None => false,
         }
     }
 
-    fn get_eth_transactions(&self, start: u64, end: u64) -> Vec<EthBlkTransaction> {
+    fn get_eth_transactions(&self, sid: u64, start: u64, end: u64) -> Vec<EthBlkTransaction> {
         let (answer_tx, answer_rx) = channel::bounded(1);
         let handle = Handle {
-            message: ContractMessage::GetAll(([0 as u8;32], start as usize, end as usize)),
+            message: ContractMessage::GetAll((sid as usize, start as usize, end as usize)),
             answer_channel: Some(answer_tx),
         };
         self.contract_handler.send(handle);
@@ -186,10 +233,10 @@ impl Performer {
     }
 
 
-    fn get_eth_curr_state(&self) -> ContractState {
+    fn get_eth_curr_state(&self, sid: u64) -> ContractState {
         let (answer_tx, answer_rx) = channel::bounded(1);
         let handle = Handle {
-            message: ContractMessage::GetCurrState(0),
+            message: ContractMessage::GetCurrState(sid as usize),
             answer_channel: Some(answer_tx),
         };
         self.contract_handler.send(handle);
@@ -314,34 +361,46 @@ impl Performer {
                     info!("{:?} receive token", self.addr);
                     self.scheduler_handler.send(scheduler::Signal::Data(token));
                 },
-                Message::ProposeBlock(proposer_addr, block_id, header) => {
+                Message::ProposeBlock(proposer_addr, sid, block_id, header) => {
                     if self.scale_id > 0 {
                         //let (curr_slot, elapsed) = get_curr_slot(self.start_sec, self.start_millis, self.slot_time);
                         //info!("recv Propose_block {:?}", elapsed);
-                        info!("recv Propose block {}", block_id);
+                        info!("recv Propose block {} on side chain {}", block_id, sid);
                         let hash_str = utils::hash_header_hex(&header);
                         //info!("{:?} receive ProposeBlock: header hash: {:?}", self.addr, hash_str);
                         let local_addr = self.addr.clone();
                         
-                        if !self.decide_node(&proposer_addr) {
+                        if !self.decide_node(sid, &proposer_addr, block_id) {
+                            continue;
+                        }
+                        if self.chain.lock().unwrap().is_faulty_proposer(&proposer_addr) {
+                            warn!("drop proposal from {:?}, it proposed an incorrectly coded block", proposer_addr);
                             continue;
                         }
 
-                        let (curr_slot, elapsed) = get_curr_slot(self.start_sec, self.start_millis, self.slot_time); 
-                        let true_block_id = curr_slot+1;
-                        if true_block_id != block_id {
-                            warn!("wrong block id {} != {} from {:?}", block_id, true_block_id, proposer_addr);
+                        if !self.clock.accepts(block_id) {
+                            warn!("block {} from {:?} is outside its slot, current slot {:?}", block_id, proposer_addr, self.clock.current_slot());
                             continue;
                         }
                         PERFORMANCE_COUNTER.record_sign_block_update(block_id);
                         let (tx, rx) = channel::unbounded();
-                        self.proposal_by.insert((proposer_addr, block_id), tx);
+                        self.proposal_by.insert((proposer_addr, sid, block_id), tx);
 
                         let header_cmt: BlockHeader = deserialize(
                             &header.clone() as &[u8]
                             ).unwrap();
 
-                        let num_symbol = BLOCK_SIZE/(BASE_SYMBOL_SIZE as u64) *((1.0/RATE) as u64);
+                        // competing proposals are kept until the contract commits one of them
+                        let prev_hash = H256(header_cmt.previous_header_hash.clone().take());
+                        let mut chain = self.chain.lock().unwrap();
+                        chain.propose(sid, block_id, prev_hash, &header);
+                        let num_proposals = chain.num_proposals(sid, block_id);
+                        drop(chain);
+                        if num_proposals > 1 {
+                            warn!("{} competing proposals for block {} on side chain {}", num_proposals, block_id, sid);
+                        }
+
+                        let num_symbol = self.params.num_base_symbols();
                         let samples_idx = get_sample_index(
                             self.scale_id, 
                             num_symbol, 
@@ -349,11 +408,12 @@ impl Performer {
 
                         let response_msg = Message::ScaleReqChunks(
                             proposer_addr, // scalenode addr 
+                            sid,
                             block_id,
                             self.scale_id);
                         peer_handle.write(response_msg);
 
-                        let keyfile = self.key_file.clone();
+                        let bls_key = self.bls_key;
                         let scaleid = self.scale_id.clone();
                         let local_aggsig = self.agg_sig.clone();
                         let broadcaster = self.server_control_sender.clone();
@@ -363,25 +423,38 @@ impl Performer {
                         let block_id_local = block_id;
                         let contract_handler = self.contract_handler.clone();
                         let num_nodes = self.num_nodes;
-                        let bin_dir = self.bin_dir.clone();
-                        let threshold = (UNDECODABLE_RATIO*(self.num_nodes as f32)).ceil() as usize ;
+                        let threshold = (self.params.undecodable_ratio*(self.num_nodes as f32)).ceil() as usize ;
+                        let chunk_thresh = self.params.decodable_symbols() / num_nodes;
                         let local_contract_handler = self.contract_handler.clone();
+                        let local_chain = self.chain.clone();
+                        let params = self.params.clone();
 
                         //info!(" {:?} start aggregating ", self.addr);
                         // timed loop
                         thread::spawn(move || {
                             let mut num_chunk = 0;
-                            let chunk_thresh = (((BLOCK_SIZE/BASE_SYMBOL_SIZE as u64) as f32 )/RATE*UNDECODABLE_RATIO) as u64 / num_nodes; 
 
                             let mut chunk_complete = false;
 
                             loop {
                                 match rx.recv() {
-                                    Ok(chunk_reply) => {
+                                    Ok(mut chunk_reply) => {
                                         //info!(" {:?} get sample ", local_addr);
+                                        if chunk_reply.header != header {
+                                            warn!("{:?} sent chunks of another header for block {} on side chain {}", proposer_addr_local, block_id, sid);
+                                            PERFORMANCE_COUNTER.record_rejected_symbols(chunk_reply.num_symbols());
+                                            local_chain.lock().unwrap().record_bad_peer(proposer_addr_local, chunk_reply.num_symbols());
+                                            continue;
+                                        }
+                                        let dropped = chunk_reply.retain_verified(&header_cmt, &params);
+                                        if dropped > 0 {
+                                            warn!("{:?} sent {} symbols failing their Merkle proof for block {} on side chain {}", proposer_addr_local, dropped, block_id, sid);
+                                            PERFORMANCE_COUNTER.record_rejected_symbols(dropped);
+                                            local_chain.lock().unwrap().record_bad_peer(proposer_addr_local, dropped);
+                                        }
                                         let mut local_db = db.lock().unwrap();
                                         // compute id
-                                        local_db.insert_cmt_sample(block_id, &chunk_reply);
+                                        local_db.insert_cmt_sample(sid, block_id, &chunk_reply);
                                         let num =  get_num_base_symbols(&chunk_reply.idx);
                                         num_chunk += num;
                                     },
@@ -391,38 +464,40 @@ impl Performer {
                                     // vote
                                     let header_str: String = hex::encode(&header);
                                     
-                                    let (sigx, sigy) = utils::_sign_bls(header_str.clone(), keyfile, &bin_dir);
-                                    let sid = 0;
+                                    let sig = bls_key.sign(&header);
+                                    let (sigx, sigy) = sig.to_dec_str();
                                     let response_msg = Message::MySign(
                                         header_str.clone(), 
                                         sid, 
                                         block_id, 
-                                        sigx.clone(), 
-                                        sigy.clone(), 
+                                        sigx, 
+                                        sigy, 
                                         scaleid);
                                     let signal = ServerSignal::ServerBroadcast(response_msg);
                                     broadcaster.send(signal);                                   
 
+                                    let key = (sid, header_str.clone());
                                     let mut aggsig = local_aggsig.lock().unwrap();
-                                    if aggsig.get(&header_str).is_none() {
-                                        aggsig.insert(header_str.clone(),  (sigx.clone(), sigy.clone(), (1 << scaleid)));
+                                    if aggsig.get(&key).is_none() {
+                                        aggsig.insert(key,  (sig, (1 << scaleid)));
                                         drop(aggsig);
                                     } else {
-                                        let ( x, y, bitset) = aggsig.get(&header_str).unwrap();
-                                        let (sigx, sigy) = utils::_aggregate_sig(x.to_string(), y.to_string(), sigx, sigy, &bin_dir);
+                                        let (agg, bitset) = aggsig.get(&key).unwrap();
+                                        let agg = Signature::aggregate(&[*agg, sig]);
                                         let bitset = bitset + (1 << scaleid);
                                         aggsig.insert(
-                                            header_str.clone(),  
-                                            (sigx.clone(), sigy.clone(), bitset.clone()));
+                                            key,  
+                                            (agg, bitset.clone()));
                                         drop(aggsig);
                                         if utils::_count_sig(bitset.clone()) >= threshold {
                                             //info!("{:?} first loop aggreg enough sig", local_addr);
                                             info!("1. Sufficient Signture block {}", block_id);
                                             PERFORMANCE_COUNTER.record_sign_block_stop(block_id as usize);
                                             PERFORMANCE_COUNTER.record_submit_block_update(block_id);
+                                            let (sigx, sigy) = agg.to_u256();
                                             let (answer_tx, answer_rx) = channel::bounded(1);
                                             let handle = Handle {
-                                                message: ContractMessage::SubmitVote(header_str.clone(), U256::from(sid), U256::from(block_id), U256::from_dec_str(sigx.as_ref()).unwrap(), U256::from_dec_str(sigy.as_ref()).unwrap(), U256::from(bitset.clone())),
+                                                message: ContractMessage::SubmitVote(header_str.clone(), U256::from(sid), U256::from(block_id), sigx, sigy, U256::from(bitset.clone())),
                                                 answer_channel: Some(answer_tx),
                                             };
                                             local_contract_handler.send(handle);
@@ -443,53 +518,77 @@ impl Performer {
                     if self.scale_id <= 0 {
                         continue;
                     }
-                    // new
-                    let decode_header = hex::decode(&header).unwrap();
-                    let header_hash_str = utils::hash_header_hex(&decode_header);
-                    let mut sigx = sigx;
-                    let mut sigy = sigy;
+                    let decode_header = match hex::decode(&header) {
+                        Ok(h) => h,
+                        Err(e) => {
+                            warn!("malformed header in vote from scale node {}", scale_id);
+                            PERFORMANCE_COUNTER.record_rejected_vote();
+                            continue;
+                        }
+                    };
+                    let sig = match Signature::from_dec_str(&sigx, &sigy) {
+                        Ok(sig) => sig,
+                        Err(e) => {
+                            warn!("malformed signature from scale node {}: {}", scale_id, e);
+                            PERFORMANCE_COUNTER.record_rejected_vote();
+                            continue;
+                        }
+                    };
+                    let valid = match self.get_scale_pub_key(scale_id) {
+                        Some(key) => sig.verify(&decode_header, &key),
+                        None => false,
+                    };
+                    if !valid {
+                        warn!("drop invalid vote from scale node {} for block {}", scale_id, bid);
+                        PERFORMANCE_COUNTER.record_rejected_vote();
+                        continue;
+                    }
                     // send to spawned thread like ScaleReqChunksReply
+                    let key = (sid, header.clone());
                     let mut aggsig = self.agg_sig.lock().unwrap();
-                    let threshold = (UNDECODABLE_RATIO*(self.num_nodes as f32)).ceil() as usize ;
+                    let threshold = (self.params.undecodable_ratio*(self.num_nodes as f32)).ceil() as usize ;
 
-                    if aggsig.get(&header).is_none() {
-                        aggsig.insert(header.clone(),  (sigx, sigy, (1 << scale_id)));
+                    if aggsig.get(&key).is_none() {
+                        aggsig.insert(key,  (sig, (1 << scale_id)));
                     } else {
-                        let ( x, y, mut bitset) = aggsig.get(&header).unwrap().clone();
+                        let (mut agg, mut bitset) = aggsig.get(&key).unwrap().clone();
                         if (1 << scale_id) & bitset.clone() == 0 {
-                            let (sigx_t, sigy_t) = utils::_aggregate_sig(x.to_string(), y.to_string(), sigx.clone(), sigy.clone(), &self.bin_dir);
-                            sigx = sigx_t;
-                            sigy = sigy_t;
+                            agg = Signature::aggregate(&[agg, sig]);
                             bitset = bitset + (1 << scale_id);
-                            aggsig.insert(header.clone(), (sigx.clone(), sigy.clone(), bitset.clone()));
+                            aggsig.insert(key.clone(), (agg, bitset.clone()));
                         }
                         drop(aggsig);
                         if utils::_count_sig(bitset.clone()) >= threshold {
-                            info!("2. Sufficient Signture block {}", bid);
+                            info!("2. Sufficient Signture block {} on side chain {}", bid, sid);
                             //info!("{:?} aggreg enough sig", self.addr);
                             PERFORMANCE_COUNTER.record_sign_block_stop(bid as usize);
                             PERFORMANCE_COUNTER.record_submit_block_update(bid);
+                            let (sigx, sigy) = agg.to_u256();
                             let (answer_tx, answer_rx) = channel::bounded(1);
                             let handle = Handle {
-                                message: ContractMessage::SubmitVote(header.clone(), U256::from(sid), U256::from(bid), U256::from_dec_str(sigx.as_ref()).unwrap(), U256::from_dec_str(sigy.as_ref()).unwrap(), U256::from(bitset.clone())),
+                                message: ContractMessage::SubmitVote(header.clone(), U256::from(sid), U256::from(bid), sigx, sigy, U256::from(bitset.clone())),
                                 answer_channel: Some(answer_tx),
                             };
                             self.contract_handler.send(handle);
                             let mut aggsig = self.agg_sig.lock().unwrap();
-                            aggsig.remove(&header);
+                            aggsig.remove(&key);
                             drop(aggsig);
                         }
                     }
                 },
-                Message::ScaleReqChunks(proposer_addr, block_id, sender_scale_id) => {
+                Message::ScaleReqChunks(proposer_addr, sid, block_id, sender_scale_id) => {
                     // this client needs to prepare chunks in response to 
                     let start = SystemTime::now();
-                    let mut mempool = self.mempool.lock().expect("lock mempool");
-                    let (header, symbols, idx) = mempool.get_cmt_sample(
-                        block_id,
-                        sender_scale_id);
-                    
+                    let mempool = self.mempool.lock().expect("lock mempool");
+                    let sample = mempool.get_cmt_sample(sid, block_id, sender_scale_id);
                     drop(mempool);
+                    let (header, symbols, idx, proofs) = match sample {
+                        Some(s) => s,
+                        None => {
+                            debug!("no samples of block {} on side chain {} for scale node {}", block_id, sid, sender_scale_id);
+                            continue;
+                        },
+                    };
                     //info!("fetched samples for scale id {} for block id {}", sender_scale_id, block_id);
                     let header_bytes = serialize(&header);
                     let hash_str = utils::hash_header_hex(&header_bytes);
@@ -497,17 +596,19 @@ impl Performer {
                         header: header_bytes.into(),
                         symbols: symbols,
                         idx: idx,
+                        proofs: proofs,
                     };
                     let response_msg = Message::ScaleReqChunksReply(
                         self.addr, // only side nodes sends it == propser_addr
+                        sid,
                         block_id,
                         symbols);
                     peer_handle.write(response_msg);
                     //info!("ScaleReq Response time {:?}", start.elapsed());
                 },
-                Message::ScaleReqChunksReply(proposer_addr, block_id, symbols) => {
+                Message::ScaleReqChunksReply(proposer_addr, sid, block_id, symbols) => {
                     if self.scale_id > 0 {
-                        match &self.proposal_by.get(&(proposer_addr, block_id)) {
+                        match &self.proposal_by.get(&(proposer_addr, sid, block_id)) {
                             Some(sender) => {
                                 sender.send(symbols);
                             },
@@ -518,18 +619,72 @@ impl Performer {
                 Message::ScaleGetAllChunks(state) => {
                     if self.scale_id > 0 {
                         let local_db = self.block_db.lock().unwrap();
-                        let chunk = local_db.get_chunk(state.block_id as u64);
+                        let chunk = local_db.get_chunk(state.sid, state.block_id);
                         drop(local_db);
-                        let response_msg = match chunk {
-                            Some(chunk) => Message::ScaleGetAllChunksReply((Some(chunk), state.block_id)),
-                            None => Message::ScaleGetAllChunksReply((None, state.block_id)),
-                        };
+                        let response_msg = Message::ScaleGetAllChunksReply((chunk, state.sid, state.block_id));
                         peer_handle.write(response_msg);
                     }
                 },
-                Message::ScaleGetAllChunksReply((chunk, block_id)) => {
+                Message::ScaleGetAllChunksReply((chunk, sid, block_id)) => {
                     //info!("{:?} recv ScaleGetAllChunksReply", self.addr);
-                    self.manager_source.send((block_id, chunk));
+                    self.manager_source.send((sid, block_id, peer_handle.addr, chunk));
+                },
+                Message::IncorrectCoding(sid, block_id, header, proof) => {
+                    // already verified and gossiped
+                    if self.chain.lock().unwrap().is_invalid(sid, block_id) {
+                        continue;
+                    }
+                    let header_cmt: BlockHeader = match deserialize(&header as &[u8]) {
+                        Ok(h) => h,
+                        Err(e) => {
+                            warn!("malformed header in incorrect-coding proof of block {} on side chain {}", block_id, sid);
+                            continue;
+                        }
+                    };
+                    let code_set = match self.code_registry.find(&header_cmt.code_id) {
+                        Some(set) => set,
+                        None => {
+                            warn!("drop incorrect-coding proof of block {} on side chain {}, unknown codes {:?}", block_id, sid, header_cmt.code_id);
+                            continue;
+                        }
+                    };
+                    if !proof.verify(&header_cmt, &code_set.decoding, &self.params) {
+                        warn!("drop invalid incorrect-coding proof of block {} on side chain {}", block_id, sid);
+                        continue;
+                    }
+                    if !self.is_committed_header(sid, block_id, &header) {
+                        warn!("incorrect-coding proof of block {} on side chain {} is for a header that is not committed", block_id, sid);
+                        continue;
+                    }
+                    let proposer = self.sidenodes.get(&sid).and_then(|ring| slot_proposer(ring, block_id));
+                    warn!("block {} on side chain {} proposed by {:?} is incorrectly coded, {:?} on layer {}", 
+                          block_id, sid, proposer, proof.error_type, proof.level);
+                    self.chain.lock().unwrap().mark_invalid(sid, block_id, proposer);
+
+                    let response_msg = Message::IncorrectCoding(sid, block_id, header, proof);
+                    let signal = ServerSignal::ServerBroadcast(response_msg);
+                    self.server_control_sender.send(signal);
+                },
+                Message::LightReqSamples(sid, block_id, indices) => {
+                    // only the proposer keeps the coded Merkle tree of a block
+                    let mempool = self.mempool.lock().expect("lock mempool");
+                    let reply = match mempool.get_block(sid, block_id) {
+                        Some(block) => {
+                            let samples: Vec<BaseSample> = indices.iter()
+                                .filter_map(|i| BaseSample::from_block(block, *i))
+                                .collect();
+                            let header: Vec<u8> = serialize(&block.block_header).into();
+                            Some(Message::LightReqSamplesReply(sid, block_id, header, samples))
+                        },
+                        None => None,
+                    };
+                    drop(mempool);
+                    if let Some(response_msg) = reply {
+                        peer_handle.write(response_msg);
+                    }
+                },
+                Message::LightReqSamplesReply(sid, block_id, header, samples) => {
+                    self.light_source.send((sid, block_id, header, samples));
                 },
             }
         } 
@@ -552,7 +707,120 @@ pub fn get_sample_index(scale_id: u64, num_trans: u64, num_node: u64) -> Vec<u32
     sample_idx
 }
 
+// committed states of blocks block_id-1 and block_id. The local chain is used as far as it
+// reaches, the contract otherwise: nodes stop before an incorrectly coded block, so the accused
+// block is usually not appended.
+fn committed_states(
+    chain: &Mutex<BlockChain>,
+    contract_handler: &Sender<Handle>,
+    sid: u64,
+    block_id: u64,
+) -> Option<(ContractState, ContractState)> {
+    if block_id == 0 {
+        return None;
+    }
+    let chain = chain.lock().unwrap();
+    let prev = if block_id == 1 {
+        Some(ContractState::genesis(sid))
+    } else {
+        chain.get_state(sid, block_id - 1)
+    };
+    let state = chain.get_state(sid, block_id);
+    drop(chain);
+    if let (Some(prev), Some(state)) = (prev, state) {
+        return Some((prev, state));
+    }
+
+    // the accused block is most often the latest one, its previous state is then local
+    let (answer_tx, answer_rx) = channel::bounded(1);
+    contract_handler.send(Handle {
+        message: ContractMessage::GetCurrState(sid as usize),
+        answer_channel: Some(answer_tx),
+    });
+    let curr = match answer_rx.recv() {
+        Ok(Answer::Success(ContractResponse::GetCurrState(s))) => s,
+        Ok(Answer::Fail(reason)) => {
+            warn!("unable to get state of side chain {}: {}", sid, reason);
+            return None;
+        },
+        Ok(_) => panic!("performer contract get wrong answer"),
+        Err(e) => panic!("performer contract channel broke"),
+    };
+    if curr.block_id < block_id {
+        return None;
+    }
+    if let (true, Some(prev)) = (curr.block_id == block_id, prev) {
+        return Some((prev, curr));
+    }
+
+    let (answer_tx, answer_rx) = channel::bounded(1);
+    contract_handler.send(Handle {
+        message: ContractMessage::GetAll((sid as usize, block_id.saturating_sub(2) as usize, block_id as usize)),
+        answer_channel: Some(answer_tx),
+    });
+    let states: Vec<ContractState> = match answer_rx.recv() {
+        Ok(Answer::Success(ContractResponse::GetAll(txs))) => txs.into_iter().map(|tx| tx.contract_state).collect(),
+        Ok(Answer::Fail(reason)) => {
+            warn!("unable to get states of side chain {}: {}", sid, reason);
+            return None;
+        },
+        Ok(_) => panic!("performer contract get wrong answer"),
+        Err(e) => panic!("performer contract channel broke"),
+    };
+    let prev = match prev {
+        Some(p) => p,
+        None => *states.iter().find(|s| s.block_id == block_id - 1)?,
+    };
+    let state = states.iter().find(|s| s.block_id == block_id)?;
+    Some((prev, *state))
+}
+
 pub fn get_num_base_symbols(idx: &Vec<Vec<u64>>) -> u64 {
     assert!(idx.len() > 0);
     idx[0].len() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(block_id: u64, prev: H256, header: &[u8]) -> ContractState {
+        ContractState { sid: 0, curr_hash: chain_hash(prev, header), block_id }
+    }
+
+    #[test]
+    fn accused_block_is_checked_against_the_contract() {
+        // the contract is at block 3, the local chain stopped at block 1
+        let s1 = state(1, H256::default(), b"one");
+        let s2 = state(2, s1.curr_hash, b"two");
+        let s3 = state(3, s2.curr_hash, b"three");
+        let contract = vec![s1, s2, s3];
+        let chain = Mutex::new(BlockChain::new());
+        chain.lock().unwrap().append(&s1);
+
+        let (contract_handler, contract_handle) = channel::unbounded::<Handle>();
+        thread::spawn(move || {
+            for handle in contract_handle.iter() {
+                let response = match handle.message {
+                    ContractMessage::GetCurrState(_) => ContractResponse::GetCurrState(contract[2]),
+                    ContractMessage::GetAll((_, start, end)) => ContractResponse::GetAll(contract.iter()
+                        .filter(|s| s.block_id as usize > start && s.block_id as usize <= end)
+                        .map(|s| EthBlkTransaction { contract_state: *s, ..Default::default() })
+                        .collect()),
+                    _ => panic!("unexpected contract message"),
+                };
+                handle.answer_channel.unwrap().send(Answer::Success(response)).unwrap();
+            }
+        });
+
+        assert_eq!(committed_states(&chain, &contract_handler, 0, 2), Some((s1, s2)));
+        assert_eq!(committed_states(&chain, &contract_handler, 0, 3), Some((s2, s3)));
+        assert_eq!(committed_states(&chain, &contract_handler, 0, 4), None);
+        assert_eq!(committed_states(&chain, &contract_handler, 0, 0), None);
+        let (prev, accused) = committed_states(&chain, &contract_handler, 0, 2).unwrap();
+        assert_eq!(chain_hash(prev.curr_hash, b"two"), accused.curr_hash);
+        assert_ne!(chain_hash(prev.curr_hash, b"forged"), accused.curr_hash);
+        // nothing is appended to the local chain
+        assert_eq!(chain.lock().unwrap().get_height(0), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use chain::decoder::Symbol;
+use super::message::Samples;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeErr {
+    Header, // samples of another block header
+    Layers(usize, usize), // expected, received number of layers
+    Malformed(usize), // layer whose symbols, indices and proofs differ in length
+}
+
+// outcome of a merge, a conflict is an index for which different bytes were received,
+// the symbol received first is kept
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Merged {
+    pub added: usize,
+    pub conflicts: Vec<(usize, u64)>, // (layer, index)
+}
+
+// symbols of one layer of the coded Merkle tree, keyed by their index on the layer
+#[derive(Debug, Clone, Default)]
+pub struct SampleLayer {
+    position: HashMap<u64, usize>,
+    symbols: Vec<Symbol>,
+    idx: Vec<u64>,
+    proofs: Vec<Vec<Symbol>>,
+}
+
+impl SampleLayer {
+    pub fn len(&self) -> usize {
+        self.idx.len()
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        self.position.contains_key(&index)
+    }
+
+    pub fn get(&self, index: u64) -> Option<&Symbol> {
+        self.position.get(&index).map(|p| &self.symbols[*p])
+    }
+
+    // true if the symbol is new, false if it is already known, an error if it has other bytes
+    fn insert(&mut self, index: u64, symbol: Symbol, proof: Vec<Symbol>) -> Result<bool, ()> {
+        match self.position.get(&index) {
+            Some(p) if self.symbols[*p] == symbol => Ok(false),
+            Some(_) => Err(()),
+            None => {
+                self.position.insert(index, self.idx.len());
+                self.symbols.push(symbol);
+                self.idx.push(index);
+                self.proofs.push(proof);
+                Ok(true)
+            },
+        }
+    }
+}
+
+// symbols of a block collected from several peers, every index is held once
+#[derive(Debug, Clone)]
+pub struct SampleSet {
+    header: Vec<u8>,
+    layers: Vec<SampleLayer>,
+}
+
+impl SampleSet {
+    pub fn new(header: Vec<u8>, num_layer: usize) -> SampleSet {
+        SampleSet {
+            header,
+            layers: vec![SampleLayer::default(); num_layer],
+        }
+    }
+
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    pub fn layer(&self, l: usize) -> &SampleLayer {
+        &self.layers[l]
+    }
+
+    pub fn merge(&mut self, samples: Samples) -> Result<Merged, MergeErr> {
+        if samples.header != self.header {
+            return Err(MergeErr::Header);
+        }
+        let num_layer = self.layers.len();
+        let lens = [samples.symbols.len(), samples.idx.len(), samples.proofs.len()];
+        if let Some(n) = lens.iter().find(|n| **n != num_layer) {
+            return Err(MergeErr::Layers(num_layer, *n));
+        }
+        for l in 0..num_layer {
+            if samples.idx[l].len() != samples.symbols[l].len() || samples.proofs[l].len() != samples.symbols[l].len() {
+                return Err(MergeErr::Malformed(l));
+            }
+        }
+
+        let mut merged = Merged::default();
+        let layers = samples.symbols.into_iter().zip(samples.idx.into_iter()).zip(samples.proofs.into_iter());
+        for (l, ((symbols, idx), proofs)) in layers.enumerate() {
+            for ((symbol, i), proof) in symbols.into_iter().zip(idx.into_iter()).zip(proofs.into_iter()) {
+                match self.layers[l].insert(i, symbol, proof) {
+                    Ok(true) => merged.added += 1,
+                    Ok(false) => (),
+                    Err(()) => merged.conflicts.push((l, i)),
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    // symbols and their indices as the tree decoder takes them
+    pub fn symbols_and_idx(&self) -> (Vec<Vec<Symbol>>, Vec<Vec<u64>>) {
+        (self.layers.iter().map(|l| l.symbols.clone()).collect(), self.layers.iter().map(|l| l.idx.clone()).collect())
+    }
+
+    pub fn to_samples(&self) -> Samples {
+        Samples {
+            header: self.header.clone(),
+            symbols: self.layers.iter().map(|l| l.symbols.clone()).collect(),
+            idx: self.layers.iter().map(|l| l.idx.clone()).collect(),
+            proofs: self.layers.iter().map(|l| l.proofs.clone()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(b: u8) -> Symbol {
+        Symbol::Base(vec![b; 4])
+    }
+
+    // two layers, symbols on the upper layer are left out
+    fn samples(header: u8, base_idx: &[u64]) -> Samples {
+        Samples {
+            header: vec![header],
+            symbols: vec![base_idx.iter().map(|i| base(*i as u8)).collect(), vec![]],
+            idx: vec![base_idx.to_vec(), vec![]],
+            proofs: vec![vec![vec![]; base_idx.len()], vec![]],
+        }
+    }
+
+    #[test]
+    fn overlapping_merge_keeps_each_index_once() {
+        let mut set = SampleSet::new(vec![1], 2);
+        assert_eq!(set.merge(samples(1, &[0, 3, 5])).unwrap(), Merged { added: 3, conflicts: vec![] });
+        assert_eq!(set.merge(samples(1, &[5, 6, 3, 7, 7])).unwrap(), Merged { added: 2, conflicts: vec![] });
+        assert_eq!(set.layer(0).len(), 5);
+        assert_eq!(set.layer(1).len(), 0);
+        assert_eq!(set.layer(0).get(6), Some(&base(6)));
+        assert!(!set.layer(0).contains(4));
+
+        let all = set.to_samples();
+        assert_eq!(all.idx[0], vec![0, 3, 5, 6, 7]);
+        assert_eq!(all.proofs[0].len(), 5);
+
+        // Samples::merge goes through the same set
+        let mut first = samples(1, &[0, 3]);
+        assert_eq!(first.merge(&samples(1, &[3, 9])).unwrap().added, 1);
+        assert_eq!(first.idx[0], vec![0, 3, 9]);
+    }
+
+    #[test]
+    fn conflicting_merge_keeps_first_symbol() {
+        let mut set = SampleSet::new(vec![1], 2);
+        set.merge(samples(1, &[0, 3])).unwrap();
+        let mut other = samples(1, &[3, 4]);
+        other.symbols[0][0] = base(0xff);
+        assert_eq!(set.merge(other).unwrap(), Merged { added: 1, conflicts: vec![(0, 3)] });
+        assert_eq!(set.layer(0).get(3), Some(&base(3)));
+
+        assert_eq!(set.merge(samples(2, &[8])), Err(MergeErr::Header));
+        let mut short = samples(1, &[8]);
+        short.proofs.pop();
+        assert_eq!(set.merge(short), Err(MergeErr::Layers(2, 1)));
+        let mut malformed = samples(1, &[8, 9]);
+        malformed.idx[0].pop();
+        assert_eq!(set.merge(malformed), Err(MergeErr::Malformed(0)));
+        assert_eq!(set.layer(0).len(), 3);
+    }
+}
@@ -5,13 +5,15 @@ use super::merkle::{MerkleHash};
 
 #[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
 pub struct ContractState {
+    pub sid: u64,
     pub curr_hash: H256,
     pub block_id: u64,
 }
 
 impl ContractState {
-    pub fn genesis () -> ContractState {
+    pub fn genesis (sid: u64) -> ContractState {
         ContractState {
+            sid: sid,
             curr_hash: H256::zero(),
             block_id: 0,
         }
@@ -24,6 +26,8 @@ impl ContractState {
 pub struct EthBlkTransaction {
      pub contract_state: ContractState,
      pub block: Block,
+     // header committed by the vote that led to contract_state, empty when not known
+     pub header: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, Default, PartialEq, Eq)]
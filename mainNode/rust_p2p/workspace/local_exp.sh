@@ -42,8 +42,8 @@ function start_node() {
 		fi
 	done
 	
-	echo "RUST_LOG=info $bin -i $peer_addr ${known_peers} $3 --api_addr $api_addr $account --key $keyfile --scale_id $4 -n $5 -l ../src/LDPC_codes -j "./scripts/abi.json" -b "../go-bls" --contract_address ${contract_address} --rpc_url ${rpc_url} --start_time ${start_time}"
-	RUST_LOG=info $bin -i $peer_addr ${known_peers} $3 --api_addr "$api_addr" $account -t $7 --key "$keyfile" --scale_id $4 -n $5 -l "../src/LDPC_codes" -j "./scripts/abi.json" -b "../go-bls" -f ${contract_address} -u ${rpc_url} --start_time ${start_time}&
+	echo "RUST_LOG=info $bin -i $peer_addr ${known_peers} $3 --api_addr $api_addr $account --key $keyfile --scale_id $4 -n $5 -l ../src/LDPC_codes -j "./scripts/abi.json" --contract_address ${contract_address} --rpc_url ${rpc_url} --start_time ${start_time}"
+	RUST_LOG=info $bin -i $peer_addr ${known_peers} $3 --api_addr "$api_addr" $account -t $7 --key "$keyfile" --scale_id $4 -n $5 -l "../src/LDPC_codes" -j "./scripts/abi.json" -f ${contract_address} -u ${rpc_url} --start_time ${start_time}&
 	pid="$!"
 	pids="$pids $pid"
 }
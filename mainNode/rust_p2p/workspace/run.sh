@@ -318,9 +318,6 @@ function prepare_payload
 	cp scripts/start-node.sh payload/common/scripts/start-node.sh
 	cp scripts/stop-node.sh payload/common/scripts/stop-node.sh
 	cp scripts/abi.json payload/common/binary/abi.json
-	scp bench:~/scale/go-bls/sign payload/common/binary/sign
-	scp bench:~/scale/go-bls/aggregate payload/common/binary/aggregate
-	scp bench:~/.cargo/bin/ethabi payload/common/binary/ethabi
 	cp -r LDPC_codes  payload/common/LDPC_codes
 
 	echo "Generate scale config files and keypairs for each node"
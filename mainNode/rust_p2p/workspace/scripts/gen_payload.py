@@ -5,7 +5,7 @@ import subprocess
 from shutil import copyfile
 
 template = """
-cd /root/payload/binary && RUST_LOG=info /root/payload/binary/system_rust -i {ip}:{p2p_port} {known_peers} {side_nodes} --api_addr {ip}:{api_port} --account /root/payload/scale-payload/account{account_name} --key /root/payload/scale-payload/node{node_name} --scale_id {scale_id} -l /root/payload/LDPC_codes -n {num_scale} -t {slot_time} -b /root/payload/binary -j /root/payload/binary/abi.json --contract_addr {contract_address} --node_url {rpc_url} --start_time {start_time}
+cd /root/payload/binary && RUST_LOG=info /root/payload/binary/system_rust -i {ip}:{p2p_port} {known_peers} {side_nodes} --api_addr {ip}:{api_port} --account /root/payload/scale-payload/account{account_name} --key /root/payload/scale-payload/node{node_name} --scale_id {scale_id} -l /root/payload/LDPC_codes -n {num_scale} -t {slot_time} -j /root/payload/binary/abi.json --contract_addr {contract_address} --node_url {rpc_url} --start_time {start_time}
 """
 
 instances_file = sys.argv[1]